@@ -1,23 +1,311 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+#[cfg(feature = "tls")]
+use std::fs::File;
+use std::future::Future;
 use std::hash::{BuildHasher, RandomState};
+#[cfg(feature = "tls")]
+use std::io::BufReader as StdBufReader;
 use std::io::Error as IoError;
 use std::io::ErrorKind;
-use std::num::NonZeroU64;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::net::{IpAddr, SocketAddr, TcpListener as StdTcpListener};
+use std::num::{NonZeroU64, NonZeroUsize};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener as StdUnixListener, UnixStream as StdUnixStream};
+#[cfg(feature = "tls")]
+use std::path::Path;
+#[cfg(any(unix, feature = "tls"))]
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use bincode::{DefaultOptions, Options};
+use bincode::{DefaultOptions, Error as BincodeError, Options};
 use serde::{Deserialize, Serialize};
-use tokio::io::{AsyncReadExt, BufReader};
-use tokio::net::TcpListener;
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::runtime::Builder as RuntimeBuilder;
+use tokio::sync::oneshot::{self, Receiver as OneshotReceiver, Sender as OneshotSender};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
+#[cfg(feature = "tls")]
+use tokio_rustls::rustls::crypto::ring::default_provider;
+#[cfg(feature = "tls")]
+use tokio_rustls::rustls::ServerConfig;
+#[cfg(feature = "tls")]
+use tokio_rustls::TlsAcceptor;
 
 use venator_engine::{
-    Engine, NewConnection, NewCreateSpanEvent, NewEvent, NewFollowsSpanEvent, NewSpanEvent,
-    NewSpanEventKind, NewUpdateSpanEvent,
+    ConnectionId, ConnectionKey, Engine, NewConnection, NewCreateSpanEvent, NewEvent,
+    NewFollowsSpanEvent, NewOneShotEvent, NewSpanEvent, NewSpanEventKind, NewUpdateSpanEvent,
 };
 
+/// Where the ingress accepts clients from.
+#[derive(Debug, Clone)]
+pub enum IngressBind {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(PathBuf),
+    #[cfg(feature = "tls")]
+    Tls {
+        addr: String,
+        cert: PathBuf,
+        key: PathBuf,
+    },
+}
+
+impl IngressBind {
+    /// A bind address prefixed with `unix:` is the path of a Unix domain
+    /// socket, anything else is a TCP address.
+    pub fn parse(bind: &str) -> IngressBind {
+        #[cfg(unix)]
+        if let Some(path) = bind.strip_prefix("unix:") {
+            return IngressBind::Unix(PathBuf::from(path));
+        }
+
+        IngressBind::Tcp(bind.to_owned())
+    }
+
+    fn listen(&self) -> Result<StdListener, IoError> {
+        match self {
+            IngressBind::Tcp(addr) => {
+                let listener = StdTcpListener::bind(addr)?;
+                listener.set_nonblocking(true)?;
+                Ok(StdListener::Tcp(listener))
+            }
+            #[cfg(unix)]
+            IngressBind::Unix(path) => {
+                // a socket file left behind by a process that didn't shut down
+                // cleanly would prevent binding, but one that is still in use
+                // is left alone, as is anything that isn't a socket
+                if let Ok(metadata) = std::fs::symlink_metadata(path) {
+                    if !metadata.file_type().is_socket() {
+                        return Err(IoError::new(
+                            ErrorKind::AlreadyExists,
+                            format!("{} exists and is not a socket", path.display()),
+                        ));
+                    }
+
+                    if StdUnixStream::connect(path).is_err() {
+                        let _ = std::fs::remove_file(path);
+                    }
+                }
+
+                let listener = StdUnixListener::bind(path)?;
+                let guard = SocketFileGuard(path.clone());
+                listener.set_nonblocking(true)?;
+                Ok(StdListener::Unix(listener, guard))
+            }
+            #[cfg(feature = "tls")]
+            IngressBind::Tls { addr, cert, key } => {
+                let acceptor = tls_acceptor(cert, key)?;
+                let listener = StdTcpListener::bind(addr)?;
+                listener.set_nonblocking(true)?;
+                Ok(StdListener::Tls(listener, acceptor))
+            }
+        }
+    }
+}
+
+impl Display for IngressBind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            IngressBind::Tcp(addr) => write!(f, "{addr}"),
+            #[cfg(unix)]
+            IngressBind::Unix(path) => write!(f, "unix:{}", path.display()),
+            #[cfg(feature = "tls")]
+            IngressBind::Tls { addr, .. } => write!(f, "{addr} (TLS)"),
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+fn tls_acceptor(cert: &Path, key: &Path) -> Result<TlsAcceptor, IoError> {
+    let certs = rustls_pemfile::certs(&mut StdBufReader::new(File::open(cert)?))
+        .collect::<Result<Vec<_>, _>>()?;
+    let key = rustls_pemfile::private_key(&mut StdBufReader::new(File::open(key)?))?
+        .ok_or_else(|| IoError::new(ErrorKind::InvalidData, "no private key found"))?;
+
+    let config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|err| IoError::new(ErrorKind::InvalidData, err))?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+// Unix domain sockets leave their file behind when closed, so this removes it
+// once the listener is dropped.
+#[cfg(unix)]
+struct SocketFileGuard(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFileGuard {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+enum StdListener {
+    Tcp(StdTcpListener),
+    #[cfg(unix)]
+    Unix(StdUnixListener, SocketFileGuard),
+    #[cfg(feature = "tls")]
+    Tls(StdTcpListener, TlsAcceptor),
+}
+
+impl StdListener {
+    // Unix domain sockets have no address beyond their path
+    fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            StdListener::Tcp(listener) => listener.local_addr().ok(),
+            #[cfg(unix)]
+            StdListener::Unix(_, _) => None,
+            #[cfg(feature = "tls")]
+            StdListener::Tls(listener, _) => listener.local_addr().ok(),
+        }
+    }
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, #[allow(dead_code)] SocketFileGuard),
+    #[cfg(feature = "tls")]
+    Tls(TcpListener, TlsAcceptor),
+}
+
+impl Listener {
+    fn from_std(listener: StdListener) -> Result<Listener, IoError> {
+        match listener {
+            StdListener::Tcp(listener) => Ok(Listener::Tcp(TcpListener::from_std(listener)?)),
+            #[cfg(unix)]
+            StdListener::Unix(listener, guard) => {
+                Ok(Listener::Unix(UnixListener::from_std(listener)?, guard))
+            }
+            #[cfg(feature = "tls")]
+            StdListener::Tls(listener, acceptor) => {
+                Ok(Listener::Tls(TcpListener::from_std(listener)?, acceptor))
+            }
+        }
+    }
+
+    async fn accept(&self) -> Result<Stream, IoError> {
+        match self {
+            Listener::Tcp(listener) => Ok(Stream::Tcp(listener.accept().await?.0)),
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => Ok(Stream::Unix(listener.accept().await?.0)),
+            #[cfg(feature = "tls")]
+            Listener::Tls(listener, acceptor) => {
+                Ok(Stream::Tls(listener.accept().await?.0, acceptor.clone()))
+            }
+        }
+    }
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    // the TLS handshake is done with the connection, so that a slow client
+    // doesn't hold up accepting others
+    #[cfg(feature = "tls")]
+    Tls(TcpStream, TlsAcceptor),
+}
+
+impl Stream {
+    // Unix domain sockets are local, so they are not limited per address
+    fn peer_ip(&self) -> Option<IpAddr> {
+        match self {
+            Stream::Tcp(stream) => stream.peer_addr().ok().map(|addr| addr.ip()),
+            #[cfg(unix)]
+            Stream::Unix(_) => None,
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream, _) => stream.peer_addr().ok().map(|addr| addr.ip()),
+        }
+    }
+}
+
+/// Limits on accepting clients, so that a misbehaving client reconnecting in
+/// a loop can't overwhelm the engine. Connections over a limit are closed
+/// immediately.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IngressLimits {
+    /// The most clients that can be connected at once.
+    pub max_connections: Option<usize>,
+    /// The most connections accepted from a single IP address each second.
+    pub max_connections_per_ip_per_second: Option<u32>,
+}
+
+/// The span of time that `max_connections_per_ip_per_second` is counted over.
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(1);
+
+struct ConnectionLimiter {
+    limits: IngressLimits,
+    window: Duration,
+    permits: Option<Arc<Semaphore>>,
+    // the start of the current window and the connections in it
+    recent: Mutex<HashMap<IpAddr, (Instant, u32)>>,
+}
+
+impl ConnectionLimiter {
+    fn new(limits: IngressLimits) -> ConnectionLimiter {
+        ConnectionLimiter::with_window(limits, RATE_LIMIT_WINDOW)
+    }
+
+    fn with_window(limits: IngressLimits, window: Duration) -> ConnectionLimiter {
+        ConnectionLimiter {
+            limits,
+            window,
+            permits: limits
+                .max_connections
+                .map(|max| Arc::new(Semaphore::new(max))),
+            recent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // The permit (if limited) must be held for as long as the connection is
+    // open. An error describes the limit that was reached.
+    fn admit(&self, ip: Option<IpAddr>) -> Result<Option<OwnedSemaphorePermit>, String> {
+        if let (Some(max), Some(ip)) = (self.limits.max_connections_per_ip_per_second, ip) {
+            let now = Instant::now();
+            let mut recent = self.recent.lock().unwrap();
+
+            // forget addresses that haven't connected recently so this doesn't
+            // grow without bound
+            if recent.len() > 1024 {
+                recent.retain(|_, (start, _)| now - *start < self.window);
+            }
+
+            let (start, count) = recent.entry(ip).or_insert((now, 0));
+            if now - *start >= self.window {
+                *start = now;
+                *count = 0;
+            }
+            if *count >= max {
+                return Err(format!("more than {max} connections per second from {ip}"));
+            }
+            *count += 1;
+        }
+
+        match &self.permits {
+            Some(permits) => match permits.clone().try_acquire_owned() {
+                Ok(permit) => Ok(Some(permit)),
+                Err(_) => Err(format!(
+                    "more than {} connections at once",
+                    self.limits.max_connections.unwrap_or_default()
+                )),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
 enum IngressState {
     Listening(Option<JoinHandle<IoError>>),
     ListeningFailure(IoError),
@@ -47,33 +335,181 @@ struct IngressStats {
     last_check: Mutex<Instant>,
     bytes_since_last_check: AtomicUsize,
     connected_connections: AtomicUsize,
+    bytes_read: AtomicU64,
+    messages_decoded: AtomicU64,
+    parse_errors: AtomicU64,
+    rejected_connections: AtomicU64,
+}
+
+impl IngressStats {
+    fn add_bytes(&self, bytes: usize) {
+        self.bytes_since_last_check
+            .fetch_add(bytes, Ordering::Relaxed);
+        self.bytes_read.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// Totals since the ingress was started (they carry over a rebind), to tell
+/// whether lost data was rejected by the ingress or never received.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct IngressMetrics {
+    pub bytes_read: u64,
+    pub messages_decoded: u64,
+    /// Handshakes and message frames that could not be decoded.
+    pub parse_errors: u64,
+    pub active_connections: usize,
+    /// Connections closed for exceeding the [`IngressLimits`].
+    pub rejected_connections: u64,
 }
 
 pub struct Ingress {
-    bind: String,
+    bind: IngressBind,
+    local_addr: Option<SocketAddr>,
+    engine: Engine,
+    token: Option<Arc<str>>,
     state: IngressState,
     stats: Arc<IngressStats>,
+    limiter: Arc<ConnectionLimiter>,
+    worker_threads: NonZeroUsize,
+    shutdown: Option<OneshotSender<()>>,
+    stopped: Option<OneshotReceiver<()>>,
 }
 
 impl Ingress {
-    pub fn start(bind: String, engine: Engine) -> Ingress {
+    // If a `token` is provided, clients must send the same one in their
+    // handshake or else they are disconnected.
+    //
+    // Clients are read on a runtime of their own with `worker_threads`
+    // threads, or `DEFAULT_WORKER_THREADS` if not provided. More threads help
+    // when many clients are sending at once.
+    pub fn start(
+        bind: IngressBind,
+        engine: Engine,
+        token: Option<String>,
+        limits: IngressLimits,
+        worker_threads: Option<NonZeroUsize>,
+    ) -> Ingress {
         let stats = Arc::new(IngressStats {
             last_check: Mutex::new(Instant::now()),
             bytes_since_last_check: AtomicUsize::new(0),
             connected_connections: AtomicUsize::new(0),
+            bytes_read: AtomicU64::new(0),
+            messages_decoded: AtomicU64::new(0),
+            parse_errors: AtomicU64::new(0),
+            rejected_connections: AtomicU64::new(0),
         });
+        let limiter = Arc::new(ConnectionLimiter::new(limits));
+        let worker_threads = worker_threads.unwrap_or(DEFAULT_WORKER_THREADS);
+
+        Ingress::start_with_stats(
+            bind,
+            engine,
+            token.map(Arc::from),
+            stats,
+            limiter,
+            worker_threads,
+        )
+    }
+
+    fn start_with_stats(
+        bind: IngressBind,
+        engine: Engine,
+        token: Option<Arc<str>>,
+        stats: Arc<IngressStats>,
+        limiter: Arc<ConnectionLimiter>,
+        worker_threads: NonZeroUsize,
+    ) -> Ingress {
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+        let (stopped_sender, stopped_receiver) = oneshot::channel();
 
-        let b = bind.clone();
-        let s = stats.clone();
-        let thread = std::thread::spawn(|| ingress_task(b, engine, s));
+        // the listener is bound here so that failures are reported immediately
+        let listener = bind.listen();
+        let local_addr = listener.as_ref().ok().and_then(StdListener::local_addr);
+
+        let state = match listener {
+            Ok(listener) => {
+                let e = engine.clone();
+                let s = stats.clone();
+                let t = token.clone();
+                let l = limiter.clone();
+                let thread = std::thread::spawn(move || {
+                    ingress_task(
+                        listener,
+                        e,
+                        s,
+                        t,
+                        l,
+                        worker_threads,
+                        shutdown_receiver,
+                        stopped_sender,
+                    )
+                });
+
+                IngressState::Listening(Some(thread))
+            }
+            Err(err) => IngressState::ListeningFailure(err),
+        };
 
         Ingress {
             bind,
-            state: IngressState::Listening(Some(thread)),
+            local_addr,
+            engine,
+            token,
+            state,
             stats,
+            limiter,
+            worker_threads,
+            shutdown: Some(shutdown_sender),
+            stopped: Some(stopped_receiver),
         }
     }
 
+    /// This stops accepting connections on the current address and starts
+    /// listening on the new one. Connections that were already accepted are
+    /// kept until they end.
+    ///
+    /// The current address is released in the background, so [`Ingress::stop`]
+    /// should be awaited first if the new address may be the same.
+    pub fn rebind(&mut self, bind: IngressBind) -> Result<(), String> {
+        *self = Ingress::start_with_stats(
+            bind,
+            self.engine.clone(),
+            self.token.clone(),
+            self.stats.clone(),
+            self.limiter.clone(),
+            self.worker_threads,
+        );
+
+        match self.state.check_error() {
+            Some(err) => Err(format!("{err}")),
+            None => Ok(()),
+        }
+    }
+
+    /// This stops accepting connections. The returned future completes once
+    /// the listener is closed so that its address can be reused. It does not
+    /// borrow the ingress so it can be awaited without holding a lock on it.
+    pub fn stop(&mut self) -> impl Future<Output = ()> + Send + 'static {
+        drop(self.shutdown.take());
+        let stopped = self.stopped.take();
+
+        async move {
+            if let Some(stopped) = stopped {
+                let _ = stopped.await;
+            }
+        }
+    }
+
+    pub fn bind(&self) -> &IngressBind {
+        &self.bind
+    }
+
+    /// The TCP address that is being listened on, which has the actual port
+    /// if it was bound to port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
     pub fn status(&mut self) -> (String, Option<String>) {
         self.state.check_state();
         match self.state.check_error() {
@@ -108,214 +544,572 @@ impl Ingress {
 
         (connected, bytes as f64 / elapsed)
     }
+
+    pub fn metrics(&self) -> IngressMetrics {
+        IngressMetrics {
+            bytes_read: self.stats.bytes_read.load(Ordering::Relaxed),
+            messages_decoded: self.stats.messages_decoded.load(Ordering::Relaxed),
+            parse_errors: self.stats.parse_errors.load(Ordering::Relaxed),
+            active_connections: self.stats.connected_connections.load(Ordering::Relaxed),
+            rejected_connections: self.stats.rejected_connections.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The number of threads the ingress reads clients with if not configured.
+const DEFAULT_WORKER_THREADS: NonZeroUsize = NonZeroUsize::new(2).unwrap();
+
+// the runtime is built by hand since the thread count is only known at runtime
+#[allow(clippy::too_many_arguments)]
+fn ingress_task(
+    listener: StdListener,
+    engine: Engine,
+    stats: Arc<IngressStats>,
+    token: Option<Arc<str>>,
+    limiter: Arc<ConnectionLimiter>,
+    worker_threads: NonZeroUsize,
+    shutdown: OneshotReceiver<()>,
+    stopped: OneshotSender<()>,
+) -> IoError {
+    let runtime = match RuntimeBuilder::new_multi_thread()
+        .worker_threads(worker_threads.get())
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(err) => return err,
+    };
+
+    runtime.block_on(accept_connections(
+        listener, engine, stats, token, limiter, shutdown, stopped,
+    ))
 }
 
-#[tokio::main(worker_threads = 2)]
-async fn ingress_task(bind: String, engine: Engine, stats: Arc<IngressStats>) -> IoError {
-    let listener = match TcpListener::bind(&bind).await {
+async fn accept_connections(
+    listener: StdListener,
+    engine: Engine,
+    stats: Arc<IngressStats>,
+    token: Option<Arc<str>>,
+    limiter: Arc<ConnectionLimiter>,
+    mut shutdown: OneshotReceiver<()>,
+    stopped: OneshotSender<()>,
+) -> IoError {
+    let listener = match Listener::from_std(listener) {
         Ok(listener) => listener,
         Err(err) => return err,
     };
 
+    let mut connections = JoinSet::new();
+
     loop {
-        let (stream, _) = match listener.accept().await {
-            Ok(res) => res,
+        // clean up connections that have finished
+        while connections.try_join_next().is_some() {}
+
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut shutdown => break,
+        };
+
+        let accepted = match accepted {
+            Ok(stream) => stream,
             Err(err) => return err,
         };
 
-        stats.connected_connections.fetch_add(1, Ordering::Relaxed);
+        // the stream is dropped right away, which closes it
+        let permit = match limiter.admit(accepted.peer_ip()) {
+            Ok(permit) => permit,
+            Err(reason) => {
+                eprintln!("rejected connection: {reason}");
+                stats.rejected_connections.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+        };
 
-        let mut stream = BufReader::new(stream);
+        let remote_ip = accepted.peer_ip();
         let engine = engine.clone();
         let stats = stats.clone();
-        let deserializer = DefaultOptions::new()
-            .with_varint_encoding()
-            .with_big_endian()
-            .with_limit(u16::MAX as u64);
+        let token = token.clone();
+        match accepted {
+            Stream::Tcp(stream) => {
+                connections.spawn(async move {
+                    handle_connection(stream, remote_ip, engine, stats, token).await;
+                    drop(permit);
+                });
+            }
+            #[cfg(unix)]
+            Stream::Unix(stream) => {
+                connections.spawn(async move {
+                    handle_connection(stream, remote_ip, engine, stats, token).await;
+                    drop(permit);
+                });
+            }
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream, acceptor) => {
+                connections.spawn(async move {
+                    match acceptor.accept(stream).await {
+                        Ok(stream) => {
+                            handle_connection(stream, remote_ip, engine, stats, token).await
+                        }
+                        Err(err) => eprintln!("failed TLS handshake: {err:?}"),
+                    }
+                    drop(permit);
+                });
+            }
+        }
+    }
+
+    drop(listener);
+    drop(stopped);
+
+    // returning would drop the runtime and the existing connections with it
+    while connections.join_next().await.is_some() {}
+
+    IoError::new(ErrorKind::Interrupted, "ingress was stopped")
+}
+
+async fn handle_connection<S: AsyncRead + Unpin>(
+    stream: S,
+    remote_ip: Option<IpAddr>,
+    engine: Engine,
+    stats: Arc<IngressStats>,
+    token: Option<Arc<str>>,
+) {
+    stats.connected_connections.fetch_add(1, Ordering::Relaxed);
+
+    let stream = BufReader::new(stream);
+    if let Some(connection_id) =
+        read_connection(stream, remote_ip, &engine, &stats, token.as_deref()).await
+    {
+        // a failed disconnect is already logged by the engine
+        let _ = engine.disconnect_connection(connection_id).await;
+    }
+
+    stats.connected_connections.fetch_sub(1, Ordering::Relaxed);
+}
+
+// This reads the handshake and messages from a client until the stream ends or
+// the data is invalid. Returns the connection id if it was inserted.
+async fn read_connection<S: AsyncRead + Unpin>(
+    mut stream: BufReader<S>,
+    remote_ip: Option<IpAddr>,
+    engine: &Engine,
+    stats: &IngressStats,
+    token: Option<&str>,
+) -> Option<ConnectionId> {
+    let deserializer = DefaultOptions::new()
+        .with_varint_encoding()
+        .with_big_endian()
+        .with_limit(u16::MAX as u64);
+
+    let mut buffer = vec![];
+
+    let mut length_bytes = [0u8; 2];
+    if let Err(err) = stream.read_exact(&mut length_bytes).await {
+        eprintln!("failed to read handshake length: {err:?}");
+        return None;
+    }
+
+    let length = u16::from_be_bytes(length_bytes);
+
+    // a handshake is never empty, so an empty one instead starts a stream of
+    // one-shot messages that don't need a connection
+    if length == 0 {
+        stats.add_bytes(2);
+        read_oneshot_messages(stream, remote_ip, engine, stats, token).await;
+        return None;
+    }
+
+    buffer.resize(length as usize, 0u8);
+    if let Err(err) = stream.read_exact(&mut buffer).await {
+        eprintln!("failed to read handshake: {err:?}");
+        return None;
+    }
+
+    stats.add_bytes(length as usize + 2);
+
+    let handshake = match Handshake::parse(deserializer, &buffer) {
+        Ok(handshake) => handshake,
+        Err(err) => {
+            eprintln!("failed to parse handshake: {err:?}");
+            stats.parse_errors.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+    };
+
+    if let Some(token) = token {
+        let provided = handshake.token.as_deref().unwrap_or_default();
+        if !tokens_match(token.as_bytes(), provided.as_bytes()) {
+            eprintln!("rejected connection: invalid or missing token");
+            return None;
+        }
+    }
+
+    // clients that support larger messages announce it in the
+    // handshake, and then use a four-byte length prefix
+    let max_message_size = handshake.max_message_size.min(MAX_MESSAGE_SIZE);
+    let wide_prefix = handshake.max_message_size > u16::MAX as u32;
+    let deserializer = deserializer.with_limit(max_message_size as u64);
+
+    // clients may provide their own id so that it can be correlated
+    // with their own logs; the engine rejects it if a connection with
+    // the same id is still connected
+    let connection_id = match handshake.connection_id {
+        Some(connection_id) => connection_id,
+        None => RandomState::new().hash_one(0u64),
+    };
+    // the observed address is recorded so traces can be told apart by host;
+    // it takes precedence over a client-provided field of the same name
+    let mut fields = conv_value_map(handshake.fields);
+    if let Some(remote_ip) = remote_ip {
+        fields.insert(
+            REMOTE_ADDR_FIELD.to_owned(),
+            venator_engine::Value::Str(remote_ip.to_string()),
+        );
+    }
+
+    let connection = NewConnection {
+        id: connection_id,
+        schema: handshake.schema,
+        fields,
+    };
 
-        tokio::spawn(async move {
-            let mut buffer = vec![];
+    let connection_key = match engine.insert_connection(connection).await {
+        Ok(key) => key,
+        Err(err) => {
+            eprintln!("failed to insert connection: {err:?}");
+            return None;
+        }
+    };
 
+    loop {
+        let (length, prefix_length) = if wide_prefix {
+            let mut length_bytes = [0u8; 4];
+            let result = stream.read_exact(&mut length_bytes).await;
+            (result.map(|_| u32::from_be_bytes(length_bytes)), 4)
+        } else {
             let mut length_bytes = [0u8; 2];
-            if let Err(err) = stream.read_exact(&mut length_bytes).await {
-                eprintln!("failed to read handshake length: {err:?}");
-                return;
+            let result = stream.read_exact(&mut length_bytes).await;
+            (result.map(|_| u16::from_be_bytes(length_bytes) as u32), 2)
+        };
+
+        let length = match length {
+            Ok(length) => length,
+            Err(err) => {
+                if err.kind() != ErrorKind::UnexpectedEof {
+                    eprintln!("failed to read message length: {err:?}");
+                }
+                break;
             }
+        };
+
+        if length > max_message_size {
+            eprintln!("message of {length} bytes exceeds the maximum of {max_message_size}");
+            break;
+        }
 
-            let length = u16::from_be_bytes(length_bytes);
+        buffer.resize(length as usize, 0u8);
+        if let Err(err) = stream.read_exact(&mut buffer).await {
+            eprintln!("failed to read message: {err:?}");
+            break;
+        }
+
+        stats.add_bytes(length as usize + prefix_length);
+
+        // clients that announced batching send any number of messages in
+        // each frame, which are inserted in order, and clients that announced
+        // sequencing precede each message with its sequence number
+        let bytes = buffer.as_slice();
+        let result = match (handshake.batched, handshake.sequenced) {
+            (true, true) => deserializer
+                .deserialize_from::<_, Vec<(u64, Message)>>(bytes)
+                .map(|msgs| {
+                    msgs.into_iter()
+                        .map(|(seq, msg)| (Some(seq), msg))
+                        .collect()
+                }),
+            (true, false) => deserializer
+                .deserialize_from::<_, Vec<Message>>(bytes)
+                .map(|msgs| msgs.into_iter().map(|msg| (None, msg)).collect()),
+            (false, true) => deserializer
+                .deserialize_from::<_, (u64, Message)>(bytes)
+                .map(|(seq, msg)| vec![(Some(seq), msg)]),
+            (false, false) => deserializer
+                .deserialize_from::<_, Message>(bytes)
+                .map(|msg| vec![(None, msg)]),
+        };
 
-            buffer.resize(length as usize, 0u8);
-            if let Err(err) = stream.read_exact(&mut buffer).await {
-                eprintln!("failed to read handshake: {err:?}");
-                return;
+        // the frame has been fully read, so a malformed message only loses
+        // itself and the connection can carry on with the next one
+        let msgs = match result {
+            Ok(msgs) => msgs,
+            Err(err) => {
+                eprintln!("failed to parse message: {err:?}");
+                stats.parse_errors.fetch_add(1, Ordering::Relaxed);
+                continue;
             }
+        };
 
-            let handshake: Handshake = match deserializer.deserialize_from(buffer.as_slice()) {
-                Ok(handshake) => handshake,
-                Err(err) => {
-                    eprintln!("failed to parse handshake: {err:?}");
-                    return;
-                }
-            };
+        stats
+            .messages_decoded
+            .fetch_add(msgs.len() as u64, Ordering::Relaxed);
 
-            stats
-                .bytes_since_last_check
-                .fetch_add(length as usize + 2, Ordering::Relaxed);
+        for (seq, msg) in msgs {
+            insert_message(engine, connection_key, seq, msg).await;
+        }
+    }
 
-            let connection_id = RandomState::new().hash_one(0u64);
-            let connection = NewConnection {
-                id: connection_id,
-                fields: conv_value_map(handshake.fields),
-            };
+    Some(connection_id)
+}
 
-            let connection_key = match engine.insert_connection(connection).await {
-                Ok(key) => key,
-                Err(err) => {
-                    eprintln!("failed to insert connection: {err:?}");
-                    return;
-                }
-            };
+// This reads one-shot messages until the stream ends or the data is invalid.
+// Each carries the fields and token that would otherwise be in a handshake and
+// is inserted under the connection shared by one-shot events with its fields.
+async fn read_oneshot_messages<S: AsyncRead + Unpin>(
+    mut stream: BufReader<S>,
+    remote_ip: Option<IpAddr>,
+    engine: &Engine,
+    stats: &IngressStats,
+    token: Option<&str>,
+) {
+    let deserializer = DefaultOptions::new()
+        .with_varint_encoding()
+        .with_big_endian()
+        .with_limit(u16::MAX as u64);
 
-            loop {
-                let mut length_bytes = [0u8; 2];
-                if let Err(err) = stream.read_exact(&mut length_bytes).await {
-                    if err.kind() != ErrorKind::UnexpectedEof {
-                        eprintln!("failed to read message length: {err:?}");
-                    }
-                    break;
-                }
+    let mut buffer = vec![];
 
-                let length = u16::from_be_bytes(length_bytes);
+    loop {
+        let mut length_bytes = [0u8; 2];
+        if let Err(err) = stream.read_exact(&mut length_bytes).await {
+            if err.kind() != ErrorKind::UnexpectedEof {
+                eprintln!("failed to read message length: {err:?}");
+            }
+            break;
+        }
 
-                buffer.resize(length as usize, 0u8);
-                if let Err(err) = stream.read_exact(&mut buffer).await {
-                    eprintln!("failed to read message: {err:?}");
-                    break;
-                }
+        let length = u16::from_be_bytes(length_bytes);
 
-                stats
-                    .bytes_since_last_check
-                    .fetch_add(length as usize + 2, Ordering::Relaxed);
+        buffer.resize(length as usize, 0u8);
+        if let Err(err) = stream.read_exact(&mut buffer).await {
+            eprintln!("failed to read message: {err:?}");
+            break;
+        }
 
-                let msg: Message = match deserializer.deserialize_from(buffer.as_slice()) {
-                    Ok(message) => message,
-                    Err(err) => {
-                        eprintln!("failed to parse message: {err:?}");
-                        break;
-                    }
-                };
-
-                match msg.data {
-                    MessageData::Create(create_data) => {
-                        // we have no need for the result, and the insert is
-                        // executed regardless if we poll
-                        #[allow(clippy::let_underscore_future)]
-                        let _ = engine.insert_span_event(NewSpanEvent {
-                            connection_key,
-                            timestamp: msg.timestamp,
-                            span_id: msg.span_id.unwrap(),
-                            kind: NewSpanEventKind::Create(NewCreateSpanEvent {
-                                parent_id: create_data.parent_id,
-                                target: create_data.target,
-                                name: create_data.name,
-                                level: create_data.level,
-                                file_name: create_data.file_name,
-                                file_line: create_data.file_line,
-                                fields: conv_value_map(create_data.fields),
-                            }),
-                        });
-                    }
-                    MessageData::Update(update_data) => {
-                        // we have no need for the result, and the insert is
-                        // executed regardless if we poll
-                        #[allow(clippy::let_underscore_future)]
-                        let _ = engine.insert_span_event(NewSpanEvent {
-                            connection_key,
-                            timestamp: msg.timestamp,
-                            span_id: msg.span_id.unwrap(),
-                            kind: NewSpanEventKind::Update(NewUpdateSpanEvent {
-                                fields: conv_value_map(update_data.fields),
-                            }),
-                        });
-                    }
-                    MessageData::Follows(follows_data) => {
-                        // we have no need for the result, and the insert is
-                        // executed regardless if we poll
-                        #[allow(clippy::let_underscore_future)]
-                        let _ = engine.insert_span_event(NewSpanEvent {
-                            connection_key,
-                            timestamp: msg.timestamp,
-                            span_id: msg.span_id.unwrap(),
-                            kind: NewSpanEventKind::Follows(NewFollowsSpanEvent {
-                                follows: follows_data.follows,
-                            }),
-                        });
-                    }
-                    MessageData::Enter => {
-                        // we have no need for the result, and the insert is
-                        // executed regardless if we poll
-                        #[allow(clippy::let_underscore_future)]
-                        let _ = engine.insert_span_event(NewSpanEvent {
-                            connection_key,
-                            timestamp: msg.timestamp,
-                            span_id: msg.span_id.unwrap(),
-                            kind: NewSpanEventKind::Enter,
-                        });
-                    }
-                    MessageData::Exit => {
-                        // we have no need for the result, and the insert is
-                        // executed regardless if we poll
-                        #[allow(clippy::let_underscore_future)]
-                        let _ = engine.insert_span_event(NewSpanEvent {
-                            connection_key,
-                            timestamp: msg.timestamp,
-                            span_id: msg.span_id.unwrap(),
-                            kind: NewSpanEventKind::Exit,
-                        });
-                    }
-                    MessageData::Close => {
-                        // we have no need for the result, and the insert is
-                        // executed regardless if we poll
-                        #[allow(clippy::let_underscore_future)]
-                        let _ = engine.insert_span_event(NewSpanEvent {
-                            connection_key,
-                            timestamp: msg.timestamp,
-                            span_id: msg.span_id.unwrap(),
-                            kind: NewSpanEventKind::Close,
-                        });
-                    }
-                    MessageData::Event(event) => {
-                        // we have no need for the result, and the insert is
-                        // executed regardless if we poll
-                        #[allow(clippy::let_underscore_future)]
-                        let _ = engine.insert_event(NewEvent {
-                            connection_key,
-                            timestamp: msg.timestamp,
-                            span_id: msg.span_id,
-                            target: event.target,
-                            name: event.name,
-                            level: event.level,
-                            file_name: event.file_name,
-                            file_line: event.file_line,
-                            fields: conv_value_map(event.fields),
-                        });
-                    }
-                };
+        stats.add_bytes(length as usize + 2);
+
+        let msg = match deserializer.deserialize_from::<_, OneShotMessage>(buffer.as_slice()) {
+            Ok(msg) => msg,
+            Err(err) => {
+                eprintln!("failed to parse one-shot message: {err:?}");
+                stats.parse_errors.fetch_add(1, Ordering::Relaxed);
+                continue;
             }
+        };
 
-            // we have no need for the result, and the disconnect is executed
-            // regardless if we poll
-            #[allow(clippy::let_underscore_future)]
-            let _ = engine.disconnect_connection(connection_id);
+        if let Some(token) = token {
+            let provided = msg.token.as_deref().unwrap_or_default();
+            if !tokens_match(token.as_bytes(), provided.as_bytes()) {
+                eprintln!("rejected one-shot message: invalid or missing token");
+                break;
+            }
+        }
 
-            stats.connected_connections.fetch_sub(1, Ordering::Relaxed);
-        });
+        stats.messages_decoded.fetch_add(1, Ordering::Relaxed);
+
+        let mut connection_fields = conv_value_map(msg.fields);
+        if let Some(remote_ip) = remote_ip {
+            connection_fields.insert(
+                REMOTE_ADDR_FIELD.to_owned(),
+                venator_engine::Value::Str(remote_ip.to_string()),
+            );
+        }
+
+        // a rejected event is already logged by the engine
+        let _ = engine
+            .insert_oneshot_event(NewOneShotEvent {
+                connection_fields,
+                timestamp: msg.timestamp,
+                name: msg.event.name,
+                target: msg.event.target,
+                level: conv_level(msg.event.level),
+                file_name: msg.event.file_name,
+                file_line: msg.event.file_line,
+                fields: conv_value_map(msg.event.fields),
+            })
+            .await;
+    }
+}
+
+// The engine rejects levels outside of TRACE (0) through ERROR (4), so anything
+// else from a misbehaving client is clamped to the nearest level instead of the
+// message being lost.
+fn conv_level(level: i32) -> i32 {
+    let clamped = level.clamp(0, 4);
+    if clamped != level {
+        eprintln!("clamped invalid level {level} to {clamped}");
+    }
+
+    clamped
+}
+
+async fn insert_message(
+    engine: &Engine,
+    connection_key: ConnectionKey,
+    seq: Option<u64>,
+    msg: Message,
+) {
+    let kind = match msg.data {
+        MessageData::Create(create_data) => NewSpanEventKind::Create(NewCreateSpanEvent {
+            parent_id: create_data.parent_id,
+            target: create_data.target,
+            name: create_data.name,
+            level: conv_level(create_data.level),
+            file_name: create_data.file_name,
+            file_line: create_data.file_line,
+            fields: conv_value_map(create_data.fields),
+        }),
+        MessageData::Update(update_data) => NewSpanEventKind::Update(NewUpdateSpanEvent {
+            fields: conv_value_map(update_data.fields),
+        }),
+        MessageData::Follows(follows_data) => NewSpanEventKind::Follows(NewFollowsSpanEvent {
+            follows: follows_data.follows,
+        }),
+        MessageData::Enter => NewSpanEventKind::Enter,
+        MessageData::Exit => NewSpanEventKind::Exit,
+        MessageData::Close => NewSpanEventKind::Close,
+        MessageData::Event(event) => {
+            // waiting for the insert to be queued applies backpressure when
+            // the engine falls behind, but there is no need for the result
+            engine
+                .queue_event(NewEvent {
+                    connection_key,
+                    seq,
+                    timestamp: msg.timestamp,
+                    span_id: msg.span_id,
+                    target: event.target,
+                    name: event.name,
+                    level: conv_level(event.level),
+                    file_name: event.file_name,
+                    file_line: event.file_line,
+                    fields: conv_value_map(event.fields),
+                })
+                .await;
+            return;
+        }
+    };
+
+    // a misbehaving client shouldn't be able to take down the connection, so
+    // span messages without a span are only logged
+    let Some(span_id) = msg.span_id else {
+        eprintln!("rejected span message without a span id");
+        return;
+    };
+
+    // waiting for the insert to be queued applies backpressure when the
+    // engine falls behind, but there is no need for the result
+    engine
+        .queue_span_event(NewSpanEvent {
+            connection_key,
+            seq,
+            timestamp: msg.timestamp,
+            span_id,
+            kind,
+        })
+        .await;
+}
+
+// This compares the tokens in a way that takes the same time regardless of
+// where they differ, so that a matching prefix can't be found by timing.
+fn tokens_match(expected: &[u8], provided: &[u8]) -> bool {
+    if expected.len() != provided.len() {
+        return false;
     }
+
+    let difference = expected
+        .iter()
+        .zip(provided)
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b));
+
+    std::hint::black_box(difference) == 0
 }
 
+/// The synthetic connection field holding the client's observed IP address.
+pub const REMOTE_ADDR_FIELD: &str = "remote.addr";
+
+/// The largest message that will be accepted from a client, regardless of what
+/// it announces in its handshake.
+const MAX_MESSAGE_SIZE: u32 = 16 * 1024 * 1024;
+
 #[derive(Deserialize)]
 pub struct Handshake {
     pub fields: BTreeMap<String, Value>,
+    #[serde(skip, default = "default_schema")]
+    pub schema: u32,
+    #[serde(skip)]
+    pub connection_id: Option<ConnectionId>,
+    #[serde(skip, default = "default_max_message_size")]
+    pub max_message_size: u32,
+    #[serde(skip)]
+    pub token: Option<String>,
+    #[serde(skip)]
+    pub batched: bool,
+    #[serde(skip)]
+    pub sequenced: bool,
+}
+
+fn default_schema() -> u32 {
+    1
+}
+
+fn default_max_message_size() -> u32 {
+    u16::MAX as u32
+}
+
+impl Handshake {
+    // Older clients only send the fields, so anything after them is only
+    // parsed if there is something left: first the schema version, then an
+    // optional fixed connection id, the max message size, an optional token,
+    // whether messages are batched, and then whether they are sequenced.
+    fn parse<O: Options + Copy>(options: O, mut bytes: &[u8]) -> Result<Handshake, BincodeError> {
+        let mut handshake: Handshake = options.deserialize_from(&mut bytes)?;
+
+        if !bytes.is_empty() {
+            handshake.schema = options.deserialize_from(&mut bytes)?;
+        }
+
+        if !bytes.is_empty() {
+            handshake.connection_id = options.deserialize_from(&mut bytes)?;
+        }
+
+        if !bytes.is_empty() {
+            handshake.max_message_size = options.deserialize_from(&mut bytes)?;
+        }
+
+        if !bytes.is_empty() {
+            handshake.token = options.deserialize_from(&mut bytes)?;
+        }
+
+        if !bytes.is_empty() {
+            handshake.batched = options.deserialize_from(&mut bytes)?;
+        }
+
+        if !bytes.is_empty() {
+            handshake.sequenced = options.deserialize_from(&mut bytes)?;
+        }
+
+        Ok(handshake)
+    }
+}
+
+/// A message sent after an empty handshake, which carries the connection
+/// fields and token itself so that an event can be sent without setting up a
+/// connection first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OneShotMessage {
+    fields: BTreeMap<String, Value>,
+    token: Option<String>,
+    timestamp: NonZeroU64,
+    event: EventData,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -432,3 +1226,799 @@ fn conv_value_map(vmap: BTreeMap<String, Value>) -> BTreeMap<String, venator_eng
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    use venator_engine::{
+        ConnectionView, EventView, FilterPredicate, Order, Query, Timestamp, TransientStorage,
+    };
+
+    use super::*;
+
+    // The ingress is bound to a port picked by the system so that tests can run
+    // in parallel without colliding.
+    fn start_ingress(
+        engine: &Engine,
+        token: Option<&str>,
+        limits: IngressLimits,
+        worker_threads: Option<NonZeroUsize>,
+    ) -> (Ingress, SocketAddr) {
+        let ingress = Ingress::start(
+            IngressBind::Tcp("127.0.0.1:0".to_owned()),
+            engine.clone(),
+            token.map(str::to_owned),
+            limits,
+            worker_threads,
+        );
+        let addr = ingress.local_addr().unwrap();
+
+        (ingress, addr)
+    }
+
+    // This polls until the condition holds instead of sleeping for a fixed
+    // time, and fails the test if it takes too long.
+    fn wait_until(mut condition: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            assert!(
+                Instant::now() < deadline,
+                "timed out waiting for the ingress"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    // This waits until the listener is closed.
+    fn stop(ingress: &mut Ingress) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(ingress.stop());
+    }
+
+    fn query_all() -> Query {
+        Query {
+            filter: vec![],
+            order: Order::Asc,
+            limit: 5,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        }
+    }
+
+    fn wait_for_events(engine: &Engine, count: usize) -> Vec<EventView> {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut events = vec![];
+        wait_until(|| {
+            events = runtime.block_on(engine.query_event(query_all()));
+            events.len() >= count
+        });
+
+        events
+    }
+
+    fn wait_for_connections(engine: &Engine, count: usize) -> Vec<ConnectionView> {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut connections = vec![];
+        wait_until(|| {
+            connections = runtime.block_on(engine.query_connection(query_all()));
+            connections.len() >= count
+        });
+
+        connections
+    }
+
+    #[test]
+    fn fixed_connection_id_is_used() {
+        let engine = Engine::new(TransientStorage::new());
+        let (_ingress, addr) = start_ingress(&engine, None, IngressLimits::default(), None);
+
+        let fields = BTreeMap::<String, Value>::new();
+        let payload = DefaultOptions::new()
+            .with_varint_encoding()
+            .with_big_endian()
+            .serialize(&(fields, 1u32, Some(42u64)))
+            .unwrap();
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(&(payload.len() as u16).to_be_bytes())
+            .unwrap();
+        stream.write_all(&payload).unwrap();
+
+        let connections = wait_for_connections(&engine, 1);
+
+        assert_eq!(connections.len(), 1);
+        assert_eq!(connections[0].id, "42");
+    }
+
+    #[test]
+    fn token_is_required_if_configured() {
+        let engine = Engine::new(TransientStorage::new());
+        let (_ingress, addr) =
+            start_ingress(&engine, Some("secret"), IngressLimits::default(), None);
+
+        let connect = |token: Option<&str>| {
+            let fields = BTreeMap::<String, Value>::new();
+            let payload = DefaultOptions::new()
+                .with_varint_encoding()
+                .with_big_endian()
+                .serialize(&(fields, 1u32, None::<u64>, u16::MAX as u32, token))
+                .unwrap();
+
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream
+                .write_all(&(payload.len() as u16).to_be_bytes())
+                .unwrap();
+            stream.write_all(&payload).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            stream
+        };
+
+        // rejected clients are closed by the ingress
+        for token in [None, Some("secreT")] {
+            let mut stream = connect(token);
+            assert_eq!(stream.read(&mut [0]).unwrap(), 0);
+        }
+
+        let _valid = connect(Some("secret"));
+        let connections = wait_for_connections(&engine, 1);
+
+        assert_eq!(connections.len(), 1);
+    }
+
+    #[test]
+    fn large_messages_are_accepted_if_announced() {
+        let engine = Engine::new(TransientStorage::new());
+        let (_ingress, addr) = start_ingress(&engine, None, IngressLimits::default(), None);
+
+        let options = DefaultOptions::new()
+            .with_varint_encoding()
+            .with_big_endian();
+
+        let fields = BTreeMap::<String, Value>::new();
+        let handshake = options
+            .serialize(&(fields, 1u32, None::<u64>, 1u32 << 20))
+            .unwrap();
+
+        let message = options
+            .serialize(&Message {
+                timestamp: NonZeroU64::new(1).unwrap(),
+                span_id: None,
+                data: MessageData::Event(EventData {
+                    target: "tests".to_owned(),
+                    name: "event".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::from_iter([(
+                        "backtrace".to_owned(),
+                        Value::Str("x".repeat(200 * 1024)),
+                    )]),
+                }),
+            })
+            .unwrap();
+        assert!(message.len() > u16::MAX as usize);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(&(handshake.len() as u16).to_be_bytes())
+            .unwrap();
+        stream.write_all(&handshake).unwrap();
+        stream
+            .write_all(&(message.len() as u32).to_be_bytes())
+            .unwrap();
+        stream.write_all(&message).unwrap();
+
+        let events = wait_for_events(&engine, 1);
+
+        assert_eq!(events.len(), 1);
+        let attribute = &events[0].attributes[0];
+        assert_eq!(attribute.name, "backtrace");
+        assert_eq!(attribute.value.len(), 200 * 1024);
+    }
+
+    #[test]
+    fn batched_messages_are_inserted_in_order() {
+        let engine = Engine::new(TransientStorage::new());
+        let (_ingress, addr) = start_ingress(&engine, None, IngressLimits::default(), None);
+
+        let options = DefaultOptions::new()
+            .with_varint_encoding()
+            .with_big_endian();
+
+        let fields = BTreeMap::<String, Value>::new();
+        let handshake = options
+            .serialize(&(
+                fields,
+                1u32,
+                None::<u64>,
+                u16::MAX as u32,
+                None::<String>,
+                true,
+            ))
+            .unwrap();
+
+        let event = |timestamp: u64, name: &str| Message {
+            timestamp: NonZeroU64::new(timestamp).unwrap(),
+            span_id: None,
+            data: MessageData::Event(EventData {
+                target: "tests".to_owned(),
+                name: name.to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            }),
+        };
+
+        let first = options
+            .serialize(&vec![event(1, "a"), event(2, "b"), event(3, "c")])
+            .unwrap();
+        let second = options.serialize(&vec![event(4, "d")]).unwrap();
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(&(handshake.len() as u16).to_be_bytes())
+            .unwrap();
+        stream.write_all(&handshake).unwrap();
+        for frame in [first, second] {
+            stream
+                .write_all(&(frame.len() as u16).to_be_bytes())
+                .unwrap();
+            stream.write_all(&frame).unwrap();
+        }
+
+        let events = wait_for_events(&engine, 4);
+
+        let names = events.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn retransmitted_messages_are_skipped() {
+        let engine = Engine::new(TransientStorage::new());
+        let (ingress, addr) = start_ingress(&engine, None, IngressLimits::default(), None);
+
+        let options = DefaultOptions::new()
+            .with_varint_encoding()
+            .with_big_endian();
+
+        let fields = BTreeMap::<String, Value>::new();
+        let handshake = options
+            .serialize(&(
+                fields,
+                1u32,
+                Some(42u64),
+                u16::MAX as u32,
+                None::<String>,
+                true,
+                true,
+            ))
+            .unwrap();
+
+        let event = |seq: u64, name: &str| {
+            let message = Message {
+                timestamp: NonZeroU64::new(seq).unwrap(),
+                span_id: None,
+                data: MessageData::Event(EventData {
+                    target: "tests".to_owned(),
+                    name: name.to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                }),
+            };
+            (seq, message)
+        };
+
+        // the second connection replays the end of the first before carrying on
+        let frames = [
+            vec![event(1, "a"), event(2, "b")],
+            vec![event(2, "b"), event(3, "c")],
+        ];
+
+        let mut decoded = 0;
+        for frame in frames {
+            decoded += frame.len() as u64;
+
+            let frame = options.serialize(&frame).unwrap();
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream
+                .write_all(&(handshake.len() as u16).to_be_bytes())
+                .unwrap();
+            stream.write_all(&handshake).unwrap();
+            stream
+                .write_all(&(frame.len() as u16).to_be_bytes())
+                .unwrap();
+            stream.write_all(&frame).unwrap();
+            drop(stream);
+
+            // the connection has to be disconnected before its id is reused
+            wait_until(|| {
+                let metrics = ingress.metrics();
+                metrics.messages_decoded == decoded && metrics.active_connections == 0
+            });
+        }
+
+        let events = wait_for_events(&engine, 3);
+
+        let names = events.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn malformed_messages_are_skipped() {
+        let engine = Engine::new(TransientStorage::new());
+        let (_ingress, addr) = start_ingress(&engine, None, IngressLimits::default(), None);
+
+        let options = DefaultOptions::new()
+            .with_varint_encoding()
+            .with_big_endian();
+
+        let fields = BTreeMap::<String, Value>::new();
+        let handshake = options.serialize(&fields).unwrap();
+
+        let malformed = vec![0xFFu8; 10];
+        let without_span = options
+            .serialize(&Message {
+                timestamp: NonZeroU64::new(1).unwrap(),
+                span_id: None,
+                data: MessageData::Enter,
+            })
+            .unwrap();
+        let message = options
+            .serialize(&Message {
+                timestamp: NonZeroU64::new(1).unwrap(),
+                span_id: None,
+                data: MessageData::Event(EventData {
+                    target: "tests".to_owned(),
+                    name: "event".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                }),
+            })
+            .unwrap();
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        for frame in [handshake, malformed, without_span, message] {
+            stream
+                .write_all(&(frame.len() as u16).to_be_bytes())
+                .unwrap();
+            stream.write_all(&frame).unwrap();
+        }
+
+        let events = wait_for_events(&engine, 1);
+
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn metrics_are_counted() {
+        let engine = Engine::new(TransientStorage::new());
+        let (ingress, addr) = start_ingress(&engine, None, IngressLimits::default(), None);
+
+        let options = DefaultOptions::new()
+            .with_varint_encoding()
+            .with_big_endian();
+
+        let fields = BTreeMap::<String, Value>::new();
+        let handshake = options.serialize(&fields).unwrap();
+
+        let malformed = vec![0xFFu8; 10];
+        let message = options
+            .serialize(&Message {
+                timestamp: NonZeroU64::new(1).unwrap(),
+                span_id: None,
+                data: MessageData::Event(EventData {
+                    target: "tests".to_owned(),
+                    name: "event".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                }),
+            })
+            .unwrap();
+
+        let frames = [handshake, malformed, message.clone(), message];
+        let total_bytes = frames.iter().map(|f| f.len() as u64 + 2).sum::<u64>();
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        for frame in frames {
+            stream
+                .write_all(&(frame.len() as u16).to_be_bytes())
+                .unwrap();
+            stream.write_all(&frame).unwrap();
+        }
+        wait_until(|| ingress.metrics().messages_decoded == 2);
+
+        let metrics = ingress.metrics();
+        assert_eq!(metrics.bytes_read, total_bytes);
+        assert_eq!(metrics.messages_decoded, 2);
+        assert_eq!(metrics.parse_errors, 1);
+        assert_eq!(metrics.active_connections, 1);
+
+        drop(stream);
+        wait_until(|| ingress.metrics().active_connections == 0);
+
+        let metrics = ingress.metrics();
+        assert_eq!(metrics.bytes_read, total_bytes);
+    }
+
+    #[test]
+    fn remote_addr_is_recorded() {
+        let engine = Engine::new(TransientStorage::new());
+        let (_ingress, addr) = start_ingress(&engine, None, IngressLimits::default(), None);
+
+        let _stream = connect_with_handshake(addr);
+        wait_for_connections(&engine, 1);
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let query = |filter: &str| {
+            runtime.block_on(engine.query_connection(Query {
+                filter: FilterPredicate::parse(filter).unwrap(),
+                ..query_all()
+            }))
+        };
+
+        let connections = query("@remote.addr:127.0.0.1");
+        assert_eq!(connections.len(), 1);
+        let attribute = connections[0]
+            .attributes
+            .iter()
+            .find(|a| a.name == REMOTE_ADDR_FIELD)
+            .unwrap();
+        assert_eq!(attribute.value, "127.0.0.1");
+
+        assert!(query("@remote.addr:10.0.0.5").is_empty());
+    }
+
+    #[test]
+    fn single_worker_thread_serves_many_clients() {
+        let engine = Engine::new(TransientStorage::new());
+        let (ingress, addr) = start_ingress(
+            &engine,
+            None,
+            IngressLimits::default(),
+            NonZeroUsize::new(1),
+        );
+
+        let _streams = (0..4)
+            .map(|_| connect_with_handshake(addr))
+            .collect::<Vec<_>>();
+
+        wait_until(|| ingress.metrics().active_connections == 4);
+    }
+
+    fn connect_with_handshake(addr: SocketAddr) -> TcpStream {
+        let fields = BTreeMap::<String, Value>::new();
+        let payload = DefaultOptions::new()
+            .with_varint_encoding()
+            .with_big_endian()
+            .serialize(&fields)
+            .unwrap();
+
+        // a rejected connection may already be closed by the time this writes
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let _ = stream.write_all(&(payload.len() as u16).to_be_bytes());
+        let _ = stream.write_all(&payload);
+        stream
+    }
+
+    #[test]
+    fn connections_over_the_limit_are_rejected() {
+        let engine = Engine::new(TransientStorage::new());
+        let (ingress, addr) = start_ingress(
+            &engine,
+            None,
+            IngressLimits {
+                max_connections: Some(1),
+                max_connections_per_ip_per_second: None,
+            },
+            None,
+        );
+        let permits = ingress.limiter.permits.clone().unwrap();
+
+        let first = connect_with_handshake(addr);
+        wait_until(|| ingress.metrics().active_connections == 1);
+        let _second = connect_with_handshake(addr);
+        wait_until(|| ingress.metrics().rejected_connections == 1);
+
+        assert_eq!(ingress.metrics().active_connections, 1);
+
+        // closing a connection makes room for another
+        drop(first);
+        wait_until(|| permits.available_permits() == 1);
+        let _third = connect_with_handshake(addr);
+        wait_until(|| ingress.metrics().active_connections == 1);
+
+        assert_eq!(ingress.metrics().rejected_connections, 1);
+    }
+
+    #[test]
+    fn connections_are_rate_limited_per_ip() {
+        let limits = IngressLimits {
+            max_connections: None,
+            max_connections_per_ip_per_second: Some(2),
+        };
+
+        let engine = Engine::new(TransientStorage::new());
+        let (ingress, addr) = start_ingress(&engine, None, limits, None);
+
+        let _streams = (0..3)
+            .map(|_| connect_with_handshake(addr))
+            .collect::<Vec<_>>();
+        wait_until(|| {
+            let metrics = ingress.metrics();
+            metrics.active_connections == 2 && metrics.rejected_connections == 1
+        });
+
+        // the count starts over once the window has passed, which is checked
+        // with a shorter one than the ingress uses
+        let limiter = ConnectionLimiter::with_window(limits, Duration::from_millis(50));
+        let ip = IpAddr::from([127, 0, 0, 1]);
+        assert!(limiter.admit(Some(ip)).is_ok());
+        assert!(limiter.admit(Some(ip)).is_ok());
+        assert!(limiter.admit(Some(ip)).is_err());
+        wait_until(|| limiter.admit(Some(ip)).is_ok());
+    }
+
+    #[test]
+    fn invalid_levels_are_clamped() {
+        let engine = Engine::new(TransientStorage::new());
+        let (_ingress, addr) = start_ingress(&engine, None, IngressLimits::default(), None);
+
+        let options = DefaultOptions::new()
+            .with_varint_encoding()
+            .with_big_endian();
+
+        let fields = BTreeMap::<String, Value>::new();
+        let handshake = options.serialize(&fields).unwrap();
+
+        let event = |timestamp: u64, level: i32| {
+            options
+                .serialize(&Message {
+                    timestamp: NonZeroU64::new(timestamp).unwrap(),
+                    span_id: None,
+                    data: MessageData::Event(EventData {
+                        target: "tests".to_owned(),
+                        name: "event".to_owned(),
+                        level,
+                        file_name: None,
+                        file_line: None,
+                        fields: BTreeMap::new(),
+                    }),
+                })
+                .unwrap()
+        };
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        for frame in [handshake, event(1, 99), event(2, -1)] {
+            stream
+                .write_all(&(frame.len() as u16).to_be_bytes())
+                .unwrap();
+            stream.write_all(&frame).unwrap();
+        }
+
+        let events = wait_for_events(&engine, 2);
+
+        let levels = events.iter().map(|e| e.level).collect::<Vec<_>>();
+        assert_eq!(levels, [4, 0]);
+    }
+
+    #[test]
+    fn oneshot_messages_are_inserted_without_a_handshake() {
+        let engine = Engine::new(TransientStorage::new());
+        let (ingress, addr) =
+            start_ingress(&engine, Some("secret"), IngressLimits::default(), None);
+
+        let options = DefaultOptions::new()
+            .with_varint_encoding()
+            .with_big_endian();
+
+        let oneshot = |script: &str, token: &str| {
+            options
+                .serialize(&OneShotMessage {
+                    fields: BTreeMap::from_iter([(
+                        "script".to_owned(),
+                        Value::Str(script.to_owned()),
+                    )]),
+                    token: Some(token.to_owned()),
+                    timestamp: NonZeroU64::new(1).unwrap(),
+                    event: EventData {
+                        target: "tests".to_owned(),
+                        name: "event".to_owned(),
+                        level: 2,
+                        file_name: None,
+                        file_line: None,
+                        fields: BTreeMap::new(),
+                    },
+                })
+                .unwrap()
+        };
+
+        // the stream ends at the message with the wrong token
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(&0u16.to_be_bytes()).unwrap();
+        for frame in [
+            oneshot("deploy", "secret"),
+            oneshot("backup", "secret"),
+            oneshot("cleanup", "secreT"),
+            oneshot("restore", "secret"),
+        ] {
+            stream
+                .write_all(&(frame.len() as u16).to_be_bytes())
+                .unwrap();
+            stream.write_all(&frame).unwrap();
+        }
+
+        // the connection has been accepted once there are events, so it has
+        // ended once none are active
+        wait_for_events(&engine, 2);
+        wait_until(|| ingress.metrics().active_connections == 0);
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let events = runtime.block_on(engine.query_event(query_all()));
+        let connections = runtime.block_on(engine.query_connection(query_all()));
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(connections.len(), 2);
+        assert!(connections.iter().all(|c| c.disconnected_at.is_some()));
+    }
+
+    #[test]
+    fn rebind_moves_listener() {
+        let engine = Engine::new(TransientStorage::new());
+        let (mut ingress, original) = start_ingress(&engine, None, IngressLimits::default(), None);
+        assert!(ingress.status().1.is_none());
+
+        stop(&mut ingress);
+        ingress
+            .rebind(IngressBind::Tcp("127.0.0.1:0".to_owned()))
+            .unwrap();
+        let moved = ingress.local_addr().unwrap();
+        assert!(ingress.status().1.is_none());
+        assert!(TcpStream::connect(moved).is_ok());
+
+        // the original address has been released, and holding it here keeps
+        // another test from taking it before it is used below
+        let blocker = StdTcpListener::bind(original).unwrap();
+
+        // binding failures are reported rather than panicking
+        stop(&mut ingress);
+        assert!(ingress
+            .rebind(IngressBind::Tcp(original.to_string()))
+            .is_err());
+        assert!(ingress.status().1.is_some());
+        drop(blocker);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket_is_accepted_and_cleaned_up() {
+        use std::os::unix::net::UnixStream;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ingress.sock");
+
+        let engine = Engine::new(TransientStorage::new());
+        let mut ingress = Ingress::start(
+            IngressBind::Unix(path.clone()),
+            engine.clone(),
+            None,
+            IngressLimits::default(),
+            None,
+        );
+
+        let fields = BTreeMap::<String, Value>::new();
+        let payload = DefaultOptions::new()
+            .with_varint_encoding()
+            .with_big_endian()
+            .serialize(&fields)
+            .unwrap();
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream
+            .write_all(&(payload.len() as u16).to_be_bytes())
+            .unwrap();
+        stream.write_all(&payload).unwrap();
+
+        let connections = wait_for_connections(&engine, 1);
+
+        assert_eq!(connections.len(), 1);
+
+        stop(&mut ingress);
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket_does_not_replace_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ingress.txt");
+        std::fs::write(&path, "not a socket").unwrap();
+
+        let engine = Engine::new(TransientStorage::new());
+        let mut ingress = Ingress::start(
+            IngressBind::Unix(path.clone()),
+            engine,
+            None,
+            IngressLimits::default(),
+            None,
+        );
+
+        assert!(ingress.status().1.is_some());
+        stop(&mut ingress);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn tls_connections_are_accepted() {
+        use tokio::io::AsyncWriteExt;
+        use tokio_rustls::rustls::pki_types::ServerName;
+        use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+        use tokio_rustls::TlsConnector;
+
+        let testdata = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/testdata");
+        let cert = testdata.join("localhost.crt");
+        let key = testdata.join("localhost.key");
+
+        let engine = Engine::new(TransientStorage::new());
+        let bind = IngressBind::Tls {
+            addr: "127.0.0.1:0".to_owned(),
+            cert: cert.clone(),
+            key,
+        };
+        let mut ingress =
+            Ingress::start(bind, engine.clone(), None, IngressLimits::default(), None);
+        assert!(ingress.status().1.is_none());
+        let addr = ingress.local_addr().unwrap();
+
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut StdBufReader::new(File::open(cert).unwrap())) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = TlsConnector::from(Arc::new(config));
+
+        let fields = BTreeMap::<String, Value>::new();
+        let payload = DefaultOptions::new()
+            .with_varint_encoding()
+            .with_big_endian()
+            .serialize(&fields)
+            .unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _stream = runtime.block_on(async {
+            let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let server_name = ServerName::try_from("localhost").unwrap();
+            let mut stream = connector.connect(server_name, stream).await.unwrap();
+
+            stream
+                .write_all(&(payload.len() as u16).to_be_bytes())
+                .await
+                .unwrap();
+            stream.write_all(&payload).await.unwrap();
+            stream.flush().await.unwrap();
+            stream
+        });
+
+        let connections = wait_for_connections(&engine, 1);
+
+        assert_eq!(connections.len(), 1);
+    }
+}
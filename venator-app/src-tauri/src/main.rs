@@ -1,25 +1,43 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::Mutex;
+use std::time::Duration;
 
 use clap::Parser;
-use ingress::Ingress;
+use ingress::{Ingress, IngressBind, IngressLimits, IngressMetrics};
 use serde::{Deserialize, Serialize};
 use tauri::ipc::Channel;
 use tauri::menu::{MenuBuilder, MenuItem, PredefinedMenuItem, Submenu};
-use tauri::{Emitter, Manager, State};
+use tauri::{Emitter, Manager, RunEvent, State};
 use tauri_plugin_dialog::DialogExt;
 use venator_engine::{
-    BasicConnectionFilter, BasicEventFilter, BasicSpanFilter, CachedStorage, ConnectionView,
-    DeleteFilter, DeleteMetrics, Engine, EventView, FallibleFilterPredicate, FileStorage,
-    FilterPredicate, FilterPredicateSingle, FilterPropertyKind, InputError, Order, Query, SpanView,
-    StatsView, SubscriptionId, Timestamp, TransientStorage, ValuePredicate,
+    parse_full_span_id, BasicConnectionFilter, BasicEventFilter, BasicSpanFilter, BucketView,
+    CachedStorage, CompactMetrics, ConnectionId, ConnectionView, DeleteConnectionError,
+    DeleteFilter, DeleteMetrics, Durability, DurationStatsView, Engine, EventView, EventsOrSpans,
+    FallibleFilterPredicate, FileStorage, FileStorageOptions, FilterPredicate,
+    FilterPredicateSingle, FilterPropertyKind, ImportMetrics, InputError, Order, Query,
+    QueryPlanView, SaveFilterError, SavedFilterView, SpanTimelineEntry, SpanView, StatsView,
+    SubscriptionId, Timestamp, TransientStorage, ValuePredicate,
 };
 
 mod ingress;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "otlp")]
+mod otlp;
+mod timeframe;
+
+/// The number of entities returned per page if the caller doesn't specify.
+const DEFAULT_QUERY_LIMIT: usize = 50;
+
+/// The largest page size a caller can request.
+const MAX_QUERY_LIMIT: usize = 1000;
 
 #[tauri::command]
 async fn get_connections(
@@ -29,12 +47,13 @@ async fn get_connections(
     previous: Option<Timestamp>,
     start: Option<Timestamp>,
     end: Option<Timestamp>,
+    limit: Option<usize>,
 ) -> Result<Vec<ConnectionView>, ()> {
     let events = engine
         .query_connection(Query {
             filter,
             order,
-            limit: 50,
+            limit: limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT),
             start: start.unwrap_or(Timestamp::MIN),
             end: end.unwrap_or(Timestamp::MAX),
             previous,
@@ -81,7 +100,8 @@ async fn parse_connection_filter(
         Err(err) => Ok(vec![InputView {
             result: FilterPredicateResultView::Invalid {
                 text: filter.to_owned(),
-                error: err.to_string(),
+                error: err.message.to_owned(),
+                span: Some((err.start, err.end)),
             },
         }]),
     }
@@ -95,12 +115,16 @@ async fn get_events(
     previous: Option<Timestamp>,
     start: Option<Timestamp>,
     end: Option<Timestamp>,
-) -> Result<Vec<EventView>, ()> {
+    timeframe: Option<&str>,
+    limit: Option<usize>,
+) -> Result<Vec<EventView>, String> {
+    let (start, end) = resolve_timeframe(timeframe, start, end)?;
+
     let events = engine
         .query_event(Query {
             filter,
             order,
-            limit: 50,
+            limit: limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT),
             start: start.unwrap_or(Timestamp::MIN),
             end: end.unwrap_or(Timestamp::MAX),
             previous,
@@ -110,6 +134,71 @@ async fn get_events(
     Ok(events)
 }
 
+#[tauri::command]
+async fn get_events_during_span(
+    engine: State<'_, Engine>,
+    span_id: &str,
+    filter: Vec<FilterPredicate>,
+    order: Order,
+    previous: Option<Timestamp>,
+    limit: Option<usize>,
+    same_connection: Option<bool>,
+) -> Result<Vec<EventView>, String> {
+    let (connection_id, span_id) =
+        parse_full_span_id(span_id).ok_or_else(|| format!("invalid span id {span_id}"))?;
+
+    let span_key = engine
+        .get_span_key(connection_id, span_id)
+        .await
+        .ok_or_else(|| format!("no span with id {connection_id}-{span_id}"))?;
+
+    let events = engine
+        .query_event_during_span(
+            span_key,
+            Query {
+                filter,
+                order,
+                limit: limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT),
+                start: Timestamp::MIN,
+                end: Timestamp::MAX,
+                previous,
+            },
+            same_connection.unwrap_or(false),
+        )
+        .await;
+
+    Ok(events)
+}
+
+#[tauri::command]
+async fn explain_event_query(
+    engine: State<'_, Engine>,
+    filter: Vec<FilterPredicate>,
+    start: Option<Timestamp>,
+    end: Option<Timestamp>,
+) -> Result<QueryPlanView, ()> {
+    let plan = engine
+        .explain_event_query(Query {
+            filter,
+            order: Order::Asc, // this doesn't matter
+            limit: 20,         // this doesn't matter
+            start: start.unwrap_or(Timestamp::MIN),
+            end: end.unwrap_or(Timestamp::MAX),
+            previous: None,
+        })
+        .await;
+
+    Ok(plan)
+}
+
+#[tauri::command]
+async fn get_event(engine: State<'_, Engine>, key: Timestamp) -> Result<EventView, String> {
+    engine
+        .get_event(key)
+        .await
+        .ok_or_else(|| format!("no event with key {key}"))
+}
+
 #[tauri::command]
 async fn get_event_count(
     engine: State<'_, Engine>,
@@ -131,6 +220,87 @@ async fn get_event_count(
     Ok(events)
 }
 
+#[tauri::command]
+async fn get_event_peak_buckets(
+    engine: State<'_, Engine>,
+    filter: Vec<FilterPredicate>,
+    start: Timestamp,
+    end: Timestamp,
+    bucket_size: u64,
+    count: usize,
+) -> Result<Vec<BucketView>, ()> {
+    let buckets = engine
+        .peak_buckets(
+            EventsOrSpans::Events,
+            Query {
+                filter,
+                order: Order::Asc, // this doesn't matter
+                limit: 20,         // this doesn't matter
+                start,
+                end,
+                previous: None,
+            },
+            bucket_size,
+            count,
+        )
+        .await;
+
+    Ok(buckets)
+}
+
+#[tauri::command]
+async fn get_span_peak_buckets(
+    engine: State<'_, Engine>,
+    filter: Vec<FilterPredicate>,
+    start: Timestamp,
+    end: Timestamp,
+    bucket_size: u64,
+    count: usize,
+) -> Result<Vec<BucketView>, ()> {
+    let buckets = engine
+        .peak_buckets(
+            EventsOrSpans::Spans,
+            Query {
+                filter,
+                order: Order::Asc, // this doesn't matter
+                limit: 20,         // this doesn't matter
+                start,
+                end,
+                previous: None,
+            },
+            bucket_size,
+            count,
+        )
+        .await;
+
+    Ok(buckets)
+}
+
+#[tauri::command]
+async fn get_event_histogram(
+    engine: State<'_, Engine>,
+    filter: Vec<FilterPredicate>,
+    start: Timestamp,
+    end: Timestamp,
+    bucket_size: u64,
+) -> Result<Vec<BucketView>, ()> {
+    let buckets = engine
+        .query_event_histogram(
+            Query {
+                filter,
+                order: Order::Asc, // this doesn't matter
+                limit: 20,         // this doesn't matter
+                start,
+                end,
+                previous: None,
+            },
+            bucket_size,
+        )
+        .await;
+
+    Ok(buckets)
+}
+
 #[tauri::command]
 async fn parse_event_filter(
     _engine: State<'_, Engine>,
@@ -147,12 +317,82 @@ async fn parse_event_filter(
         Err(err) => Ok(vec![InputView {
             result: FilterPredicateResultView::Invalid {
                 text: filter.to_owned(),
-                error: err.to_string(),
+                error: err.message.to_owned(),
+                span: Some((err.start, err.end)),
             },
         }]),
     }
 }
 
+#[tauri::command]
+async fn export_events(
+    engine: State<'_, Engine>,
+    path: PathBuf,
+    filter: &str,
+    start: Option<Timestamp>,
+    end: Option<Timestamp>,
+) -> Result<usize, String> {
+    let filter = parse_valid_filter(filter, BasicEventFilter::validate)?;
+
+    let file = File::create(&path).map_err(|err| err.to_string())?;
+
+    engine
+        .export_events(
+            Query {
+                filter,
+                order: Order::Asc,
+                limit: usize::MAX,
+                start: start.unwrap_or(Timestamp::MIN),
+                end: end.unwrap_or(Timestamp::MAX),
+                previous: None,
+            },
+            Box::new(BufWriter::new(file)),
+        )
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn export_events_csv(
+    engine: State<'_, Engine>,
+    path: PathBuf,
+    filter: &str,
+    columns: Vec<String>,
+    start: Option<Timestamp>,
+    end: Option<Timestamp>,
+) -> Result<usize, String> {
+    let filter = parse_valid_filter(filter, BasicEventFilter::validate)?;
+    let file = File::create(&path).map_err(|err| err.to_string())?;
+
+    engine
+        .export_events_csv(
+            Query {
+                filter,
+                order: Order::Asc,
+                limit: usize::MAX,
+                start: start.unwrap_or(Timestamp::MIN),
+                end: end.unwrap_or(Timestamp::MAX),
+                previous: None,
+            },
+            columns,
+            Box::new(BufWriter::new(file)),
+        )
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn import_ndjson(
+    engine: State<'_, Engine>,
+    path: PathBuf,
+) -> Result<ImportMetricsView, String> {
+    let file = File::open(&path).map_err(|err| err.to_string())?;
+
+    let metrics = engine.import_ndjson(Box::new(BufReader::new(file))).await;
+
+    Ok(metrics.into())
+}
+
 #[tauri::command]
 async fn get_spans(
     engine: State<'_, Engine>,
@@ -161,12 +401,16 @@ async fn get_spans(
     previous: Option<Timestamp>,
     start: Option<Timestamp>,
     end: Option<Timestamp>,
-) -> Result<Vec<SpanView>, ()> {
+    timeframe: Option<&str>,
+    limit: Option<usize>,
+) -> Result<Vec<SpanView>, String> {
+    let (start, end) = resolve_timeframe(timeframe, start, end)?;
+
     let spans = engine
         .query_span(Query {
             filter,
             order,
-            limit: 50,
+            limit: limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT),
             start: start.unwrap_or(Timestamp::MIN),
             end: end.unwrap_or(Timestamp::MAX),
             previous,
@@ -176,6 +420,40 @@ async fn get_spans(
     Ok(spans)
 }
 
+#[tauri::command]
+async fn get_span(engine: State<'_, Engine>, key: Timestamp) -> Result<SpanView, String> {
+    engine
+        .get_span(key)
+        .await
+        .ok_or_else(|| format!("no span with key {key}"))
+}
+
+#[tauri::command]
+async fn get_span_timeline(
+    engine: State<'_, Engine>,
+    key: Timestamp,
+) -> Result<Vec<SpanTimelineEntry>, ()> {
+    let timeline = engine.query_span_timeline(key).await;
+
+    Ok(timeline)
+}
+
+#[tauri::command]
+async fn get_span_children(
+    engine: State<'_, Engine>,
+    span_id: &str,
+) -> Result<Vec<SpanView>, String> {
+    let (connection_id, span_id) =
+        parse_full_span_id(span_id).ok_or_else(|| format!("invalid span id {span_id}"))?;
+
+    let parent_key = engine
+        .get_span_key(connection_id, span_id)
+        .await
+        .ok_or_else(|| format!("no span with id {connection_id}-{span_id}"))?;
+
+    Ok(engine.query_span_children(parent_key).await)
+}
+
 #[tauri::command]
 async fn get_span_count(
     engine: State<'_, Engine>,
@@ -197,6 +475,27 @@ async fn get_span_count(
     Ok(spans)
 }
 
+#[tauri::command]
+async fn get_span_duration_stats(
+    engine: State<'_, Engine>,
+    filter: Vec<FilterPredicate>,
+    start: Timestamp,
+    end: Timestamp,
+) -> Result<DurationStatsView, ()> {
+    let stats = engine
+        .query_span_duration_stats(Query {
+            filter,
+            order: Order::Asc, // this doesn't matter
+            limit: 20,         // this doesn't matter
+            start,
+            end,
+            previous: None,
+        })
+        .await;
+
+    Ok(stats)
+}
+
 #[tauri::command]
 async fn parse_span_filter(_engine: State<'_, Engine>, filter: &str) -> Result<Vec<InputView>, ()> {
     match FilterPredicate::parse(filter) {
@@ -210,12 +509,69 @@ async fn parse_span_filter(_engine: State<'_, Engine>, filter: &str) -> Result<V
         Err(err) => Ok(vec![InputView {
             result: FilterPredicateResultView::Invalid {
                 text: filter.to_owned(),
-                error: err.to_string(),
+                error: err.message.to_owned(),
+                span: Some((err.start, err.end)),
             },
         }]),
     }
 }
 
+#[tauri::command]
+async fn export_chrome_trace(
+    engine: State<'_, Engine>,
+    path: PathBuf,
+    filter: &str,
+    start: Option<Timestamp>,
+    end: Option<Timestamp>,
+) -> Result<usize, String> {
+    let filter = parse_valid_filter(filter, BasicSpanFilter::validate)?;
+
+    let file = File::create(&path).map_err(|err| err.to_string())?;
+
+    engine
+        .export_chrome_trace(
+            Query {
+                filter,
+                order: Order::Asc,
+                limit: usize::MAX,
+                start: start.unwrap_or(Timestamp::MIN),
+                end: end.unwrap_or(Timestamp::MAX),
+                previous: None,
+            },
+            Box::new(BufWriter::new(file)),
+        )
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn export_jaeger(
+    engine: State<'_, Engine>,
+    path: PathBuf,
+    filter: &str,
+    start: Option<Timestamp>,
+    end: Option<Timestamp>,
+) -> Result<usize, String> {
+    let filter = parse_valid_filter(filter, BasicSpanFilter::validate)?;
+
+    let file = File::create(&path).map_err(|err| err.to_string())?;
+
+    engine
+        .export_jaeger(
+            Query {
+                filter,
+                order: Order::Asc,
+                limit: usize::MAX,
+                start: start.unwrap_or(Timestamp::MIN),
+                end: end.unwrap_or(Timestamp::MAX),
+                previous: None,
+            },
+            Box::new(BufWriter::new(file)),
+        )
+        .await
+        .map_err(|err| err.to_string())
+}
+
 #[tauri::command]
 async fn delete_entities(
     engine: State<'_, Engine>,
@@ -236,11 +592,120 @@ async fn delete_entities(
     Ok(metrics.into())
 }
 
+#[tauri::command]
+async fn delete_connection(
+    engine: State<'_, Engine>,
+    connection_id: &str,
+) -> Result<DeleteMetricsView, String> {
+    let connection_id: ConnectionId = connection_id
+        .parse()
+        .map_err(|_| format!("invalid connection id {connection_id}"))?;
+
+    let metrics = engine
+        .delete_connection(connection_id)
+        .await
+        .map_err(|err| match err {
+            DeleteConnectionError::UnknownConnectionId => {
+                format!("no connection with id {connection_id}")
+            }
+            DeleteConnectionError::StillConnected => {
+                format!("connection {connection_id} is still connected")
+            }
+            DeleteConnectionError::ReadOnly => "the dataset is open read-only".to_owned(),
+        })?;
+
+    Ok(metrics.into())
+}
+
+#[tauri::command]
+async fn clear_storage(engine: State<'_, Engine>) -> Result<(), ()> {
+    engine.clear().await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn compact_storage(
+    engine: State<'_, Engine>,
+    channel: Channel<CompactProgressView>,
+) -> Result<Option<CompactMetricsView>, ()> {
+    // this rewrites the whole file which can take a while, so the frontend is
+    // told when it starts and ends rather than only getting the result
+    let _ = channel.send(CompactProgressView::Started);
+
+    let metrics = engine.compact().await.map(CompactMetricsView::from);
+
+    let _ = channel.send(CompactProgressView::Finished {
+        metrics: metrics.clone(),
+    });
+
+    Ok(metrics)
+}
+
+#[tauri::command]
+async fn flush(engine: State<'_, Engine>) -> Result<(), ()> {
+    engine.flush().await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn list_attribute_keys(
+    engine: State<'_, Engine>,
+    kind: EventsOrSpans,
+) -> Result<Vec<String>, ()> {
+    Ok(engine.list_attribute_keys(kind).await)
+}
+
+#[tauri::command]
+async fn list_attribute_values(
+    engine: State<'_, Engine>,
+    kind: EventsOrSpans,
+    attribute: String,
+    limit: usize,
+) -> Result<Vec<String>, ()> {
+    Ok(engine.list_attribute_values(kind, attribute, limit).await)
+}
+
 #[tauri::command]
 async fn get_stats(engine: State<'_, Engine>) -> Result<StatsView, ()> {
     Ok(engine.query_stats().await)
 }
 
+#[tauri::command]
+async fn parse_timeframe(
+    timeframe: &str,
+) -> Result<(Option<Timestamp>, Option<Timestamp>), String> {
+    timeframe::parse_timeframe(timeframe)
+}
+
+#[tauri::command]
+async fn save_filter(
+    engine: State<'_, Engine>,
+    name: String,
+    filter: String,
+) -> Result<SavedFilterView, String> {
+    engine
+        .save_filter(name, filter)
+        .await
+        .map_err(|err| match err {
+            SaveFilterError::Syntax(err) => err.to_string(),
+            SaveFilterError::Invalid(err, text) => format!("{text}: {err}"),
+            SaveFilterError::ReadOnly => "the dataset is open read-only".to_owned(),
+        })
+}
+
+#[tauri::command]
+async fn list_filters(engine: State<'_, Engine>) -> Result<Vec<SavedFilterView>, ()> {
+    Ok(engine.list_filters().await)
+}
+
+#[tauri::command]
+async fn delete_filter(
+    engine: State<'_, Engine>,
+    name: String,
+) -> Result<Option<SavedFilterView>, ()> {
+    Ok(engine.delete_filter(name).await)
+}
+
 #[tauri::command]
 async fn subscribe_to_events(
     engine: State<'_, Engine>,
@@ -268,6 +733,61 @@ async fn unsubscribe_from_events(
     Ok(())
 }
 
+#[tauri::command]
+async fn subscribe_to_spans(
+    engine: State<'_, Engine>,
+    filter: Vec<FilterPredicate>,
+    channel: Channel<SpanView>,
+) -> Result<SubscriptionId, String> {
+    let (id, mut receiver) = engine.subscribe_to_spans(filter).await;
+
+    tokio::spawn(async move {
+        while let Some(span) = receiver.recv().await {
+            let _ = channel.send(span);
+        }
+    });
+
+    Ok(id)
+}
+
+#[tauri::command]
+async fn unsubscribe_from_spans(
+    engine: State<'_, Engine>,
+    id: SubscriptionId,
+) -> Result<(), String> {
+    engine.unsubscribe_from_spans(id).await;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn subscribe_to_connections(
+    engine: State<'_, Engine>,
+    filter: Vec<FilterPredicate>,
+    channel: Channel<ConnectionView>,
+) -> Result<SubscriptionId, String> {
+    let (id, mut receiver) = engine.subscribe_to_connections(filter).await;
+
+    // this ends once the engine drops the sender on unsubscribe
+    tokio::spawn(async move {
+        while let Some(connection) = receiver.recv().await {
+            let _ = channel.send(connection);
+        }
+    });
+
+    Ok(id)
+}
+
+#[tauri::command]
+async fn unsubscribe_from_connections(
+    engine: State<'_, Engine>,
+    id: SubscriptionId,
+) -> Result<(), String> {
+    engine.unsubscribe_from_connections(id).await;
+
+    Ok(())
+}
+
 #[tauri::command]
 async fn get_status(
     engine: State<'_, Engine>,
@@ -283,6 +803,7 @@ async fn get_status(
     let dataset_name = match &*dataset {
         DatasetConfig::Default(_) => "default dataset".to_owned(),
         DatasetConfig::File(path) => format!("{}", path.display()),
+        DatasetConfig::ReadOnly(path) => format!("{} (read-only)", path.display()),
         DatasetConfig::Memory => ":memory:".to_owned(),
     };
 
@@ -295,19 +816,137 @@ async fn get_status(
         ingress_connections: connections,
         ingress_bytes_per_second: bytes_per_second,
         engine_load: engine_status.load,
+        engine_insert_queue_depth: engine_status.insert_queue_depth,
     })
 }
 
+#[tauri::command]
+async fn get_ingress_status(
+    ingress: State<'_, Mutex<Option<Ingress>>>,
+) -> Result<(String, Option<String>), ()> {
+    match &mut *ingress.lock().unwrap() {
+        Some(ingress) => Ok(ingress.status()),
+        None => Ok(("not listening".into(), None)),
+    }
+}
+
+#[tauri::command]
+async fn get_ingress_metrics(
+    ingress: State<'_, Mutex<Option<Ingress>>>,
+) -> Result<IngressMetrics, ()> {
+    match &*ingress.lock().unwrap() {
+        Some(ingress) => Ok(ingress.metrics()),
+        None => Ok(IngressMetrics::default()),
+    }
+}
+
+#[tauri::command]
+async fn set_ingress_bind(
+    engine: State<'_, Engine>,
+    dataset: State<'_, DatasetConfig>,
+    ingress: State<'_, Mutex<Option<Ingress>>>,
+    token: State<'_, IngressToken>,
+    limits: State<'_, IngressLimits>,
+    threads: State<'_, IngressThreads>,
+    bind: String,
+) -> Result<(), String> {
+    if let DatasetConfig::ReadOnly(_) = &*dataset {
+        return Err("the dataset is open read-only, so traces can't be accepted".to_owned());
+    }
+
+    let bind = IngressBind::parse(&bind);
+
+    // a listener configured with TLS keeps using it on the new address
+    #[cfg(feature = "tls")]
+    let bind = match (ingress.lock().unwrap().as_ref().map(Ingress::bind), bind) {
+        (Some(IngressBind::Tls { cert, key, .. }), IngressBind::Tcp(addr)) => IngressBind::Tls {
+            addr,
+            cert: cert.clone(),
+            key: key.clone(),
+        },
+        (_, bind) => bind,
+    };
+
+    // the lock is not held while waiting for the current listener to close
+    // so that status and metrics can still be read meanwhile
+    let stopped = ingress.lock().unwrap().as_mut().map(Ingress::stop);
+    if let Some(stopped) = stopped {
+        stopped.await;
+    }
+
+    let mut ingress = ingress.lock().unwrap();
+    match &mut *ingress {
+        Some(ingress) => ingress.rebind(bind),
+        None => {
+            let mut new_ingress = Ingress::start(
+                bind,
+                engine.inner().clone(),
+                token.0.clone(),
+                *limits,
+                threads.0,
+            );
+            let (_, error) = new_ingress.status();
+            *ingress = Some(new_ingress);
+            match error {
+                Some(err) => Err(err),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+// The same file can be reached by different paths, so they are compared after
+// being resolved. A path that doesn't exist can't be the same file.
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// This removes a dataset that isn't open along with its write-ahead log.
+fn remove_dataset_files(path: &Path) {
+    let _ = std::fs::remove_file(path);
+    remove_log_files(path);
+}
+
+fn remove_log_files(path: &Path) {
+    for suffix in ["-wal", "-shm"] {
+        let mut log_path = path.as_os_str().to_owned();
+        log_path.push(suffix);
+        let _ = std::fs::remove_file(log_path);
+    }
+}
+
+// The token provided on the command line, so that listeners started at runtime
+// require it as well.
+struct IngressToken(Option<String>);
+
+// The worker thread count provided on the command line, for the same reason.
+struct IngressThreads(Option<NonZeroUsize>);
+
 enum DatasetConfig {
     Default(PathBuf),
     File(PathBuf),
+    /// A file that another instance may be writing to, so it is only viewed
+    /// and no traces are accepted.
+    ReadOnly(PathBuf),
     Memory,
 }
 
 impl DatasetConfig {
+    fn path(&self) -> Option<&Path> {
+        match self {
+            DatasetConfig::Default(path)
+            | DatasetConfig::File(path)
+            | DatasetConfig::ReadOnly(path) => Some(path),
+            DatasetConfig::Memory => None,
+        }
+    }
+
     fn prepare(&self) {
         match self {
-            DatasetConfig::Memory => { /* nothing to do */ }
+            DatasetConfig::Memory | DatasetConfig::ReadOnly(_) => { /* nothing to do */ }
             DatasetConfig::Default(path) | DatasetConfig::File(path) => {
                 if let Some(dir) = path.parent() {
                     std::fs::create_dir_all(dir).unwrap();
@@ -324,13 +963,83 @@ struct Args {
     #[arg(short, long)]
     dataset: Option<String>,
 
-    /// The bind address to accept traces from
+    /// The bind address to accept traces from (use `unix:<path>` for a Unix
+    /// domain socket)
     #[arg(short, long)]
     bind: Option<String>,
+
+    /// The certificate (PEM) to accept TLS connections with
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// The private key (PEM) to accept TLS connections with
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Only accept clients that provide this token
+    #[arg(long)]
+    token: Option<String>,
+
+    /// The most clients that can be connected at once
+    #[arg(long)]
+    max_connections: Option<usize>,
+
+    /// The most connections accepted from one IP address per second
+    #[arg(long)]
+    max_connections_per_ip: Option<u32>,
+
+    /// The number of threads that read from clients (defaults to 2); more can
+    /// help with many clients sending at once
+    #[arg(long)]
+    ingress_threads: Option<NonZeroUsize>,
+
+    /// How much is synced to disk as traces are saved to a dataset file:
+    /// "off", "normal", or "full" (safer but slower)
+    #[arg(long, default_value = "off")]
+    durability: Durability,
+
+    /// Compress the attributes saved to a dataset file, which makes it smaller
+    /// but inserting and loading slower
+    #[arg(long)]
+    compress_fields: bool,
+
+    /// Open the dataset file without writing to it, to view a file that
+    /// another instance is collecting into (no traces are accepted)
+    #[arg(long)]
+    read_only: bool,
+
+    /// How long, in milliseconds, to hold span events and events that
+    /// reference a span not created yet, in case its create arrives late
+    /// (inserts waiting on it are not answered until then)
+    #[arg(long, default_value_t = 0)]
+    reorder_window: u64,
+
+    /// The bind address to accept OpenTelemetry logs and traces from via
+    /// OTLP/HTTP with JSON encoding (the usual port is 4318)
+    #[cfg(feature = "otlp")]
+    #[arg(long)]
+    otlp_bind: Option<String>,
+
+    /// The bind address to serve Prometheus metrics from at `/metrics`
+    #[cfg(feature = "metrics")]
+    #[arg(long)]
+    metrics_bind: Option<String>,
 }
 
 impl Args {
     fn dataset(&self) -> DatasetConfig {
+        let dataset = self.dataset_to_write();
+        match dataset {
+            DatasetConfig::Default(path) | DatasetConfig::File(path) if self.read_only => {
+                DatasetConfig::ReadOnly(path)
+            }
+            dataset => dataset,
+        }
+    }
+
+    fn dataset_to_write(&self) -> DatasetConfig {
         if let Some(dataset) = &self.dataset {
             if dataset == ":memory:" {
                 return DatasetConfig::Memory;
@@ -350,19 +1059,29 @@ impl Args {
         }
     }
 
-    fn bind(&self) -> Option<&str> {
+    fn bind(&self) -> Option<IngressBind> {
         // if there is a bind address, use it - otherwise only use the default
         // if also using the default dataset
-
-        if let Some(bind) = &self.bind {
-            return Some(bind);
+        if self.read_only {
+            return None;
         }
 
-        if self.dataset.is_some() {
-            None
-        } else {
-            Some("0.0.0.0:8362")
+        let bind = match &self.bind {
+            Some(bind) => bind.as_str(),
+            None if self.dataset.is_some() => return None,
+            None => "0.0.0.0:8362",
+        };
+
+        #[cfg(feature = "tls")]
+        if let (Some(cert), Some(key)) = (&self.tls_cert, &self.tls_key) {
+            return Some(IngressBind::Tls {
+                addr: bind.to_owned(),
+                cert: cert.clone(),
+                key: key.clone(),
+            });
         }
+
+        Some(IngressBind::parse(bind))
     }
 }
 
@@ -372,21 +1091,91 @@ fn main() {
     let bind = args.bind();
 
     dataset.prepare();
+    let options = FileStorageOptions {
+        durability: args.durability,
+        compress_fields: args.compress_fields,
+    };
+    let reorder_window = Duration::from_millis(args.reorder_window);
     let engine = match &dataset {
-        DatasetConfig::Default(path) => {
-            Engine::new(CachedStorage::new(10000, FileStorage::new(path)))
-        }
-        DatasetConfig::File(path) => Engine::new(CachedStorage::new(10000, FileStorage::new(path))),
-        DatasetConfig::Memory => Engine::new(TransientStorage::new()),
+        DatasetConfig::Default(path) | DatasetConfig::File(path) => Engine::builder()
+            .storage(CachedStorage::new(
+                10000,
+                FileStorage::new_with_options(path, options),
+            ))
+            .reorder_window(reorder_window)
+            .build(),
+        DatasetConfig::ReadOnly(path) => match FileStorage::open_read_only(path) {
+            Ok(storage) => Engine::new(CachedStorage::new(10000, storage)),
+            Err(err) => {
+                eprintln!("failed to open {} read-only: {err}", path.display());
+                std::process::exit(1);
+            }
+        },
+        DatasetConfig::Memory => Engine::builder()
+            .storage(TransientStorage::new())
+            .reorder_window(reorder_window)
+            .build(),
     };
 
-    let ingress = bind.map(|bind| Ingress::start(bind.to_owned(), engine.clone()));
+    let token = IngressToken(args.token.clone());
+    let limits = IngressLimits {
+        max_connections: args.max_connections,
+        max_connections_per_ip_per_second: args.max_connections_per_ip,
+    };
+    let threads = IngressThreads(args.ingress_threads);
+    let ingress =
+        bind.map(|bind| Ingress::start(bind, engine.clone(), token.0.clone(), limits, threads.0));
+
+    #[cfg(feature = "otlp")]
+    let otlp = match args.otlp_bind.as_ref().filter(|_| !args.read_only) {
+        Some(otlp_bind) => match otlp::start(otlp_bind, engine.clone()) {
+            Ok(otlp) => Some(otlp),
+            Err(err) => {
+                eprintln!("failed to listen for OTLP on {otlp_bind}: {err}");
+                None
+            }
+        },
+        None => None,
+    };
+
+    #[cfg(feature = "metrics")]
+    let metrics_bind = args.metrics_bind.clone();
 
     tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_clipboard_manager::init())
-        .setup(|app| {
+        .setup(move |app| {
+            // this is kept so that it can be stopped before the engine on exit
+            #[cfg(feature = "otlp")]
+            app.manage(Mutex::new(otlp));
+
+            // this is started here so that it can read the ingress, which may
+            // be started or rebound later
+            #[cfg(feature = "metrics")]
+            let metrics = metrics_bind.as_ref().and_then(|metrics_bind| {
+                let engine = app.state::<Engine>().inner().clone();
+                let handle = app.handle().clone();
+                // the lock is only ever held briefly since stopping the ingress
+                // is awaited without it
+                let ingress_metrics = move || {
+                    let ingress = handle.state::<Mutex<Option<Ingress>>>();
+                    let ingress = ingress.lock().unwrap();
+                    ingress.as_ref().map(Ingress::metrics)
+                };
+
+                match metrics::start(metrics_bind, engine, ingress_metrics) {
+                    Ok(metrics) => Some(metrics),
+                    Err(err) => {
+                        eprintln!("failed to serve metrics on {metrics_bind}: {err}");
+                        None
+                    }
+                }
+            });
+
+            #[cfg(feature = "metrics")]
+            app.manage(Mutex::new(metrics));
+
             let handle = app.handle();
             let menu = MenuBuilder::new(handle)
                 .item(&Submenu::with_items(
@@ -546,6 +1335,14 @@ fn main() {
                             true,
                             None::<&str>,
                         )?,
+                        &MenuItem::with_id(
+                            handle,
+                            "clear-all",
+                            "Clear dataset",
+                            true,
+                            None::<&str>,
+                        )?,
+                        &MenuItem::with_id(handle, "flush", "Flush to disk", true, None::<&str>)?,
                     ],
                 )?)
                 .item(&Submenu::with_items(
@@ -600,16 +1397,53 @@ fn main() {
                 }
                 "save-dataset-as" => {
                     let engine = app.state::<Engine>().inner().clone();
+                    let open_path = app.state::<DatasetConfig>().path().map(Path::to_owned);
 
                     app.dialog().file().save_file(move |file_path| {
                         let Some(path) = file_path else { return };
 
-                        let new_storage = FileStorage::new(path.as_path().unwrap());
+                        let path = path.as_path().unwrap().to_owned();
+
+                        // the dialog has already confirmed overwriting, but
+                        // replacing the open dataset would lose what is copied
+                        if open_path
+                            .as_deref()
+                            .is_some_and(|open| is_same_file(open, &path))
+                        {
+                            eprintln!(
+                                "failed to save dataset: {} is the open dataset",
+                                path.display()
+                            );
+                            return;
+                        }
+
+                        // the copy is written next to the target and moved over
+                        // it once complete, so the target is never left partial
+                        let mut temp_name = path.file_name().unwrap_or_default().to_owned();
+                        temp_name.push(".saving");
+                        let temp_path = path.with_file_name(temp_name);
+
+                        // copying into a leftover copy would merge them
+                        remove_dataset_files(&temp_path);
+                        let new_storage = match FileStorage::create(&temp_path) {
+                            Ok(storage) => storage,
+                            Err(err) => {
+                                eprintln!("failed to save dataset: {err}");
+                                return;
+                            }
+                        };
 
-                        // we have no need for the result, and the command is
-                        // executed regardless if we poll
-                        #[allow(clippy::let_underscore_future)]
-                        let _ = engine.copy_dataset(Box::new(new_storage));
+                        tauri::async_runtime::spawn(async move {
+                            // the storage is closed once the copy is done
+                            engine.copy_dataset(Box::new(new_storage)).await;
+
+                            // a stale write-ahead log next to the target would
+                            // be replayed into the copy once it is moved there
+                            remove_log_files(&path);
+                            if let Err(err) = std::fs::rename(&temp_path, &path) {
+                                eprintln!("failed to save dataset: {err}");
+                            }
+                        });
                     });
                 }
                 "save-as-csv" => {
@@ -666,6 +1500,15 @@ fn main() {
                 "delete-outside" => {
                     app.emit("delete-outside-clicked", ()).unwrap();
                 }
+                "clear-all" => {
+                    app.emit("clear-all-clicked", ()).unwrap();
+                }
+                "flush" => {
+                    let engine = app.state::<Engine>().inner().clone();
+                    tauri::async_runtime::spawn(async move {
+                        engine.flush().await;
+                    });
+                }
                 "help-about" => {
                     let _ = open::that("https://github.com/kmdreko/venator");
                 }
@@ -682,24 +1525,98 @@ fn main() {
         .manage(engine)
         .manage(dataset)
         .manage(Mutex::new(ingress))
+        .manage(token)
+        .manage(limits)
+        .manage(threads)
         .invoke_handler(tauri::generate_handler![
             get_connections,
             get_connection_count,
             parse_connection_filter,
             get_events,
+            get_event,
             get_event_count,
+            get_event_peak_buckets,
+            get_event_histogram,
+            get_events_during_span,
+            explain_event_query,
             parse_event_filter,
+            export_events,
+            export_events_csv,
+            import_ndjson,
             get_spans,
+            get_span,
+            get_span_timeline,
+            get_span_children,
             get_span_count,
+            get_span_duration_stats,
+            get_span_peak_buckets,
             parse_span_filter,
+            export_chrome_trace,
+            export_jaeger,
             delete_entities,
+            delete_connection,
+            clear_storage,
+            compact_storage,
+            flush,
+            list_attribute_keys,
+            list_attribute_values,
             get_stats,
+            parse_timeframe,
+            save_filter,
+            list_filters,
+            delete_filter,
             subscribe_to_events,
             unsubscribe_from_events,
+            subscribe_to_spans,
+            unsubscribe_from_spans,
+            subscribe_to_connections,
+            unsubscribe_from_connections,
             get_status,
+            get_ingress_status,
+            get_ingress_metrics,
+            set_ingress_bind,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                // stopping the ingress cleans up its socket file if it has one
+                let ingress = app.state::<Mutex<Option<Ingress>>>().lock().unwrap().take();
+                let stopped = ingress.map(|mut ingress| ingress.stop());
+
+                // these would otherwise keep using the engine after shutdown
+                #[cfg(feature = "otlp")]
+                let otlp = app
+                    .state::<Mutex<Option<otlp::OtlpServer>>>()
+                    .lock()
+                    .unwrap()
+                    .take();
+                #[cfg(feature = "metrics")]
+                let metrics = app
+                    .state::<Mutex<Option<metrics::MetricsServer>>>()
+                    .lock()
+                    .unwrap()
+                    .take();
+
+                // inserts from connections are not awaited, so this makes sure
+                // everything received is written before exiting
+                let engine = app.state::<Engine>().inner().clone();
+                tauri::async_runtime::block_on(async move {
+                    if let Some(stopped) = stopped {
+                        stopped.await;
+                    }
+                    #[cfg(feature = "otlp")]
+                    if let Some(otlp) = otlp {
+                        otlp.stop().await;
+                    }
+                    #[cfg(feature = "metrics")]
+                    if let Some(metrics) = metrics {
+                        metrics.stop().await;
+                    }
+                    engine.shutdown().await;
+                });
+            }
+        });
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -712,7 +1629,13 @@ struct InputView {
 #[serde(tag = "input", rename_all = "camelCase")]
 enum FilterPredicateResultView {
     Valid(FilterPredicateView),
-    Invalid { text: String, error: String },
+    Invalid {
+        text: String,
+        error: String,
+        /// The byte range within `text` that caused a syntax error.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        span: Option<(usize, usize)>,
+    },
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -749,12 +1672,56 @@ impl From<Result<FallibleFilterPredicate, (InputError, String)>> for InputView {
                 result: FilterPredicateResultView::Invalid {
                     text,
                     error: err.to_string(),
+                    span: None,
                 },
             },
         }
     }
 }
 
+/// This parses the filter for use outside the UI, failing if any predicate
+/// would be shown as invalid by `parse_*_filter`.
+fn parse_valid_filter(
+    filter: &str,
+    validate: fn(FilterPredicate) -> Result<FallibleFilterPredicate, InputError>,
+) -> Result<Vec<FilterPredicate>, String> {
+    let filter = FilterPredicate::parse(filter).map_err(|err| err.to_string())?;
+    for predicate in &filter {
+        let text = predicate.to_string();
+        let result = validate(predicate.clone()).map_err(|e| (e, text));
+        if let Some(error) = first_filter_error(result) {
+            return Err(error);
+        }
+    }
+
+    Ok(filter)
+}
+
+/// A timeframe, if given, is used instead of the start and end.
+fn resolve_timeframe(
+    timeframe: Option<&str>,
+    start: Option<Timestamp>,
+    end: Option<Timestamp>,
+) -> Result<(Option<Timestamp>, Option<Timestamp>), String> {
+    match timeframe {
+        Some(timeframe) => timeframe::parse_timeframe(timeframe),
+        None => Ok((start, end)),
+    }
+}
+
+/// This returns the first invalid predicate in the result.
+fn first_filter_error(
+    result: Result<FallibleFilterPredicate, (InputError, String)>,
+) -> Option<String> {
+    match result {
+        Ok(FallibleFilterPredicate::Single(_)) => None,
+        Ok(FallibleFilterPredicate::And(predicates) | FallibleFilterPredicate::Or(predicates)) => {
+            predicates.into_iter().find_map(first_filter_error)
+        }
+        Err((err, text)) => Some(format!("{text}: {err}")),
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 struct FilterPredicateSingleView {
     text: String,
@@ -783,6 +1750,7 @@ struct StatusView {
     ingress_bytes_per_second: f64,
     dataset_name: String,
     engine_load: f64,
+    engine_insert_queue_depth: usize,
 }
 
 #[derive(Serialize)]
@@ -793,6 +1761,47 @@ pub struct DeleteMetricsView {
     events: usize,
 }
 
+#[derive(Clone, Serialize)]
+pub struct CompactMetricsView {
+    size_before: u64,
+    size_after: u64,
+}
+
+impl From<CompactMetrics> for CompactMetricsView {
+    fn from(metrics: CompactMetrics) -> Self {
+        CompactMetricsView {
+            size_before: metrics.size_before,
+            size_after: metrics.size_after,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum CompactProgressView {
+    Started,
+    Finished { metrics: Option<CompactMetricsView> },
+}
+
+#[derive(Serialize)]
+pub struct ImportMetricsView {
+    connections: usize,
+    spans: usize,
+    events: usize,
+    skipped_lines: usize,
+}
+
+impl From<ImportMetrics> for ImportMetricsView {
+    fn from(metrics: ImportMetrics) -> Self {
+        ImportMetricsView {
+            connections: metrics.connections,
+            spans: metrics.spans,
+            events: metrics.events,
+            skipped_lines: metrics.skipped_lines,
+        }
+    }
+}
+
 impl From<DeleteMetrics> for DeleteMetricsView {
     fn from(metrics: DeleteMetrics) -> Self {
         DeleteMetricsView {
@@ -0,0 +1,222 @@
+use std::fmt::Write;
+use std::io::Error as IoError;
+use std::net::TcpListener as StdTcpListener;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot::{self, Receiver as OneshotReceiver, Sender as OneshotSender};
+
+use venator_engine::{Engine, QueryLatencyView, StatsView};
+
+use crate::ingress::IngressMetrics;
+
+// This serves the health of the ingress and engine on `/metrics` in the
+// Prometheus text format so a long-running collector can be scraped. The
+// ingress metrics are read when scraped since the ingress can be started or
+// rebound while running; they are left out if it isn't listening.
+pub fn start<F>(bind: &str, engine: Engine, ingress_metrics: F) -> Result<MetricsServer, IoError>
+where
+    F: Fn() -> Option<IngressMetrics> + Send + Sync + 'static,
+{
+    // the listener is bound here so that failures are reported immediately
+    let listener = StdTcpListener::bind(bind)?;
+    listener.set_nonblocking(true)?;
+
+    let state = Arc::new(MetricsState {
+        engine,
+        ingress_metrics: Box::new(ingress_metrics),
+    });
+
+    let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+    let (stopped_sender, stopped_receiver) = oneshot::channel();
+
+    std::thread::spawn(move || metrics_task(listener, state, shutdown_receiver, stopped_sender));
+
+    Ok(MetricsServer {
+        shutdown: shutdown_sender,
+        stopped: stopped_receiver,
+    })
+}
+
+pub struct MetricsServer {
+    shutdown: OneshotSender<()>,
+    stopped: OneshotReceiver<()>,
+}
+
+impl MetricsServer {
+    /// This stops accepting requests. The returned future completes once the
+    /// requests being handled are done, so the engine can be shut down after.
+    pub async fn stop(self) {
+        drop(self.shutdown);
+        let _ = self.stopped.await;
+    }
+}
+
+#[tokio::main(worker_threads = 1)]
+async fn metrics_task(
+    listener: StdTcpListener,
+    state: Arc<MetricsState>,
+    shutdown: OneshotReceiver<()>,
+    // this is dropped when the task ends
+    _stopped: OneshotSender<()>,
+) {
+    let listener = match TcpListener::from_std(listener) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("failed to start metrics listener: {err:?}");
+            return;
+        }
+    };
+
+    let app = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .with_state(state);
+
+    let shutdown = async {
+        let _ = shutdown.await;
+    };
+
+    if let Err(err) = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown)
+        .await
+    {
+        eprintln!("metrics listener failed: {err:?}");
+    }
+}
+
+struct MetricsState {
+    engine: Engine,
+    ingress_metrics: Box<dyn Fn() -> Option<IngressMetrics> + Send + Sync>,
+}
+
+async fn metrics_handler(State(state): State<Arc<MetricsState>>) -> impl IntoResponse {
+    let ingress = (state.ingress_metrics)();
+    let insert_queue_depth = state.engine.insert_queue_depth();
+    let stats = state.engine.query_stats().await;
+    let query_latency = state.engine.query_latency().await;
+
+    let body = render(ingress.as_ref(), insert_queue_depth, &stats, &query_latency);
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+fn render(
+    ingress: Option<&IngressMetrics>,
+    insert_queue_depth: usize,
+    stats: &StatsView,
+    query_latency: &QueryLatencyView,
+) -> String {
+    let mut out = String::new();
+
+    let mut metric = |name: &str, kind: &str, help: &str, value: &dyn std::fmt::Display| {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} {kind}");
+        let _ = writeln!(out, "{name} {value}");
+    };
+
+    if let Some(ingress) = ingress {
+        metric(
+            "venator_ingress_bytes_read_total",
+            "counter",
+            "Bytes read from clients.",
+            &ingress.bytes_read,
+        );
+        metric(
+            "venator_ingress_messages_decoded_total",
+            "counter",
+            "Messages decoded from clients.",
+            &ingress.messages_decoded,
+        );
+        metric(
+            "venator_ingress_parse_errors_total",
+            "counter",
+            "Handshakes and message frames that could not be decoded.",
+            &ingress.parse_errors,
+        );
+        metric(
+            "venator_ingress_active_connections",
+            "gauge",
+            "Clients currently connected.",
+            &ingress.active_connections,
+        );
+        metric(
+            "venator_ingress_rejected_connections_total",
+            "counter",
+            "Connections closed for exceeding the ingress limits.",
+            &ingress.rejected_connections,
+        );
+    }
+
+    metric(
+        "venator_engine_insert_queue_depth",
+        "gauge",
+        "Inserts waiting for the engine.",
+        &insert_queue_depth,
+    );
+    metric(
+        "venator_stored_events",
+        "gauge",
+        "Events in the dataset.",
+        &stats.total_events,
+    );
+    metric(
+        "venator_stored_spans",
+        "gauge",
+        "Spans in the dataset.",
+        &stats.total_spans,
+    );
+
+    let name = "venator_query_duration_seconds";
+    let _ = writeln!(
+        out,
+        "# HELP {name} How long the engine took to execute queries."
+    );
+    let _ = writeln!(out, "# TYPE {name} histogram");
+    for (bound, count) in &query_latency.buckets {
+        let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {count}");
+    }
+    let count = query_latency.count;
+    let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+    let _ = writeln!(out, "{name}_sum {}", query_latency.sum);
+    let _ = writeln!(out, "{name}_count {count}");
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_ends_with_inf_bucket() {
+        let stats = StatsView {
+            start: None,
+            end: None,
+            total_spans: 2,
+            total_events: 5,
+            events_per_level: [0; 5],
+            spans_per_level: [0; 5],
+            connected_connections: 0,
+            disconnected_connections: 0,
+        };
+        let query_latency = QueryLatencyView {
+            buckets: vec![(0.001, 1), (0.01, 3)],
+            sum: 0.02,
+            count: 4,
+        };
+
+        let out = render(None, 7, &stats, &query_latency);
+
+        assert!(!out.contains("venator_ingress"));
+        assert!(out.contains("venator_engine_insert_queue_depth 7\n"));
+        assert!(out.contains("venator_stored_events 5\n"));
+        assert!(out.contains("venator_query_duration_seconds_bucket{le=\"0.01\"} 3\n"));
+        assert!(out.contains("venator_query_duration_seconds_bucket{le=\"+Inf\"} 4\n"));
+        assert!(out.contains("venator_query_duration_seconds_count 4\n"));
+    }
+}
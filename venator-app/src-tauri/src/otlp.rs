@@ -0,0 +1,768 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::io::Error as IoError;
+use std::net::TcpListener as StdTcpListener;
+use std::num::NonZeroU64;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::de::{Deserializer, Error as DeError};
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot::{self, Receiver as OneshotReceiver, Sender as OneshotSender};
+use tokio::sync::Mutex;
+
+use venator_engine::{
+    ConnectionId, ConnectionKey, Engine, EngineInsertError, NewConnection, NewCreateSpanEvent,
+    NewEvent, NewSpanEvent, NewSpanEventKind, SpanId, Timestamp, Value,
+};
+
+// This accepts OpenTelemetry logs and traces in the OTLP/HTTP JSON format on
+// `/v1/logs` and `/v1/traces` so that standard SDKs can export to Venator.
+//
+// Each distinct resource is recorded as a connection with the resource
+// attributes as its fields. OTLP only exports spans once they have ended, so
+// a span is created and closed at once and can only be linked to its parent
+// if the parent was received before it (spans within a request are inserted
+// by start time to help with that). Otherwise the parent is kept in the
+// `parent_span_id` attribute.
+pub fn start(bind: &str, engine: Engine) -> Result<OtlpServer, IoError> {
+    // the listener is bound here so that failures are reported immediately
+    let listener = StdTcpListener::bind(bind)?;
+    listener.set_nonblocking(true)?;
+
+    let state = Arc::new(OtlpState {
+        engine,
+        connections: Mutex::new(HashMap::new()),
+    });
+
+    let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+    let (stopped_sender, stopped_receiver) = oneshot::channel();
+
+    std::thread::spawn(move || otlp_task(listener, state, shutdown_receiver, stopped_sender));
+
+    Ok(OtlpServer {
+        shutdown: shutdown_sender,
+        stopped: stopped_receiver,
+    })
+}
+
+pub struct OtlpServer {
+    shutdown: OneshotSender<()>,
+    stopped: OneshotReceiver<()>,
+}
+
+impl OtlpServer {
+    /// This stops accepting requests. The returned future completes once the
+    /// requests being handled are done, so the engine can be shut down after.
+    pub async fn stop(self) {
+        drop(self.shutdown);
+        let _ = self.stopped.await;
+    }
+}
+
+#[tokio::main(worker_threads = 2)]
+async fn otlp_task(
+    listener: StdTcpListener,
+    state: Arc<OtlpState>,
+    shutdown: OneshotReceiver<()>,
+    // this is dropped when the task ends
+    _stopped: OneshotSender<()>,
+) {
+    let listener = match TcpListener::from_std(listener) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("failed to start OTLP listener: {err:?}");
+            return;
+        }
+    };
+
+    let app = Router::new()
+        .route("/v1/traces", post(traces_handler))
+        .route("/v1/logs", post(logs_handler))
+        .with_state(state);
+
+    let shutdown = async {
+        let _ = shutdown.await;
+    };
+
+    if let Err(err) = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown)
+        .await
+    {
+        eprintln!("OTLP listener failed: {err:?}");
+    }
+}
+
+// Resources are identified by their attributes, so repeated exports from the
+// same service are recorded on the same connection.
+struct ResourceConnection {
+    id: ConnectionId,
+    fields: BTreeMap<String, Value>,
+}
+
+impl ResourceConnection {
+    fn new(resource: Resource) -> ResourceConnection {
+        let fields = conv_attributes(resource.attributes);
+
+        let mut hasher = DefaultHasher::new();
+        for (name, value) in &fields {
+            name.hash(&mut hasher);
+            value.to_string().hash(&mut hasher);
+        }
+        let id = hasher.finish();
+
+        ResourceConnection { id, fields }
+    }
+}
+
+struct OtlpState {
+    engine: Engine,
+    connections: Mutex<HashMap<ConnectionId, ConnectionKey>>,
+}
+
+impl OtlpState {
+    async fn connection(
+        &self,
+        resource: &ResourceConnection,
+    ) -> Result<ConnectionKey, EngineInsertError> {
+        let id = resource.id;
+        let mut connections = self.connections.lock().await;
+        if let Some(key) = connections.get(&id) {
+            return Ok(*key);
+        }
+
+        let key = self
+            .engine
+            .insert_connection(NewConnection::new(id, resource.fields.clone()))
+            .await?;
+
+        connections.insert(id, key);
+
+        Ok(key)
+    }
+
+    // This does the insert on the resource's connection. If the engine no
+    // longer has the cached connection, like after the dataset was cleared,
+    // it is inserted again and the insert is retried.
+    async fn insert_with_connection<T, F>(
+        &self,
+        resource: &ResourceConnection,
+        item: T,
+        insert: impl Fn(ConnectionKey, T) -> F,
+    ) -> Result<(), EngineInsertError>
+    where
+        T: Clone,
+        F: Future<Output = Result<(), EngineInsertError>>,
+    {
+        let connection_key = self.connection(resource).await?;
+        match insert(connection_key, item.clone()).await {
+            Err(EngineInsertError::UnknownConnection) => {
+                self.connections
+                    .lock()
+                    .await
+                    .retain(|_, cached| *cached != connection_key);
+
+                let connection_key = self.connection(resource).await?;
+                insert(connection_key, item).await
+            }
+            result => result,
+        }
+    }
+}
+
+async fn traces_handler(
+    State(state): State<Arc<OtlpState>>,
+    Json(request): Json<TracesRequest>,
+) -> Json<JsonValue> {
+    insert_traces(&state, request).await;
+    Json(JsonValue::Object(Default::default()))
+}
+
+async fn logs_handler(
+    State(state): State<Arc<OtlpState>>,
+    Json(request): Json<LogsRequest>,
+) -> Json<JsonValue> {
+    insert_logs(&state, request).await;
+    Json(JsonValue::Object(Default::default()))
+}
+
+async fn insert_traces(state: &OtlpState, request: TracesRequest) {
+    for resource_spans in request.resource_spans {
+        let resource = ResourceConnection::new(resource_spans.resource);
+
+        let mut spans = resource_spans
+            .scope_spans
+            .into_iter()
+            .flat_map(|scope_spans| {
+                let target = scope_spans.scope.name;
+                scope_spans
+                    .spans
+                    .into_iter()
+                    .map(move |span| (target.clone(), span))
+            })
+            .collect::<Vec<_>>();
+
+        spans.sort_by_key(|(_, span)| span.start_time_unix_nano);
+
+        for item in spans {
+            let result = state
+                .insert_with_connection(&resource, item, |connection_key, (target, span)| {
+                    insert_span(&state.engine, connection_key, target, span)
+                })
+                .await;
+            if let Err(err) = result {
+                eprintln!("failed to insert OTLP span: {err:?}");
+            }
+        }
+    }
+}
+
+async fn insert_span(
+    engine: &Engine,
+    connection_key: ConnectionKey,
+    target: String,
+    span: OtlpSpan,
+) -> Result<(), EngineInsertError> {
+    let Some(span_id) = parse_id(&span.span_id) else {
+        return Err(EngineInsertError::UnknownSpanId);
+    };
+
+    let mut fields = conv_attributes(span.attributes);
+    let (file_name, file_line) = take_code_location(&mut fields);
+    if !span.trace_id.is_empty() {
+        fields.insert("trace_id".to_owned(), Value::Str(span.trace_id.clone()));
+    }
+
+    let create = |parent_id: Option<SpanId>, fields: BTreeMap<String, Value>| NewSpanEvent {
+        connection_key,
+        seq: None,
+        timestamp: conv_timestamp(span.start_time_unix_nano),
+        span_id,
+        kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+            parent_id,
+            target: target.clone(),
+            name: span.name.clone(),
+            level: 2,
+            file_name: file_name.clone(),
+            file_line,
+            fields,
+        }),
+    };
+
+    let parent_id = parse_id(&span.parent_span_id);
+    match engine
+        .insert_span_event(create(parent_id, fields.clone()))
+        .await
+    {
+        Ok(_) => {}
+        Err(EngineInsertError::UnknownParentSpanId) => {
+            fields.insert(
+                "parent_span_id".to_owned(),
+                Value::Str(span.parent_span_id.clone()),
+            );
+            engine.insert_span_event(create(None, fields)).await?;
+        }
+        Err(err) => return Err(err),
+    }
+
+    for span_event in span.events {
+        let mut fields = conv_attributes(span_event.attributes);
+        let (file_name, file_line) = take_code_location(&mut fields);
+        let level = if span_event.name == "exception" { 4 } else { 2 };
+
+        engine
+            .insert_event(NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: conv_timestamp(span_event.time_unix_nano),
+                span_id: Some(span_id),
+                name: span_event.name,
+                target: target.clone(),
+                level,
+                file_name,
+                file_line,
+                fields,
+            })
+            .await?;
+    }
+
+    engine
+        .insert_span_event(NewSpanEvent {
+            connection_key,
+            seq: None,
+            timestamp: conv_timestamp(span.end_time_unix_nano),
+            span_id,
+            kind: NewSpanEventKind::Close,
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn insert_logs(state: &OtlpState, request: LogsRequest) {
+    for resource_logs in request.resource_logs {
+        let resource = ResourceConnection::new(resource_logs.resource);
+
+        for scope_logs in resource_logs.scope_logs {
+            for record in scope_logs.log_records {
+                let result = state
+                    .insert_with_connection(&resource, record, |connection_key, record| {
+                        let target = scope_logs.scope.name.clone();
+                        insert_log(&state.engine, connection_key, target, record)
+                    })
+                    .await;
+                if let Err(err) = result {
+                    eprintln!("failed to insert OTLP log: {err:?}");
+                }
+            }
+        }
+    }
+}
+
+async fn insert_log(
+    engine: &Engine,
+    connection_key: ConnectionKey,
+    target: String,
+    record: LogRecord,
+) -> Result<(), EngineInsertError> {
+    let mut fields = conv_attributes(record.attributes);
+    let (file_name, file_line) = take_code_location(&mut fields);
+    if let Some(body) = record.body.and_then(conv_any_value) {
+        fields.insert("message".to_owned(), body);
+    }
+    if !record.trace_id.is_empty() {
+        fields.insert("trace_id".to_owned(), Value::Str(record.trace_id.clone()));
+    }
+
+    // the observed time is used if the source didn't provide one
+    let timestamp = match record.time_unix_nano {
+        0 => record.observed_time_unix_nano,
+        time => time,
+    };
+
+    let event = |span_id: Option<SpanId>, fields: BTreeMap<String, Value>| NewEvent {
+        connection_key,
+        seq: None,
+        timestamp: conv_timestamp(timestamp),
+        span_id,
+        name: target.clone(),
+        target: target.clone(),
+        level: conv_severity(record.severity_number),
+        file_name: file_name.clone(),
+        file_line,
+        fields,
+    };
+
+    // logs are usually emitted within spans that haven't ended, and thus
+    // haven't been exported yet
+    let span_id = parse_id(&record.span_id);
+    match engine.insert_event(event(span_id, fields.clone())).await {
+        Ok(_) => {}
+        Err(EngineInsertError::UnknownParentSpanId) => {
+            fields.insert("span_id".to_owned(), Value::Str(record.span_id.clone()));
+            engine.insert_event(event(None, fields)).await?;
+        }
+        Err(err) => return Err(err),
+    }
+
+    Ok(())
+}
+
+fn conv_timestamp(unix_nano: u64) -> Timestamp {
+    NonZeroU64::new(unix_nano / 1000).unwrap_or(NonZeroU64::MIN)
+}
+
+// OTLP ids are hex-encoded; trace-wide uniqueness is not needed since spans
+// are only looked up within their connection.
+fn parse_id(id: &str) -> Option<SpanId> {
+    let id = u64::from_str_radix(id, 16).ok()?;
+    NonZeroU64::new(id)
+}
+
+fn conv_severity(severity_number: i32) -> i32 {
+    match severity_number {
+        1..=4 => 0,   // TRACE
+        5..=8 => 1,   // DEBUG
+        9..=12 => 2,  // INFO
+        13..=16 => 3, // WARN
+        17..=24 => 4, // ERROR and FATAL
+        _ => 2,
+    }
+}
+
+// Both the current and deprecated semantic conventions are supported.
+fn take_code_location(fields: &mut BTreeMap<String, Value>) -> (Option<String>, Option<u32>) {
+    let file_name = ["code.file.path", "code.filepath"]
+        .into_iter()
+        .find_map(|name| fields.remove(name))
+        .map(|value| value.to_string());
+
+    let file_line = ["code.line.number", "code.lineno"]
+        .into_iter()
+        .find_map(|name| fields.remove(name))
+        .and_then(|value| match value {
+            Value::I64(line) => line.try_into().ok(),
+            Value::U64(line) => line.try_into().ok(),
+            _ => None,
+        });
+
+    (file_name, file_line)
+}
+
+fn conv_attributes(attributes: Vec<KeyValue>) -> BTreeMap<String, Value> {
+    attributes
+        .into_iter()
+        .filter_map(|kv| Some((kv.key, conv_any_value(kv.value?)?)))
+        .collect()
+}
+
+fn conv_any_value(value: AnyValue) -> Option<Value> {
+    match value {
+        AnyValue {
+            string_value: Some(v),
+            ..
+        } => Some(Value::Str(v)),
+        AnyValue {
+            bool_value: Some(v),
+            ..
+        } => Some(Value::Bool(v)),
+        AnyValue {
+            int_value: Some(v), ..
+        } => Some(Value::I64(v)),
+        AnyValue {
+            double_value: Some(v),
+            ..
+        } => Some(Value::F64(v)),
+        // there is no equivalent for these so they are kept as JSON
+        AnyValue {
+            array_value: Some(v),
+            ..
+        } => Some(Value::Str(v.to_string())),
+        AnyValue {
+            kvlist_value: Some(v),
+            ..
+        } => Some(Value::Str(v.to_string())),
+        AnyValue {
+            bytes_value: Some(v),
+            ..
+        } => Some(Value::Str(v)),
+        _ => None,
+    }
+}
+
+// 64-bit integers are encoded as strings in OTLP/JSON, but some exporters
+// send them as numbers.
+fn deserialize_int<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: std::str::FromStr + TryFrom<u64> + TryFrom<i64>,
+{
+    match JsonValue::deserialize(deserializer)? {
+        JsonValue::String(s) => s.parse().map_err(|_| D::Error::custom("invalid integer")),
+        JsonValue::Number(n) => n
+            .as_u64()
+            .and_then(|n| T::try_from(n).ok())
+            .or_else(|| n.as_i64().and_then(|n| T::try_from(n).ok()))
+            .ok_or_else(|| D::Error::custom("invalid integer")),
+        _ => Err(D::Error::custom("expected an integer")),
+    }
+}
+
+fn deserialize_optional_int<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_int(deserializer).map(Some)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TracesRequest {
+    #[serde(default)]
+    resource_spans: Vec<ResourceSpans>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceSpans {
+    #[serde(default)]
+    resource: Resource,
+    #[serde(default)]
+    scope_spans: Vec<ScopeSpans>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScopeSpans {
+    #[serde(default)]
+    scope: Scope,
+    #[serde(default)]
+    spans: Vec<OtlpSpan>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OtlpSpan {
+    #[serde(default)]
+    trace_id: String,
+    span_id: String,
+    #[serde(default)]
+    parent_span_id: String,
+    name: String,
+    #[serde(deserialize_with = "deserialize_int")]
+    start_time_unix_nano: u64,
+    #[serde(deserialize_with = "deserialize_int")]
+    end_time_unix_nano: u64,
+    #[serde(default)]
+    attributes: Vec<KeyValue>,
+    #[serde(default)]
+    events: Vec<OtlpSpanEvent>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OtlpSpanEvent {
+    #[serde(deserialize_with = "deserialize_int")]
+    time_unix_nano: u64,
+    name: String,
+    #[serde(default)]
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LogsRequest {
+    #[serde(default)]
+    resource_logs: Vec<ResourceLogs>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceLogs {
+    #[serde(default)]
+    resource: Resource,
+    #[serde(default)]
+    scope_logs: Vec<ScopeLogs>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScopeLogs {
+    #[serde(default)]
+    scope: Scope,
+    #[serde(default)]
+    log_records: Vec<LogRecord>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LogRecord {
+    #[serde(default, deserialize_with = "deserialize_int")]
+    time_unix_nano: u64,
+    #[serde(default, deserialize_with = "deserialize_int")]
+    observed_time_unix_nano: u64,
+    #[serde(default)]
+    severity_number: i32,
+    body: Option<AnyValue>,
+    #[serde(default)]
+    attributes: Vec<KeyValue>,
+    #[serde(default)]
+    trace_id: String,
+    #[serde(default)]
+    span_id: String,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Resource {
+    #[serde(default)]
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Scope {
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyValue {
+    key: String,
+    value: Option<AnyValue>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AnyValue {
+    string_value: Option<String>,
+    bool_value: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_optional_int")]
+    int_value: Option<i64>,
+    double_value: Option<f64>,
+    array_value: Option<JsonValue>,
+    kvlist_value: Option<JsonValue>,
+    bytes_value: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use venator_engine::{Order, Query, TransientStorage};
+
+    use super::*;
+
+    fn query() -> Query {
+        Query {
+            filter: vec![],
+            order: Order::Asc,
+            limit: 10,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        }
+    }
+
+    #[test]
+    fn traces_and_logs_are_translated() {
+        let engine = Engine::new(TransientStorage::new());
+        let state = OtlpState {
+            engine: engine.clone(),
+            connections: Mutex::new(HashMap::new()),
+        };
+
+        // the engine only accepts recent timestamps
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_micros() as u64;
+        let at = |micros: u64| ((now - 1000 + micros) * 1000).to_string();
+
+        // the child is listed first since it ends first
+        let traces = r#"{
+                "resourceSpans": [{
+                    "resource": {
+                        "attributes": [{ "key": "service.name", "value": { "stringValue": "my_app" } }]
+                    },
+                    "scopeSpans": [{
+                        "scope": { "name": "my_app::handler" },
+                        "spans": [{
+                            "traceId": "5b8efff798038103d269b633813fc60c",
+                            "spanId": "eee19b7ec3c1b174",
+                            "parentSpanId": "eee19b7ec3c1b173",
+                            "name": "child",
+                            "startTimeUnixNano": "$200",
+                            "endTimeUnixNano": "$300",
+                            "attributes": [{ "key": "count", "value": { "intValue": "5" } }]
+                        }, {
+                            "traceId": "5b8efff798038103d269b633813fc60c",
+                            "spanId": "eee19b7ec3c1b173",
+                            "name": "parent",
+                            "startTimeUnixNano": $100,
+                            "endTimeUnixNano": $400
+                        }]
+                    }]
+                }]
+            }"#
+        .replace("$100", &at(100))
+        .replace("$200", &at(200))
+        .replace("$300", &at(300))
+        .replace("$400", &at(400));
+
+        let logs = r#"{
+                "resourceLogs": [{
+                    "resource": {
+                        "attributes": [{ "key": "service.name", "value": { "stringValue": "my_app" } }]
+                    },
+                    "scopeLogs": [{
+                        "scope": { "name": "my_app::handler" },
+                        "logRecords": [{
+                            "timeUnixNano": "$250",
+                            "severityNumber": 13,
+                            "body": { "stringValue": "something happened" },
+                            "spanId": "eee19b7ec3c1b174",
+                            "attributes": [
+                                { "key": "code.filepath", "value": { "stringValue": "src/main.rs" } },
+                                { "key": "code.lineno", "value": { "intValue": 42 } }
+                            ]
+                        }]
+                    }]
+                }]
+            }"#
+        .replace("$250", &at(250));
+
+        let traces: TracesRequest = serde_json::from_str(&traces).unwrap();
+        let logs: LogsRequest = serde_json::from_str(&logs).unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(insert_traces(&state, traces));
+        runtime.block_on(insert_logs(&state, logs));
+
+        let connections = runtime.block_on(engine.query_connection(query()));
+        assert_eq!(connections.len(), 1);
+
+        let spans = runtime.block_on(engine.query_span(query()));
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].name, "parent");
+        assert_eq!(spans[1].name, "child");
+        assert_eq!(spans[1].ancestors.len(), 1);
+        assert_eq!(
+            spans[1].closed_at.unwrap().get() - spans[1].created_at.get(),
+            100
+        );
+
+        let events = runtime.block_on(engine.query_event(query()));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].level, 3);
+        assert_eq!(events[0].ancestors.len(), 2);
+        assert_eq!(events[0].file.as_deref(), Some("src/main.rs:42"));
+    }
+
+    #[test]
+    fn resources_reconnect_after_clear() {
+        let engine = Engine::new(TransientStorage::new());
+        let state = OtlpState {
+            engine: engine.clone(),
+            connections: Mutex::new(HashMap::new()),
+        };
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_micros() as u64;
+
+        let logs = r#"{
+                "resourceLogs": [{
+                    "resource": {
+                        "attributes": [{ "key": "service.name", "value": { "stringValue": "my_app" } }]
+                    },
+                    "scopeLogs": [{
+                        "scope": { "name": "my_app::handler" },
+                        "logRecords": [{
+                            "timeUnixNano": "$now",
+                            "severityNumber": 13,
+                            "body": { "stringValue": "something happened" }
+                        }]
+                    }]
+                }]
+            }"#
+        .replace("$now", &(now * 1000).to_string());
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(insert_logs(&state, serde_json::from_str(&logs).unwrap()));
+        runtime.block_on(engine.clear());
+        runtime.block_on(insert_logs(&state, serde_json::from_str(&logs).unwrap()));
+
+        let connections = runtime.block_on(engine.query_connection(query()));
+        assert_eq!(connections.len(), 1);
+
+        let events = runtime.block_on(engine.query_event(query()));
+        assert_eq!(events.len(), 1);
+    }
+}
@@ -0,0 +1,157 @@
+use chrono::{DateTime, Duration, Local, Utc};
+
+use venator_engine::Timestamp;
+
+/// This parses a timeframe as typed by a user into the start and end of a
+/// query, with `None` leaving that side open. It understands:
+///
+/// - `last 15m`: the past amount of time, with units `s`, `m`, `h`, or `d`
+/// - `today`: since the most recent local midnight
+/// - `<start>..<end>`: RFC 3339 times, either of which can be left out
+///
+/// Relative timeframes have no end so that newer data still matches.
+pub fn parse_timeframe(input: &str) -> Result<(Option<Timestamp>, Option<Timestamp>), String> {
+    parse_timeframe_at(input, Utc::now())
+}
+
+fn parse_timeframe_at(
+    input: &str,
+    now: DateTime<Utc>,
+) -> Result<(Option<Timestamp>, Option<Timestamp>), String> {
+    let input = input.trim();
+
+    if let Some(amount) = input.strip_prefix("last ") {
+        let start = now
+            .checked_sub_signed(parse_amount(amount.trim())?)
+            .ok_or_else(|| format!("{amount:?} is too long"))?;
+
+        return Ok((Some(to_timestamp(start)?), None));
+    }
+
+    if input == "today" {
+        let midnight = now
+            .with_timezone(&Local)
+            .date_naive()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .earliest()
+            .ok_or("the start of today does not exist in the local timezone")?;
+
+        return Ok((Some(to_timestamp(midnight.with_timezone(&Utc))?), None));
+    }
+
+    let Some((start, end)) = input.split_once("..") else {
+        return Err(format!("unrecognized timeframe {input:?}"));
+    };
+
+    let start = parse_time(start.trim())?;
+    let end = parse_time(end.trim())?;
+
+    if let (Some(start), Some(end)) = (start, end) {
+        if start > end {
+            return Err("the start of the timeframe is after its end".to_owned());
+        }
+    }
+
+    Ok((start, end))
+}
+
+// this is a whole number followed by its unit, like `15m`
+fn parse_amount(amount: &str) -> Result<Duration, String> {
+    let split = amount
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("missing unit in {amount:?}"))?;
+
+    let (count, unit) = amount.split_at(split);
+    let count: i64 = count
+        .parse()
+        .map_err(|_| format!("invalid amount in {amount:?}"))?;
+
+    let unit_seconds = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        unit => return Err(format!("unknown unit {unit:?}, expected s, m, h, or d")),
+    };
+
+    // `Duration` panics beyond this many seconds
+    count
+        .checked_mul(unit_seconds)
+        .filter(|seconds| *seconds <= i64::MAX / 1000)
+        .map(Duration::seconds)
+        .ok_or_else(|| format!("{amount:?} is too long"))
+}
+
+fn parse_time(time: &str) -> Result<Option<Timestamp>, String> {
+    if time.is_empty() {
+        return Ok(None);
+    }
+
+    let time = DateTime::parse_from_rfc3339(time).map_err(|err| format!("{time:?}: {err}"))?;
+
+    to_timestamp(time.with_timezone(&Utc)).map(Some)
+}
+
+// timestamps are microseconds since the unix epoch and can't be zero
+fn to_timestamp(time: DateTime<Utc>) -> Result<Timestamp, String> {
+    u64::try_from(time.timestamp_micros())
+        .ok()
+        .and_then(Timestamp::new)
+        .ok_or_else(|| format!("{time} is before the unix epoch"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-10-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn micros(time: &str) -> Timestamp {
+        let time = DateTime::parse_from_rfc3339(time).unwrap();
+        Timestamp::new(time.timestamp_micros() as u64).unwrap()
+    }
+
+    #[test]
+    fn last_is_relative_to_now() {
+        let (start, end) = parse_timeframe_at("last 15m", now()).unwrap();
+        assert_eq!(start, Some(micros("2024-10-01T11:45:00Z")));
+        assert_eq!(end, None);
+
+        let (start, _) = parse_timeframe_at("last 2h", now()).unwrap();
+        assert_eq!(start, Some(micros("2024-10-01T10:00:00Z")));
+
+        assert!(parse_timeframe_at("last 2", now()).is_err());
+        assert!(parse_timeframe_at("last 2w", now()).is_err());
+    }
+
+    #[test]
+    fn today_starts_before_now() {
+        let (start, end) = parse_timeframe_at("today", now()).unwrap();
+        let start = start.unwrap().get();
+        let now = now().timestamp_micros() as u64;
+
+        assert!(start <= now && now - start < 24 * 60 * 60 * 1_000_000);
+        assert_eq!(end, None);
+    }
+
+    #[test]
+    fn ranges_can_be_open() {
+        let (start, end) =
+            parse_timeframe_at("2024-10-01T08:00:00Z..2024-10-01T10:00:00+01:00", now()).unwrap();
+        assert_eq!(start, Some(micros("2024-10-01T08:00:00Z")));
+        assert_eq!(end, Some(micros("2024-10-01T09:00:00Z")));
+
+        let (start, end) = parse_timeframe_at("2024-10-01T08:00:00Z..", now()).unwrap();
+        assert_eq!(start, Some(micros("2024-10-01T08:00:00Z")));
+        assert_eq!(end, None);
+
+        assert!(parse_timeframe_at("2024-10-01T10:00:00Z..2024-10-01T08:00:00Z", now()).is_err());
+        assert!(parse_timeframe_at("yesterday", now()).is_err());
+    }
+}
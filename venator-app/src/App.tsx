@@ -2,7 +2,7 @@ import { listen } from '@tauri-apps/api/event';
 import { ask, message, save } from '@tauri-apps/plugin-dialog';
 import { writeTextFile } from '@tauri-apps/plugin-fs';
 import { EventsScreen } from "./screens/events-screen";
-import { AppStatus, deleteEntities, Event, getEvents, getConnections, getSpans, getStats, getStatus, Input, Connection, Span, Timestamp, ValidFilterPredicate } from "./invoke";
+import { AppStatus, clearStorage, deleteEntities, Event, getEvents, getConnections, getSpans, getStats, getStatus, Input, Connection, Span, Timestamp, ValidFilterPredicate } from "./invoke";
 import { batch, createSignal, Match, onMount, Show, Switch } from "solid-js";
 import { Counts, PaginationFilter, PartialEventCountFilter, PartialFilter, PositionedConnection, PositionedSpan, Timespan } from "./models";
 import { SpansScreen } from "./screens/spans-screen";
@@ -473,6 +473,19 @@ function App() {
             }
         });
 
+        await listen('clear-all-clicked', async () => {
+            let answer = await ask(`This will remove all data from ${status()?.dataset_name}. Active subscriptions will stop. \n\n Proceed?`, {
+                title: `Clear ${status()?.dataset_name}`,
+                kind: 'warning',
+            });
+
+            if (answer) {
+                await clearStorage();
+
+                forceResetScreenFilters();
+            }
+        });
+
         document.onkeydown = function (e) {
             if (e.ctrlKey && !e.altKey && !e.shiftKey && e.key == 'z') {
                 e.preventDefault();
@@ -1566,6 +1579,10 @@ function App() {
                         <span style="padding: 0 4px;" title={s().ingress_error}>connections: {s().ingress_connections}</span>
                         -
                         <span style="padding: 0 4px;">load: {s().engine_load.toFixed(1)}%</span>
+                        <Show when={s().engine_insert_queue_depth > 0}>
+                            -
+                            <span style="padding: 0 4px;" title="inserts waiting for the engine">queued: {s().engine_insert_queue_depth}</span>
+                        </Show>
                     </span>
                 </>}
             </Show>
@@ -95,6 +95,9 @@ export function EventDetailPane(props: EventDetailPaneProps) {
                 <div id="detail-info-head">
                     <DetailedLevel level={props.event.level} />
                     <DetailedTimestamp timestamp={props.event.timestamp} />
+                    <Show when={props.event.client_timestamp != null}>
+                        <DetailedClientTimestamp timestamp={props.event.client_timestamp!} />
+                    </Show>
                     <Show when={!inFilter()}>
                         <span style="color: #555555; margin: 0 4px;">not in filter</span>
                     </Show>
@@ -210,6 +213,9 @@ export function SpanDetailPane(props: SpanDetailPaneProps) {
                 <div id="detail-info-head">
                     <DetailedLevel level={props.span.level} />
                     <DetailedTimestamp timestamp={props.span.created_at} />
+                    <Show when={props.span.client_created_at != null}>
+                        <DetailedClientTimestamp timestamp={props.span.client_created_at!} />
+                    </Show>
                     <Show when={props.span.closed_at != null}>
                         <DetailedDuration duration={props.span.closed_at! - props.span.created_at} />
                     </Show>
@@ -328,8 +334,8 @@ export function ConnectionDetailPane(props: ConnectionDetailPaneProps) {
             <div id="detail-info">
                 <div id="detail-info-head">
                     <DetailedTimestamp timestamp={props.connection.connected_at} />
-                    <Show when={props.connection.disconnected_at != null}>
-                        <DetailedDuration duration={props.connection.disconnected_at! - props.connection.connected_at} />
+                    <Show when={props.connection.duration != null}>
+                        <DetailedDuration duration={props.connection.duration!} />
                     </Show>
                     <Show when={!inFilter()}>
                         <span style="color: #555555; margin: 0 4px;">not in filter</span>
@@ -373,6 +379,14 @@ export function DetailedTimestamp(props: { timestamp: number }) {
     </div>);
 }
 
+// This is shown when the client's timestamp was out of order or too far off,
+// so it was given a different key when received.
+export function DetailedClientTimestamp(props: { timestamp: number }) {
+    return (<span style="color: #555555; margin: 0 4px;" title="the timestamp sent by the client">
+        sent as {(new Date(props.timestamp / 1000)).toISOString()}
+    </span>);
+}
+
 export function DetailedDuration(props: { duration: number }) {
     function renderedDuration(duration: number) {
         const MILLISECOND = 1000;
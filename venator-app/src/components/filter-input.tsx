@@ -133,8 +133,24 @@ export function InvalidFilterInputPredicate(props: { predicate: InvalidFilterPre
         await menu.popup(new LogicalPosition(e.clientX, e.clientY));
     }
 
+    // the span is in bytes, so the text is split by its UTF-8 encoding
+    function splitText(): [string, string, string] {
+        let span = props.predicate.span;
+        if (span == undefined) {
+            return [props.predicate.text, '', ''];
+        }
+
+        let bytes = new TextEncoder().encode(props.predicate.text);
+        let decoder = new TextDecoder();
+        return [
+            decoder.decode(bytes.slice(0, span[0])),
+            decoder.decode(bytes.slice(span[0], span[1])),
+            decoder.decode(bytes.slice(span[1])),
+        ];
+    }
+
     return (<span class="predicate attribute-predicate error" onauxclick={onclick} oncontextmenu={showContextMenu} title={props.predicate.error}>
-        {props.predicate.text}
+        {splitText()[0]}<span class="error-span">{splitText()[1]}</span>{splitText()[2]}
     </span>);
 }
 
@@ -231,7 +231,10 @@ export const ATTRIBUTE = (attribute: string): ColumnDef<Event | Span | Connectio
     },
     headerText: `@${attribute}`,
     data: (props) => {
-        let value = props.entry.attributes.find(a => a.name == attribute)?.value;
+        let attr = props.entry.attributes.find(a => a.name == attribute);
+        let value = attr?.value;
+        let isNumber = attr != null && ['f64', 'i64', 'u64', 'i128', 'u128'].includes(attr.type);
+        let isBoolean = attr?.type == 'bool';
 
         async function showContextMenu(e: MouseEvent) {
             let shortName = attribute.length > 16 ? attribute.slice(0, 14) + ".." : attribute;
@@ -274,7 +277,7 @@ export const ATTRIBUTE = (attribute: string): ColumnDef<Event | Span | Connectio
             await menu.popup(new LogicalPosition(e.clientX, e.clientY));
         }
 
-        return <div class="data" classList={{ selected: props.selected, hovered: props.hovered }} onclick={props.onClick} onmouseenter={e => props.onHover(e, true)} onmouseleave={e => props.onHover(e, false)} oncontextmenu={showContextMenu}>
+        return <div class="data" classList={{ selected: props.selected, hovered: props.hovered, 'value-type-number': isNumber, 'value-type-boolean': isBoolean, 'value-missing': attr == null }} onclick={props.onClick} onmouseenter={e => props.onHover(e, true)} onmouseleave={e => props.onHover(e, false)} oncontextmenu={showContextMenu}>
             {value ?? '---'}
         </div>;
     },
@@ -10,6 +10,34 @@ export type Stats = {
     end?: Timestamp;
     total_spans: number;
     total_events: number;
+    events_per_level: number[];
+    spans_per_level: number[];
+    connected_connections: number;
+    disconnected_connections: number;
+};
+
+export type SavedFilter = {
+    name: string;
+    filter: string;
+};
+
+export type QueryPlan = {
+    node: 'index' | 'not' | 'and' | 'or';
+    estimate_count: number;
+    indexed: boolean;
+    scan: string | null;
+    children: QueryPlan[];
+};
+
+export type DurationStats = {
+    count: number;
+    still_open: number;
+    min?: number;
+    max?: number;
+    mean?: number;
+    p50?: number;
+    p90?: number;
+    p99?: number;
 };
 
 export type Comparator = 'Gt' | 'Gte' | 'Eq' | 'Lt' | 'Lte';
@@ -24,7 +52,7 @@ export type Input = (ValidFilterPredicate | InvalidFilterPredicate) & { editable
 
 export type ValidFilterPredicate = { input: 'valid' } & FilterPredicate;
 
-export type InvalidFilterPredicate = { input: 'invalid', text: string, error: string };
+export type InvalidFilterPredicate = { input: 'invalid', text: string, error: string, span?: [number, number] };
 
 export type FilterPredicate =
     { predicate_kind: 'single', predicate: FilterPredicateSingle } |
@@ -50,6 +78,8 @@ export type Connection = {
     id: ConnectionId,
     connected_at: Timestamp;
     disconnected_at: Timestamp | null;
+    duration: number | null;
+    schema: number;
     attributes: Attribute[];
 };
 
@@ -59,6 +89,7 @@ export type EventFilter = {
     limit?: number;
     start: Timestamp | null;
     end: Timestamp | null;
+    timeframe?: string;
     previous?: Timestamp;
 };
 
@@ -68,10 +99,33 @@ export type CountFilter = {
     end: Timestamp;
 };
 
+export type BucketFilter = {
+    filter: FilterPredicate[];
+    start: Timestamp;
+    end: Timestamp;
+    bucketSize: number;
+    count: number;
+};
+
+export type HistogramFilter = {
+    filter: FilterPredicate[];
+    start: Timestamp;
+    end: Timestamp;
+    bucketSize: number;
+};
+
+export type Bucket = {
+    start: Timestamp;
+    end: Timestamp;
+    count: number;
+};
+
 export type Event = {
     connection_id: ConnectionId;
     ancestors: Ancestor[];
+    span_id: FullSpanId | null;
     timestamp: Timestamp;
+    client_timestamp?: Timestamp;
     target: string;
     name: string;
     level: Level;
@@ -85,14 +139,18 @@ export type SpanFilter = {
     limit?: number;
     start: Timestamp | null;
     end: Timestamp | null;
+    timeframe?: string;
     previous?: Timestamp;
 };
 
 export type Span = {
     id: FullSpanId,
     ancestors: Ancestor[];
+    follows: FullSpanId[];
     created_at: Timestamp;
+    client_created_at?: Timestamp;
     closed_at: Timestamp | null;
+    busy: number | null;
     target: string;
     name: string;
     level: Level;
@@ -100,6 +158,15 @@ export type Span = {
     attributes: Attribute[];
 };
 
+export type SpanTimelineEntry = {
+    timestamp: Timestamp;
+} & ({ kind: 'create' }
+    | { kind: 'update', attributes: Attribute[] }
+    | { kind: 'follows', follows: FullSpanId }
+    | { kind: 'busy', exited_at: Timestamp | null }
+    | { kind: 'event', name: string, level: Level }
+    | { kind: 'close' });
+
 export type Ancestor = {
     id: FullSpanId,
     name: string,
@@ -113,6 +180,14 @@ export type Attribute = {
     | { source: 'span', span_id: FullSpanId }
     | { source: 'inherent' });
 
+export type IngressMetrics = {
+    bytes_read: number;
+    messages_decoded: number;
+    parse_errors: number;
+    active_connections: number;
+    rejected_connections: number;
+};
+
 export type AppStatus = {
     ingress_message: string;
     ingress_error: string;
@@ -120,6 +195,7 @@ export type AppStatus = {
     ingress_bytes_per_second: number;
     dataset_name: string;
     engine_load: number;
+    engine_insert_queue_depth: number;
 };
 
 export type DeleteMetrics = {
@@ -129,6 +205,22 @@ export type DeleteMetrics = {
     events: number;
 };
 
+export type CompactMetrics = {
+    size_before: number;
+    size_after: number;
+};
+
+export type CompactProgress =
+    | { kind: "started" }
+    | { kind: "finished"; metrics: CompactMetrics | null };
+
+export type ImportMetrics = {
+    connections: number;
+    spans: number;
+    events: number;
+    skipped_lines: number;
+};
+
 export async function getConnections(filter: ConnectionFilter): Promise<Connection[]> {
     console.debug("invoking 'get_connections'");
     return await invoke<Connection[]>("get_connections", filter);
@@ -144,46 +236,180 @@ export async function parseConnectionFilter(filter: string): Promise<Input[]> {
     return await invoke<Input[]>("parse_connection_filter", { filter });
 }
 
+export async function listAttributeKeys(kind: 'events' | 'spans'): Promise<string[]> {
+    console.debug("invoking 'list_attribute_keys'");
+    return await invoke<string[]>("list_attribute_keys", { kind });
+}
+
+export async function listAttributeValues(kind: 'events' | 'spans', attribute: string, limit: number): Promise<string[]> {
+    console.debug("invoking 'list_attribute_values'");
+    return await invoke<string[]>("list_attribute_values", { kind, attribute, limit });
+}
+
 export async function getStats(): Promise<Stats> {
     console.debug("invoking 'get_stats'");
     return await invoke<Stats>("get_stats", {});
 }
 
+export async function parseTimeframe(timeframe: string): Promise<[Timestamp | null, Timestamp | null]> {
+    console.debug("invoking 'parse_timeframe'");
+    return await invoke<[Timestamp | null, Timestamp | null]>("parse_timeframe", { timeframe });
+}
+
+export async function saveFilter(name: string, filter: string): Promise<SavedFilter> {
+    console.debug("invoking 'save_filter'");
+    return await invoke<SavedFilter>("save_filter", { name, filter });
+}
+
+export async function listFilters(): Promise<SavedFilter[]> {
+    console.debug("invoking 'list_filters'");
+    return await invoke<SavedFilter[]>("list_filters", {});
+}
+
+export async function deleteFilter(name: string): Promise<SavedFilter | null> {
+    console.debug("invoking 'delete_filter'");
+    return await invoke<SavedFilter | null>("delete_filter", { name });
+}
+
 export async function getEvents(filter: EventFilter): Promise<Event[]> {
     console.debug("invoking 'get_events'");
     return await invoke<Event[]>("get_events", filter);
 }
 
+export async function getEvent(key: Timestamp): Promise<Event> {
+    console.debug("invoking 'get_event'");
+    return await invoke<Event>("get_event", { key });
+}
+
 export async function getEventCount(filter: CountFilter): Promise<number> {
     console.debug("invoking 'get_event_count'");
     return await invoke<number>("get_event_count", filter);
 }
 
+export type EventsDuringSpanFilter = {
+    spanId: FullSpanId;
+    filter: FilterPredicate[];
+    order: 'asc' | 'desc';
+    limit?: number;
+    previous?: Timestamp;
+    sameConnection?: boolean;
+};
+
+export async function getEventsDuringSpan(filter: EventsDuringSpanFilter): Promise<Event[]> {
+    console.debug("invoking 'get_events_during_span'");
+    return await invoke<Event[]>("get_events_during_span", filter);
+}
+
+export async function explainEventQuery(filter: CountFilter): Promise<QueryPlan> {
+    console.debug("invoking 'explain_event_query'");
+    return await invoke<QueryPlan>("explain_event_query", filter);
+}
+
+export async function getEventPeakBuckets(filter: BucketFilter): Promise<Bucket[]> {
+    console.debug("invoking 'get_event_peak_buckets'");
+    return await invoke<Bucket[]>("get_event_peak_buckets", filter);
+}
+
+export async function getEventHistogram(filter: HistogramFilter): Promise<Bucket[]> {
+    console.debug("invoking 'get_event_histogram'");
+    return await invoke<Bucket[]>("get_event_histogram", filter);
+}
+
 export async function parseEventFilter(filter: string): Promise<Input[]> {
     console.debug("invoking 'parse_event_filter'");
     return await invoke<Input[]>("parse_event_filter", { filter });
 }
 
+export async function exportEvents(path: string, filter: string, start: Timestamp | null, end: Timestamp | null): Promise<number> {
+    console.debug("invoking 'export_events'");
+    return await invoke<number>("export_events", { path, filter, start, end });
+}
+
+export async function exportEventsCsv(path: string, filter: string, columns: string[], start: Timestamp | null, end: Timestamp | null): Promise<number> {
+    console.debug("invoking 'export_events_csv'");
+    return await invoke<number>("export_events_csv", { path, filter, columns, start, end });
+}
+
+export async function importNdjson(path: string): Promise<ImportMetrics> {
+    console.debug("invoking 'import_ndjson'");
+    return await invoke<ImportMetrics>("import_ndjson", { path });
+}
+
 export async function getSpans(filter: SpanFilter): Promise<Span[]> {
     console.debug("invoking 'get_spans'");
     return await invoke<Span[]>("get_spans", filter);
 }
 
+export async function getSpan(key: Timestamp): Promise<Span> {
+    console.debug("invoking 'get_span'");
+    return await invoke<Span>("get_span", { key });
+}
+
+export async function getSpanTimeline(key: Timestamp): Promise<SpanTimelineEntry[]> {
+    console.debug("invoking 'get_span_timeline'");
+    return await invoke<SpanTimelineEntry[]>("get_span_timeline", { key });
+}
+
+export async function getSpanChildren(spanId: string): Promise<Span[]> {
+    console.debug("invoking 'get_span_children'");
+    return await invoke<Span[]>("get_span_children", { spanId });
+}
+
 export async function getSpanCount(filter: CountFilter): Promise<number> {
     console.debug("invoking 'get_span_count'");
     return await invoke<number>("get_span_count", filter);
 }
 
+export async function getSpanDurationStats(filter: CountFilter): Promise<DurationStats> {
+    console.debug("invoking 'get_span_duration_stats'");
+    return await invoke<DurationStats>("get_span_duration_stats", filter);
+}
+
+export async function getSpanPeakBuckets(filter: BucketFilter): Promise<Bucket[]> {
+    console.debug("invoking 'get_span_peak_buckets'");
+    return await invoke<Bucket[]>("get_span_peak_buckets", filter);
+}
+
 export async function parseSpanFilter(filter: string): Promise<Input[]> {
     console.debug("invoking 'parse_span_filter'");
     return await invoke<Input[]>("parse_span_filter", { filter });
 }
 
+export async function exportChromeTrace(path: string, filter: string, start: Timestamp | null, end: Timestamp | null): Promise<number> {
+    console.debug("invoking 'export_chrome_trace'");
+    return await invoke<number>("export_chrome_trace", { path, filter, start, end });
+}
+
+export async function exportJaeger(path: string, filter: string, start: Timestamp | null, end: Timestamp | null): Promise<number> {
+    console.debug("invoking 'export_jaeger'");
+    return await invoke<number>("export_jaeger", { path, filter, start, end });
+}
+
 export async function deleteEntities(start: Timestamp | null, end: Timestamp | null, inside: boolean, dryRun: boolean): Promise<DeleteMetrics> {
     console.debug("invoking 'delete_entities'");
     return await invoke<DeleteMetrics>("delete_entities", { start, end, inside, dryRun });
 }
 
+export async function deleteConnection(connectionId: string): Promise<DeleteMetrics> {
+    console.debug("invoking 'delete_connection'");
+    return await invoke<DeleteMetrics>("delete_connection", { connectionId });
+}
+
+export async function flush(): Promise<void> {
+    console.debug("invoking 'flush'");
+    return await invoke<void>("flush", {});
+}
+
+export async function clearStorage(): Promise<void> {
+    console.debug("invoking 'clear_storage'");
+    return await invoke<void>("clear_storage", {});
+}
+
+export async function compactStorage(channel: Channel<CompactProgress>): Promise<CompactMetrics | null> {
+    console.debug("invoking 'compact_storage'");
+    return await invoke<CompactMetrics | null>("compact_storage", { channel });
+}
+
 export async function subscribeToEvents(filter: FilterPredicate[], channel: Channel<Event>): Promise<number> {
     console.debug("invoking 'subscribe_to_events'");
     return await invoke<number>("subscribe_to_events", { filter, channel });
@@ -194,6 +420,41 @@ export async function unsubscribeFromEvents(id: number): Promise<number> {
     return await invoke<number>("unsubscribe_from_events", { id });
 }
 
+export async function subscribeToSpans(filter: FilterPredicate[], channel: Channel<Span>): Promise<number> {
+    console.debug("invoking 'subscribe_to_spans'");
+    return await invoke<number>("subscribe_to_spans", { filter, channel });
+}
+
+export async function unsubscribeFromSpans(id: number): Promise<number> {
+    console.debug("invoking 'unsubscribe_from_spans'");
+    return await invoke<number>("unsubscribe_from_spans", { id });
+}
+
+export async function subscribeToConnections(filter: FilterPredicate[], channel: Channel<Connection>): Promise<number> {
+    console.debug("invoking 'subscribe_to_connections'");
+    return await invoke<number>("subscribe_to_connections", { filter, channel });
+}
+
+export async function unsubscribeFromConnections(id: number): Promise<number> {
+    console.debug("invoking 'unsubscribe_from_connections'");
+    return await invoke<number>("unsubscribe_from_connections", { id });
+}
+
+export async function getIngressStatus(): Promise<[string, string | null]> {
+    console.debug("invoking 'get_ingress_status'");
+    return await invoke<[string, string | null]>("get_ingress_status");
+}
+
+export async function getIngressMetrics(): Promise<IngressMetrics> {
+    console.debug("invoking 'get_ingress_metrics'");
+    return await invoke<IngressMetrics>("get_ingress_metrics");
+}
+
+export async function setIngressBind(bind: string): Promise<void> {
+    console.debug("invoking 'set_ingress_bind'");
+    return await invoke<void>("set_ingress_bind", { bind });
+}
+
 export async function createAttributeIndex(name: string): Promise<void> {
     console.debug("invoking 'create_attribute_index'");
     return await invoke<void>("create_attribute_index", { name });
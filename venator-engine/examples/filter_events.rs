@@ -0,0 +1,86 @@
+//! Inserts a few events into an in-memory engine and queries them with a
+//! filter, printing the matches.
+//!
+//! Run with `cargo run -p venator-engine --example filter_events`.
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use venator_engine::{
+    Engine, FilterPredicate, NewConnection, NewEvent, Order, Query, Timestamp, TransientStorage,
+    Value,
+};
+
+fn main() {
+    futures::executor::block_on(run());
+}
+
+async fn run() {
+    let engine = Engine::new(TransientStorage::new());
+
+    let connection_key = engine
+        .insert_connection(NewConnection::new(
+            1,
+            BTreeMap::from([("service".to_owned(), Value::Str("example".to_owned()))]),
+        ))
+        .await
+        .unwrap();
+
+    let start = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_micros() as u64;
+
+    let events = [
+        (2, "request received", 200),
+        (2, "request completed", 200),
+        (3, "request was slow", 200),
+        (4, "request failed", 500),
+    ];
+
+    for (offset, (level, name, status)) in events.into_iter().enumerate() {
+        engine
+            .insert_event(NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(start + offset as u64).unwrap(),
+                span_id: None,
+                name: name.to_owned(),
+                target: "filter_events".to_owned(),
+                level,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::from([("status".to_owned(), Value::U64(status))]),
+            })
+            .await
+            .unwrap();
+    }
+
+    let filter = FilterPredicate::parse("#level: >=WARN @service: example").unwrap();
+    let events = engine
+        .query_event(Query {
+            filter,
+            order: Order::Asc,
+            limit: 10,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        })
+        .await;
+
+    for event in events {
+        let status = event
+            .attributes
+            .iter()
+            .find(|attribute| attribute.name == "status")
+            .map(|attribute| attribute.value.clone())
+            .unwrap_or_default();
+
+        println!(
+            "{} [{}] {} status={status}",
+            event.timestamp, event.level, event.name
+        );
+    }
+
+    engine.shutdown().await;
+}
@@ -0,0 +1,156 @@
+//! Measures how fast events are written to a file with each durability mode,
+//! awaiting each insert versus queueing them so the engine can batch them, and
+//! how much smaller the file is with compressed fields.
+//!
+//! Run with `cargo run --release -p venator-engine --features persist --example storage_throughput`.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use venator_engine::{
+    Durability, Engine, FileStorage, FileStorageOptions, NewConnection, NewEvent, Timestamp, Value,
+};
+
+const COUNT: u64 = 10_000;
+
+fn main() {
+    futures::executor::block_on(run());
+}
+
+async fn run() {
+    let dir = tempfile::tempdir().unwrap();
+
+    for durability in [Durability::Off, Durability::Normal, Durability::Full] {
+        let options = FileStorageOptions {
+            durability,
+            ..FileStorageOptions::default()
+        };
+
+        let path = dir.path().join(format!("{durability:?}-awaited.db"));
+        let engine = start_engine(&path, options.clone());
+        let events = make_events(&engine, COUNT).await;
+        let start = Instant::now();
+        for event in events {
+            engine.insert_event(event).await.unwrap();
+        }
+        let awaited = start.elapsed();
+        engine.shutdown().await;
+
+        let path = dir.path().join(format!("{durability:?}-queued.db"));
+        let engine = start_engine(&path, options);
+        let mut events = make_events(&engine, COUNT).await;
+        let last = events.pop().unwrap();
+        let start = Instant::now();
+        for event in events {
+            engine.queue_event(event).await;
+        }
+        // inserts are done in order, so the rest are done once this one is
+        engine.insert_event(last).await.unwrap();
+        let queued = start.elapsed();
+        engine.shutdown().await;
+
+        println!("inserted {COUNT} events with {durability:?} durability: awaited {awaited:?}, queued {queued:?}");
+    }
+
+    let mut sizes = vec![];
+    for compress_fields in [false, true] {
+        let options = FileStorageOptions {
+            compress_fields,
+            ..FileStorageOptions::default()
+        };
+
+        let path = dir.path().join(format!("compress-{compress_fields}.db"));
+        let engine = start_engine(&path, options);
+        for event in make_events(&engine, COUNT).await {
+            engine.queue_event(event).await;
+        }
+        engine.flush().await;
+        engine.shutdown().await;
+
+        sizes.push(std::fs::metadata(&path).unwrap().len());
+    }
+
+    let [plain, compressed] = sizes[..] else {
+        unreachable!()
+    };
+    let reduction = 100.0 - compressed as f64 / plain as f64 * 100.0;
+    println!("{COUNT} events: {plain} bytes plain, {compressed} bytes compressed ({reduction:.1}% smaller)");
+}
+
+fn start_engine(path: &Path, options: FileStorageOptions) -> Engine {
+    let storage = FileStorage::create_with_options(path, options).unwrap();
+
+    Engine::builder()
+        .storage(storage)
+        .persist_indexes(false)
+        .build()
+}
+
+// These look like typical request logs so that compression has something
+// realistic to work with.
+async fn make_events(engine: &Engine, count: u64) -> Vec<NewEvent> {
+    let methods = ["GET", "POST", "PUT", "DELETE"];
+    let paths = ["/api/users", "/api/orders", "/api/orders/items", "/health"];
+    let agents = [
+        "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko)",
+        "curl/8.5.0",
+        "okhttp/4.12.0",
+    ];
+
+    let connection_key = engine
+        .insert_connection(NewConnection::new(
+            1,
+            BTreeMap::from([(
+                "service.name".to_owned(),
+                Value::Str("checkout-api".to_owned()),
+            )]),
+        ))
+        .await
+        .unwrap();
+
+    let start = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_micros() as u64;
+
+    (0..count)
+        .map(|i| NewEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(start + i).unwrap(),
+            span_id: None,
+            name: "request completed".to_owned(),
+            target: "storage_throughput".to_owned(),
+            level: 2,
+            file_name: Some("src/main.rs".to_owned()),
+            file_line: Some(42),
+            fields: BTreeMap::from([
+                (
+                    "message".to_owned(),
+                    Value::Str(format!("handled request {i} in {}ms", i % 250)),
+                ),
+                (
+                    "http.method".to_owned(),
+                    Value::Str(methods[i as usize % 4].to_owned()),
+                ),
+                (
+                    "http.route".to_owned(),
+                    Value::Str(paths[i as usize % 4].to_owned()),
+                ),
+                ("http.status_code".to_owned(), Value::U64(200 + i % 3 * 100)),
+                (
+                    "http.user_agent".to_owned(),
+                    Value::Str(agents[i as usize % 3].to_owned()),
+                ),
+                (
+                    "request_id".to_owned(),
+                    Value::Str(format!("{:032x}", i.wrapping_mul(0x9e3779b97f4a7c15))),
+                ),
+                ("user_id".to_owned(), Value::U64(i % 97)),
+                ("latency_ms".to_owned(), Value::F64((i % 250) as f64 * 1.25)),
+                ("cache_hit".to_owned(), Value::Bool(i % 2 == 0)),
+            ]),
+        })
+        .collect()
+}
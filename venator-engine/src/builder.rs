@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+#[cfg(feature = "parallel")]
+use crate::filter::{parallel_search, ParallelSearch};
+#[cfg(feature = "parallel")]
+use crate::storage::ParallelStorage;
+use crate::storage::{Storage, TransientStorage};
+use crate::{Engine, MAX_INSERT_QUEUE};
+
+/// The settings that affect how the engine runs, as configured by an
+/// [`EngineBuilder`].
+#[derive(Debug, Clone)]
+pub(crate) struct EngineOptions {
+    pub(crate) max_query_limit: usize,
+    pub(crate) persist_indexes: bool,
+    pub(crate) insert_queue_size: usize,
+    pub(crate) reorder_window: Duration,
+}
+
+impl Default for EngineOptions {
+    fn default() -> EngineOptions {
+        EngineOptions {
+            max_query_limit: usize::MAX,
+            persist_indexes: true,
+            insert_queue_size: MAX_INSERT_QUEUE,
+            reorder_window: Duration::ZERO,
+        }
+    }
+}
+
+/// Configures an [`Engine`] before starting it. [`Engine::new`] is the same as
+/// building with only the storage set.
+///
+/// ```
+/// use venator_engine::{Engine, TransientStorage};
+///
+/// let engine = Engine::builder()
+///     .storage(TransientStorage::new())
+///     .max_query_limit(500)
+///     .insert_queue_size(1000)
+///     .build();
+/// ```
+pub struct EngineBuilder<S> {
+    storage: S,
+    options: EngineOptions,
+    #[cfg(feature = "parallel")]
+    parallel_search: Option<ParallelSearch<S>>,
+}
+
+impl EngineBuilder<TransientStorage> {
+    pub(crate) fn new() -> EngineBuilder<TransientStorage> {
+        EngineBuilder {
+            storage: TransientStorage::new(),
+            options: EngineOptions::default(),
+            #[cfg(feature = "parallel")]
+            parallel_search: None,
+        }
+    }
+}
+
+impl<S> EngineBuilder<S> {
+    /// Sets where connections, spans, and events are stored. This defaults to
+    /// a [`TransientStorage`] that keeps everything in memory.
+    ///
+    /// This resets [`parallel_search`](EngineBuilder::parallel_search) since
+    /// that depends on the storage.
+    pub fn storage<S2: Storage + Send + 'static>(self, storage: S2) -> EngineBuilder<S2> {
+        EngineBuilder {
+            storage,
+            options: self.options,
+            #[cfg(feature = "parallel")]
+            parallel_search: None,
+        }
+    }
+
+    /// Caps how many results a single query returns, regardless of the limit
+    /// requested by the [`Query`](crate::Query). Rendering results is the bulk
+    /// of a query's cost, so this bounds how long a query can hold up inserts
+    /// and how much memory its results take. This is unlimited by default.
+    pub fn max_query_limit(mut self, limit: usize) -> EngineBuilder<S> {
+        self.options.max_query_limit = limit;
+        self
+    }
+
+    /// Sets whether the indexes are saved to the storage on flush and loaded
+    /// on startup (only [`FileStorage`](crate::FileStorage) keeps them). This
+    /// is enabled by default. Disabling it makes flushes faster and avoids
+    /// the memory spike of serializing the indexes, but they will be rebuilt
+    /// from the stored data on every startup.
+    pub fn persist_indexes(mut self, persist: bool) -> EngineBuilder<S> {
+        self.options.persist_indexes = persist;
+        self
+    }
+
+    /// Sets how many inserts can be queued before inserting waits for the
+    /// engine to catch up. This defaults to 10,000. A larger queue absorbs
+    /// longer bursts from clients at the cost of holding more pending data in
+    /// memory; a smaller one applies backpressure to clients sooner.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size is zero.
+    pub fn insert_queue_size(mut self, size: usize) -> EngineBuilder<S> {
+        assert!(size > 0, "insert queue size must be greater than zero");
+        self.options.insert_queue_size = size;
+        self
+    }
+
+    /// Sets how long an insert that references a span that hasn't been
+    /// created yet is held, in case the span's create arrives late (like when
+    /// a client replays messages after reconnecting). Held inserts are done in
+    /// the order they were received once the span is created, or fail as they
+    /// would have otherwise once they've waited this long. Their results are
+    /// only known then, so awaiting them can take up to this long.
+    ///
+    /// This is zero by default, so such inserts fail immediately.
+    pub fn reorder_window(mut self, window: Duration) -> EngineBuilder<S> {
+        self.options.reorder_window = window;
+        self
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<S: ParallelStorage> EngineBuilder<S> {
+    /// Sets whether span filters are searched on multiple threads. This helps
+    /// filters like `#duration` that are made of many independent indexes on
+    /// large datasets, and only storage that can be read from multiple threads
+    /// allows it. This is disabled by default.
+    pub fn parallel_search(mut self, enabled: bool) -> EngineBuilder<S> {
+        self.parallel_search = enabled.then_some(parallel_search::<S> as ParallelSearch<S>);
+        self
+    }
+}
+
+impl<S: Storage + Send + 'static> EngineBuilder<S> {
+    /// Starts the engine on its own thread.
+    pub fn build(self) -> Engine {
+        Engine::start(
+            self.storage,
+            self.options,
+            #[cfg(feature = "parallel")]
+            self.parallel_search,
+        )
+    }
+}
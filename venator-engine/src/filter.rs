@@ -1,21 +1,33 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
+#[cfg(feature = "parallel")]
+use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Display, Error as FmtError, Formatter};
-use std::ops::{Add, Range};
+use std::ops::{Add, Bound, Range, RangeInclusive};
 
 use attribute::{ValueFilter, ValueStringComparison};
 use input::{FilterPredicate, FilterPredicateSingle, FilterPropertyKind, ValuePredicate};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use regex::Regex;
 use serde::Deserialize;
 use wildcard::WildcardBuilder;
 
-use crate::index::{EventIndexes, SpanDurationIndex, SpanIndexes};
-use crate::models::{parse_full_span_id, EventKey, Level, SpanKey, Timestamp, ValueOperator};
+use crate::index::{ConnectionIndexes, EventIndexes, SpanDurationIndex, SpanIndexes};
+use crate::models::{
+    parse_full_span_id, Connection, EventKey, Level, QueryPlanView, SpanKey, Timestamp, Value,
+    ValueOperator,
+};
+#[cfg(feature = "parallel")]
+use crate::storage::ParallelStorage;
 use crate::storage::Storage;
-use crate::{ConnectionId, ConnectionKey, EventContext, RawEngine, SpanContext, SpanId};
+use crate::{
+    now, ConnectionId, ConnectionKey, EventContext, EventContextCache, RawEngine, SpanContext,
+    SpanId,
+};
 
-pub mod attribute;
-pub mod input;
+pub(crate) mod attribute;
+pub(crate) mod input;
 
 #[derive(Clone)]
 pub enum FallibleFilterPredicate {
@@ -36,7 +48,7 @@ pub struct Query {
     pub previous: Option<Timestamp>,
 }
 
-pub enum IndexedEventFilter<'i> {
+pub(crate) enum IndexedEventFilter<'i> {
     Single(&'i [Timestamp], Option<NonIndexedEventFilter>),
     Not(&'i [Timestamp], Box<IndexedEventFilter<'i>>),
     And(Vec<IndexedEventFilter<'i>>),
@@ -44,7 +56,7 @@ pub enum IndexedEventFilter<'i> {
 }
 
 impl IndexedEventFilter<'_> {
-    pub fn build(
+    pub(crate) fn build(
         filter: Option<BasicEventFilter>,
         event_indexes: &EventIndexes,
     ) -> IndexedEventFilter<'_> {
@@ -99,18 +111,24 @@ impl IndexedEventFilter<'_> {
 
                     IndexedEventFilter::Single(target_index, None)
                 }
-                ValueStringComparison::Compare(_, _) => IndexedEventFilter::Single(
-                    &event_indexes.all,
-                    Some(NonIndexedEventFilter::Target(filter)),
-                ),
-                ValueStringComparison::Wildcard(_) => IndexedEventFilter::Single(
-                    &event_indexes.all,
-                    Some(NonIndexedEventFilter::Target(filter)),
-                ),
-                ValueStringComparison::Regex(_) => IndexedEventFilter::Single(
-                    &event_indexes.all,
-                    Some(NonIndexedEventFilter::Target(filter)),
-                ),
+                ValueStringComparison::Compare(_, _)
+                | ValueStringComparison::Wildcard(_)
+                | ValueStringComparison::Regex(_) => {
+                    // there are few distinct targets, so matching against the
+                    // index keys is much cheaper than checking every entry
+                    let filters = event_indexes
+                        .targets
+                        .iter()
+                        .filter(|(target, _)| filter.matches(target))
+                        .map(|(_, target_index)| IndexedEventFilter::Single(target_index, None))
+                        .collect::<Vec<_>>();
+
+                    if filters.is_empty() {
+                        IndexedEventFilter::Single(&[], None)
+                    } else {
+                        IndexedEventFilter::Or(filters)
+                    }
+                }
                 ValueStringComparison::All => IndexedEventFilter::Single(
                     &event_indexes.all,
                     Some(NonIndexedEventFilter::Target(filter)),
@@ -147,6 +165,10 @@ impl IndexedEventFilter<'_> {
                     Some(NonIndexedEventFilter::File(filter)),
                 ),
             },
+            BasicEventFilter::Line(op, line) => IndexedEventFilter::Single(
+                &event_indexes.all,
+                Some(NonIndexedEventFilter::Line(op, line)),
+            ),
             BasicEventFilter::Ancestor(ancestor_key) => {
                 let index = event_indexes
                     .descendents
@@ -188,10 +210,61 @@ impl IndexedEventFilter<'_> {
                     IndexedEventFilter::Single(&[], None)
                 }
             }
+            BasicEventFilter::AttributeExists(attribute) => {
+                if let Some(attr_index) = event_indexes.attributes.get(&attribute) {
+                    let filters = attr_index
+                        .make_exists_filter()
+                        .into_iter()
+                        .map(|i| IndexedEventFilter::Single(i, None))
+                        .collect();
+
+                    IndexedEventFilter::Or(filters)
+                } else {
+                    // every attribute is indexed, so without one there are
+                    // no entities with that attribute
+                    IndexedEventFilter::Single(&[], None)
+                }
+            }
+            BasicEventFilter::AttributeContains(attribute, _)
+            | BasicEventFilter::AttributeCaseInsensitive(attribute, _)
+                if !event_indexes.attributes.contains_key(&attribute) =>
+            {
+                // an attribute that was never seen can't match, which avoids a
+                // full scan for a misspelled attribute
+                IndexedEventFilter::Single(&[], None)
+            }
+            BasicEventFilter::AttributeContains(attribute, value) => {
+                // substrings can't be looked up in the attribute index, so this
+                // is a full scan
+                IndexedEventFilter::Single(
+                    &event_indexes.all,
+                    Some(NonIndexedEventFilter::AttributeContains(attribute, value)),
+                )
+            }
+            BasicEventFilter::AttributeCaseInsensitive(attribute, value) => {
+                // the attribute index is case-sensitive, so this is a full scan
+                IndexedEventFilter::Single(
+                    &event_indexes.all,
+                    Some(NonIndexedEventFilter::AttributeCaseInsensitive(
+                        attribute, value,
+                    )),
+                )
+            }
+            BasicEventFilter::FullText(text) => {
+                // the text can be in any attribute, so this is a full scan
+                IndexedEventFilter::Single(
+                    &event_indexes.all,
+                    Some(NonIndexedEventFilter::FullText(text)),
+                )
+            }
             BasicEventFilter::Not(filter) => IndexedEventFilter::Not(
                 &event_indexes.all,
                 Box::new(IndexedEventFilter::build(Some(*filter), event_indexes)),
             ),
+            // an empty filter places no restrictions
+            BasicEventFilter::And(filters) if filters.is_empty() => {
+                IndexedEventFilter::Single(&event_indexes.all, None)
+            }
             BasicEventFilter::And(filters) => IndexedEventFilter::And(
                 filters
                     .into_iter()
@@ -208,9 +281,15 @@ impl IndexedEventFilter<'_> {
     }
 
     // This searches for an entry equal to or beyond the provided entry
-    pub fn search<S: Storage>(
+    //
+    // The entries are trimmed as the search progresses and matches tend to be
+    // clustered, so the next bound is found by expanding from the near end of
+    // what remains rather than bisecting all of it. On a dense index this makes
+    // iterating an AND filter roughly 5x faster ascending and 7x descending.
+    pub(crate) fn search<S: Storage>(
         &mut self,
         storage: &S,
+        cache: &EventContextCache,
         mut entry: Timestamp,
         order: Order,
         bound: Timestamp,
@@ -218,7 +297,7 @@ impl IndexedEventFilter<'_> {
         match self {
             IndexedEventFilter::Single(entries, filter) => match order {
                 Order::Asc => loop {
-                    let idx = entries.lower_bound(&entry);
+                    let idx = entries.lower_bound_via_expansion(&entry);
                     *entries = &entries[idx..];
                     let found_entry = entries.first().cloned();
 
@@ -228,7 +307,7 @@ impl IndexedEventFilter<'_> {
                     }
 
                     if let Some(filter) = filter {
-                        if filter.matches(EventContext::new(found_entry, storage)) {
+                        if filter.matches(EventContext::with_cache(found_entry, storage, cache)) {
                             return Some(found_entry);
                         } else {
                             entry = found_entry.saturating_add(1);
@@ -238,7 +317,7 @@ impl IndexedEventFilter<'_> {
                     }
                 },
                 Order::Desc => loop {
-                    let idx = entries.upper_bound(&entry);
+                    let idx = entries.upper_bound_via_expansion(&entry);
                     *entries = &entries[..idx];
                     let found_entry = entries.last().cloned();
 
@@ -248,7 +327,7 @@ impl IndexedEventFilter<'_> {
                     }
 
                     if let Some(filter) = filter {
-                        if filter.matches(EventContext::new(found_entry, storage)) {
+                        if filter.matches(EventContext::with_cache(found_entry, storage, cache)) {
                             return Some(found_entry);
                         } else {
                             entry = Timestamp::new(found_entry.get() - 1).unwrap();
@@ -260,7 +339,7 @@ impl IndexedEventFilter<'_> {
             },
             IndexedEventFilter::Not(entries, filter) => match order {
                 Order::Asc => loop {
-                    let idx = entries.lower_bound(&entry);
+                    let idx = entries.lower_bound_via_expansion(&entry);
                     *entries = &entries[idx..];
                     let found_entry = entries.first().cloned();
 
@@ -269,7 +348,8 @@ impl IndexedEventFilter<'_> {
                         return None;
                     }
 
-                    let nested_entry = filter.search(storage, found_entry, order, found_entry);
+                    let nested_entry =
+                        filter.search(storage, cache, found_entry, order, found_entry);
 
                     if nested_entry != Some(found_entry) {
                         return Some(found_entry);
@@ -278,7 +358,7 @@ impl IndexedEventFilter<'_> {
                     }
                 },
                 Order::Desc => loop {
-                    let idx = entries.upper_bound(&entry);
+                    let idx = entries.upper_bound_via_expansion(&entry);
                     *entries = &entries[..idx];
                     let found_entry = entries.last().cloned();
 
@@ -287,7 +367,8 @@ impl IndexedEventFilter<'_> {
                         return None;
                     }
 
-                    let nested_entry = filter.search(storage, found_entry, order, found_entry);
+                    let nested_entry =
+                        filter.search(storage, cache, found_entry, order, found_entry);
 
                     if nested_entry != Some(found_entry) {
                         return Some(found_entry);
@@ -299,10 +380,10 @@ impl IndexedEventFilter<'_> {
             IndexedEventFilter::And(indexed_filters) => {
                 let mut current = entry;
                 'outer: loop {
-                    current = indexed_filters[0].search(storage, current, order, bound)?;
+                    current = indexed_filters[0].search(storage, cache, current, order, bound)?;
 
                     for indexed_filter in &mut indexed_filters[1..] {
-                        match indexed_filter.search(storage, current, order, current) {
+                        match indexed_filter.search(storage, cache, current, order, current) {
                             Some(found_entry) if found_entry != current => {
                                 current = found_entry;
                                 continue 'outer;
@@ -324,10 +405,12 @@ impl IndexedEventFilter<'_> {
                 }
             }
             IndexedEventFilter::Or(indexed_filters) => {
-                let mut next_entry = indexed_filters[0].search(storage, entry, order, bound);
+                let mut next_entry = indexed_filters[0].search(storage, cache, entry, order, bound);
                 for indexed_filter in &mut indexed_filters[1..] {
                     let bound = next_entry.unwrap_or(bound);
-                    if let Some(found_entry) = indexed_filter.search(storage, entry, order, bound) {
+                    if let Some(found_entry) =
+                        indexed_filter.search(storage, cache, entry, order, bound)
+                    {
                         if let Some(next_entry) = &mut next_entry {
                             match order {
                                 Order::Asc if *next_entry > found_entry => {
@@ -422,7 +505,7 @@ impl IndexedEventFilter<'_> {
         }
     }
 
-    pub fn trim_to_timeframe(&mut self, start: Timestamp, end: Timestamp) {
+    pub(crate) fn trim_to_timeframe(&mut self, start: Timestamp, end: Timestamp) {
         match self {
             IndexedEventFilter::Single(index, _) => {
                 let start_idx = index.lower_bound(&start);
@@ -447,7 +530,7 @@ impl IndexedEventFilter<'_> {
         }
     }
 
-    pub fn optimize(&mut self) {
+    pub(crate) fn optimize(&mut self) {
         match self {
             IndexedEventFilter::Single(_, _) => { /* nothing to do */ }
             IndexedEventFilter::Not(_, _) => { /* nothing to do */ }
@@ -455,6 +538,42 @@ impl IndexedEventFilter<'_> {
             IndexedEventFilter::Or(filters) => filters.sort_by_key(Self::estimate_count),
         }
     }
+
+    pub(crate) fn explain(&self) -> QueryPlanView {
+        let estimate_count = self.estimate_count();
+        match self {
+            IndexedEventFilter::Single(_, filter) => QueryPlanView {
+                node: "index".to_owned(),
+                estimate_count,
+                indexed: filter.is_none(),
+                scan: filter.as_ref().map(NonIndexedEventFilter::describe),
+                children: Vec::new(),
+            },
+            IndexedEventFilter::Not(_, filter) => {
+                let child = filter.explain();
+                QueryPlanView {
+                    node: "not".to_owned(),
+                    estimate_count,
+                    indexed: child.indexed,
+                    scan: None,
+                    children: vec![child],
+                }
+            }
+            IndexedEventFilter::And(filters) | IndexedEventFilter::Or(filters) => {
+                let children = filters.iter().map(Self::explain).collect::<Vec<_>>();
+                QueryPlanView {
+                    node: match self {
+                        IndexedEventFilter::And(_) => "and".to_owned(),
+                        _ => "or".to_owned(),
+                    },
+                    estimate_count,
+                    indexed: children.iter().all(|child| child.indexed),
+                    scan: None,
+                    children,
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -476,12 +595,21 @@ pub enum InputError {
     InvalidParentOperator,
     InvalidStackValue,
     InvalidStackOperator,
+    InvalidAncestorsValue,
+    InvalidAncestorsOperator,
     InvalidConnectedValue,
     InvalidDisconnectedValue,
+    InvalidSchemaValue,
+    InvalidRootValue,
+    InvalidListValue,
+    InvalidCaseInsensitiveValue,
     InvalidWildcardValue,
     InvalidRegexValue,
     InvalidFileOperator,
     InvalidFileValue,
+    InvalidLineValue,
+    InvalidSearchValue,
+    InvalidSearchOperator,
 }
 
 impl Display for InputError {
@@ -504,12 +632,26 @@ impl Display for InputError {
             InputError::InvalidParentOperator => write!(f, "invalid #parent operator"),
             InputError::InvalidStackValue => write!(f, "invalid #stack value"),
             InputError::InvalidStackOperator => write!(f, "invalid #stack operator"),
+            InputError::InvalidAncestorsValue => write!(f, "invalid #ancestors value"),
+            InputError::InvalidAncestorsOperator => write!(f, "invalid #ancestors operator"),
             InputError::InvalidConnectedValue => write!(f, "invalid #connected value"),
             InputError::InvalidDisconnectedValue => write!(f, "invalid #disconnected value"),
+            InputError::InvalidSchemaValue => write!(f, "invalid #schema value"),
+            InputError::InvalidRootValue => write!(f, "invalid #root value"),
+            InputError::InvalidListValue => write!(f, "invalid list value"),
+            InputError::InvalidCaseInsensitiveValue => {
+                write!(
+                    f,
+                    "case-insensitive values are only valid for '@' properties"
+                )
+            }
             InputError::InvalidWildcardValue => write!(f, "invalid wildcard syntax"),
             InputError::InvalidRegexValue => write!(f, "invalid regex syntax"),
             InputError::InvalidFileOperator => write!(f, "invalid #file operator"),
             InputError::InvalidFileValue => write!(f, "invalid #file value"),
+            InputError::InvalidLineValue => write!(f, "invalid #line value"),
+            InputError::InvalidSearchValue => write!(f, "invalid #search value"),
+            InputError::InvalidSearchOperator => write!(f, "invalid #search operator"),
         }
     }
 }
@@ -536,16 +678,28 @@ impl FileFilter {
     }
 }
 
+fn line_matches(op: ValueOperator, file_line: Option<u32>, line: u32) -> bool {
+    match file_line {
+        Some(file_line) => op.compare(file_line, line),
+        None => false, // entities without a line cannot match a #line
+    }
+}
+
 pub enum BasicEventFilter {
     Timestamp(ValueOperator, Timestamp),
     Level(Level),
     Connection(ConnectionKey),
     Target(ValueStringComparison),
     File(FileFilter),
+    Line(ValueOperator, u32),
     Ancestor(SpanKey),
     Root,
     Parent(SpanKey),
     Attribute(String, ValueFilter),
+    AttributeExists(String),
+    AttributeContains(String, String),
+    AttributeCaseInsensitive(String, String),
+    FullText(String),
     Not(Box<BasicEventFilter>),
     And(Vec<BasicEventFilter>),
     Or(Vec<BasicEventFilter>),
@@ -559,10 +713,15 @@ impl BasicEventFilter {
             BasicEventFilter::Connection(_) => {}
             BasicEventFilter::Target(_) => {}
             BasicEventFilter::File(_) => {}
+            BasicEventFilter::Line(_, _) => {}
             BasicEventFilter::Ancestor(_) => {}
             BasicEventFilter::Root => {}
             BasicEventFilter::Parent(_) => {}
             BasicEventFilter::Attribute(_, _) => {}
+            BasicEventFilter::AttributeExists(_) => {}
+            BasicEventFilter::AttributeContains(_, _) => {}
+            BasicEventFilter::FullText(_) => {}
+            BasicEventFilter::AttributeCaseInsensitive(_, _) => {}
             BasicEventFilter::Not(_) => {}
             BasicEventFilter::And(filters) => {
                 for filter in &mut *filters {
@@ -591,7 +750,6 @@ impl BasicEventFilter {
 
     pub fn validate(predicate: FilterPredicate) -> Result<FallibleFilterPredicate, InputError> {
         use FilterPropertyKind::*;
-        use ValueOperator::*;
 
         let predicate = match predicate {
             FilterPredicate::Single(single) => single,
@@ -616,7 +774,8 @@ impl BasicEventFilter {
         let property_kind = predicate
             .property_kind
             .unwrap_or(match predicate.property.as_str() {
-                "level" | "connection" | "parent" | "target" | "file" | "stack" => Inherent,
+                "level" | "connection" | "parent" | "root" | "target" | "file" | "line"
+                | "stack" | "search" => Inherent,
                 _ => Attribute,
             });
 
@@ -627,20 +786,7 @@ impl BasicEventFilter {
                     _ => return Err(InputError::InvalidLevelValue),
                 };
 
-                let _level = match value.as_str() {
-                    "TRACE" => Level::Trace,
-                    "DEBUG" => Level::Debug,
-                    "INFO" => Level::Info,
-                    "WARN" => Level::Warn,
-                    "ERROR" => Level::Error,
-                    _ => return Err(InputError::InvalidLevelValue),
-                };
-
-                let _above = match op {
-                    Eq => false,
-                    Gte => true,
-                    _ => return Err(InputError::InvalidLevelOperator),
-                };
+                let _levels = parse_level_range(op, value)?;
             }
             (Inherent, "connection") => {
                 validate_value_predicate(
@@ -658,6 +804,7 @@ impl BasicEventFilter {
                     },
                     |_| Err(InputError::InvalidConnectionValue),
                     |_| Err(InputError::InvalidConnectionValue),
+                    |_| Err(InputError::InvalidCaseInsensitiveValue),
                 )?;
             }
             (Inherent, "parent") => {
@@ -677,8 +824,24 @@ impl BasicEventFilter {
                     },
                     |_| Err(InputError::InvalidParentValue),
                     |_| Err(InputError::InvalidParentValue),
+                    |_| Err(InputError::InvalidCaseInsensitiveValue),
                 )?;
             }
+            (Inherent, "root") => validate_value_predicate(
+                &predicate.value,
+                |op, value| {
+                    if *op != ValueOperator::Eq {
+                        return Err(InputError::InvalidRootValue);
+                    }
+
+                    let _: bool = value.parse().map_err(|_| InputError::InvalidRootValue)?;
+
+                    Ok(())
+                },
+                |_| Err(InputError::InvalidRootValue),
+                |_| Err(InputError::InvalidRootValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
+            )?,
             (Inherent, "target") => validate_value_predicate(
                 &predicate.value,
                 |_op, _value| Ok(()),
@@ -693,6 +856,7 @@ impl BasicEventFilter {
                     Regex::new(regex).map_err(|_| InputError::InvalidRegexValue)?;
                     Ok(())
                 },
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "file") => validate_value_predicate(
                 &predicate.value,
@@ -728,6 +892,30 @@ impl BasicEventFilter {
                     Regex::new(regex).map_err(|_| InputError::InvalidRegexValue)?;
                     Ok(())
                 },
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
+            )?,
+            (Inherent, "line") => validate_value_predicate(
+                &predicate.value,
+                |_op, value| {
+                    let _: u32 = value.parse().map_err(|_| InputError::InvalidLineValue)?;
+                    Ok(())
+                },
+                |_| Err(InputError::InvalidLineValue),
+                |_| Err(InputError::InvalidLineValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
+            )?,
+            (Inherent, "search") => validate_value_predicate(
+                &predicate.value,
+                |op, _value| {
+                    if *op != ValueOperator::Eq {
+                        return Err(InputError::InvalidSearchOperator);
+                    }
+
+                    Ok(())
+                },
+                |_| Err(InputError::InvalidSearchValue),
+                |_| Err(InputError::InvalidSearchValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "stack") => {
                 validate_value_predicate(
@@ -743,6 +931,7 @@ impl BasicEventFilter {
                     },
                     |_| Err(InputError::InvalidStackValue),
                     |_| Err(InputError::InvalidStackValue),
+                    |_| Err(InputError::InvalidCaseInsensitiveValue),
                 )?;
             }
             (Inherent, _) => {
@@ -763,6 +952,7 @@ impl BasicEventFilter {
                         Regex::new(regex).map_err(|_| InputError::InvalidRegexValue)?;
                         Ok(())
                     },
+                    |_| Ok(()),
                 )?;
             }
         };
@@ -779,7 +969,6 @@ impl BasicEventFilter {
         span_key_map: &HashMap<(ConnectionKey, SpanId), SpanKey>,
     ) -> Result<BasicEventFilter, InputError> {
         use FilterPropertyKind::*;
-        use ValueOperator::*;
 
         let predicate = match predicate {
             FilterPredicate::Single(single) => single,
@@ -802,7 +991,8 @@ impl BasicEventFilter {
         let property_kind = predicate
             .property_kind
             .unwrap_or(match predicate.property.as_str() {
-                "level" | "connection" | "parent" | "target" | "stack" => Inherent,
+                "level" | "connection" | "parent" | "root" | "target" | "file" | "line"
+                | "stack" | "search" => Inherent,
                 _ => Attribute,
             });
 
@@ -813,29 +1003,19 @@ impl BasicEventFilter {
                     _ => return Err(InputError::InvalidLevelValue),
                 };
 
-                let level = match value.as_str() {
-                    "TRACE" => Level::Trace,
-                    "DEBUG" => Level::Debug,
-                    "INFO" => Level::Info,
-                    "WARN" => Level::Warn,
-                    "ERROR" => Level::Error,
-                    _ => return Err(InputError::InvalidLevelValue),
-                };
-
-                let above = match op {
-                    Eq => false,
-                    Gte => true,
-                    _ => return Err(InputError::InvalidLevelOperator),
-                };
+                // the levels are expanded to an `Or` of each individual level
+                // in the range, so `<=INFO` is TRACE|DEBUG|INFO, `>=INFO` is
+                // INFO|WARN|ERROR, and `DEBUG..WARN` is DEBUG|INFO|WARN
+                let levels = parse_level_range(op, value)?;
 
-                if above {
+                if levels.start() == levels.end() {
+                    BasicEventFilter::Level(*levels.start())
+                } else {
                     BasicEventFilter::Or(
-                        ((level as i32)..5)
+                        ((*levels.start() as i32)..=(*levels.end() as i32))
                             .map(|l| BasicEventFilter::Level(l.try_into().unwrap()))
                             .collect(),
                     )
-                } else {
-                    BasicEventFilter::Level(level)
                 }
             }
             (Inherent, "connection") => filterify_event_filter(
@@ -858,6 +1038,7 @@ impl BasicEventFilter {
                 },
                 |_| Err(InputError::InvalidConnectionValue),
                 |_| Err(InputError::InvalidConnectionValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "parent") => filterify_event_filter(
                 predicate.value,
@@ -885,8 +1066,28 @@ impl BasicEventFilter {
                         Ok(BasicEventFilter::Parent(parent_key))
                     }
                 },
-                |_| Err(InputError::InvalidConnectionValue),
-                |_| Err(InputError::InvalidConnectionValue),
+                |_| Err(InputError::InvalidParentValue),
+                |_| Err(InputError::InvalidParentValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
+            )?,
+            (Inherent, "root") => filterify_event_filter(
+                predicate.value,
+                |op, value| {
+                    if op != ValueOperator::Eq {
+                        return Err(InputError::InvalidRootValue);
+                    }
+
+                    let root: bool = value.parse().map_err(|_| InputError::InvalidRootValue)?;
+
+                    if root {
+                        Ok(BasicEventFilter::Root)
+                    } else {
+                        Ok(BasicEventFilter::Not(Box::new(BasicEventFilter::Root)))
+                    }
+                },
+                |_| Err(InputError::InvalidRootValue),
+                |_| Err(InputError::InvalidRootValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "target") => filterify_event_filter(
                 predicate.value,
@@ -909,6 +1110,7 @@ impl BasicEventFilter {
                     let filter = ValueStringComparison::Regex(regex);
                     Ok(BasicEventFilter::Target(filter))
                 },
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "file") => filterify_event_filter(
                 predicate.value,
@@ -974,6 +1176,30 @@ impl BasicEventFilter {
                         line: None,
                     }))
                 },
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
+            )?,
+            (Inherent, "line") => filterify_event_filter(
+                predicate.value,
+                |op, value| {
+                    let line: u32 = value.parse().map_err(|_| InputError::InvalidLineValue)?;
+                    Ok(BasicEventFilter::Line(op, line))
+                },
+                |_| Err(InputError::InvalidLineValue),
+                |_| Err(InputError::InvalidLineValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
+            )?,
+            (Inherent, "search") => filterify_event_filter(
+                predicate.value,
+                |op, value| {
+                    if op != ValueOperator::Eq {
+                        return Err(InputError::InvalidSearchOperator);
+                    }
+
+                    Ok(BasicEventFilter::FullText(value))
+                },
+                |_| Err(InputError::InvalidSearchValue),
+                |_| Err(InputError::InvalidSearchValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "stack") => filterify_event_filter(
                 predicate.value,
@@ -998,6 +1224,7 @@ impl BasicEventFilter {
                 },
                 |_| Err(InputError::InvalidStackValue),
                 |_| Err(InputError::InvalidStackValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, _) => {
                 return Err(InputError::InvalidInherentProperty);
@@ -1009,6 +1236,18 @@ impl BasicEventFilter {
                     Ok(BasicEventFilter::Attribute(name.to_owned(), value_filter))
                 },
                 |wildcard| {
+                    // a bare `*` only checks that the attribute exists
+                    if wildcard == "*" {
+                        return Ok(BasicEventFilter::AttributeExists(name.to_owned()));
+                    }
+
+                    if let Some(value) = wildcard_to_contains(&wildcard) {
+                        return Ok(BasicEventFilter::AttributeContains(
+                            name.to_owned(),
+                            value.to_owned(),
+                        ));
+                    }
+
                     let value_filter = ValueFilter::from_wildcard(wildcard)?;
                     Ok(BasicEventFilter::Attribute(name.to_owned(), value_filter))
                 },
@@ -1016,6 +1255,12 @@ impl BasicEventFilter {
                     let value_filter = ValueFilter::from_regex(regex)?;
                     Ok(BasicEventFilter::Attribute(name.to_owned(), value_filter))
                 },
+                |value| {
+                    Ok(BasicEventFilter::AttributeCaseInsensitive(
+                        name.to_owned(),
+                        value,
+                    ))
+                },
             )?,
         };
 
@@ -1032,6 +1277,7 @@ impl BasicEventFilter {
             BasicEventFilter::File(filter) => {
                 filter.matches(event.file_name.as_deref(), event.file_line)
             }
+            BasicEventFilter::Line(op, line) => line_matches(*op, event.file_line, *line),
             BasicEventFilter::Ancestor(span_key) => context.parents().any(|p| p.key() == *span_key),
             BasicEventFilter::Root => event.span_key.is_none(),
             BasicEventFilter::Parent(parent_key) => event.span_key == Some(*parent_key),
@@ -1039,6 +1285,14 @@ impl BasicEventFilter {
                 .attribute(attribute)
                 .map(|v| value_filter.matches(v))
                 .unwrap_or(false),
+            BasicEventFilter::AttributeExists(attribute) => context.attribute(attribute).is_some(),
+            BasicEventFilter::AttributeContains(attribute, value) => {
+                attribute_contains(context.attribute(attribute), value)
+            }
+            BasicEventFilter::AttributeCaseInsensitive(attribute, value) => {
+                attribute_eq_ignore_case(context.attribute(attribute), value)
+            }
+            BasicEventFilter::FullText(text) => full_text_matches(context, text),
             BasicEventFilter::Not(inner_filter) => !inner_filter.matches(context),
             BasicEventFilter::And(filters) => filters.iter().all(|f| f.matches(context)),
             BasicEventFilter::Or(filters) => filters.iter().any(|f| f.matches(context)),
@@ -1046,14 +1300,33 @@ impl BasicEventFilter {
     }
 }
 
-pub enum NonIndexedEventFilter {
+pub(crate) enum NonIndexedEventFilter {
     Parent(SpanKey),
     Target(ValueStringComparison),
     File(FileFilter),
+    Line(ValueOperator, u32),
     Attribute(String, Box<ValueFilter>),
+    AttributeContains(String, String),
+    AttributeCaseInsensitive(String, String),
+    FullText(String),
 }
 
 impl NonIndexedEventFilter {
+    fn describe(&self) -> String {
+        match self {
+            NonIndexedEventFilter::Parent(_) => "#parent".to_owned(),
+            NonIndexedEventFilter::Target(_) => "#target".to_owned(),
+            NonIndexedEventFilter::File(_) => "#file".to_owned(),
+            NonIndexedEventFilter::Line(_, _) => "#line".to_owned(),
+            NonIndexedEventFilter::Attribute(name, _) => format!("@{name}"),
+            NonIndexedEventFilter::AttributeContains(name, _) => format!("@{name} contains"),
+            NonIndexedEventFilter::AttributeCaseInsensitive(name, _) => {
+                format!("@{name} case-insensitive")
+            }
+            NonIndexedEventFilter::FullText(_) => "#search".to_owned(),
+        }
+    }
+
     fn matches<S: Storage>(&self, context: EventContext<'_, S>) -> bool {
         let event = context.event();
         match self {
@@ -1062,24 +1335,33 @@ impl NonIndexedEventFilter {
             NonIndexedEventFilter::File(filter) => {
                 filter.matches(event.file_name.as_deref(), event.file_line)
             }
+            NonIndexedEventFilter::Line(op, line) => line_matches(*op, event.file_line, *line),
             NonIndexedEventFilter::Attribute(attribute, value_filter) => context
-                .attribute(attribute)
-                .map(|v| value_filter.matches(v))
-                .unwrap_or(false),
+                .with_attribute(attribute, |v| {
+                    v.map(|v| value_filter.matches(v)).unwrap_or(false)
+                }),
+            NonIndexedEventFilter::AttributeContains(attribute, value) => {
+                context.with_attribute(attribute, |v| attribute_contains(v, value))
+            }
+            NonIndexedEventFilter::AttributeCaseInsensitive(attribute, value) => {
+                context.with_attribute(attribute, |v| attribute_eq_ignore_case(v, value))
+            }
+            NonIndexedEventFilter::FullText(text) => full_text_matches(&context, text),
         }
     }
 }
 
-pub struct IndexedEventFilterIterator<'i, S> {
+pub(crate) struct IndexedEventFilterIterator<'i, S> {
     filter: IndexedEventFilter<'i>,
     order: Order,
     start_key: Timestamp,
     end_key: Timestamp,
     storage: &'i S,
+    cache: EventContextCache,
 }
 
 impl<'i, S> IndexedEventFilterIterator<'i, S> {
-    pub fn new(query: Query, engine: &'i RawEngine<S>) -> IndexedEventFilterIterator<'i, S> {
+    pub(crate) fn new(query: Query, engine: &'i RawEngine<S>) -> IndexedEventFilterIterator<'i, S> {
         let mut filter = BasicEventFilter::And(
             query
                 .filter
@@ -1122,10 +1404,15 @@ impl<'i, S> IndexedEventFilterIterator<'i, S> {
             start_key,
             end_key,
             storage: &engine.storage,
+            cache: EventContextCache::new(),
         }
     }
 
-    pub fn new_internal(
+    pub(crate) fn explain(&self) -> QueryPlanView {
+        self.filter.explain()
+    }
+
+    pub(crate) fn new_internal(
         filter: IndexedEventFilter<'i>,
         engine: &'i RawEngine<S>,
     ) -> IndexedEventFilterIterator<'i, S> {
@@ -1135,6 +1422,7 @@ impl<'i, S> IndexedEventFilterIterator<'i, S> {
             end_key: Timestamp::MAX,
             start_key: Timestamp::MIN,
             storage: &engine.storage,
+            cache: EventContextCache::new(),
         }
     }
 }
@@ -1146,9 +1434,13 @@ where
     type Item = EventKey;
 
     fn next(&mut self) -> Option<EventKey> {
-        let event_key =
-            self.filter
-                .search(self.storage, self.start_key, self.order, self.end_key)?;
+        let event_key = self.filter.search(
+            self.storage,
+            &self.cache,
+            self.start_key,
+            self.order,
+            self.end_key,
+        )?;
 
         match self.order {
             Order::Asc => self.start_key = event_key.saturating_add(1),
@@ -1163,7 +1455,7 @@ where
     }
 }
 
-pub enum IndexedSpanFilter<'i> {
+pub(crate) enum IndexedSpanFilter<'i> {
     Single(&'i [Timestamp], Option<NonIndexedSpanFilter>),
     Stratified(&'i [Timestamp], Range<u64>, Option<NonIndexedSpanFilter>),
     Not(&'i [Timestamp], Box<IndexedSpanFilter<'i>>),
@@ -1172,7 +1464,7 @@ pub enum IndexedSpanFilter<'i> {
 }
 
 impl IndexedSpanFilter<'_> {
-    pub fn build(
+    pub(crate) fn build(
         filter: Option<BasicSpanFilter>,
         span_indexes: &SpanIndexes,
     ) -> IndexedSpanFilter<'_> {
@@ -1203,6 +1495,10 @@ impl IndexedSpanFilter<'_> {
 
                 IndexedSpanFilter::Or(filters)
             }
+            BasicSpanFilter::Busy(busy_filter) => IndexedSpanFilter::Single(
+                &span_indexes.all,
+                Some(NonIndexedSpanFilter::Busy(busy_filter, now())),
+            ),
             BasicSpanFilter::Created(op, value) => match op {
                 ValueOperator::Gt => {
                     let idx = span_indexes.all.upper_bound(&value);
@@ -1227,8 +1523,10 @@ impl IndexedSpanFilter<'_> {
                 }
             },
             BasicSpanFilter::Closed(op, value) => {
-                let filters = span_indexes.durations.to_stratified_indexes();
-                let filters = filters
+                // the open index is handled separately below
+                let mut filters: Vec<_> = span_indexes
+                    .durations
+                    .to_stratified_closed_indexes()
                     .into_iter()
                     .map(|(index, range)| {
                         match op {
@@ -1284,6 +1582,18 @@ impl IndexedSpanFilter<'_> {
                     })
                     .collect();
 
+                // an open span will close some time after now, so it is
+                // considered to be after any timestamp and never before
+                match op {
+                    ValueOperator::Gt | ValueOperator::Gte => {
+                        filters.push(IndexedSpanFilter::Single(
+                            &span_indexes.durations.open,
+                            None,
+                        ));
+                    }
+                    ValueOperator::Eq | ValueOperator::Lt | ValueOperator::Lte => {}
+                }
+
                 IndexedSpanFilter::Or(filters)
             }
             BasicSpanFilter::Connection(connection_key) => {
@@ -1323,6 +1633,20 @@ impl IndexedSpanFilter<'_> {
                     Some(NonIndexedSpanFilter::Name(filter)),
                 ),
             },
+            BasicSpanFilter::NamePrefix(prefix) => {
+                let filters = span_indexes
+                    .names
+                    .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+                    .take_while(|(name, _)| name.starts_with(&prefix))
+                    .map(|(_, name_index)| IndexedSpanFilter::Single(name_index, None))
+                    .collect::<Vec<_>>();
+
+                if filters.is_empty() {
+                    IndexedSpanFilter::Single(&[], None)
+                } else {
+                    IndexedSpanFilter::Or(filters)
+                }
+            }
             BasicSpanFilter::Target(filter) => match filter {
                 ValueStringComparison::None => IndexedSpanFilter::Single(&[], None),
                 ValueStringComparison::Compare(ValueOperator::Eq, value) => {
@@ -1334,18 +1658,24 @@ impl IndexedSpanFilter<'_> {
 
                     IndexedSpanFilter::Single(target_index, None)
                 }
-                ValueStringComparison::Compare(_, _) => IndexedSpanFilter::Single(
-                    &span_indexes.all,
-                    Some(NonIndexedSpanFilter::Target(filter)),
-                ),
-                ValueStringComparison::Wildcard(_) => IndexedSpanFilter::Single(
-                    &span_indexes.all,
-                    Some(NonIndexedSpanFilter::Target(filter)),
-                ),
-                ValueStringComparison::Regex(_) => IndexedSpanFilter::Single(
-                    &span_indexes.all,
-                    Some(NonIndexedSpanFilter::Target(filter)),
-                ),
+                ValueStringComparison::Compare(_, _)
+                | ValueStringComparison::Wildcard(_)
+                | ValueStringComparison::Regex(_) => {
+                    // there are few distinct targets, so matching against the
+                    // index keys is much cheaper than checking every entry
+                    let filters = span_indexes
+                        .targets
+                        .iter()
+                        .filter(|(target, _)| filter.matches(target))
+                        .map(|(_, target_index)| IndexedSpanFilter::Single(target_index, None))
+                        .collect::<Vec<_>>();
+
+                    if filters.is_empty() {
+                        IndexedSpanFilter::Single(&[], None)
+                    } else {
+                        IndexedSpanFilter::Or(filters)
+                    }
+                }
                 ValueStringComparison::All => IndexedSpanFilter::Single(
                     &span_indexes.all,
                     Some(NonIndexedSpanFilter::Target(filter)),
@@ -1382,6 +1712,10 @@ impl IndexedSpanFilter<'_> {
                     Some(NonIndexedSpanFilter::File(filter)),
                 ),
             },
+            BasicSpanFilter::Line(op, line) => IndexedSpanFilter::Single(
+                &span_indexes.all,
+                Some(NonIndexedSpanFilter::Line(op, line)),
+            ),
             BasicSpanFilter::Ancestor(ancestor_key) => {
                 let index = span_indexes
                     .descendents
@@ -1391,15 +1725,27 @@ impl IndexedSpanFilter<'_> {
 
                 IndexedSpanFilter::Single(index, None)
             }
+            BasicSpanFilter::AncestorOf(span_key) => {
+                // ancestors are always created before their descendents
+                let idx = span_indexes.all.lower_bound(&span_key);
+                if span_indexes.all.get(idx) != Some(&span_key) {
+                    return IndexedSpanFilter::Single(&[], None);
+                }
+
+                IndexedSpanFilter::Single(
+                    &span_indexes.all[..idx],
+                    Some(NonIndexedSpanFilter::AncestorOf(span_key)),
+                )
+            }
             BasicSpanFilter::Root => IndexedSpanFilter::Single(&span_indexes.roots, None),
             BasicSpanFilter::Parent(parent_key) => {
                 let index = span_indexes
-                    .descendents
+                    .children
                     .get(&parent_key)
                     .map(Vec::as_slice)
                     .unwrap_or_default();
 
-                IndexedSpanFilter::Single(index, Some(NonIndexedSpanFilter::Parent(parent_key)))
+                IndexedSpanFilter::Single(index, None)
             }
             BasicSpanFilter::Attribute(attribute, value_filter) => {
                 if let Some(attr_index) = span_indexes.attributes.get(&attribute) {
@@ -1421,10 +1767,39 @@ impl IndexedSpanFilter<'_> {
                     IndexedSpanFilter::Single(&[], None)
                 }
             }
+            BasicSpanFilter::AttributeContains(attribute, _)
+            | BasicSpanFilter::AttributeCaseInsensitive(attribute, _)
+                if !span_indexes.attributes.contains_key(&attribute) =>
+            {
+                // an attribute that was never seen can't match, which avoids a
+                // full scan for a misspelled attribute
+                IndexedSpanFilter::Single(&[], None)
+            }
+            BasicSpanFilter::AttributeContains(attribute, value) => {
+                // substrings can't be looked up in the attribute index, so this
+                // is a full scan
+                IndexedSpanFilter::Single(
+                    &span_indexes.all,
+                    Some(NonIndexedSpanFilter::AttributeContains(attribute, value)),
+                )
+            }
+            BasicSpanFilter::AttributeCaseInsensitive(attribute, value) => {
+                // the attribute index is case-sensitive, so this is a full scan
+                IndexedSpanFilter::Single(
+                    &span_indexes.all,
+                    Some(NonIndexedSpanFilter::AttributeCaseInsensitive(
+                        attribute, value,
+                    )),
+                )
+            }
             BasicSpanFilter::Not(filter) => IndexedSpanFilter::Not(
                 &span_indexes.all,
                 Box::new(IndexedSpanFilter::build(Some(*filter), span_indexes)),
             ),
+            // an empty filter places no restrictions
+            BasicSpanFilter::And(filters) if filters.is_empty() => {
+                IndexedSpanFilter::Single(&span_indexes.all, None)
+            }
             BasicSpanFilter::And(filters) => IndexedSpanFilter::And(
                 filters
                     .into_iter()
@@ -1453,9 +1828,10 @@ impl IndexedSpanFilter<'_> {
         }
     }
 
-    // This searches for an entry equal to or beyond the provided entry
+    // This searches for an entry equal to or beyond the provided entry. See
+    // `IndexedEventFilter::search` for why the bounds are found via expansion.
     #[allow(clippy::too_many_arguments)]
-    pub fn search<S: Storage>(
+    pub(crate) fn search<S: Storage>(
         &mut self,
         storage: &S,
         mut entry: Timestamp, // this is the current lower bound for span keys
@@ -1467,7 +1843,7 @@ impl IndexedSpanFilter<'_> {
         match self {
             IndexedSpanFilter::Single(entries, filter) => match order {
                 Order::Asc => loop {
-                    let idx = entries.lower_bound(&entry);
+                    let idx = entries.lower_bound_via_expansion(&entry);
                     *entries = &entries[idx..];
                     let found_entry = entries.first().cloned();
 
@@ -1477,12 +1853,18 @@ impl IndexedSpanFilter<'_> {
                     }
 
                     if found_entry < start {
-                        let span = storage.get_span(found_entry).unwrap();
-                        if let Some(closed_at) = span.closed_at {
-                            if closed_at <= start {
-                                entry = found_entry.saturating_add(1);
-                                continue;
+                        // spans that closed before the start are skipped, as are
+                        // any that are missing from storage
+                        let skip = match storage.get_span(found_entry) {
+                            Some(span) => {
+                                span.closed_at.is_some_and(|closed_at| closed_at <= start)
                             }
+                            None => true,
+                        };
+
+                        if skip {
+                            entry = found_entry.saturating_add(1);
+                            continue;
                         }
                     }
 
@@ -1497,7 +1879,7 @@ impl IndexedSpanFilter<'_> {
                     }
                 },
                 Order::Desc => loop {
-                    let idx = entries.upper_bound(&entry);
+                    let idx = entries.upper_bound_via_expansion(&entry);
                     *entries = &entries[..idx];
                     let found_entry = entries.last().cloned();
 
@@ -1507,12 +1889,18 @@ impl IndexedSpanFilter<'_> {
                     }
 
                     if found_entry < start {
-                        let span = storage.get_span(found_entry).unwrap();
-                        if let Some(closed_at) = span.closed_at {
-                            if closed_at <= start {
-                                entry = Timestamp::new(found_entry.get() - 1).unwrap();
-                                continue;
+                        // spans that closed before the start are skipped, as are
+                        // any that are missing from storage
+                        let skip = match storage.get_span(found_entry) {
+                            Some(span) => {
+                                span.closed_at.is_some_and(|closed_at| closed_at <= start)
                             }
+                            None => true,
+                        };
+
+                        if skip {
+                            entry = Timestamp::new(found_entry.get() - 1).unwrap();
+                            continue;
                         }
                     }
 
@@ -1529,7 +1917,7 @@ impl IndexedSpanFilter<'_> {
             },
             IndexedSpanFilter::Stratified(entries, _, filter) => match order {
                 Order::Asc => loop {
-                    let idx = entries.lower_bound(&entry);
+                    let idx = entries.lower_bound_via_expansion(&entry);
                     *entries = &entries[idx..];
                     let found_entry = entries.first().cloned();
 
@@ -1539,12 +1927,18 @@ impl IndexedSpanFilter<'_> {
                     }
 
                     if found_entry < start {
-                        let span = storage.get_span(found_entry).unwrap();
-                        if let Some(closed_at) = span.closed_at {
-                            if closed_at <= start {
-                                entry = found_entry.saturating_add(1);
-                                continue;
+                        // spans that closed before the start are skipped, as are
+                        // any that are missing from storage
+                        let skip = match storage.get_span(found_entry) {
+                            Some(span) => {
+                                span.closed_at.is_some_and(|closed_at| closed_at <= start)
                             }
+                            None => true,
+                        };
+
+                        if skip {
+                            entry = found_entry.saturating_add(1);
+                            continue;
                         }
                     }
 
@@ -1559,7 +1953,7 @@ impl IndexedSpanFilter<'_> {
                     }
                 },
                 Order::Desc => loop {
-                    let idx = entries.upper_bound(&entry);
+                    let idx = entries.upper_bound_via_expansion(&entry);
                     *entries = &entries[..idx];
                     let found_entry = entries.last().cloned();
 
@@ -1569,12 +1963,18 @@ impl IndexedSpanFilter<'_> {
                     }
 
                     if found_entry < start {
-                        let span = storage.get_span(found_entry).unwrap();
-                        if let Some(closed_at) = span.closed_at {
-                            if closed_at <= start {
-                                entry = Timestamp::new(found_entry.get() - 1).unwrap();
-                                continue;
+                        // spans that closed before the start are skipped, as are
+                        // any that are missing from storage
+                        let skip = match storage.get_span(found_entry) {
+                            Some(span) => {
+                                span.closed_at.is_some_and(|closed_at| closed_at <= start)
                             }
+                            None => true,
+                        };
+
+                        if skip {
+                            entry = Timestamp::new(found_entry.get() - 1).unwrap();
+                            continue;
                         }
                     }
 
@@ -1591,7 +1991,7 @@ impl IndexedSpanFilter<'_> {
             },
             IndexedSpanFilter::Not(entries, filter) => match order {
                 Order::Asc => loop {
-                    let idx = entries.lower_bound(&entry);
+                    let idx = entries.lower_bound_via_expansion(&entry);
                     *entries = &entries[idx..];
                     let found_entry = entries.first().cloned();
 
@@ -1601,12 +2001,18 @@ impl IndexedSpanFilter<'_> {
                     }
 
                     if found_entry < start {
-                        let span = storage.get_span(found_entry).unwrap();
-                        if let Some(closed_at) = span.closed_at {
-                            if closed_at <= start {
-                                entry = found_entry.saturating_add(1);
-                                continue;
+                        // spans that closed before the start are skipped, as are
+                        // any that are missing from storage
+                        let skip = match storage.get_span(found_entry) {
+                            Some(span) => {
+                                span.closed_at.is_some_and(|closed_at| closed_at <= start)
                             }
+                            None => true,
+                        };
+
+                        if skip {
+                            entry = found_entry.saturating_add(1);
+                            continue;
                         }
                     }
 
@@ -1620,7 +2026,7 @@ impl IndexedSpanFilter<'_> {
                     }
                 },
                 Order::Desc => loop {
-                    let idx = entries.upper_bound(&entry);
+                    let idx = entries.upper_bound_via_expansion(&entry);
                     *entries = &entries[..idx];
                     let found_entry = entries.last().cloned();
 
@@ -1632,12 +2038,18 @@ impl IndexedSpanFilter<'_> {
                     // even if we're negating the filter, the span needs to be
                     // in range
                     if found_entry < start {
-                        let span = storage.get_span(found_entry).unwrap();
-                        if let Some(closed_at) = span.closed_at {
-                            if closed_at <= start {
-                                entry = Timestamp::new(found_entry.get() - 1).unwrap();
-                                continue;
+                        // spans that closed before the start are skipped, as are
+                        // any that are missing from storage
+                        let skip = match storage.get_span(found_entry) {
+                            Some(span) => {
+                                span.closed_at.is_some_and(|closed_at| closed_at <= start)
                             }
+                            None => true,
+                        };
+
+                        if skip {
+                            entry = Timestamp::new(found_entry.get() - 1).unwrap();
+                            continue;
                         }
                     }
 
@@ -1705,6 +2117,149 @@ impl IndexedSpanFilter<'_> {
         }
     }
 
+    // This finds up to `limit` entries by calling `search` repeatedly.
+    #[cfg(feature = "parallel")]
+    #[allow(clippy::too_many_arguments)]
+    fn search_many<S: Storage>(
+        &mut self,
+        storage: &S,
+        entry: Timestamp,
+        order: Order,
+        bound: Timestamp,
+        start: Timestamp,
+        limit: usize,
+    ) -> Vec<Timestamp> {
+        let mut entries = vec![];
+        let mut current = entry;
+        while entries.len() < limit {
+            let Some(found) = self.search(storage, current, order, bound, start) else {
+                break;
+            };
+
+            entries.push(found);
+            current = match order {
+                Order::Asc => found.saturating_add(1),
+                Order::Desc => Timestamp::new(found.get() - 1).unwrap(),
+            };
+        }
+
+        entries
+    }
+
+    // This finds up to `limit` entries like `search_many` except that the
+    // filters of an OR are searched in parallel. A `#duration` filter is an OR
+    // over many stratified indexes that don't depend on each other, so on large
+    // datasets they are better searched at once. This is done for a batch of
+    // entries at a time so the threads are only coordinated once per batch.
+    //
+    // Each filter of an OR finds its own batch, but one that fills its batch
+    // may have more entries before the last ones found by the others, so the
+    // merged entries are only kept up to the earliest (or latest) last entry of
+    // a full batch. Searching narrows the indexes past what was found, so the
+    // filters that found entries beyond that are put back to where they were
+    // to find them again in the next batch. The entries of an AND are those of
+    // its first filter, which is the most selective, that the others match as
+    // well.
+    #[cfg(feature = "parallel")]
+    #[allow(clippy::too_many_arguments)]
+    fn search_parallel<S: ParallelStorage>(
+        &mut self,
+        storage: &S,
+        entry: Timestamp,
+        order: Order,
+        bound: Timestamp,
+        start: Timestamp,
+        limit: usize,
+    ) -> Vec<Timestamp> {
+        match self {
+            IndexedSpanFilter::And(indexed_filters) => {
+                let (first, rest) = indexed_filters.split_first_mut().unwrap();
+
+                let mut entries = vec![];
+                let mut current = entry;
+                loop {
+                    let candidates =
+                        first.search_parallel(storage, current, order, bound, start, limit);
+                    let exhausted = candidates.len() < limit;
+
+                    for &candidate in &candidates {
+                        let matches = rest.iter_mut().all(|indexed_filter| {
+                            indexed_filter.search(storage, candidate, order, candidate, start)
+                                == Some(candidate)
+                        });
+                        if matches {
+                            entries.push(candidate);
+                        }
+                    }
+
+                    // an empty batch would look like there are no more entries
+                    if exhausted || !entries.is_empty() {
+                        break entries;
+                    }
+
+                    let last = *candidates.last().unwrap();
+                    current = match order {
+                        Order::Asc => last.saturating_add(1),
+                        Order::Desc => Timestamp::new(last.get() - 1).unwrap(),
+                    };
+                }
+            }
+            IndexedSpanFilter::Or(indexed_filters) => {
+                let (batches, mut positions): (Vec<_>, Vec<_>) = indexed_filters
+                    .par_iter_mut()
+                    .map(|indexed_filter| {
+                        let mut positions = vec![];
+                        indexed_filter.save_positions(&mut positions);
+                        let batch = indexed_filter
+                            .search_parallel(storage, entry, order, bound, start, limit);
+                        (batch, positions)
+                    })
+                    .unzip();
+
+                let cutoff = batches
+                    .iter()
+                    .filter(|batch| batch.len() == limit)
+                    .filter_map(|batch| batch.last().copied())
+                    .reduce(|a, b| match order {
+                        Order::Asc => Ord::min(a, b),
+                        Order::Desc => Ord::max(a, b),
+                    });
+
+                if let Some(cutoff) = cutoff {
+                    for ((indexed_filter, batch), positions) in
+                        indexed_filters.iter_mut().zip(&batches).zip(&mut positions)
+                    {
+                        let beyond = batch.last().is_some_and(|last| match order {
+                            Order::Asc => *last > cutoff,
+                            Order::Desc => *last < cutoff,
+                        });
+                        if beyond {
+                            indexed_filter.restore_positions(&mut positions.drain(..));
+                        }
+                    }
+                }
+
+                let mut entries = batches
+                    .into_iter()
+                    .flatten()
+                    .filter(|found| match (order, cutoff) {
+                        (_, None) => true,
+                        (Order::Asc, Some(cutoff)) => *found <= cutoff,
+                        (Order::Desc, Some(cutoff)) => *found >= cutoff,
+                    })
+                    .collect::<Vec<_>>();
+
+                match order {
+                    Order::Asc => entries.sort_unstable(),
+                    Order::Desc => entries.sort_unstable_by(|a, b| b.cmp(a)),
+                }
+                entries.dedup();
+                entries
+            }
+            _ => self.search_many(storage, entry, order, bound, start, limit),
+        }
+    }
+
     // This gives an estimate of the number of elements the filter may select.
     // It doesn't use any heuristics but rather returns the theoretical maximum.
     fn estimate_count(&self) -> usize {
@@ -1737,31 +2292,112 @@ impl IndexedSpanFilter<'_> {
         }
     }
 
-    pub fn optimize(&mut self) {
-        match self {
-            IndexedSpanFilter::Single(_, _) => { /* nothing to do */ }
-            IndexedSpanFilter::Stratified(_, _, _) => { /* TODO: convert to AND and sort */ }
-            IndexedSpanFilter::Not(_, inner_filter) => {
-                inner_filter.optimize();
-            }
-            IndexedSpanFilter::And(filters) => filters.sort_by_key(Self::estimate_count),
-            IndexedSpanFilter::Or(filters) => filters.sort_by_key(Self::estimate_count),
-        }
-    }
-
-    pub fn trim_to_timeframe(&mut self, start: Timestamp, end: Timestamp) {
+    fn size_hint(&self) -> (usize, Option<usize>) {
         match self {
             IndexedSpanFilter::Single(index, _) => {
-                // we can trim the end
-                let trim_end = end;
-
-                let end_idx = index.upper_bound(&trim_end);
-
-                *index = &index[..end_idx];
+                // Even without a non-indexed filter, spans created before the
+                // search start are skipped if they closed before it. So the
+                // full range is possible.
+                (0, Some(index.len()))
             }
-            IndexedSpanFilter::Stratified(index, duration_range, _) => {
-                // we can trim to "max duration" before `start`
-                let trim_start = Timestamp::new(start.get().saturating_sub(duration_range.end))
+            IndexedSpanFilter::Stratified(index, _, _) => {
+                // Stratified indexes are trimmed loosely and so may include
+                // spans that don't overlap the timeframe. So the full range is
+                // possible.
+                (0, Some(index.len()))
+            }
+            IndexedSpanFilter::Not(index, _) => {
+                // The full range is possible
+                (0, Some(index.len()))
+            }
+            IndexedSpanFilter::And(filters) => match filters.len() {
+                0 => (0, Some(0)),
+                1 => filters[0].size_hint(),
+                _ => {
+                    // With multiple filters AND-ed together, the potential min
+                    // is zero (where none agree) and potential max is the
+                    // smallest maximum.
+                    let max = filters.iter().fold(None, |max, filter| {
+                        merge(max, filter.size_hint().1, usize::min)
+                    });
+
+                    (0, max)
+                }
+            },
+            IndexedSpanFilter::Or(filters) => match filters.len() {
+                0 => (0, Some(0)),
+                1 => filters[0].size_hint(),
+                _ => {
+                    // With multiple filters OR-ed together, the potential min
+                    // is the largest minimum and potential max is the sum of
+                    // maximums.
+                    filters.iter().fold((0, None), |(a_min, a_max), filter| {
+                        let (min, max) = filter.size_hint();
+                        (usize::max(a_min, min), merge(a_max, max, Add::add))
+                    })
+                }
+            },
+        }
+    }
+
+    pub(crate) fn optimize(&mut self) {
+        match self {
+            IndexedSpanFilter::Single(_, _) => { /* nothing to do */ }
+            IndexedSpanFilter::Stratified(_, _, _) => {
+                // there is nothing to reorder within a stratified filter, the
+                // parent AND will place it relative to its siblings
+            }
+            IndexedSpanFilter::Not(_, inner_filter) => {
+                inner_filter.optimize();
+            }
+            IndexedSpanFilter::And(filters) => {
+                let mut flattened = Vec::with_capacity(filters.len());
+                for mut filter in std::mem::take(filters) {
+                    filter.optimize();
+
+                    // nested ANDs and lone stratified filters (that are wrapped
+                    // in an OR by duration filters) are pulled up so they can
+                    // be sorted with their siblings
+                    match filter {
+                        IndexedSpanFilter::And(inner_filters) => flattened.extend(inner_filters),
+                        IndexedSpanFilter::Or(mut inner_filters) if inner_filters.len() == 1 => {
+                            flattened.push(inner_filters.pop().unwrap());
+                        }
+                        filter => flattened.push(filter),
+                    }
+                }
+
+                // the first filter drives the search so the cheapest should be
+                // first, and on ties a non-stratified filter is preferred since
+                // stratified ones may include spans outside the timeframe
+                flattened.sort_by_key(|f| (f.estimate_count(), f.is_stratified()));
+
+                *filters = flattened;
+            }
+            IndexedSpanFilter::Or(filters) if filters.is_empty() => {
+                // an empty OR matches nothing
+                *self = IndexedSpanFilter::Single(&[], None);
+            }
+            IndexedSpanFilter::Or(filters) => {
+                filters.iter_mut().for_each(Self::optimize);
+                filters.sort_by_key(Self::estimate_count);
+            }
+        }
+    }
+
+    pub(crate) fn trim_to_timeframe(&mut self, start: Timestamp, end: Timestamp) {
+        match self {
+            IndexedSpanFilter::Single(index, _) => {
+                // we can trim the end
+                let trim_end = end;
+
+                let end_idx = index.upper_bound(&trim_end);
+
+                *index = &index[..end_idx];
+            }
+            IndexedSpanFilter::Stratified(index, duration_range, _) => {
+                // we can trim to "max duration" before `start`
+                let trim_start = Timestamp::new(start.get().saturating_sub(duration_range.end))
                     .unwrap_or(Timestamp::MIN);
 
                 // we can trim by the end
@@ -1790,6 +2426,21 @@ impl IndexedSpanFilter<'_> {
                 .for_each(|f| f.trim_to_timeframe(start, end)),
         }
     }
+
+    // This sets where spans that are currently entered are considered busy
+    // until, so that `#busy` matches the value the span is rendered with.
+    pub(crate) fn bound_busy(&mut self, end: Timestamp) {
+        match self {
+            IndexedSpanFilter::Single(_, filter) | IndexedSpanFilter::Stratified(_, _, filter) => {
+                if let Some(NonIndexedSpanFilter::Busy(_, busy_end)) = filter {
+                    *busy_end = end;
+                }
+            }
+            IndexedSpanFilter::Not(_, inner_filter) => inner_filter.bound_busy(end),
+            IndexedSpanFilter::And(filters) => filters.iter_mut().for_each(|f| f.bound_busy(end)),
+            IndexedSpanFilter::Or(filters) => filters.iter_mut().for_each(|f| f.bound_busy(end)),
+        }
+    }
 }
 
 impl<'a> IndexedSpanFilter<'a> {
@@ -1797,7 +2448,7 @@ impl<'a> IndexedSpanFilter<'a> {
     // `Stratified` filters can be trimmed. If there are no stratified filters
     // or the filter is constructed in a way that not all filters are covered,
     // this will add the necessary `Stratified` filters to the root.
-    pub fn ensure_stratified(&mut self, duration_index: &'a SpanDurationIndex) {
+    pub(crate) fn ensure_stratified(&mut self, duration_index: &'a SpanDurationIndex) {
         if self.is_stratified() {
             return;
         }
@@ -1829,36 +2480,48 @@ impl<'a> IndexedSpanFilter<'a> {
 pub enum BasicSpanFilter {
     Level(Level),
     Duration(DurationFilter),
+    Busy(DurationFilter),
     Created(ValueOperator, Timestamp),
     Closed(ValueOperator, Timestamp),
     Connection(ConnectionKey),
     Name(ValueStringComparison),
+    NamePrefix(String),
     Target(ValueStringComparison),
     File(FileFilter),
+    Line(ValueOperator, u32),
     Ancestor(SpanKey),
+    AncestorOf(SpanKey),
     Root,
     Parent(SpanKey),
     Attribute(String, ValueFilter),
+    AttributeContains(String, String),
+    AttributeCaseInsensitive(String, String),
     Not(Box<BasicSpanFilter>),
     And(Vec<BasicSpanFilter>),
     Or(Vec<BasicSpanFilter>),
 }
 
 impl BasicSpanFilter {
-    fn simplify(&mut self) {
+    pub fn simplify(&mut self) {
         match self {
             BasicSpanFilter::Level(_) => {}
             BasicSpanFilter::Duration(_) => {}
+            BasicSpanFilter::Busy(_) => {}
             BasicSpanFilter::Created(_, _) => {}
             BasicSpanFilter::Closed(_, _) => {}
             BasicSpanFilter::Connection(_) => {}
             BasicSpanFilter::Name(_) => {}
+            BasicSpanFilter::NamePrefix(_) => {}
             BasicSpanFilter::Target(_) => {}
             BasicSpanFilter::File(_) => {}
+            BasicSpanFilter::Line(_, _) => {}
             BasicSpanFilter::Ancestor(_) => {}
+            BasicSpanFilter::AncestorOf(_) => {}
             BasicSpanFilter::Root => {}
             BasicSpanFilter::Parent(_) => {}
             BasicSpanFilter::Attribute(_, _) => {}
+            BasicSpanFilter::AttributeContains(_, _) => {}
+            BasicSpanFilter::AttributeCaseInsensitive(_, _) => {}
             BasicSpanFilter::Not(_) => {}
             BasicSpanFilter::And(filters) => {
                 for filter in &mut *filters {
@@ -1885,9 +2548,51 @@ impl BasicSpanFilter {
         }
     }
 
+    pub(crate) fn matches<S: Storage>(&self, context: &SpanContext<'_, S>) -> bool {
+        let span = context.span();
+        match self {
+            BasicSpanFilter::Level(level) => span.level == *level,
+            BasicSpanFilter::Duration(filter) => filter.matches(span.duration()),
+            BasicSpanFilter::Busy(filter) => filter.matches(span.busy(Some(now()))),
+            BasicSpanFilter::Created(op, value) => op.compare(span.created_at, *value),
+            BasicSpanFilter::Closed(op, value) => {
+                let Some(closed_at) = span.closed_at else {
+                    // an open span is considered closed after any timestamp
+                    return matches!(op, ValueOperator::Gt | ValueOperator::Gte);
+                };
+
+                op.compare(closed_at, *value)
+            }
+            BasicSpanFilter::Connection(connection_key) => span.connection_key == *connection_key,
+            BasicSpanFilter::Name(filter) => filter.matches(&span.name),
+            BasicSpanFilter::NamePrefix(prefix) => span.name.starts_with(prefix.as_str()),
+            BasicSpanFilter::Target(filter) => filter.matches(&span.target),
+            BasicSpanFilter::File(filter) => {
+                filter.matches(span.file_name.as_deref(), span.file_line)
+            }
+            BasicSpanFilter::Line(op, line) => line_matches(*op, span.file_line, *line),
+            BasicSpanFilter::Ancestor(span_key) => context.parents().any(|p| p.key() == *span_key),
+            BasicSpanFilter::AncestorOf(span_key) => is_ancestor_of(context, *span_key),
+            BasicSpanFilter::Root => span.parent_key.is_none(),
+            BasicSpanFilter::Parent(parent_key) => span.parent_key == Some(*parent_key),
+            BasicSpanFilter::Attribute(attribute, value_filter) => context
+                .attribute(attribute)
+                .map(|v| value_filter.matches(v))
+                .unwrap_or(false),
+            BasicSpanFilter::AttributeContains(attribute, value) => {
+                attribute_contains(context.attribute(attribute), value)
+            }
+            BasicSpanFilter::AttributeCaseInsensitive(attribute, value) => {
+                attribute_eq_ignore_case(context.attribute(attribute), value)
+            }
+            BasicSpanFilter::Not(inner_filter) => !inner_filter.matches(context),
+            BasicSpanFilter::And(filters) => filters.iter().all(|f| f.matches(context)),
+            BasicSpanFilter::Or(filters) => filters.iter().any(|f| f.matches(context)),
+        }
+    }
+
     pub fn validate(predicate: FilterPredicate) -> Result<FallibleFilterPredicate, InputError> {
         use FilterPropertyKind::*;
-        use ValueOperator::*;
 
         let predicate = match predicate {
             FilterPredicate::Single(single) => single,
@@ -1912,8 +2617,8 @@ impl BasicSpanFilter {
         let property_kind = predicate
             .property_kind
             .unwrap_or(match predicate.property.as_str() {
-                "level" | "connection" | "duration" | "name" | "target" | "file" | "parent"
-                | "created" | "closed" | "stack" => Inherent,
+                "level" | "connection" | "duration" | "busy" | "name" | "target" | "file"
+                | "line" | "parent" | "created" | "closed" | "stack" | "ancestors" => Inherent,
                 _ => Attribute,
             });
 
@@ -1924,22 +2629,9 @@ impl BasicSpanFilter {
                     _ => return Err(InputError::InvalidLevelValue),
                 };
 
-                let _level = match value.as_str() {
-                    "TRACE" => Level::Trace,
-                    "DEBUG" => Level::Debug,
-                    "INFO" => Level::Info,
-                    "WARN" => Level::Warn,
-                    "ERROR" => Level::Error,
-                    _ => return Err(InputError::InvalidLevelValue),
-                };
-
-                let _above = match op {
-                    Eq => false,
-                    Gte => true,
-                    _ => return Err(InputError::InvalidLevelOperator),
-                };
+                let _levels = parse_level_range(op, value)?;
             }
-            (Inherent, "duration") => validate_value_predicate(
+            (Inherent, "duration" | "busy") => validate_value_predicate(
                 &predicate.value,
                 |op, value| {
                     DurationFilter::from_input(*op, value)?;
@@ -1947,6 +2639,7 @@ impl BasicSpanFilter {
                 },
                 |_| Err(InputError::InvalidDurationValue),
                 |_| Err(InputError::InvalidDurationValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "name") => validate_value_predicate(
                 &predicate.value,
@@ -1962,6 +2655,7 @@ impl BasicSpanFilter {
                     Regex::new(regex).map_err(|_| InputError::InvalidRegexValue)?;
                     Ok(())
                 },
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "target") => validate_value_predicate(
                 &predicate.value,
@@ -1977,6 +2671,7 @@ impl BasicSpanFilter {
                     Regex::new(regex).map_err(|_| InputError::InvalidRegexValue)?;
                     Ok(())
                 },
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "file") => validate_value_predicate(
                 &predicate.value,
@@ -2012,6 +2707,17 @@ impl BasicSpanFilter {
                     Regex::new(regex).map_err(|_| InputError::InvalidRegexValue)?;
                     Ok(())
                 },
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
+            )?,
+            (Inherent, "line") => validate_value_predicate(
+                &predicate.value,
+                |_op, value| {
+                    let _: u32 = value.parse().map_err(|_| InputError::InvalidLineValue)?;
+                    Ok(())
+                },
+                |_| Err(InputError::InvalidLineValue),
+                |_| Err(InputError::InvalidLineValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "connection") => {
                 validate_value_predicate(
@@ -2029,6 +2735,7 @@ impl BasicSpanFilter {
                     },
                     |_| Err(InputError::InvalidConnectionValue),
                     |_| Err(InputError::InvalidConnectionValue),
+                    |_| Err(InputError::InvalidCaseInsensitiveValue),
                 )?;
             }
             (Inherent, "created") => {
@@ -2042,6 +2749,7 @@ impl BasicSpanFilter {
                     },
                     |_| Err(InputError::InvalidCreatedValue),
                     |_| Err(InputError::InvalidCreatedValue),
+                    |_| Err(InputError::InvalidCaseInsensitiveValue),
                 )?;
             }
             (Inherent, "closed") => {
@@ -2055,6 +2763,7 @@ impl BasicSpanFilter {
                     },
                     |_| Err(InputError::InvalidClosedValue),
                     |_| Err(InputError::InvalidClosedValue),
+                    |_| Err(InputError::InvalidCaseInsensitiveValue),
                 )?;
             }
             (Inherent, "parent") => {
@@ -2074,6 +2783,7 @@ impl BasicSpanFilter {
                     },
                     |_| Err(InputError::InvalidParentValue),
                     |_| Err(InputError::InvalidParentValue),
+                    |_| Err(InputError::InvalidCaseInsensitiveValue),
                 )?;
             }
             (Inherent, "stack") => {
@@ -2090,6 +2800,25 @@ impl BasicSpanFilter {
                     },
                     |_| Err(InputError::InvalidStackValue),
                     |_| Err(InputError::InvalidStackValue),
+                    |_| Err(InputError::InvalidCaseInsensitiveValue),
+                )?;
+            }
+            (Inherent, "ancestors") => {
+                validate_value_predicate(
+                    &predicate.value,
+                    |op, value| {
+                        if *op != ValueOperator::Eq {
+                            return Err(InputError::InvalidAncestorsOperator);
+                        }
+
+                        let _ =
+                            parse_full_span_id(value).ok_or(InputError::InvalidAncestorsValue)?;
+
+                        Ok(())
+                    },
+                    |_| Err(InputError::InvalidAncestorsValue),
+                    |_| Err(InputError::InvalidAncestorsValue),
+                    |_| Err(InputError::InvalidCaseInsensitiveValue),
                 )?;
             }
             (Inherent, _) => {
@@ -2110,6 +2839,7 @@ impl BasicSpanFilter {
                         Regex::new(regex).map_err(|_| InputError::InvalidRegexValue)?;
                         Ok(())
                     },
+                    |_| Ok(()),
                 )?;
             }
         }
@@ -2126,7 +2856,6 @@ impl BasicSpanFilter {
         span_key_map: &HashMap<(ConnectionKey, SpanId), SpanKey>,
     ) -> Result<BasicSpanFilter, InputError> {
         use FilterPropertyKind::*;
-        use ValueOperator::*;
 
         let predicate = match predicate {
             FilterPredicate::Single(single) => single,
@@ -2149,8 +2878,8 @@ impl BasicSpanFilter {
         let property_kind = predicate
             .property_kind
             .unwrap_or(match predicate.property.as_str() {
-                "level" | "connection" | "duration" | "name" | "target" | "file" | "parent"
-                | "created" | "closed" | "stack" => Inherent,
+                "level" | "connection" | "duration" | "busy" | "name" | "target" | "file"
+                | "line" | "parent" | "created" | "closed" | "stack" | "ancestors" => Inherent,
                 _ => Attribute,
             });
 
@@ -2161,29 +2890,19 @@ impl BasicSpanFilter {
                     _ => return Err(InputError::InvalidLevelValue),
                 };
 
-                let level = match value.as_str() {
-                    "TRACE" => Level::Trace,
-                    "DEBUG" => Level::Debug,
-                    "INFO" => Level::Info,
-                    "WARN" => Level::Warn,
-                    "ERROR" => Level::Error,
-                    _ => return Err(InputError::InvalidLevelValue),
-                };
-
-                let above = match op {
-                    Gte => true,
-                    Eq => false,
-                    _ => return Err(InputError::InvalidLevelOperator),
-                };
+                // the levels are expanded to an `Or` of each individual level
+                // in the range, so `<=INFO` is TRACE|DEBUG|INFO, `>=INFO` is
+                // INFO|WARN|ERROR, and `DEBUG..WARN` is DEBUG|INFO|WARN
+                let levels = parse_level_range(op, value)?;
 
-                if above {
+                if levels.start() == levels.end() {
+                    BasicSpanFilter::Level(*levels.start())
+                } else {
                     BasicSpanFilter::Or(
-                        ((level as i32)..5)
+                        ((*levels.start() as i32)..=(*levels.end() as i32))
                             .map(|l| BasicSpanFilter::Level(l.try_into().unwrap()))
                             .collect(),
                     )
-                } else {
-                    BasicSpanFilter::Level(level)
                 }
             }
             (Inherent, "duration") => filterify_span_filter(
@@ -2195,6 +2914,18 @@ impl BasicSpanFilter {
                 },
                 |_| Err(InputError::InvalidDurationValue),
                 |_| Err(InputError::InvalidDurationValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
+            )?,
+            (Inherent, "busy") => filterify_span_filter(
+                predicate.value,
+                |op, value| {
+                    Ok(BasicSpanFilter::Busy(DurationFilter::from_input(
+                        op, &value,
+                    )?))
+                },
+                |_| Err(InputError::InvalidDurationValue),
+                |_| Err(InputError::InvalidDurationValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "name") => filterify_span_filter(
                 predicate.value,
@@ -2203,6 +2934,13 @@ impl BasicSpanFilter {
                     Ok(BasicSpanFilter::Name(filter))
                 },
                 |wildcard| {
+                    // a lone trailing `*` can use the name index directly
+                    if let Some(prefix) = wildcard.strip_suffix('*') {
+                        if !prefix.contains(['*', '\\']) {
+                            return Ok(BasicSpanFilter::NamePrefix(prefix.to_owned()));
+                        }
+                    }
+
                     let wildcard = WildcardBuilder::from_owned(wildcard.into_bytes())
                         .without_one_metasymbol()
                         .build()
@@ -2217,6 +2955,7 @@ impl BasicSpanFilter {
                     let filter = ValueStringComparison::Regex(regex);
                     Ok(BasicSpanFilter::Name(filter))
                 },
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "target") => filterify_span_filter(
                 predicate.value,
@@ -2239,6 +2978,7 @@ impl BasicSpanFilter {
                     let filter = ValueStringComparison::Regex(regex);
                     Ok(BasicSpanFilter::Target(filter))
                 },
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "file") => filterify_span_filter(
                 predicate.value,
@@ -2304,6 +3044,17 @@ impl BasicSpanFilter {
                         line: None,
                     }))
                 },
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
+            )?,
+            (Inherent, "line") => filterify_span_filter(
+                predicate.value,
+                |op, value| {
+                    let line: u32 = value.parse().map_err(|_| InputError::InvalidLineValue)?;
+                    Ok(BasicSpanFilter::Line(op, line))
+                },
+                |_| Err(InputError::InvalidLineValue),
+                |_| Err(InputError::InvalidLineValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "connection") => filterify_span_filter(
                 predicate.value,
@@ -2325,6 +3076,7 @@ impl BasicSpanFilter {
                 },
                 |_| Err(InputError::InvalidConnectionValue),
                 |_| Err(InputError::InvalidConnectionValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "created") => filterify_span_filter(
                 predicate.value,
@@ -2336,6 +3088,7 @@ impl BasicSpanFilter {
                 },
                 |_| Err(InputError::InvalidCreatedValue),
                 |_| Err(InputError::InvalidCreatedValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "closed") => filterify_span_filter(
                 predicate.value,
@@ -2347,6 +3100,7 @@ impl BasicSpanFilter {
                 },
                 |_| Err(InputError::InvalidClosedValue),
                 |_| Err(InputError::InvalidClosedValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "parent") => filterify_span_filter(
                 predicate.value,
@@ -2374,8 +3128,9 @@ impl BasicSpanFilter {
                         Ok(BasicSpanFilter::Parent(parent_key))
                     }
                 },
-                |_| Err(InputError::InvalidConnectionValue),
-                |_| Err(InputError::InvalidConnectionValue),
+                |_| Err(InputError::InvalidParentValue),
+                |_| Err(InputError::InvalidParentValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "stack") => filterify_span_filter(
                 predicate.value,
@@ -2400,6 +3155,32 @@ impl BasicSpanFilter {
                 },
                 |_| Err(InputError::InvalidStackValue),
                 |_| Err(InputError::InvalidStackValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
+            )?,
+            (Inherent, "ancestors") => filterify_span_filter(
+                predicate.value,
+                |op, value| {
+                    if op != ValueOperator::Eq {
+                        return Err(InputError::InvalidAncestorsOperator);
+                    }
+
+                    let (connection_id, span_id) =
+                        parse_full_span_id(&value).ok_or(InputError::InvalidAncestorsValue)?;
+
+                    let connection_key = connection_key_map
+                        .get(&connection_id)
+                        .copied()
+                        .unwrap_or(ConnectionKey::MIN);
+                    let span_key = span_key_map
+                        .get(&(connection_key, span_id))
+                        .copied()
+                        .unwrap_or(SpanKey::MIN);
+
+                    Ok(BasicSpanFilter::AncestorOf(span_key))
+                },
+                |_| Err(InputError::InvalidAncestorsValue),
+                |_| Err(InputError::InvalidAncestorsValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, _) => {
                 return Err(InputError::InvalidInherentProperty);
@@ -2411,6 +3192,13 @@ impl BasicSpanFilter {
                     Ok(BasicSpanFilter::Attribute(name.to_owned(), value_filter))
                 },
                 |wildcard| {
+                    if let Some(value) = wildcard_to_contains(&wildcard) {
+                        return Ok(BasicSpanFilter::AttributeContains(
+                            name.to_owned(),
+                            value.to_owned(),
+                        ));
+                    }
+
                     let value_filter = ValueFilter::from_wildcard(wildcard)?;
                     Ok(BasicSpanFilter::Attribute(name.to_owned(), value_filter))
                 },
@@ -2418,6 +3206,12 @@ impl BasicSpanFilter {
                     let value_filter = ValueFilter::from_regex(regex)?;
                     Ok(BasicSpanFilter::Attribute(name.to_owned(), value_filter))
                 },
+                |value| {
+                    Ok(BasicSpanFilter::AttributeCaseInsensitive(
+                        name.to_owned(),
+                        value,
+                    ))
+                },
             )?,
         };
 
@@ -2425,14 +3219,20 @@ impl BasicSpanFilter {
     }
 }
 
-pub enum NonIndexedSpanFilter {
+pub(crate) enum NonIndexedSpanFilter {
     Duration(DurationFilter),
+    // the timestamp is where a span that is currently entered is considered
+    // busy until
+    Busy(DurationFilter, Timestamp),
     Closed(ValueOperator, Timestamp),
     Name(ValueStringComparison),
     Target(ValueStringComparison),
     File(FileFilter),
-    Parent(SpanKey),
+    Line(ValueOperator, u32),
+    AncestorOf(SpanKey),
     Attribute(String, ValueFilter),
+    AttributeContains(String, String),
+    AttributeCaseInsensitive(String, String),
 }
 
 impl NonIndexedSpanFilter {
@@ -2440,9 +3240,11 @@ impl NonIndexedSpanFilter {
         let span = context.span();
         match self {
             NonIndexedSpanFilter::Duration(filter) => filter.matches(span.duration()),
+            NonIndexedSpanFilter::Busy(filter, end) => filter.matches(span.busy(Some(*end))),
             NonIndexedSpanFilter::Closed(op, value) => {
                 let Some(closed_at) = span.closed_at else {
-                    return false; // never match an open span
+                    // an open span is considered closed after any timestamp
+                    return matches!(op, ValueOperator::Gt | ValueOperator::Gte);
                 };
 
                 op.compare(closed_at, *value)
@@ -2452,11 +3254,18 @@ impl NonIndexedSpanFilter {
             NonIndexedSpanFilter::File(filter) => {
                 filter.matches(span.file_name.as_deref(), span.file_line)
             }
-            NonIndexedSpanFilter::Parent(parent_key) => span.parent_key == Some(*parent_key),
+            NonIndexedSpanFilter::Line(op, line) => line_matches(*op, span.file_line, *line),
+            NonIndexedSpanFilter::AncestorOf(span_key) => is_ancestor_of(context, *span_key),
             NonIndexedSpanFilter::Attribute(attribute, value_filter) => context
                 .attribute(attribute)
                 .map(|v| value_filter.matches(v))
                 .unwrap_or(false),
+            NonIndexedSpanFilter::AttributeContains(attribute, value) => {
+                attribute_contains(context.attribute(attribute), value)
+            }
+            NonIndexedSpanFilter::AttributeCaseInsensitive(attribute, value) => {
+                attribute_eq_ignore_case(context.attribute(attribute), value)
+            }
         }
     }
 }
@@ -2534,17 +3343,82 @@ impl DurationFilter {
     }
 }
 
-pub struct IndexedSpanFilterIterator<'i, S> {
+#[cfg(feature = "parallel")]
+impl<'i> IndexedSpanFilter<'i> {
+    // This saves the parts of the indexes that are left to search, which are
+    // narrowed as the filter is searched, so they can be restored.
+    fn save_positions(&self, positions: &mut Vec<&'i [Timestamp]>) {
+        match self {
+            IndexedSpanFilter::Single(entries, _) => positions.push(entries),
+            IndexedSpanFilter::Stratified(entries, _, _) => positions.push(entries),
+            IndexedSpanFilter::Not(entries, indexed_filter) => {
+                positions.push(entries);
+                indexed_filter.save_positions(positions);
+            }
+            IndexedSpanFilter::And(indexed_filters) | IndexedSpanFilter::Or(indexed_filters) => {
+                for indexed_filter in indexed_filters {
+                    indexed_filter.save_positions(positions);
+                }
+            }
+        }
+    }
+
+    fn restore_positions(&mut self, positions: &mut impl Iterator<Item = &'i [Timestamp]>) {
+        match self {
+            IndexedSpanFilter::Single(entries, _) => *entries = positions.next().unwrap(),
+            IndexedSpanFilter::Stratified(entries, _, _) => *entries = positions.next().unwrap(),
+            IndexedSpanFilter::Not(entries, indexed_filter) => {
+                *entries = positions.next().unwrap();
+                indexed_filter.restore_positions(positions);
+            }
+            IndexedSpanFilter::And(indexed_filters) | IndexedSpanFilter::Or(indexed_filters) => {
+                for indexed_filter in indexed_filters {
+                    indexed_filter.restore_positions(positions);
+                }
+            }
+        }
+    }
+}
+
+/// How many span keys are found at a time when searching in parallel.
+#[cfg(feature = "parallel")]
+const PARALLEL_BATCH_SIZE: usize = 64;
+
+/// Searches a span filter in parallel for a batch of keys. This can only be
+/// made for [`ParallelStorage`], so the engine keeps one if it was configured
+/// to search in parallel and the storage allows it.
+#[cfg(feature = "parallel")]
+pub(crate) type ParallelSearch<S> =
+    fn(&mut IndexedSpanFilter<'_>, &S, Timestamp, Order, Timestamp, Timestamp) -> Vec<Timestamp>;
+
+#[cfg(feature = "parallel")]
+pub(crate) fn parallel_search<S: ParallelStorage>(
+    filter: &mut IndexedSpanFilter<'_>,
+    storage: &S,
+    entry: Timestamp,
+    order: Order,
+    bound: Timestamp,
+    start: Timestamp,
+) -> Vec<Timestamp> {
+    filter.search_parallel(storage, entry, order, bound, start, PARALLEL_BATCH_SIZE)
+}
+
+pub(crate) struct IndexedSpanFilterIterator<'i, S> {
     filter: IndexedSpanFilter<'i>,
     order: Order,
     curr_key: Timestamp,
     start_key: Timestamp,
     end_key: Timestamp,
     storage: &'i S,
+    #[cfg(feature = "parallel")]
+    parallel_search: Option<ParallelSearch<S>>,
+    // the rest of the batch found by the last parallel search
+    #[cfg(feature = "parallel")]
+    found: VecDeque<SpanKey>,
 }
 
 impl<'i, S> IndexedSpanFilterIterator<'i, S> {
-    pub fn new(query: Query, engine: &'i RawEngine<S>) -> IndexedSpanFilterIterator<'i, S> {
+    pub(crate) fn new(query: Query, engine: &'i RawEngine<S>) -> IndexedSpanFilterIterator<'i, S> {
         let mut filter = BasicSpanFilter::And(
             query
                 .filter
@@ -2602,6 +3476,7 @@ impl<'i, S> IndexedSpanFilterIterator<'i, S> {
 
         filter.ensure_stratified(&engine.span_indexes.durations);
         filter.trim_to_timeframe(start, end);
+        filter.bound_busy(query.end.min(now()));
         filter.optimize();
 
         let (start_key, end_key) = match query.order {
@@ -2616,10 +3491,14 @@ impl<'i, S> IndexedSpanFilterIterator<'i, S> {
             end_key,
             start_key,
             storage: &engine.storage,
+            #[cfg(feature = "parallel")]
+            parallel_search: engine.parallel_search,
+            #[cfg(feature = "parallel")]
+            found: VecDeque::new(),
         }
     }
 
-    pub fn new_internal(
+    pub(crate) fn new_internal(
         filter: IndexedSpanFilter<'i>,
         engine: &'i RawEngine<S>,
     ) -> IndexedSpanFilterIterator<'i, S> {
@@ -2630,6 +3509,10 @@ impl<'i, S> IndexedSpanFilterIterator<'i, S> {
             end_key: Timestamp::MAX,
             start_key: Timestamp::MIN,
             storage: &engine.storage,
+            #[cfg(feature = "parallel")]
+            parallel_search: engine.parallel_search,
+            #[cfg(feature = "parallel")]
+            found: VecDeque::new(),
         }
     }
 }
@@ -2641,6 +3524,31 @@ where
     type Item = SpanKey;
 
     fn next(&mut self) -> Option<SpanKey> {
+        #[cfg(feature = "parallel")]
+        if let Some(parallel_search) = self.parallel_search {
+            if self.found.is_empty() {
+                let found = parallel_search(
+                    &mut self.filter,
+                    self.storage,
+                    self.curr_key,
+                    self.order,
+                    self.end_key,
+                    self.start_key,
+                );
+
+                if let Some(&last) = found.last() {
+                    match self.order {
+                        Order::Asc => self.curr_key = last.saturating_add(1),
+                        Order::Desc => self.curr_key = Timestamp::new(last.get() - 1).unwrap(),
+                    };
+                }
+
+                self.found = found.into();
+            }
+
+            return self.found.pop_front();
+        }
+
         let span_key = self.filter.search(
             self.storage,
             self.curr_key,
@@ -2657,16 +3565,19 @@ where
         Some(span_key)
     }
 
-    // fn size_hint(&self) -> (usize, Option<usize>) {
-    //     self.filter.size_hint()
-    // }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.filter.size_hint()
+    }
 }
 
 pub enum BasicConnectionFilter {
     Duration(DurationFilter),
     Connected(ValueOperator, Timestamp),
     Disconnected(ValueOperator, Timestamp),
+    StillConnected,
+    Schema(ValueOperator, u32),
     Attribute(String, ValueFilter),
+    AttributeCaseInsensitive(String, String),
     Not(Box<BasicConnectionFilter>),
     And(Vec<BasicConnectionFilter>),
     Or(Vec<BasicConnectionFilter>),
@@ -2678,7 +3589,10 @@ impl BasicConnectionFilter {
             BasicConnectionFilter::Duration(_) => {}
             BasicConnectionFilter::Connected(_, _) => {}
             BasicConnectionFilter::Disconnected(_, _) => {}
+            BasicConnectionFilter::StillConnected => {}
+            BasicConnectionFilter::Schema(_, _) => {}
             BasicConnectionFilter::Attribute(_, _) => {}
+            BasicConnectionFilter::AttributeCaseInsensitive(_, _) => {}
             BasicConnectionFilter::Not(_) => {}
             BasicConnectionFilter::And(filters) => {
                 for filter in &mut *filters {
@@ -2731,7 +3645,7 @@ impl BasicConnectionFilter {
         let property_kind = predicate
             .property_kind
             .unwrap_or(match predicate.property.as_str() {
-                "duration" | "connected" | "disconnected" => Inherent,
+                "duration" | "connected" | "disconnected" | "schema" => Inherent,
                 _ => Attribute,
             });
 
@@ -2744,6 +3658,7 @@ impl BasicConnectionFilter {
                 },
                 |_| Err(InputError::InvalidDurationValue),
                 |_| Err(InputError::InvalidDurationValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "connected") => validate_value_predicate(
                 &predicate.value,
@@ -2756,10 +3671,19 @@ impl BasicConnectionFilter {
                 },
                 |_| Err(InputError::InvalidConnectedValue),
                 |_| Err(InputError::InvalidConnectedValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "disconnected") => validate_value_predicate(
                 &predicate.value,
-                |_op, value| {
+                |op, value| {
+                    if value == "none" {
+                        if *op != ValueOperator::Eq {
+                            return Err(InputError::InvalidDisconnectedValue);
+                        }
+
+                        return Ok(());
+                    }
+
                     let _: Timestamp = value
                         .parse()
                         .map_err(|_| InputError::InvalidDisconnectedValue)?;
@@ -2768,6 +3692,18 @@ impl BasicConnectionFilter {
                 },
                 |_| Err(InputError::InvalidDisconnectedValue),
                 |_| Err(InputError::InvalidDisconnectedValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
+            )?,
+            (Inherent, "schema") => validate_value_predicate(
+                &predicate.value,
+                |_op, value| {
+                    let _: u32 = value.parse().map_err(|_| InputError::InvalidSchemaValue)?;
+
+                    Ok(())
+                },
+                |_| Err(InputError::InvalidSchemaValue),
+                |_| Err(InputError::InvalidSchemaValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, _) => {
                 return Err(InputError::InvalidInherentProperty);
@@ -2787,6 +3723,7 @@ impl BasicConnectionFilter {
                         Regex::new(regex).map_err(|_| InputError::InvalidRegexValue)?;
                         Ok(())
                     },
+                    |_| Ok(()),
                 )?;
             }
         }
@@ -2821,7 +3758,7 @@ impl BasicConnectionFilter {
         let property_kind = predicate
             .property_kind
             .unwrap_or(match predicate.property.as_str() {
-                "duration" | "connected" | "disconnected" => Inherent,
+                "duration" | "connected" | "disconnected" | "schema" => Inherent,
                 _ => Attribute,
             });
 
@@ -2835,6 +3772,7 @@ impl BasicConnectionFilter {
                 },
                 |_| Err(InputError::InvalidDurationValue),
                 |_| Err(InputError::InvalidDurationValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "connected") => filterify_connection_filter(
                 predicate.value,
@@ -2847,10 +3785,20 @@ impl BasicConnectionFilter {
                 },
                 |_| Err(InputError::InvalidConnectedValue),
                 |_| Err(InputError::InvalidConnectedValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, "disconnected") => filterify_connection_filter(
                 predicate.value,
                 |op, value| {
+                    // connections that are still connected have no value
+                    if value == "none" {
+                        if op != ValueOperator::Eq {
+                            return Err(InputError::InvalidDisconnectedValue);
+                        }
+
+                        return Ok(BasicConnectionFilter::StillConnected);
+                    }
+
                     let at: Timestamp = value
                         .parse()
                         .map_err(|_| InputError::InvalidDisconnectedValue)?;
@@ -2859,6 +3807,18 @@ impl BasicConnectionFilter {
                 },
                 |_| Err(InputError::InvalidDisconnectedValue),
                 |_| Err(InputError::InvalidDisconnectedValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
+            )?,
+            (Inherent, "schema") => filterify_connection_filter(
+                predicate.value,
+                |op, value| {
+                    let schema: u32 = value.parse().map_err(|_| InputError::InvalidSchemaValue)?;
+
+                    Ok(BasicConnectionFilter::Schema(op, schema))
+                },
+                |_| Err(InputError::InvalidSchemaValue),
+                |_| Err(InputError::InvalidSchemaValue),
+                |_| Err(InputError::InvalidCaseInsensitiveValue),
             )?,
             (Inherent, _) => {
                 return Err(InputError::InvalidInherentProperty);
@@ -2886,6 +3846,12 @@ impl BasicConnectionFilter {
                         value_filter,
                     ))
                 },
+                |value| {
+                    Ok(BasicConnectionFilter::AttributeCaseInsensitive(
+                        name.to_owned(),
+                        value,
+                    ))
+                },
             )?,
         };
 
@@ -2893,7 +3859,9 @@ impl BasicConnectionFilter {
     }
 
     pub fn matches<S: Storage>(&self, storage: &S, entry: Timestamp) -> bool {
-        let connection = storage.get_connection(entry).unwrap();
+        let Some(connection) = storage.get_connection(entry) else {
+            return false; // connections missing from storage cannot match
+        };
         match self {
             BasicConnectionFilter::Duration(filter) => filter.matches(connection.duration()),
             BasicConnectionFilter::Connected(op, value) => {
@@ -2906,11 +3874,16 @@ impl BasicConnectionFilter {
 
                 op.compare(disconnected_at, *value)
             }
+            BasicConnectionFilter::StillConnected => connection.disconnected_at.is_none(),
+            BasicConnectionFilter::Schema(op, value) => op.compare(connection.schema, *value),
             BasicConnectionFilter::Attribute(attribute, value_filter) => connection
                 .fields
                 .get(attribute)
                 .map(|v| value_filter.matches(v))
                 .unwrap_or(false),
+            BasicConnectionFilter::AttributeCaseInsensitive(attribute, value) => {
+                attribute_eq_ignore_case(connection.fields.get(attribute), value)
+            }
             BasicConnectionFilter::Not(inner_filter) => !inner_filter.matches(storage, entry),
             BasicConnectionFilter::And(filters) => {
                 filters.iter().all(|f| f.matches(storage, entry))
@@ -2920,47 +3893,539 @@ impl BasicConnectionFilter {
     }
 }
 
-fn validate_value_predicate(
-    value: &ValuePredicate,
-    comparison_validator: impl Fn(&ValueOperator, &str) -> Result<(), InputError> + Clone,
-    wildcard_validator: impl Fn(&str) -> Result<(), InputError> + Clone,
-    regex_validator: impl Fn(&str) -> Result<(), InputError> + Clone,
-) -> Result<(), InputError> {
-    match value {
-        ValuePredicate::Not(predicate) => validate_value_predicate(
-            predicate,
-            comparison_validator,
-            wildcard_validator,
-            regex_validator,
-        ),
-        ValuePredicate::Comparison(op, value) => comparison_validator(op, value),
-        ValuePredicate::Wildcard(wildcard) => wildcard_validator(wildcard),
-        ValuePredicate::Regex(regex) => regex_validator(regex),
-        ValuePredicate::And(predicates) => predicates.iter().try_for_each(|p| {
-            validate_value_predicate(
-                p,
-                comparison_validator.clone(),
-                wildcard_validator.clone(),
-                regex_validator.clone(),
-            )
-        }),
-        ValuePredicate::Or(predicates) => predicates.iter().try_for_each(|p| {
-            validate_value_predicate(
-                p,
-                comparison_validator.clone(),
-                wildcard_validator.clone(),
-                regex_validator.clone(),
-            )
-        }),
-    }
+pub(crate) enum IndexedConnectionFilter<'i> {
+    Single(&'i [Timestamp], Option<NonIndexedConnectionFilter>),
+    Not(&'i [Timestamp], Box<IndexedConnectionFilter<'i>>),
+    And(Vec<IndexedConnectionFilter<'i>>),
+    Or(Vec<IndexedConnectionFilter<'i>>),
 }
 
-fn filterify_event_filter(
-    value: ValuePredicate,
-    comparison_filterifier: impl Fn(ValueOperator, String) -> Result<BasicEventFilter, InputError>
-        + Clone,
-    wildcard_filterifier: impl Fn(String) -> Result<BasicEventFilter, InputError> + Clone,
-    regex_filterifier: impl Fn(String) -> Result<BasicEventFilter, InputError> + Clone,
+impl IndexedConnectionFilter<'_> {
+    pub(crate) fn build(
+        filter: Option<BasicConnectionFilter>,
+        connection_indexes: &ConnectionIndexes,
+    ) -> IndexedConnectionFilter<'_> {
+        let Some(filter) = filter else {
+            return IndexedConnectionFilter::Single(&connection_indexes.all, None);
+        };
+
+        match filter {
+            BasicConnectionFilter::Duration(duration_filter) => {
+                // connections that are still connected have no duration
+                IndexedConnectionFilter::Single(
+                    &connection_indexes.disconnected,
+                    Some(NonIndexedConnectionFilter::Duration(duration_filter)),
+                )
+            }
+            // connection keys are when they connected
+            BasicConnectionFilter::Connected(op, value) => {
+                let all = &connection_indexes.all;
+                let entries = match op {
+                    ValueOperator::Gt => &all[all.upper_bound(&value)..],
+                    ValueOperator::Gte => &all[all.lower_bound(&value)..],
+                    ValueOperator::Eq => &all[all.lower_bound(&value)..all.upper_bound(&value)],
+                    ValueOperator::Lte => &all[..all.upper_bound(&value)],
+                    ValueOperator::Lt => &all[..all.lower_bound(&value)],
+                };
+
+                IndexedConnectionFilter::Single(entries, None)
+            }
+            BasicConnectionFilter::Disconnected(op, value) => {
+                // a connection can't disconnect before it connected, so those
+                // that connected after the value can be skipped when looking
+                // for earlier disconnects
+                let disconnected = &connection_indexes.disconnected;
+                let entries = match op {
+                    ValueOperator::Gt | ValueOperator::Gte => disconnected.as_slice(),
+                    ValueOperator::Eq | ValueOperator::Lte => {
+                        &disconnected[..disconnected.upper_bound(&value)]
+                    }
+                    ValueOperator::Lt => &disconnected[..disconnected.lower_bound(&value)],
+                };
+
+                IndexedConnectionFilter::Single(
+                    entries,
+                    Some(NonIndexedConnectionFilter::Disconnected(op, value)),
+                )
+            }
+            BasicConnectionFilter::StillConnected => {
+                IndexedConnectionFilter::Single(&connection_indexes.connected, None)
+            }
+            BasicConnectionFilter::Schema(op, value) => IndexedConnectionFilter::Single(
+                &connection_indexes.all,
+                Some(NonIndexedConnectionFilter::Schema(op, value)),
+            ),
+            BasicConnectionFilter::Attribute(attribute, value_filter) => {
+                if let Some(attr_index) = connection_indexes.attributes.get(&attribute) {
+                    let filters = attr_index
+                        .make_indexed_filter(value_filter)
+                        .into_iter()
+                        .map(|(i, f)| {
+                            IndexedConnectionFilter::Single(
+                                i,
+                                f.map(|f| {
+                                    NonIndexedConnectionFilter::Attribute(
+                                        attribute.clone(),
+                                        Box::new(f),
+                                    )
+                                }),
+                            )
+                        })
+                        .collect();
+
+                    IndexedConnectionFilter::Or(filters)
+                } else {
+                    // we are creating indexes for all attributes, so if one
+                    // doesn't exist, then there are no entities with that attribute
+                    IndexedConnectionFilter::Single(&[], None)
+                }
+            }
+            BasicConnectionFilter::AttributeCaseInsensitive(attribute, _)
+                if !connection_indexes.attributes.contains_key(&attribute) =>
+            {
+                IndexedConnectionFilter::Single(&[], None)
+            }
+            BasicConnectionFilter::AttributeCaseInsensitive(attribute, value) => {
+                // the attribute index is case-sensitive, so this is a full scan
+                IndexedConnectionFilter::Single(
+                    &connection_indexes.all,
+                    Some(NonIndexedConnectionFilter::AttributeCaseInsensitive(
+                        attribute, value,
+                    )),
+                )
+            }
+            BasicConnectionFilter::Not(filter) => IndexedConnectionFilter::Not(
+                &connection_indexes.all,
+                Box::new(IndexedConnectionFilter::build(
+                    Some(*filter),
+                    connection_indexes,
+                )),
+            ),
+            // an empty filter places no restrictions
+            BasicConnectionFilter::And(filters) if filters.is_empty() => {
+                IndexedConnectionFilter::Single(&connection_indexes.all, None)
+            }
+            BasicConnectionFilter::And(filters) => IndexedConnectionFilter::And(
+                filters
+                    .into_iter()
+                    .map(|f| IndexedConnectionFilter::build(Some(f), connection_indexes))
+                    .collect(),
+            ),
+            BasicConnectionFilter::Or(filters) => IndexedConnectionFilter::Or(
+                filters
+                    .into_iter()
+                    .map(|f| IndexedConnectionFilter::build(Some(f), connection_indexes))
+                    .collect(),
+            ),
+        }
+    }
+
+    // This searches for an entry equal to or beyond the provided entry. It is
+    // the same as `IndexedEventFilter::search` but checks the connections held
+    // by the engine rather than going to storage.
+    pub(crate) fn search(
+        &mut self,
+        connections: &BTreeMap<ConnectionKey, Connection>,
+        mut entry: Timestamp,
+        order: Order,
+        bound: Timestamp,
+    ) -> Option<Timestamp> {
+        match self {
+            IndexedConnectionFilter::Single(entries, filter) => match order {
+                Order::Asc => loop {
+                    let idx = entries.lower_bound_via_expansion(&entry);
+                    *entries = &entries[idx..];
+
+                    let found_entry = entries.first().cloned()?;
+                    if found_entry > bound {
+                        return None;
+                    }
+
+                    match filter {
+                        Some(filter) if !filter.matches(&connections[&found_entry]) => {
+                            entry = found_entry.saturating_add(1);
+                        }
+                        _ => return Some(found_entry),
+                    }
+                },
+                Order::Desc => loop {
+                    let idx = entries.upper_bound_via_expansion(&entry);
+                    *entries = &entries[..idx];
+
+                    let found_entry = entries.last().cloned()?;
+                    if found_entry < bound {
+                        return None;
+                    }
+
+                    match filter {
+                        Some(filter) if !filter.matches(&connections[&found_entry]) => {
+                            entry = Timestamp::new(found_entry.get() - 1)?;
+                        }
+                        _ => return Some(found_entry),
+                    }
+                },
+            },
+            IndexedConnectionFilter::Not(entries, filter) => match order {
+                Order::Asc => loop {
+                    let idx = entries.lower_bound_via_expansion(&entry);
+                    *entries = &entries[idx..];
+
+                    let found_entry = entries.first().cloned()?;
+                    if found_entry > bound {
+                        return None;
+                    }
+
+                    let nested_entry = filter.search(connections, found_entry, order, found_entry);
+
+                    if nested_entry != Some(found_entry) {
+                        return Some(found_entry);
+                    } else {
+                        entry = found_entry.saturating_add(1);
+                    }
+                },
+                Order::Desc => loop {
+                    let idx = entries.upper_bound_via_expansion(&entry);
+                    *entries = &entries[..idx];
+
+                    let found_entry = entries.last().cloned()?;
+                    if found_entry < bound {
+                        return None;
+                    }
+
+                    let nested_entry = filter.search(connections, found_entry, order, found_entry);
+
+                    if nested_entry != Some(found_entry) {
+                        return Some(found_entry);
+                    } else {
+                        entry = Timestamp::new(found_entry.get() - 1)?;
+                    }
+                },
+            },
+            IndexedConnectionFilter::And(indexed_filters) => {
+                let mut current = entry;
+                'outer: loop {
+                    current = indexed_filters[0].search(connections, current, order, bound)?;
+
+                    for indexed_filter in &mut indexed_filters[1..] {
+                        match indexed_filter.search(connections, current, order, current) {
+                            Some(found_entry) if found_entry != current => {
+                                current = found_entry;
+                                continue 'outer;
+                            }
+                            Some(_) => { /* continue */ }
+                            None => {
+                                match order {
+                                    Order::Asc => current = current.saturating_add(1),
+                                    Order::Desc => current = Timestamp::new(current.get() - 1)?,
+                                }
+                                continue 'outer;
+                            }
+                        }
+                    }
+
+                    break Some(current);
+                }
+            }
+            IndexedConnectionFilter::Or(indexed_filters) => {
+                let mut next_entry = None;
+                for indexed_filter in indexed_filters.iter_mut() {
+                    let bound = next_entry.unwrap_or(bound);
+                    if let Some(found_entry) =
+                        indexed_filter.search(connections, entry, order, bound)
+                    {
+                        next_entry = match (order, next_entry) {
+                            (Order::Asc, Some(next_entry)) => Some(next_entry.min(found_entry)),
+                            (Order::Desc, Some(next_entry)) => Some(next_entry.max(found_entry)),
+                            (_, None) => Some(found_entry),
+                        };
+                    }
+                }
+
+                next_entry
+            }
+        }
+    }
+
+    // This gives the theoretical maximum number of elements the filter may
+    // select. See `IndexedEventFilter::estimate_count`.
+    fn estimate_count(&self) -> usize {
+        match self {
+            IndexedConnectionFilter::Single(index, _) => index.len(),
+            IndexedConnectionFilter::Not(index, _) => index.len(),
+            IndexedConnectionFilter::And(filters) => {
+                filters.iter().map(Self::estimate_count).min().unwrap_or(0)
+            }
+            IndexedConnectionFilter::Or(filters) => filters.iter().map(Self::estimate_count).sum(),
+        }
+    }
+
+    pub(crate) fn optimize(&mut self) {
+        match self {
+            IndexedConnectionFilter::Single(_, _) => { /* nothing to do */ }
+            IndexedConnectionFilter::Not(_, _) => { /* nothing to do */ }
+            IndexedConnectionFilter::And(filters) => {
+                filters.iter_mut().for_each(Self::optimize);
+                filters.sort_by_key(Self::estimate_count);
+            }
+            IndexedConnectionFilter::Or(filters) => {
+                filters.iter_mut().for_each(Self::optimize);
+                filters.sort_by_key(Self::estimate_count);
+            }
+        }
+    }
+}
+
+pub(crate) enum NonIndexedConnectionFilter {
+    Duration(DurationFilter),
+    Disconnected(ValueOperator, Timestamp),
+    Schema(ValueOperator, u32),
+    Attribute(String, Box<ValueFilter>),
+    AttributeCaseInsensitive(String, String),
+}
+
+impl NonIndexedConnectionFilter {
+    fn matches(&self, connection: &Connection) -> bool {
+        match self {
+            NonIndexedConnectionFilter::Duration(filter) => filter.matches(connection.duration()),
+            NonIndexedConnectionFilter::Disconnected(op, value) => connection
+                .disconnected_at
+                .map(|disconnected_at| op.compare(disconnected_at, *value))
+                .unwrap_or(false),
+            NonIndexedConnectionFilter::Schema(op, value) => op.compare(connection.schema, *value),
+            NonIndexedConnectionFilter::Attribute(attribute, value_filter) => connection
+                .fields
+                .get(attribute)
+                .map(|v| value_filter.matches(v))
+                .unwrap_or(false),
+            NonIndexedConnectionFilter::AttributeCaseInsensitive(attribute, value) => {
+                attribute_eq_ignore_case(connection.fields.get(attribute), value)
+            }
+        }
+    }
+}
+
+pub(crate) struct IndexedConnectionFilterIterator<'i> {
+    filter: IndexedConnectionFilter<'i>,
+    order: Order,
+    start_key: Timestamp,
+    end_key: Timestamp,
+    connections: &'i BTreeMap<ConnectionKey, Connection>,
+}
+
+impl<'i> IndexedConnectionFilterIterator<'i> {
+    /// Unlike events and spans, a connection matches the query's timeframe if
+    /// it was connected at any point within it rather than by its key alone.
+    pub(crate) fn new<S>(
+        query: Query,
+        engine: &'i RawEngine<S>,
+    ) -> IndexedConnectionFilterIterator<'i> {
+        let mut filter = BasicConnectionFilter::And(
+            query
+                .filter
+                .into_iter()
+                .map(|p| BasicConnectionFilter::from_predicate(p).unwrap())
+                .collect(),
+        );
+        filter.simplify();
+
+        // connections can't have been connected within the timeframe if they
+        // connected after it ended or disconnected before it started
+        let filter = BasicConnectionFilter::And(vec![
+            filter,
+            BasicConnectionFilter::Connected(ValueOperator::Lte, query.end),
+            BasicConnectionFilter::Not(Box::new(BasicConnectionFilter::Disconnected(
+                ValueOperator::Lt,
+                query.start,
+            ))),
+        ]);
+
+        let mut filter = IndexedConnectionFilter::build(Some(filter), &engine.connection_indexes);
+        filter.optimize();
+
+        let (start_key, end_key) = match (query.order, query.previous) {
+            (Order::Asc, Some(prev)) => (prev.saturating_add(1), Timestamp::MAX),
+            (Order::Asc, None) => (Timestamp::MIN, Timestamp::MAX),
+            (Order::Desc, Some(prev)) => (Timestamp::new(prev.get() - 1).unwrap(), Timestamp::MIN),
+            (Order::Desc, None) => (Timestamp::MAX, Timestamp::MIN),
+        };
+
+        IndexedConnectionFilterIterator {
+            filter,
+            order: query.order,
+            start_key,
+            end_key,
+            connections: &engine.connections,
+        }
+    }
+}
+
+impl Iterator for IndexedConnectionFilterIterator<'_> {
+    type Item = ConnectionKey;
+
+    fn next(&mut self) -> Option<ConnectionKey> {
+        let connection_key =
+            self.filter
+                .search(self.connections, self.start_key, self.order, self.end_key)?;
+
+        match self.order {
+            Order::Asc => self.start_key = connection_key.saturating_add(1),
+            Order::Desc => self.start_key = Timestamp::new(connection_key.get() - 1).unwrap(),
+        }
+
+        Some(connection_key)
+    }
+}
+
+/// This parses the value of a `#level` predicate into the inclusive range of
+/// levels it matches. Supported forms are `LEVEL` (exactly), `>=LEVEL` (at or
+/// above), `<=LEVEL` (at or below), and `LOW..HIGH` (between, inclusive).
+fn parse_level_range(op: &ValueOperator, value: &str) -> Result<RangeInclusive<Level>, InputError> {
+    fn parse_level(value: &str) -> Result<Level, InputError> {
+        match value {
+            "TRACE" => Ok(Level::Trace),
+            "DEBUG" => Ok(Level::Debug),
+            "INFO" => Ok(Level::Info),
+            "WARN" => Ok(Level::Warn),
+            "ERROR" => Ok(Level::Error),
+            _ => Err(InputError::InvalidLevelValue),
+        }
+    }
+
+    if let Some((low, high)) = value.split_once("..") {
+        if *op != ValueOperator::Eq {
+            return Err(InputError::InvalidLevelOperator);
+        }
+
+        let low = parse_level(low)?;
+        let high = parse_level(high)?;
+
+        if (low as i32) > (high as i32) {
+            return Err(InputError::InvalidLevelValue);
+        }
+
+        return Ok(low..=high);
+    }
+
+    let level = parse_level(value)?;
+
+    match op {
+        ValueOperator::Eq => Ok(level..=level),
+        ValueOperator::Gte => Ok(level..=Level::Error),
+        ValueOperator::Lte => Ok(Level::Trace..=level),
+        _ => Err(InputError::InvalidLevelOperator),
+    }
+}
+
+// A "contains" predicate is the same as a wildcard with the value surrounded by
+// `*`s, so it is converted to one for properties that don't handle it specially.
+fn contains_to_wildcard(value: &str) -> String {
+    format!("*{}*", value.replace('\\', "\\\\").replace('*', "\\*"))
+}
+
+// This is the inverse of `contains_to_wildcard` but only for values that don't
+// need any escapes.
+fn wildcard_to_contains(wildcard: &str) -> Option<&str> {
+    let value = wildcard.strip_prefix('*')?.strip_suffix('*')?;
+
+    if value.is_empty() || value.contains(['*', '\\']) {
+        return None;
+    }
+
+    Some(value)
+}
+
+// checks if the span in the context is a transitive parent of the given span
+fn is_ancestor_of<S: Storage>(context: &SpanContext<'_, S>, span_key: SpanKey) -> bool {
+    let Some(span) = context.storage.get_span(span_key) else {
+        return false;
+    };
+
+    let key = context.span().key();
+    let span_context = SpanContext::with_span(&span, context.storage);
+
+    // this is a statement so the iterator is dropped before `span_context`
+    return span_context.parents().any(|parent| parent.key() == key);
+}
+
+fn attribute_contains(attribute: Option<&Value>, value: &str) -> bool {
+    match attribute {
+        Some(Value::Str(s)) => s.contains(value),
+        _ => false,
+    }
+}
+
+// This checks the event's name, target, and every attribute it would resolve
+// (including those inherited from its spans and connection) for the text.
+fn full_text_matches<S: Storage>(context: &EventContext<'_, S>, text: &str) -> bool {
+    let event = context.event();
+    if event.name.contains(text) || event.target.contains(text) {
+        return true;
+    }
+
+    context.attributes().any(|(_, value)| match value {
+        Value::Str(s) => s.contains(text),
+        value => value.to_string().contains(text),
+    })
+}
+
+fn attribute_eq_ignore_case(attribute: Option<&Value>, value: &str) -> bool {
+    match attribute {
+        Some(Value::Str(s)) => s.eq_ignore_ascii_case(value),
+        _ => false,
+    }
+}
+
+fn validate_value_predicate(
+    value: &ValuePredicate,
+    comparison_validator: impl Fn(&ValueOperator, &str) -> Result<(), InputError> + Clone,
+    wildcard_validator: impl Fn(&str) -> Result<(), InputError> + Clone,
+    regex_validator: impl Fn(&str) -> Result<(), InputError> + Clone,
+    case_insensitive_validator: impl Fn(&str) -> Result<(), InputError> + Clone,
+) -> Result<(), InputError> {
+    match value {
+        ValuePredicate::Not(predicate) => validate_value_predicate(
+            predicate,
+            comparison_validator,
+            wildcard_validator,
+            regex_validator,
+            case_insensitive_validator,
+        ),
+        ValuePredicate::Comparison(op, value) => comparison_validator(op, value),
+        ValuePredicate::Wildcard(wildcard) => wildcard_validator(wildcard),
+        ValuePredicate::Regex(regex) => regex_validator(regex),
+        ValuePredicate::Contains(value) => wildcard_validator(&contains_to_wildcard(value)),
+        ValuePredicate::CaseInsensitive(value) => case_insensitive_validator(value),
+        ValuePredicate::And(predicates) => predicates.iter().try_for_each(|p| {
+            validate_value_predicate(
+                p,
+                comparison_validator.clone(),
+                wildcard_validator.clone(),
+                regex_validator.clone(),
+                case_insensitive_validator.clone(),
+            )
+        }),
+        ValuePredicate::List(items) if items.is_empty() => Err(InputError::InvalidListValue),
+        ValuePredicate::List(predicates) | ValuePredicate::Or(predicates) => {
+            predicates.iter().try_for_each(|p| {
+                validate_value_predicate(
+                    p,
+                    comparison_validator.clone(),
+                    wildcard_validator.clone(),
+                    regex_validator.clone(),
+                    case_insensitive_validator.clone(),
+                )
+            })
+        }
+    }
+}
+
+fn filterify_event_filter(
+    value: ValuePredicate,
+    comparison_filterifier: impl Fn(ValueOperator, String) -> Result<BasicEventFilter, InputError>
+        + Clone,
+    wildcard_filterifier: impl Fn(String) -> Result<BasicEventFilter, InputError> + Clone,
+    regex_filterifier: impl Fn(String) -> Result<BasicEventFilter, InputError> + Clone,
+    case_insensitive_filterifier: impl Fn(String) -> Result<BasicEventFilter, InputError> + Clone,
 ) -> Result<BasicEventFilter, InputError> {
     match value {
         ValuePredicate::Not(predicate) => {
@@ -2969,11 +4434,14 @@ fn filterify_event_filter(
                 comparison_filterifier,
                 wildcard_filterifier,
                 regex_filterifier,
+                case_insensitive_filterifier,
             )?)))
         }
         ValuePredicate::Comparison(op, value) => comparison_filterifier(op, value),
         ValuePredicate::Wildcard(wildcard) => wildcard_filterifier(wildcard),
         ValuePredicate::Regex(regex) => regex_filterifier(regex),
+        ValuePredicate::Contains(value) => wildcard_filterifier(contains_to_wildcard(&value)),
+        ValuePredicate::CaseInsensitive(value) => case_insensitive_filterifier(value),
         ValuePredicate::And(predicates) => Ok(BasicEventFilter::And(
             predicates
                 .into_iter()
@@ -2983,23 +4451,28 @@ fn filterify_event_filter(
                         comparison_filterifier.clone(),
                         wildcard_filterifier.clone(),
                         regex_filterifier.clone(),
+                        case_insensitive_filterifier.clone(),
                     )
                 })
                 .collect::<Result<_, _>>()?,
         )),
-        ValuePredicate::Or(predicates) => Ok(BasicEventFilter::Or(
-            predicates
-                .into_iter()
-                .map(|p| {
-                    filterify_event_filter(
-                        p,
-                        comparison_filterifier.clone(),
-                        wildcard_filterifier.clone(),
-                        regex_filterifier.clone(),
-                    )
-                })
-                .collect::<Result<_, _>>()?,
-        )),
+        ValuePredicate::List(items) if items.is_empty() => Err(InputError::InvalidListValue),
+        ValuePredicate::List(predicates) | ValuePredicate::Or(predicates) => {
+            Ok(BasicEventFilter::Or(
+                predicates
+                    .into_iter()
+                    .map(|p| {
+                        filterify_event_filter(
+                            p,
+                            comparison_filterifier.clone(),
+                            wildcard_filterifier.clone(),
+                            regex_filterifier.clone(),
+                            case_insensitive_filterifier.clone(),
+                        )
+                    })
+                    .collect::<Result<_, _>>()?,
+            ))
+        }
     }
 }
 
@@ -3009,6 +4482,7 @@ fn filterify_span_filter(
         + Clone,
     wildcard_filterifier: impl Fn(String) -> Result<BasicSpanFilter, InputError> + Clone,
     regex_filterifier: impl Fn(String) -> Result<BasicSpanFilter, InputError> + Clone,
+    case_insensitive_filterifier: impl Fn(String) -> Result<BasicSpanFilter, InputError> + Clone,
 ) -> Result<BasicSpanFilter, InputError> {
     match value {
         ValuePredicate::Not(predicate) => {
@@ -3017,11 +4491,14 @@ fn filterify_span_filter(
                 comparison_filterifier,
                 wildcard_filterifier,
                 regex_filterifier,
+                case_insensitive_filterifier,
             )?)))
         }
         ValuePredicate::Comparison(op, value) => comparison_filterifier(op, value),
         ValuePredicate::Wildcard(wildcard) => wildcard_filterifier(wildcard),
         ValuePredicate::Regex(regex) => regex_filterifier(regex),
+        ValuePredicate::Contains(value) => wildcard_filterifier(contains_to_wildcard(&value)),
+        ValuePredicate::CaseInsensitive(value) => case_insensitive_filterifier(value),
         ValuePredicate::And(predicates) => Ok(BasicSpanFilter::And(
             predicates
                 .into_iter()
@@ -3031,23 +4508,28 @@ fn filterify_span_filter(
                         comparison_filterifier.clone(),
                         wildcard_filterifier.clone(),
                         regex_filterifier.clone(),
+                        case_insensitive_filterifier.clone(),
                     )
                 })
                 .collect::<Result<_, _>>()?,
         )),
-        ValuePredicate::Or(predicates) => Ok(BasicSpanFilter::Or(
-            predicates
-                .into_iter()
-                .map(|p| {
-                    filterify_span_filter(
-                        p,
-                        comparison_filterifier.clone(),
-                        wildcard_filterifier.clone(),
-                        regex_filterifier.clone(),
-                    )
-                })
-                .collect::<Result<_, _>>()?,
-        )),
+        ValuePredicate::List(items) if items.is_empty() => Err(InputError::InvalidListValue),
+        ValuePredicate::List(predicates) | ValuePredicate::Or(predicates) => {
+            Ok(BasicSpanFilter::Or(
+                predicates
+                    .into_iter()
+                    .map(|p| {
+                        filterify_span_filter(
+                            p,
+                            comparison_filterifier.clone(),
+                            wildcard_filterifier.clone(),
+                            regex_filterifier.clone(),
+                            case_insensitive_filterifier.clone(),
+                        )
+                    })
+                    .collect::<Result<_, _>>()?,
+            ))
+        }
     }
 }
 
@@ -3057,6 +4539,7 @@ fn filterify_connection_filter(
         + Clone,
     wildcard_filterifier: impl Fn(String) -> Result<BasicConnectionFilter, InputError> + Clone,
     regex_filterifier: impl Fn(String) -> Result<BasicConnectionFilter, InputError> + Clone,
+    case_insensitive_filterifier: impl Fn(String) -> Result<BasicConnectionFilter, InputError> + Clone,
 ) -> Result<BasicConnectionFilter, InputError> {
     match value {
         ValuePredicate::Not(predicate) => Ok(BasicConnectionFilter::Not(Box::new(
@@ -3065,11 +4548,14 @@ fn filterify_connection_filter(
                 comparison_filterifier,
                 wildcard_filterifier,
                 regex_filterifier,
+                case_insensitive_filterifier,
             )?,
         ))),
         ValuePredicate::Comparison(op, value) => comparison_filterifier(op, value),
         ValuePredicate::Wildcard(wildcard) => wildcard_filterifier(wildcard),
         ValuePredicate::Regex(regex) => regex_filterifier(regex),
+        ValuePredicate::Contains(value) => wildcard_filterifier(contains_to_wildcard(&value)),
+        ValuePredicate::CaseInsensitive(value) => case_insensitive_filterifier(value),
         ValuePredicate::And(predicates) => Ok(BasicConnectionFilter::And(
             predicates
                 .into_iter()
@@ -3079,23 +4565,28 @@ fn filterify_connection_filter(
                         comparison_filterifier.clone(),
                         wildcard_filterifier.clone(),
                         regex_filterifier.clone(),
+                        case_insensitive_filterifier.clone(),
                     )
                 })
                 .collect::<Result<_, _>>()?,
         )),
-        ValuePredicate::Or(predicates) => Ok(BasicConnectionFilter::Or(
-            predicates
-                .into_iter()
-                .map(|p| {
-                    filterify_connection_filter(
-                        p,
-                        comparison_filterifier.clone(),
-                        wildcard_filterifier.clone(),
-                        regex_filterifier.clone(),
-                    )
-                })
-                .collect::<Result<_, _>>()?,
-        )),
+        ValuePredicate::List(items) if items.is_empty() => Err(InputError::InvalidListValue),
+        ValuePredicate::List(predicates) | ValuePredicate::Or(predicates) => {
+            Ok(BasicConnectionFilter::Or(
+                predicates
+                    .into_iter()
+                    .map(|p| {
+                        filterify_connection_filter(
+                            p,
+                            comparison_filterifier.clone(),
+                            wildcard_filterifier.clone(),
+                            regex_filterifier.clone(),
+                            case_insensitive_filterifier.clone(),
+                        )
+                    })
+                    .collect::<Result<_, _>>()?,
+            ))
+        }
     }
 }
 
@@ -3107,7 +4598,7 @@ pub enum Order {
 }
 
 #[allow(dead_code)]
-pub trait BoundSearch<T> {
+pub(crate) trait BoundSearch<T> {
     // This finds the first index of an item that is not less than the provided
     // item. This works via a binary-search algorithm.
     //
@@ -3266,6 +4757,291 @@ mod tests {
         assert_eq!([0, 0, 2, 2].upper_bound_via_expansion(&1), 2);
     }
 
+    #[test]
+    fn span_filter_size_hint_bounds() {
+        let timestamps = |n: u64| {
+            (1..=n)
+                .map(|i| Timestamp::new(i).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let a = timestamps(10);
+        let b = timestamps(20);
+
+        // spans may be skipped for closing before the start, so no filter has
+        // a guaranteed minimum
+        assert_eq!(
+            IndexedSpanFilter::Single(&a, None).size_hint(),
+            (0, Some(10))
+        );
+        assert_eq!(
+            IndexedSpanFilter::Stratified(&b, 0..10, None).size_hint(),
+            (0, Some(20))
+        );
+
+        let and = IndexedSpanFilter::And(vec![
+            IndexedSpanFilter::Single(&a, None),
+            IndexedSpanFilter::Stratified(&b, 0..10, None),
+        ]);
+        assert_eq!(and.size_hint(), (0, Some(10)));
+
+        let or = IndexedSpanFilter::Or(vec![
+            IndexedSpanFilter::Single(&a, None),
+            IndexedSpanFilter::Stratified(&b, 0..10, None),
+        ]);
+        assert_eq!(or.size_hint(), (0, Some(30)));
+
+        assert_eq!(IndexedSpanFilter::And(vec![]).size_hint(), (0, Some(0)));
+        assert_eq!(IndexedSpanFilter::Or(vec![]).size_hint(), (0, Some(0)));
+    }
+
+    #[test]
+    fn optimize_orders_nested_span_filters_by_estimate() {
+        let timestamps = |n: u64| {
+            (1..=n)
+                .map(|i| Timestamp::new(i).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let durations = timestamps(100);
+        let attributes = timestamps(10);
+        let names = timestamps(50);
+
+        // like `#duration: >1s @env: prod` where only one duration bucket
+        // applies, nested in an AND with a name filter
+        let mut filter = IndexedSpanFilter::And(vec![
+            IndexedSpanFilter::Or(vec![IndexedSpanFilter::Stratified(&durations, 0..10, None)]),
+            IndexedSpanFilter::And(vec![
+                IndexedSpanFilter::Single(&names, None),
+                IndexedSpanFilter::Single(&attributes, None),
+            ]),
+        ]);
+
+        filter.optimize();
+
+        let IndexedSpanFilter::And(filters) = &filter else {
+            panic!("expected AND filter");
+        };
+
+        let counts = filters
+            .iter()
+            .map(IndexedSpanFilter::estimate_count)
+            .collect::<Vec<_>>();
+
+        assert_eq!(counts, [10, 50, 100]);
+        assert!(matches!(filters[2], IndexedSpanFilter::Stratified(_, _, _)));
+    }
+
+    #[test]
+    fn optimize_prefers_non_stratified_span_filter_on_tie() {
+        let timestamps = (1..=10)
+            .map(|i| Timestamp::new(i).unwrap())
+            .collect::<Vec<_>>();
+
+        let mut filter = IndexedSpanFilter::And(vec![
+            IndexedSpanFilter::Stratified(&timestamps, 0..10, None),
+            IndexedSpanFilter::Single(&timestamps, None),
+        ]);
+
+        filter.optimize();
+
+        let IndexedSpanFilter::And(filters) = &filter else {
+            panic!("expected AND filter");
+        };
+
+        assert!(matches!(filters[0], IndexedSpanFilter::Single(_, _)));
+        assert!(matches!(filters[1], IndexedSpanFilter::Stratified(_, _, _)));
+    }
+
+    #[test]
+    fn event_filter_search_finds_all_matches() {
+        let timestamps = |keep: fn(u64) -> bool| {
+            (2..=2000)
+                .filter(|i| keep(*i))
+                .map(|i| Timestamp::new(i).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // a dense index, a sparse one, and one with clustered runs
+        let dense = timestamps(|i| i % 5 != 0);
+        let sparse = timestamps(|i| i % 97 == 0);
+        let clustered = timestamps(|i| (i / 100) % 3 == 0);
+
+        fn search_all(mut filter: IndexedEventFilter<'_>, order: Order) -> Vec<Timestamp> {
+            let storage = crate::TransientStorage::new();
+            let cache = EventContextCache::new();
+            let (mut entry, bound) = match order {
+                Order::Asc => (Timestamp::MIN, Timestamp::MAX),
+                Order::Desc => (Timestamp::new(2000).unwrap(), Timestamp::MIN),
+            };
+
+            let mut found = vec![];
+            while let Some(found_entry) = filter.search(&storage, &cache, entry, order, bound) {
+                found.push(found_entry);
+                entry = match order {
+                    Order::Asc => found_entry.saturating_add(1),
+                    Order::Desc => Timestamp::new(found_entry.get() - 1).unwrap(),
+                };
+            }
+            found
+        }
+
+        let and = || {
+            IndexedEventFilter::And(vec![
+                IndexedEventFilter::Single(&dense, None),
+                IndexedEventFilter::Single(&clustered, None),
+            ])
+        };
+        let or = || {
+            IndexedEventFilter::Or(vec![
+                IndexedEventFilter::Single(&sparse, None),
+                IndexedEventFilter::Single(&clustered, None),
+            ])
+        };
+        let not = || {
+            IndexedEventFilter::Not(
+                &dense,
+                Box::new(IndexedEventFilter::Single(&clustered, None)),
+            )
+        };
+
+        let expected_and = dense
+            .iter()
+            .filter(|t| clustered.contains(t))
+            .cloned()
+            .collect::<Vec<_>>();
+        let expected_or = (2..=2000)
+            .map(|i| Timestamp::new(i).unwrap())
+            .filter(|t| sparse.contains(t) || clustered.contains(t))
+            .collect::<Vec<_>>();
+        let expected_not = dense
+            .iter()
+            .filter(|t| !clustered.contains(t))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let reversed = |mut expected: Vec<Timestamp>| {
+            expected.reverse();
+            expected
+        };
+
+        assert_eq!(search_all(and(), Order::Asc), expected_and);
+        assert_eq!(search_all(and(), Order::Desc), reversed(expected_and));
+        assert_eq!(search_all(or(), Order::Asc), expected_or);
+        assert_eq!(search_all(or(), Order::Desc), reversed(expected_or));
+        assert_eq!(search_all(not(), Order::Asc), expected_not);
+        assert_eq!(search_all(not(), Order::Desc), reversed(expected_not));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_span_search_matches_sequential() {
+        use std::collections::BTreeMap;
+
+        use crate::{NewConnection, NewCreateSpanEvent, NewSpanEvent, NewSpanEventKind};
+        use crate::{Query, TransientStorage};
+
+        let mut engine = RawEngine::new(TransientStorage::new());
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        // durations up to 600ms spread the spans across the duration strata,
+        // and some are left open
+        for i in 1..=300u64 {
+            let span_id = i.try_into().unwrap();
+            let created_at = i * 1000;
+            engine
+                .insert_span_event(NewSpanEvent {
+                    connection_key,
+                    seq: None,
+                    timestamp: Timestamp::new(created_at).unwrap(),
+                    span_id,
+                    kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                        parent_id: None,
+                        target: "crate::filter::tests".to_owned(),
+                        name: "span".to_owned(),
+                        level: 2,
+                        file_name: None,
+                        file_line: None,
+                        fields: BTreeMap::new(),
+                    }),
+                })
+                .unwrap();
+
+            if i % 7 != 0 {
+                let duration = (i * 7919) % 600_000 + 1;
+                engine
+                    .insert_span_event(NewSpanEvent {
+                        connection_key,
+                        seq: None,
+                        timestamp: Timestamp::new(created_at + duration).unwrap(),
+                        span_id,
+                        kind: NewSpanEventKind::Close,
+                    })
+                    .unwrap();
+            }
+        }
+
+        let query = |filter: &str, order: Order| Query {
+            filter: FilterPredicate::parse(filter).unwrap(),
+            order,
+            limit: usize::MAX,
+            start: Timestamp::new(100_000).unwrap(),
+            end: Timestamp::new(800_000).unwrap(),
+            previous: None,
+        };
+
+        for filter in [
+            "#duration: >5ms",
+            "#duration: (>1ms AND <300ms)",
+            "#duration: >5ms #level: INFO",
+            "#level: INFO",
+        ] {
+            for order in [Order::Asc, Order::Desc] {
+                engine.parallel_search = None;
+                let sequential = IndexedSpanFilterIterator::new(query(filter, order), &engine)
+                    .collect::<Vec<_>>();
+
+                engine.parallel_search = Some(parallel_search::<TransientStorage>);
+                let parallel = IndexedSpanFilterIterator::new(query(filter, order), &engine)
+                    .collect::<Vec<_>>();
+
+                // there are more than a batch of them
+                assert!(sequential.len() > PARALLEL_BATCH_SIZE);
+                assert_eq!(parallel, sequential, "for {filter}");
+
+                // smaller batches fill up before others, which leaves entries
+                // beyond them to be found again in the next batch
+                for limit in [1, 2, 5] {
+                    let mut iter = IndexedSpanFilterIterator::new(query(filter, order), &engine);
+                    let mut batched = vec![];
+                    loop {
+                        let batch = iter.filter.search_parallel(
+                            iter.storage,
+                            iter.curr_key,
+                            iter.order,
+                            iter.end_key,
+                            iter.start_key,
+                            limit,
+                        );
+                        let Some(&last) = batch.last() else {
+                            break;
+                        };
+
+                        batched.extend(batch);
+                        iter.curr_key = match order {
+                            Order::Asc => last.saturating_add(1),
+                            Order::Desc => Timestamp::new(last.get() - 1).unwrap(),
+                        };
+                    }
+
+                    assert_eq!(batched, sequential, "for {filter} in batches of {limit}");
+                }
+            }
+        }
+    }
+
     // #[test]
     // fn parse_level_into_filter() {
     //     assert_eq!(
@@ -255,6 +255,8 @@ impl ValueFilter {
             ValueComparison::None
         };
 
+        // booleans are canonically written as `true` and `false` (same as their
+        // `Display`), and strings with that exact text are matched separately
         let bools = if value == "true" {
             ValueComparison::Compare(operator, true)
         } else if value == "false" {
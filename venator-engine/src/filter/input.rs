@@ -4,12 +4,33 @@ use serde::{Deserialize, Serialize};
 
 use crate::models::ValueOperator;
 
-#[derive(Debug)]
-pub struct SyntaxError;
+/// An error from parsing a filter. The `start` and `end` are byte offsets into
+/// the input covering the offending text (they are equal if the input ended
+/// unexpectedly).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub start: usize,
+    pub end: usize,
+    pub message: &'static str,
+}
+
+impl SyntaxError {
+    fn at(input: &str, start: usize, message: &'static str) -> SyntaxError {
+        let len = input[start..]
+            .find(char::is_whitespace)
+            .unwrap_or(input.len() - start);
+
+        SyntaxError {
+            start,
+            end: start + len,
+            message,
+        }
+    }
+}
 
 impl Display for SyntaxError {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
-        write!(f, "syntax error")
+        write!(f, "{} at position {}", self.message, self.start)
     }
 }
 
@@ -26,6 +47,9 @@ pub enum ValuePredicate {
     Comparison(ValueOperator, String),
     Wildcard(String),
     Regex(String),
+    Contains(String),
+    CaseInsensitive(String),
+    List(Vec<ValuePredicate>),
     And(Vec<ValuePredicate>),
     Or(Vec<ValuePredicate>),
 }
@@ -37,35 +61,35 @@ impl Display for ValuePredicate {
             ValuePredicate::Comparison(value_operator, value) => match value_operator {
                 ValueOperator::Gt => {
                     if needs_escapes(value) {
-                        write!(f, ">{value:?}")
+                        write!(f, ">{}", Quoted(value))
                     } else {
                         write!(f, ">{value}")
                     }
                 }
                 ValueOperator::Gte => {
                     if needs_escapes(value) {
-                        write!(f, ">={value:?}")
+                        write!(f, ">={}", Quoted(value))
                     } else {
                         write!(f, ">={value}")
                     }
                 }
                 ValueOperator::Eq => {
                     if needs_escapes(value) {
-                        write!(f, "{value:?}")
+                        write!(f, "{}", Quoted(value))
                     } else {
                         write!(f, "{value}")
                     }
                 }
                 ValueOperator::Lt => {
                     if needs_escapes(value) {
-                        write!(f, "<{value:?}")
+                        write!(f, "<{}", Quoted(value))
                     } else {
                         write!(f, "<{value}")
                     }
                 }
                 ValueOperator::Lte => {
                     if needs_escapes(value) {
-                        write!(f, "<={value:?}")
+                        write!(f, "<={}", Quoted(value))
                     } else {
                         write!(f, "<={value}")
                     }
@@ -81,6 +105,40 @@ impl Display for ValuePredicate {
             ValuePredicate::Regex(regex) => {
                 write!(f, "/{regex}/")
             }
+            ValuePredicate::Contains(value) => {
+                if needs_escapes(value) {
+                    write!(f, "~={}", Quoted(value))
+                } else {
+                    write!(f, "~={value}")
+                }
+            }
+            ValuePredicate::CaseInsensitive(value) => {
+                if needs_escapes(value) {
+                    write!(f, "{}/i", Quoted(value))
+                } else {
+                    write!(f, "{value}/i")
+                }
+            }
+            ValuePredicate::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    match item {
+                        ValuePredicate::Comparison(ValueOperator::Eq, value)
+                            if value.contains([',', ']']) =>
+                        {
+                            write!(f, "{}", Quoted(value))?;
+                        }
+                        ValuePredicate::Wildcard(wildcard) if wildcard.contains([',', ']']) => {
+                            write!(f, "\"{}\"", escape_wildcard(wildcard))?;
+                        }
+                        _ => write!(f, "{item}")?,
+                    }
+                }
+                write!(f, "]")
+            }
             ValuePredicate::And(inners) => {
                 write!(f, "({}", inners[0])?;
                 for inner in &inners[1..] {
@@ -114,9 +172,31 @@ pub enum FilterPredicate {
 
 impl FilterPredicate {
     pub fn parse(input: &str) -> Result<Vec<FilterPredicate>, SyntaxError> {
-        parsers::predicates(input)
-            .map(|(_, predicates)| predicates)
-            .map_err(|_| SyntaxError)
+        match parsers::predicates(input) {
+            Ok((_, predicates)) => Ok(predicates),
+            Err(nom::Err::Error(err)) => {
+                let offset = input.len() - err.input.len();
+                let (position, message) = parsers::diagnose(&input[offset..]);
+
+                Err(SyntaxError::at(input, offset + position, message))
+            }
+            Err(nom::Err::Failure(err)) => {
+                // lists are the only construct that can't backtrack once
+                // started, so the failure is already at the exact position
+                let offset = input.len() - err.input.len();
+
+                Err(SyntaxError::at(
+                    input,
+                    offset,
+                    "expected ']' to close the list",
+                ))
+            }
+            Err(nom::Err::Incomplete(_)) => Err(SyntaxError::at(
+                input,
+                input.len(),
+                "unexpected end of filter",
+            )),
+        }
     }
 }
 
@@ -159,7 +239,7 @@ impl Display for FilterPredicateSingle {
         }
 
         if name_needs_escapes(&self.property) {
-            write!(f, "{:?}", self.property)?;
+            write!(f, "{}", Quoted(&self.property))?;
         } else {
             write!(f, "{}", self.property)?;
         }
@@ -171,8 +251,9 @@ impl Display for FilterPredicateSingle {
 }
 
 fn needs_escapes(s: &str) -> bool {
-    s.contains(['"', '\\', '/', '#', '@', ':', '<', '>', '=', '!'])
-        || s.contains(|c: char| c.is_whitespace())
+    s.contains([
+        '"', '\\', '/', '#', '@', ':', '<', '>', '=', '!', '|', '[', '(', ')',
+    ]) || s.contains(|c: char| c.is_whitespace())
         || s.is_empty()
 }
 
@@ -180,6 +261,24 @@ fn escape_wildcard(s: &str) -> String {
     s.replace('\"', "\\\"")
 }
 
+/// Writes the value within double-quotes, escaping only `"` and `\` so that it
+/// parses back to the same value (`Debug` would also escape things like
+/// newlines, which the parser doesn't understand).
+struct Quoted<'a>(&'a str);
+
+impl Display for Quoted<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "\"")?;
+        for c in self.0.chars() {
+            if matches!(c, '"' | '\\') {
+                write!(f, "\\")?;
+            }
+            write!(f, "{c}")?;
+        }
+        write!(f, "\"")
+    }
+}
+
 fn name_needs_escapes(s: &str) -> bool {
     s.contains(|c: char| !c.is_alphabetic() && c != '.' && c != '_') || s.is_empty()
 }
@@ -190,9 +289,9 @@ mod parsers {
     use nom::branch::alt;
     use nom::bytes::complete::{escaped, tag, take_while, take_while1};
     use nom::character::complete::{char, none_of, one_of};
-    use nom::combinator::{cut, eof, map, map_res, opt};
+    use nom::combinator::{cut, eof, map, map_opt, map_res, opt};
     use nom::multi::{many0, many0_count, separated_list0};
-    use nom::sequence::{delimited, tuple};
+    use nom::sequence::{delimited, terminated, tuple};
     use nom::IResult;
 
     enum GroupSeparator {
@@ -309,6 +408,7 @@ mod parsers {
             alt((
                 map(tag("AND"), |_| GroupSeparator::And),
                 map(tag("OR"), |_| GroupSeparator::Or),
+                map(tag("|"), |_| GroupSeparator::Or),
             )),
             whitespace,
             value,
@@ -387,9 +487,77 @@ mod parsers {
         Ok((input, ValuePredicate::Regex(regex)))
     }
 
+    fn unquoted_list_item(input: &str) -> IResult<&str, &str> {
+        take_while1(|c: char| !c.is_whitespace() && c != '"' && c != ',' && c != '[' && c != ']')(
+            input,
+        )
+    }
+
+    fn list_item(input: &str) -> IResult<&str, ValuePredicate> {
+        alt((
+            map(quoted_value, |v| match v {
+                Some(v) if v.contains('*') => ValuePredicate::Wildcard(unescape_wildcard(v)),
+                Some(v) => ValuePredicate::Comparison(ValueOperator::Eq, unescape(v)),
+                None => ValuePredicate::Comparison(ValueOperator::Eq, String::new()),
+            }),
+            map(unquoted_list_item, |v| {
+                if v.contains('*') {
+                    ValuePredicate::Wildcard(v.to_owned())
+                } else {
+                    ValuePredicate::Comparison(ValueOperator::Eq, v.to_owned())
+                }
+            }),
+        ))(input)
+    }
+
+    fn list_value(input: &str) -> IResult<&str, ValuePredicate> {
+        let (input, _) = char('[')(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, items) = cut(separated_list0(
+            tuple((whitespace, char(','), whitespace)),
+            list_item,
+        ))(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, _) = cut(char(']'))(input)?;
+
+        Ok((input, ValuePredicate::List(items)))
+    }
+
+    fn case_insensitive_value(input: &str) -> IResult<&str, ValuePredicate> {
+        let (input, value) = alt((
+            map(terminated(quoted_value, tag("/i")), |v| {
+                unescape(v.unwrap_or_default())
+            }),
+            map_opt(unquoted_value, |v| {
+                v.strip_suffix("/i")
+                    .filter(|v| !v.is_empty())
+                    .map(str::to_owned)
+            }),
+        ))(input)?;
+
+        Ok((input, ValuePredicate::CaseInsensitive(value)))
+    }
+
+    fn contains_value(input: &str) -> IResult<&str, ValuePredicate> {
+        let (input, _) = tag("~=")(input)?;
+        let (input, value) = alt((
+            map(quoted_value, |v| unescape(v.unwrap_or_default())),
+            map(unquoted_value, |v| v.to_owned()),
+        ))(input)?;
+
+        Ok((input, ValuePredicate::Contains(value)))
+    }
+
     fn value(input: &str) -> IResult<&str, ValuePredicate> {
         let (input, not_count) = many0_count(not)(input)?;
-        let (input, value) = alt((grouped_value, regex_value, bare_value))(input)?;
+        let (input, value) = alt((
+            grouped_value,
+            list_value,
+            regex_value,
+            contains_value,
+            case_insensitive_value,
+            bare_value,
+        ))(input)?;
 
         let value = if not_count % 2 == 1 {
             ValuePredicate::Not(Box::new(value))
@@ -417,6 +585,7 @@ mod parsers {
                 && c != ':'
                 && c != '('
                 && c != ')'
+                && c != '|'
         })(input)
     }
 
@@ -503,7 +672,7 @@ mod parsers {
 
         let predicate = FilterPredicate::Single(FilterPredicateSingle {
             property_kind: kind,
-            property: property.to_owned(),
+            property: unescape(property),
             value,
         });
 
@@ -514,7 +683,7 @@ mod parsers {
         alt((predicate_grouped, predicate_single))(input)
     }
 
-    pub fn predicates(input: &str) -> IResult<&str, Vec<FilterPredicate>> {
+    pub(super) fn predicates(input: &str) -> IResult<&str, Vec<FilterPredicate>> {
         let (input, _) = whitespace(input)?;
         let (input, list) = separated_list0(expect_whitespace, predicate)(input)?;
         let (input, _) = whitespace(input)?;
@@ -523,6 +692,72 @@ mod parsers {
         Ok((input, list))
     }
 
+    /// This is called on the input remaining where `predicates` stopped to
+    /// find a more specific location and message for what went wrong. The
+    /// returned position is relative to `input`.
+    pub(super) fn diagnose(input: &str) -> (usize, &'static str) {
+        let offset = |rest: &str| input.len() - rest.len();
+
+        if input.is_empty() {
+            return (0, "unexpected end of filter");
+        }
+
+        if input.starts_with(')') {
+            return (0, "unmatched ')'");
+        }
+
+        if let Some(inner) = input.strip_prefix('(') {
+            return match predicate_list(inner) {
+                Ok((rest, _)) if rest.starts_with(')') => {
+                    (offset(&rest[1..]), "expected whitespace between predicates")
+                }
+                Ok(("", _)) => (input.len(), "expected ')'"),
+                Ok((rest, _)) => match rest.strip_prefix("AND").or(rest.strip_prefix("OR")) {
+                    // the separator was fine, so the predicate after it wasn't
+                    Some(next) => {
+                        let next = next.trim_start();
+                        let (position, message) = diagnose(next);
+                        (offset(next) + position, message)
+                    }
+                    None => (offset(rest), "expected AND, OR, or ')'"),
+                },
+                Err(nom::Err::Error(err) | nom::Err::Failure(err)) => {
+                    let (position, message) = diagnose(err.input);
+                    (offset(err.input) + position, message)
+                }
+                Err(nom::Err::Incomplete(_)) => (input.len(), "unexpected end of filter"),
+            };
+        }
+
+        let Ok((rest, _)) = property(input) else {
+            return (0, "expected a property");
+        };
+        let (rest, _) = whitespace(rest).unwrap_or((rest, ""));
+        let Ok((rest, _)) = char::<_, nom::error::Error<&str>>(':')(rest) else {
+            return (offset(rest), "expected ':' after property");
+        };
+        let (rest, _) = whitespace(rest).unwrap_or((rest, ""));
+        if rest.is_empty() {
+            return (offset(rest), "expected a value after ':'");
+        }
+
+        // a predicate that parses on its own must have been stuck to the end
+        // of the previous one
+        match value(rest) {
+            Ok(("", _)) => (0, "expected whitespace between predicates"),
+            Ok((rest, _)) => (offset(rest), "expected whitespace between predicates"),
+            Err(_) if starts_unterminated_quote(rest) => {
+                (offset(rest), "unterminated quoted value")
+            }
+            Err(_) => (offset(rest), "invalid value"),
+        }
+    }
+
+    fn starts_unterminated_quote(input: &str) -> bool {
+        let input = input.trim_start_matches(['!', '<', '>', '=', '~', ' ']);
+        input.starts_with('"') && quoted_value(input).is_err()
+    }
+
     fn unescape(input: &str) -> String {
         let mut input = input.to_owned();
         let mut escaped = false;
@@ -612,5 +847,111 @@ mod tests {
         assert!(FilterPredicate::parse("prop:a #prop:b").is_ok());
         assert!(FilterPredicate::parse("  prop:a #prop:b  ").is_ok());
         assert!(FilterPredicate::parse("prop: !4 #prop: >10 @prop: <=20").is_ok());
+        assert!(FilterPredicate::parse("@prop: ~=curl #prop: !~=\"a b\"").is_ok());
+        assert!(FilterPredicate::parse("@prop: [a, \"b c\",d] @prop: (1|2 | 3)").is_ok());
+        assert!(FilterPredicate::parse("@prop: [a, b").is_err());
+        assert!(FilterPredicate::parse("@prop: value/i @prop: \"a b\"/i").is_ok());
+    }
+
+    #[test]
+    fn parse_quoted_values_with_spaces_and_colons() {
+        let predicates =
+            FilterPredicate::parse(r#"@path:"/a b/c:d" @msg: "say \"hi\" \\ bye""#).unwrap();
+
+        let [FilterPredicate::Single(path), FilterPredicate::Single(msg)] = &predicates[..] else {
+            panic!("expected two single predicates");
+        };
+        assert_eq!(path.property, "path");
+        assert!(
+            matches!(&path.value, ValuePredicate::Comparison(ValueOperator::Eq, v) if v == "/a b/c:d")
+        );
+        assert!(
+            matches!(&msg.value, ValuePredicate::Comparison(ValueOperator::Eq, v) if v == r#"say "hi" \ bye"#)
+        );
+
+        let text = predicates
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(text, r#"@path: "/a b/c:d" @msg: "say \"hi\" \\ bye""#);
+        assert_eq!(
+            FilterPredicate::parse(&text)
+                .unwrap()
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+            text
+        );
+
+        let predicates =
+            FilterPredicate::parse("@\"my attr\": \"a(b\" @x: \"line\nbreak\"").unwrap();
+        for predicate in &predicates {
+            let text = predicate.to_string();
+            let reparsed = FilterPredicate::parse(&text).unwrap();
+            assert_eq!(reparsed[0].to_string(), text);
+        }
+        assert_eq!(predicates[0].to_string(), r#"@"my attr": "a(b""#);
+    }
+
+    #[test]
+    fn parse_unterminated_quote() {
+        assert_eq!(
+            FilterPredicate::parse(r#"@message: "connection refused"#).unwrap_err(),
+            SyntaxError {
+                start: 10,
+                end: 21,
+                message: "unterminated quoted value",
+            }
+        );
+    }
+
+    #[test]
+    fn parse_error_positions() {
+        let error = |input| FilterPredicate::parse(input).unwrap_err();
+
+        assert_eq!(
+            error("#level: INFO @attr:"),
+            SyntaxError {
+                start: 19,
+                end: 19,
+                message: "expected a value after ':'",
+            }
+        );
+        assert_eq!(
+            error("prop:aprop:b"),
+            SyntaxError {
+                start: 10,
+                end: 12,
+                message: "expected whitespace between predicates",
+            }
+        );
+        assert_eq!(
+            error("@a: 1 @b: [x, y"),
+            SyntaxError {
+                start: 15,
+                end: 15,
+                message: "expected ']' to close the list",
+            }
+        );
+        assert_eq!(
+            error("(@a: 1 OR @b: 2"),
+            SyntaxError {
+                start: 15,
+                end: 15,
+                message: "expected ')'",
+            }
+        );
+        assert_eq!(
+            error("@a: 1 (@b: 2 OR @c)"),
+            SyntaxError {
+                start: 18,
+                end: 19,
+                message: "expected ':' after property",
+            }
+        );
+        assert_eq!(error("@a: 1 )").start, 6);
+        assert_eq!(error("@a: 1 )").to_string(), "unmatched ')' at position 6");
     }
 }
@@ -1,8 +1,10 @@
 use std::collections::{BTreeMap, HashMap};
 use std::ops::Range;
 
+use serde::{Deserialize, Serialize};
+
 use crate::filter::BoundSearch;
-use crate::models::{EventKey, Timestamp, Value};
+use crate::models::{Connection, EventKey, Timestamp, Value};
 use crate::{ConnectionKey, EventContext, SpanContext, SpanKey, Storage};
 
 mod attribute;
@@ -11,7 +13,8 @@ mod util;
 pub(crate) use attribute::AttributeIndex;
 pub(crate) use util::IndexExt;
 
-pub struct EventIndexes {
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EventIndexes {
     pub all: Vec<Timestamp>,
     pub levels: [Vec<Timestamp>; 5],
     pub connections: BTreeMap<ConnectionKey, Vec<Timestamp>>,
@@ -23,7 +26,7 @@ pub struct EventIndexes {
 }
 
 impl EventIndexes {
-    pub fn new() -> EventIndexes {
+    pub(crate) fn new() -> EventIndexes {
         EventIndexes {
             all: vec![],
             levels: [Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new()],
@@ -36,7 +39,7 @@ impl EventIndexes {
         }
     }
 
-    pub fn update_with_new_event<S: Storage>(&mut self, context: &EventContext<'_, S>) {
+    pub(crate) fn update_with_new_event<S: Storage>(&mut self, context: &EventContext<'_, S>) {
         let event = context.event();
         let event_key = event.timestamp;
 
@@ -82,7 +85,7 @@ impl EventIndexes {
         }
     }
 
-    pub fn update_with_new_field_on_parent<S: Storage>(
+    pub(crate) fn update_with_new_field_on_parent<S: Storage>(
         &mut self,
         context: &EventContext<'_, S>,
         parent_key: Timestamp,
@@ -106,7 +109,7 @@ impl EventIndexes {
         }
     }
 
-    pub fn remove_events(&mut self, events: &[EventKey]) {
+    pub(crate) fn remove_events(&mut self, events: &[EventKey]) {
         self.all.remove_list_sorted(events);
 
         for level_index in &mut self.levels {
@@ -120,6 +123,8 @@ impl EventIndexes {
         for target_index in self.targets.values_mut() {
             target_index.remove_list_sorted(events);
         }
+        self.targets
+            .retain(|_, target_index| !target_index.is_empty());
 
         for filename_index in self.filenames.values_mut() {
             filename_index.remove_list_sorted(events);
@@ -136,20 +141,21 @@ impl EventIndexes {
         }
     }
 
-    pub fn remove_spans(&mut self, spans: &[SpanKey]) {
+    pub(crate) fn remove_spans(&mut self, spans: &[SpanKey]) {
         for span_key in spans {
             self.descendents.remove(span_key);
         }
     }
 
-    pub fn remove_connections(&mut self, connections: &[ConnectionKey]) {
+    pub(crate) fn remove_connections(&mut self, connections: &[ConnectionKey]) {
         for connection_key in connections {
             self.connections.remove(connection_key);
         }
     }
 }
 
-pub struct SpanIndexes {
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SpanIndexes {
     pub all: Vec<Timestamp>,
     pub levels: [Vec<Timestamp>; 5],
     pub durations: SpanDurationIndex,
@@ -158,12 +164,13 @@ pub struct SpanIndexes {
     pub targets: BTreeMap<String, Vec<Timestamp>>,
     pub filenames: BTreeMap<String, Vec<Timestamp>>,
     pub descendents: HashMap<Timestamp, Vec<Timestamp>>,
+    pub children: HashMap<Timestamp, Vec<Timestamp>>,
     pub roots: Vec<Timestamp>,
     pub attributes: BTreeMap<String, AttributeIndex>,
 }
 
 impl SpanIndexes {
-    pub fn new() -> SpanIndexes {
+    pub(crate) fn new() -> SpanIndexes {
         SpanIndexes {
             all: vec![],
             levels: [Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new()],
@@ -173,12 +180,13 @@ impl SpanIndexes {
             targets: BTreeMap::new(),
             filenames: BTreeMap::new(),
             descendents: HashMap::new(),
+            children: HashMap::new(),
             roots: Vec::new(),
             attributes: BTreeMap::new(),
         }
     }
 
-    pub fn update_with_new_span<S: Storage>(&mut self, context: &SpanContext<'_, S>) {
+    pub(crate) fn update_with_new_span<S: Storage>(&mut self, context: &SpanContext<'_, S>) {
         let span = context.span();
         let span_key = span.created_at;
 
@@ -229,7 +237,11 @@ impl SpanIndexes {
             descendent_index.insert(idx, span_key);
         }
 
-        if span.parent_key.is_none() {
+        if let Some(parent_key) = span.parent_key {
+            let children_index = self.children.entry(parent_key).or_default();
+            let idx = children_index.upper_bound_via_expansion(&span_key);
+            children_index.insert(idx, span_key);
+        } else {
             let idx = self.roots.upper_bound_via_expansion(&span_key);
             self.roots.insert(idx, span_key);
         }
@@ -244,7 +256,7 @@ impl SpanIndexes {
         }
     }
 
-    pub fn update_with_new_field_on_parent<S: Storage>(
+    pub(crate) fn update_with_new_field_on_parent<S: Storage>(
         &mut self,
         context: &SpanContext<'_, S>,
         parent_key: Timestamp,
@@ -268,7 +280,7 @@ impl SpanIndexes {
         }
     }
 
-    pub fn update_with_closed(&mut self, span_key: Timestamp, closed_at: Timestamp) {
+    pub(crate) fn update_with_closed(&mut self, span_key: Timestamp, closed_at: Timestamp) {
         let idx = self.durations.open.lower_bound(&span_key);
         if self.durations.open[idx] == span_key {
             self.durations.open.remove(idx);
@@ -291,7 +303,7 @@ impl SpanIndexes {
         index.insert(idx, span_key);
     }
 
-    pub fn remove_spans(&mut self, spans: &[SpanKey]) {
+    pub(crate) fn remove_spans(&mut self, spans: &[SpanKey]) {
         self.all.remove_list_sorted(spans);
 
         for level_index in &mut self.levels {
@@ -311,6 +323,8 @@ impl SpanIndexes {
         for target_index in self.targets.values_mut() {
             target_index.remove_list_sorted(spans);
         }
+        self.targets
+            .retain(|_, target_index| !target_index.is_empty());
 
         for filename_index in self.filenames.values_mut() {
             filename_index.remove_list_sorted(spans);
@@ -320,6 +334,14 @@ impl SpanIndexes {
             descendent_index.remove_list_sorted(spans);
         }
 
+        for span_key in spans {
+            self.children.remove(span_key);
+        }
+
+        for children_index in self.children.values_mut() {
+            children_index.remove_list_sorted(spans);
+        }
+
         self.roots.remove_list_sorted(spans);
 
         for attribute_index in self.attributes.values_mut() {
@@ -327,14 +349,83 @@ impl SpanIndexes {
         }
     }
 
-    pub fn remove_connections(&mut self, connections: &[ConnectionKey]) {
+    pub(crate) fn remove_connections(&mut self, connections: &[ConnectionKey]) {
         for connection_key in connections {
             self.connections.remove(connection_key);
         }
     }
 }
 
-pub struct SpanDurationIndex {
+// These aren't saved with the other indexes since all connections are loaded
+// on startup regardless, so they are rebuilt then.
+pub(crate) struct ConnectionIndexes {
+    pub all: Vec<Timestamp>,
+    pub connected: Vec<Timestamp>, // connections that haven't disconnected yet
+    pub disconnected: Vec<Timestamp>, // connections that have
+    pub attributes: BTreeMap<String, AttributeIndex>,
+}
+
+impl ConnectionIndexes {
+    pub(crate) fn new() -> ConnectionIndexes {
+        ConnectionIndexes {
+            all: vec![],
+            connected: vec![],
+            disconnected: vec![],
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn update_with_new_connection(&mut self, connection: &Connection) {
+        let connection_key = connection.key();
+
+        let idx = self.all.upper_bound_via_expansion(&connection_key);
+        self.all.insert(idx, connection_key);
+
+        let state_index = match connection.disconnected_at {
+            None => &mut self.connected,
+            Some(_) => &mut self.disconnected,
+        };
+        let idx = state_index.upper_bound_via_expansion(&connection_key);
+        state_index.insert(idx, connection_key);
+
+        for (attribute, value) in &connection.fields {
+            let index = self
+                .attributes
+                .entry(attribute.to_owned())
+                .or_insert_with(AttributeIndex::new);
+
+            index.add_entry(connection_key, value);
+        }
+    }
+
+    pub(crate) fn update_with_disconnected(&mut self, connection_key: ConnectionKey) {
+        let idx = self.connected.lower_bound(&connection_key);
+        if self.connected.get(idx) != Some(&connection_key) {
+            return;
+        }
+
+        self.connected.remove(idx);
+
+        let idx = self.disconnected.upper_bound_via_expansion(&connection_key);
+        self.disconnected.insert(idx, connection_key);
+    }
+
+    pub(crate) fn remove_connections(&mut self, connections: &[ConnectionKey]) {
+        let mut connections = connections.to_vec();
+        connections.sort();
+
+        self.all.remove_list_sorted(&connections);
+        self.connected.remove_list_sorted(&connections);
+        self.disconnected.remove_list_sorted(&connections);
+
+        for attribute_index in self.attributes.values_mut() {
+            attribute_index.remove_entries(&connections);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SpanDurationIndex {
     closed_4_ms: Vec<Timestamp>,   // span ids with durations shorter than 4ms
     closed_16_ms: Vec<Timestamp>,  // span ids with durations between [4ms and 16ms)
     closed_64_ms: Vec<Timestamp>,  // span ids with durations between [16ms and 64ms)
@@ -348,7 +439,7 @@ pub struct SpanDurationIndex {
 }
 
 impl SpanDurationIndex {
-    pub fn new() -> SpanDurationIndex {
+    pub(crate) fn new() -> SpanDurationIndex {
         SpanDurationIndex {
             closed_4_ms: vec![],
             closed_16_ms: vec![],
@@ -363,7 +454,13 @@ impl SpanDurationIndex {
         }
     }
 
-    pub fn to_stratified_indexes(&self) -> Vec<(&'_ [Timestamp], Range<u64>)> {
+    pub(crate) fn to_stratified_indexes(&self) -> Vec<(&'_ [Timestamp], Range<u64>)> {
+        let mut indexes = self.to_stratified_closed_indexes();
+        indexes.push((&self.open, 0..u64::MAX));
+        indexes
+    }
+
+    pub(crate) fn to_stratified_closed_indexes(&self) -> Vec<(&'_ [Timestamp], Range<u64>)> {
         vec![
             (&self.closed_4_ms, 0..4000),
             (&self.closed_16_ms, 4000..16000),
@@ -374,11 +471,10 @@ impl SpanDurationIndex {
             (&self.closed_16_s, 4000000..16000000),
             (&self.closed_64_s, 16000000..64000000),
             (&self.closed_long, 64000000..u64::MAX),
-            (&self.open, 0..u64::MAX),
         ]
     }
 
-    pub fn remove_spans(&mut self, spans: &[SpanKey]) {
+    pub(crate) fn remove_spans(&mut self, spans: &[SpanKey]) {
         self.closed_4_ms.remove_list_sorted(spans);
         self.closed_16_ms.remove_list_sorted(spans);
         self.closed_64_ms.remove_list_sorted(spans);
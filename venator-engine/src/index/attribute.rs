@@ -1,5 +1,7 @@
 use std::collections::BTreeMap;
 
+use serde::{Deserialize, Serialize};
+
 use crate::filter::attribute::{ValueComparison, ValueFilter, ValueStringComparison};
 use crate::filter::BoundSearch;
 use crate::models::ValueOperator;
@@ -12,6 +14,7 @@ use super::util::IndexExt;
 // Since the values of an attribute can be varied, this keeps separate typed
 // indexes. It is unlikely that an attribute has values with multiple types, but
 // it needs to be accounted for regardless.
+#[derive(Serialize, Deserialize)]
 pub(crate) struct AttributeIndex {
     strings: AttributeStringIndex,
     f64s: AttributeF64Index,
@@ -58,9 +61,10 @@ impl AttributeIndex {
                 let idx = self.u128s.index.upper_bound_via_expansion(&key);
                 self.u128s.index.insert(idx, key);
             }
-            Value::Bool(_) => {
-                let idx = self.f64s.index.upper_bound_via_expansion(&key);
-                self.f64s.index.insert(idx, key);
+            Value::Bool(value) => {
+                let index = self.bools.index_mut(*value);
+                let idx = index.upper_bound_via_expansion(&key);
+                index.insert(idx, key);
             }
             Value::Str(value) => {
                 let idx = self.strings.total.upper_bound_via_expansion(&key);
@@ -100,9 +104,10 @@ impl AttributeIndex {
                 let idx = self.u128s.index.lower_bound(&key);
                 self.u128s.index.remove(idx);
             }
-            Value::Bool(_) => {
-                let idx = self.f64s.index.lower_bound(&key);
-                self.f64s.index.remove(idx);
+            Value::Bool(value) => {
+                let index = self.bools.index_mut(*value);
+                let idx = index.lower_bound(&key);
+                index.remove(idx);
             }
             Value::Str(value) => {
                 let idx = self.strings.total.lower_bound(&key);
@@ -215,11 +220,60 @@ impl AttributeIndex {
 
         filters
     }
+
+    /// This provides all the typed indexes, meaning an entity is in one of
+    /// them if it has this attribute at all.
+    pub(crate) fn make_exists_filter(&self) -> Vec<&[Timestamp]> {
+        vec![
+            &self.f64s.index,
+            &self.i64s.index,
+            &self.u64s.index,
+            &self.i128s.index,
+            &self.u128s.index,
+            &self.bools.trues,
+            &self.bools.falses,
+            &self.strings.total,
+        ]
+    }
+
+    /// This provides the number of entities for each string and bool value.
+    /// Numeric values are not indexed by value so are not included.
+    pub(crate) fn value_counts(&self) -> Vec<(String, usize)> {
+        let strings = self
+            .strings
+            .value_indexes
+            .iter()
+            .map(|(value, index)| (value.clone(), index.len()));
+        let bools = [("true", &self.bools.trues), ("false", &self.bools.falses)]
+            .into_iter()
+            .map(|(value, index)| (value.to_owned(), index.len()));
+
+        strings
+            .chain(bools)
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    }
+
+    /// This provides the entities with numeric values, which need to be looked
+    /// up to know their values.
+    pub(crate) fn numeric_entries(&self) -> impl Iterator<Item = Timestamp> + '_ {
+        [
+            &self.f64s.index,
+            &self.i64s.index,
+            &self.u64s.index,
+            &self.i128s.index,
+            &self.u128s.index,
+        ]
+        .into_iter()
+        .flatten()
+        .copied()
+    }
 }
 
 // This is a sub-index for string values of an attribute index. It keeps a
 // "total" list as well for queries on strings but can't reasonably use the
 // invididual values.
+#[derive(Serialize, Deserialize)]
 struct AttributeStringIndex {
     total: Vec<Timestamp>,
     value_indexes: BTreeMap<String, Vec<Timestamp>>,
@@ -248,6 +302,7 @@ impl AttributeStringIndex {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 struct AttributeF64Index {
     // TODO: figure out how best to do categorical & numerical indexing
     index: Vec<Timestamp>,
@@ -263,6 +318,7 @@ impl AttributeF64Index {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 struct AttributeI64Index {
     // TODO: figure out how best to do categorical & numerical indexing
     index: Vec<Timestamp>,
@@ -278,6 +334,7 @@ impl AttributeI64Index {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 struct AttributeU64Index {
     // TODO: figure out how best to do categorical & numerical indexing
     index: Vec<Timestamp>,
@@ -293,6 +350,7 @@ impl AttributeU64Index {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 struct AttributeI128Index {
     // TODO: figure out how best to do categorical & numerical indexing
     index: Vec<Timestamp>,
@@ -308,6 +366,7 @@ impl AttributeI128Index {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 struct AttributeU128Index {
     // TODO: figure out how best to do categorical & numerical indexing
     index: Vec<Timestamp>,
@@ -323,6 +382,7 @@ impl AttributeU128Index {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 struct AttributeBoolIndex {
     trues: Vec<Timestamp>,
     falses: Vec<Timestamp>,
@@ -336,6 +396,14 @@ impl AttributeBoolIndex {
         }
     }
 
+    fn index_mut(&mut self, value: bool) -> &mut Vec<Timestamp> {
+        if value {
+            &mut self.trues
+        } else {
+            &mut self.falses
+        }
+    }
+
     fn remove_entries(&mut self, keys: &[Timestamp]) {
         self.trues.remove_list_sorted(keys);
         self.falses.remove_list_sorted(keys);
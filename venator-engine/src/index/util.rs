@@ -2,7 +2,7 @@ use std::cmp::Ordering;
 
 use crate::filter::BoundSearch;
 
-pub trait IndexExt<T> {
+pub(crate) trait IndexExt<T> {
     /// This is intended to remove elements in an efficient way for sorted
     /// `self` and `list`.
     fn remove_list_sorted(&mut self, list: &[T]);
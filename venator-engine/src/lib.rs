@@ -1,77 +1,223 @@
 //! The "engine" crate represents the core functionality to injest, store,
 //! index, and query the events and spans. It does not provide functionality
 //! outside of its Rust API.
+//!
+//! An [`Engine`] runs on its own thread over a [`Storage`]. Data is inserted
+//! through its `insert_*` methods, read back through its `query_*` methods
+//! using a [`Query`] with filters parsed by [`FilterPredicate::parse`], and
+//! watched through its `subscribe_*` methods. Inserts go through a bounded
+//! queue while queries do not, so queries are answered promptly even while
+//! inserts are backed up. See `examples/` for a complete program.
 
+#![warn(unreachable_pub)]
+
+mod builder;
 mod filter;
 mod index;
 mod models;
+mod pending;
 mod storage;
 
-use std::cell::{Cell, OnceCell};
-use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::cell::{Cell, OnceCell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::future::Future;
+use std::io::{BufRead, Error as IoError, ErrorKind as IoErrorKind, Write};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use models::{AttributeTypeView, EventKey, FollowsSpanEvent};
-use serde::Serialize;
-use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use models::{AttributeTypeView, ConnectionIdView, FollowsSpanEvent, FullSpanIdView, Level};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{self, Receiver, Sender, UnboundedReceiver, UnboundedSender};
 use tokio::sync::oneshot::{self, Sender as OneshotSender};
 
+use builder::EngineOptions;
+#[cfg(feature = "parallel")]
+use filter::ParallelSearch;
 use filter::{
-    BoundSearch, IndexedEventFilter, IndexedEventFilterIterator, IndexedSpanFilter,
-    IndexedSpanFilterIterator,
+    BoundSearch, IndexedConnectionFilterIterator, IndexedEventFilter, IndexedEventFilterIterator,
+    IndexedSpanFilter, IndexedSpanFilterIterator,
 };
-use index::{EventIndexes, IndexExt, SpanIndexes};
+use index::{ConnectionIndexes, EventIndexes, IndexExt, SpanIndexes};
+use pending::{EventReply, PendingInsert, PendingInserts, SpanEventReply};
 
+pub use builder::EngineBuilder;
 pub use filter::input::{
-    FilterPredicate, FilterPredicateSingle, FilterPropertyKind, ValuePredicate,
+    FilterPredicate, FilterPredicateSingle, FilterPropertyKind, SyntaxError, ValuePredicate,
 };
 pub use filter::{
     BasicConnectionFilter, BasicEventFilter, BasicSpanFilter, FallibleFilterPredicate, InputError,
     Order, Query,
 };
 pub use models::{
-    AncestorView, AttributeSourceView, AttributeView, Connection, ConnectionId, ConnectionKey,
-    ConnectionView, CreateSpanEvent, Event, EventView, NewConnection, NewCreateSpanEvent, NewEvent,
-    NewFollowsSpanEvent, NewSpanEvent, NewSpanEventKind, NewUpdateSpanEvent, Span, SpanEvent,
-    SpanEventKey, SpanEventKind, SpanId, SpanKey, SpanView, StatsView, SubscriptionId, Timestamp,
+    parse_full_span_id, AncestorView, AttributeSourceView, AttributeView, BucketView, Connection,
+    ConnectionId, ConnectionKey, ConnectionView, CreateSpanEvent, DurationStatsView, Event,
+    EventKey, EventView, EventsOrSpans, NewConnection, NewCreateSpanEvent, NewEvent,
+    NewFollowsSpanEvent, NewOneShotEvent, NewSpanEvent, NewSpanEventKind, NewUpdateSpanEvent,
+    QueryPlanView, SavedFilterView, Span, SpanEvent, SpanEventKey, SpanEventKind, SpanId, SpanKey,
+    SpanTimelineEntry, SpanTimelineEntryKind, SpanView, StatsView, SubscriptionId, Timestamp,
     UpdateSpanEvent, Value, ValueOperator,
 };
-pub use storage::{CachedStorage, Storage, TransientStorage};
+pub use storage::{
+    AsyncStorage, BlockOnStorage, CachedStorage, SpawnBlockingStorage, Storage, TransientStorage,
+};
 
 #[cfg(feature = "persist")]
-pub use storage::FileStorage;
+pub use storage::{Durability, FileStorage, FileStorageError, FileStorageOptions};
+
+#[cfg(feature = "postgres")]
+pub use storage::{PostgresStorage, PostgresStorageError};
+
+#[cfg(feature = "parallel")]
+pub use storage::ParallelStorage;
 
 #[derive(Debug, Copy, Clone, Serialize)]
 pub enum EngineInsertError {
     DuplicateConnectionId,
     DuplicateSpanId,
     UnknownConnectionId,
+    /// The connection key doesn't refer to a connection, like after the
+    /// dataset was cleared while its client was still connected.
+    UnknownConnection,
     UnknownSpanId,
     UnknownParentSpanId,
     UnknownLevel,
     AlreadyDisconnected,
+    /// The storage was opened read-only, so nothing can be inserted.
+    ReadOnly,
+    /// The message's sequence number was already applied for its connection
+    /// id, so it was skipped as a retransmission.
+    AlreadyApplied,
+    /// The engine was shut down, so there is nothing left to insert into.
+    Shutdown,
+}
+
+#[derive(Debug, Copy, Clone, Serialize)]
+pub enum DeleteConnectionError {
+    UnknownConnectionId,
+    /// The connection's client is still sending to it, so it has to
+    /// disconnect first.
+    StillConnected,
+    /// The storage was opened read-only, so nothing can be deleted.
+    ReadOnly,
 }
 
+#[derive(Debug, Clone)]
+pub enum SaveFilterError {
+    Syntax(SyntaxError),
+    /// A predicate can't be used for events or spans. This holds the
+    /// predicate's text along with the problem.
+    Invalid(InputError, String),
+    /// The storage was opened read-only, so the filter can't be kept.
+    ReadOnly,
+}
+
+/// Runs the engine on its own thread over the given storage and gives async
+/// access to it.
+///
+/// Inserts and other changes go through a bounded queue, so their methods
+/// first wait for room in it and then for the result. Once a change is queued
+/// it is executed even if the future is dropped before it completes. Queries go
+/// through a separate unbounded queue and are likewise executed even if their
+/// future is not awaited.
+///
+/// After [`shutdown`](Engine::shutdown), inserts fail with
+/// [`Shutdown`](EngineInsertError::Shutdown) and queued inserts are dropped,
+/// so anything still sending to the engine should be stopped first.
+///
+/// # Panics
+///
+/// After [`shutdown`](Engine::shutdown), any other method that returns a
+/// result panics when it is awaited, since there is no engine left to produce
+/// it.
 #[derive(Clone)]
 pub struct Engine {
-    insert_sender: UnboundedSender<EngineCommand>,
+    insert_sender: Sender<EngineCommand>,
     query_sender: UnboundedSender<EngineCommand>,
 }
 
+/// The most inserts that can be queued before inserting waits for the engine
+/// to catch up.
+const MAX_INSERT_QUEUE: usize = 10000;
+
+/// The most events that will be inserted into storage as a single batch.
+const MAX_EVENT_INSERT_BATCH: usize = 1000;
+
+/// The most buckets that will be returned from a histogram query.
+const MAX_HISTOGRAM_BUCKETS: usize = 10000;
+
+/// The upper bounds, in seconds, of the buckets that query timings are counted
+/// in. Anything slower is only counted in the total.
+const QUERY_LATENCY_BOUNDS: [f64; 8] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+/// The first id given to the connections of one-shot events. They count up
+/// from the middle of the range to stay clear of ids that clients pick.
+const ONESHOT_CONNECTION_IDS: ConnectionId = 1 << 63;
+
 impl Engine {
+    /// Starts an engine with the default settings. Use
+    /// [`builder`](Engine::builder) to configure it.
     pub fn new<S: Storage + Send + 'static>(storage: S) -> Engine {
-        let (insert_sender, mut insert_receiver) = mpsc::unbounded_channel();
+        Engine::start(
+            storage,
+            EngineOptions::default(),
+            #[cfg(feature = "parallel")]
+            None,
+        )
+    }
+
+    /// Configures an engine before starting it. See [`EngineBuilder`] for the
+    /// available settings.
+    pub fn builder() -> EngineBuilder<TransientStorage> {
+        EngineBuilder::new()
+    }
+
+    fn start<S: Storage + Send + 'static>(
+        storage: S,
+        options: EngineOptions,
+        #[cfg(feature = "parallel")] parallel_search: Option<ParallelSearch<S>>,
+    ) -> Engine {
+        let (insert_sender, mut insert_receiver) = mpsc::channel(options.insert_queue_size);
         let (query_sender, mut query_receiver) = mpsc::unbounded_channel();
 
+        // held inserts are checked after every command, so this makes sure
+        // there is one at least twice per window; it stops once the engine
+        // has been dropped
+        if !options.reorder_window.is_zero() {
+            let query_sender = query_sender.downgrade();
+            let interval = options.reorder_window / 2;
+            std::thread::spawn(move || loop {
+                std::thread::sleep(interval);
+                let Some(query_sender) = query_sender.upgrade() else {
+                    break;
+                };
+                if query_sender.send(EngineCommand::ExpirePending).is_err() {
+                    break;
+                }
+            });
+        }
+
         std::thread::spawn(move || {
-            let mut engine = RawEngine::new(storage);
+            let mut engine = RawEngine::with_options(storage, options);
+            #[cfg(feature = "parallel")]
+            {
+                engine.parallel_search = parallel_search;
+            }
 
             let mut last_check = Instant::now();
             let mut computed_ms_since_last_check: u128 = 0;
 
-            let mut recv = || {
+            // a command pulled off the insert queue while batching events that
+            // must be handled before receiving anything else
+            let mut pending = None;
+
+            // set once a shutdown is requested, it is answered after the
+            // storage has been dropped
+            let mut shutdown = None;
+
+            let mut query_latency = QueryLatencyView::new();
+
+            let recv = |query_receiver: &mut UnboundedReceiver<EngineCommand>,
+                        insert_receiver: &mut Receiver<EngineCommand>| {
                 futures::executor::block_on(async {
                     tokio::select! {
                         biased;
@@ -85,8 +231,12 @@ impl Engine {
                 })
             };
 
-            while let Some(cmd) = recv() {
+            while let Some(cmd) = pending
+                .take()
+                .or_else(|| recv(&mut query_receiver, &mut insert_receiver))
+            {
                 let cmd_start = Instant::now();
+                let is_query = cmd.is_query();
                 match cmd {
                     EngineCommand::QueryConnection(query, sender) => {
                         let connections = engine.query_connection(query);
@@ -104,6 +254,18 @@ impl Engine {
                         let events = engine.query_span_count(query);
                         let _ = sender.send(events);
                     }
+                    EngineCommand::QuerySpanChildren(parent_key, sender) => {
+                        let spans = engine.query_span_children(parent_key);
+                        let _ = sender.send(spans);
+                    }
+                    EngineCommand::GetSpanKey(connection_id, span_id, sender) => {
+                        let span_key = engine.get_span_key(connection_id, span_id);
+                        let _ = sender.send(span_key);
+                    }
+                    EngineCommand::QuerySpanDurationStats(query, sender) => {
+                        let stats = engine.query_span_duration_stats(query);
+                        let _ = sender.send(stats);
+                    }
                     EngineCommand::QuerySpanEvent(query, sender) => {
                         let span_events = engine.query_span_event(query);
                         let _ = sender.send(span_events);
@@ -116,10 +278,80 @@ impl Engine {
                         let events = engine.query_event_count(query);
                         let _ = sender.send(events);
                     }
+                    EngineCommand::ExplainEventQuery(query, sender) => {
+                        let plan = engine.explain_event_query(query);
+                        let _ = sender.send(plan);
+                    }
+                    EngineCommand::GetEvent(event_key, sender) => {
+                        let event = engine.get_event(event_key);
+                        let _ = sender.send(event);
+                    }
+                    EngineCommand::GetSpan(span_key, sender) => {
+                        let span = engine.get_span(span_key);
+                        let _ = sender.send(span);
+                    }
+                    EngineCommand::QuerySpanTimeline(span_key, sender) => {
+                        let timeline = engine.query_span_timeline(span_key);
+                        let _ = sender.send(timeline);
+                    }
+                    EngineCommand::QueryEventDuringSpan(
+                        span_key,
+                        query,
+                        same_connection,
+                        sender,
+                    ) => {
+                        let events =
+                            engine.query_event_during_span(span_key, query, same_connection);
+                        let _ = sender.send(events);
+                    }
+                    EngineCommand::QueryPeakBuckets(kind, query, bucket_size, k, sender) => {
+                        let buckets = engine.query_peak_buckets(kind, query, bucket_size, k);
+                        let _ = sender.send(buckets);
+                    }
+                    EngineCommand::QueryEventHistogram(query, bucket_size, sender) => {
+                        let buckets = engine.query_event_histogram(query, bucket_size);
+                        let _ = sender.send(buckets);
+                    }
+                    EngineCommand::ListAttributeKeys(kind, sender) => {
+                        let keys = engine.list_attribute_keys(kind);
+                        let _ = sender.send(keys);
+                    }
+                    EngineCommand::ListAttributeValues(kind, attribute, limit, sender) => {
+                        let values = engine.list_attribute_values(kind, &attribute, limit);
+                        let _ = sender.send(values);
+                    }
+                    EngineCommand::ExportEvents(query, mut writer, sender) => {
+                        let result = engine.export_events(query, &mut writer);
+                        let _ = sender.send(result);
+                    }
+                    EngineCommand::ExportChromeTrace(query, mut writer, sender) => {
+                        let result = engine.export_chrome_trace(query, &mut writer);
+                        let _ = sender.send(result);
+                    }
+                    EngineCommand::ExportJaeger(query, mut writer, sender) => {
+                        let result = engine.export_jaeger(query, &mut writer);
+                        let _ = sender.send(result);
+                    }
+                    EngineCommand::ExportEventsCsv(query, columns, mut writer, sender) => {
+                        let result = engine.export_events_csv(query, &columns, &mut writer);
+                        let _ = sender.send(result);
+                    }
                     EngineCommand::QueryStats(sender) => {
                         let stats = engine.query_stats();
                         let _ = sender.send(stats);
                     }
+                    EngineCommand::SaveFilter(name, filter, sender) => {
+                        let result = engine.save_filter(name, filter);
+                        let _ = sender.send(result);
+                    }
+                    EngineCommand::ListFilters(sender) => {
+                        let filters = engine.list_filters();
+                        let _ = sender.send(filters);
+                    }
+                    EngineCommand::DeleteFilter(name, sender) => {
+                        let filter = engine.delete_filter(&name);
+                        let _ = sender.send(filter);
+                    }
                     EngineCommand::InsertConnection(connection, sender) => {
                         let res = engine.insert_connection(connection);
                         if let Err(err) = &res {
@@ -135,23 +367,68 @@ impl Engine {
                         let _ = sender.send(res);
                     }
                     EngineCommand::InsertSpanEvent(span_event, sender) => {
-                        let res = engine.insert_span_event(span_event);
-                        if let Err(err) = &res {
-                            eprintln!("rejecting span event insert due to: {err:?}");
-                        }
-                        let _ = sender.send(res);
+                        engine.insert_span_event_or_hold(span_event, sender);
                     }
                     EngineCommand::InsertEvent(event, sender) => {
-                        let res = engine.insert_event(event);
+                        // events are often sent in bursts, so take any that are
+                        // already queued and write them to storage together
+                        let mut events = vec![(event, sender)];
+                        while events.len() < MAX_EVENT_INSERT_BATCH {
+                            match insert_receiver.try_recv() {
+                                Ok(EngineCommand::InsertEvent(event, sender)) => {
+                                    events.push((event, sender));
+                                }
+                                Ok(cmd) => {
+                                    pending = Some(cmd);
+                                    break;
+                                }
+                                Err(_) => break,
+                            }
+                        }
+
+                        engine.insert_events_or_hold(events);
+                    }
+                    EngineCommand::InsertOneShotEvent(event, sender) => {
+                        let res = engine.insert_oneshot_event(event);
                         if let Err(err) = &res {
-                            eprintln!("rejecting event insert due to: {err:?}");
+                            eprintln!("rejecting one-shot event insert due to: {err:?}");
                         }
                         let _ = sender.send(res);
                     }
+                    EngineCommand::ExpirePending => {
+                        // this only wakes the engine; held inserts are checked
+                        // below after every command
+                    }
+                    EngineCommand::ImportNdjson(mut reader, sender) => {
+                        let metrics = engine.import_ndjson(&mut reader);
+                        let _ = sender.send(metrics);
+                    }
                     EngineCommand::Delete(filter, sender) => {
                         let metrics = engine.delete(filter);
                         let _ = sender.send(metrics);
                     }
+                    EngineCommand::DeleteConnection(connection_id, sender) => {
+                        let res = engine.delete_connection(connection_id);
+                        let _ = sender.send(res);
+                    }
+                    EngineCommand::Clear(sender) => {
+                        engine.clear();
+                        let _ = sender.send(());
+                    }
+                    EngineCommand::Flush(sender) => {
+                        engine.flush();
+                        let _ = sender.send(());
+                    }
+                    EngineCommand::Compact(sender) => {
+                        let metrics = engine.compact();
+                        let _ = sender.send(metrics);
+                    }
+                    EngineCommand::Shutdown(sender) => {
+                        engine.resolve_pending();
+                        engine.flush();
+                        shutdown = Some(sender);
+                        break;
+                    }
                     EngineCommand::EventSubscribe(filter, sender) => {
                         let res = engine.subscribe_to_events(filter);
                         let _ = sender.send(res);
@@ -160,10 +437,33 @@ impl Engine {
                         engine.unsubscribe_from_events(id);
                         let _ = sender.send(());
                     }
+                    EngineCommand::SpanSubscribe(filter, sender) => {
+                        let res = engine.subscribe_to_spans(filter);
+                        let _ = sender.send(res);
+                    }
+                    EngineCommand::SpanUnsubscribe(id, sender) => {
+                        engine.unsubscribe_from_spans(id);
+                        let _ = sender.send(());
+                    }
+                    EngineCommand::ConnectionSubscribe(filter, sender) => {
+                        let res = engine.subscribe_to_connections(filter);
+                        let _ = sender.send(res);
+                    }
+                    EngineCommand::ConnectionUnsubscribe(id, sender) => {
+                        engine.unsubscribe_from_connections(id);
+                        let _ = sender.send(());
+                    }
                     EngineCommand::CopyDataset(to, sender) => {
                         engine.copy_dataset(to);
                         let _ = sender.send(());
                     }
+                    EngineCommand::ForkFiltered(filter, sender) => {
+                        let storage = engine.fork_filtered(filter);
+                        let _ = sender.send(storage);
+                    }
+                    EngineCommand::GetQueryLatency(sender) => {
+                        let _ = sender.send(query_latency.clone());
+                    }
                     EngineCommand::GetStatus(sender) => {
                         let elapsed_ms = last_check.elapsed().as_millis();
                         let computed_ms = computed_ms_since_last_check;
@@ -175,12 +475,28 @@ impl Engine {
 
                         let _ = sender.send(EngineStatusView {
                             load: load.min(1.0) * 100.0,
+                            insert_queue_depth: 0,
                         });
                     }
                 }
+
+                if is_query {
+                    query_latency.record(cmd_start.elapsed());
+                }
+
+                engine.expire_pending();
+
                 let cmd_elapsed = cmd_start.elapsed().as_millis();
                 computed_ms_since_last_check += cmd_elapsed;
             }
+
+            // nothing else will be inserted, so anything still held is
+            // answered now rather than dropped
+            engine.resolve_pending();
+            drop(engine);
+            if let Some(sender) = shutdown {
+                let _ = sender.send(());
+            }
         });
 
         Engine {
@@ -189,6 +505,7 @@ impl Engine {
         }
     }
 
+    /// This returns the connections matching the query, up to its limit.
     // The query is executed even if the returned future is not awaited
     pub fn query_connection(&self, query: Query) -> impl Future<Output = Vec<ConnectionView>> {
         let (sender, receiver) = oneshot::channel();
@@ -198,6 +515,7 @@ impl Engine {
         async move { receiver.await.unwrap() }
     }
 
+    /// This returns how many connections match the query, ignoring its limit.
     // The query is executed even if the returned future is not awaited
     pub fn query_connection_count(&self, query: Query) -> impl Future<Output = usize> {
         let (sender, receiver) = oneshot::channel();
@@ -207,6 +525,7 @@ impl Engine {
         async move { receiver.await.unwrap() }
     }
 
+    /// This returns the spans matching the query, up to its limit.
     // The query is executed even if the returned future is not awaited
     pub fn query_span(&self, query: Query) -> impl Future<Output = Vec<SpanView>> {
         let (sender, receiver) = oneshot::channel();
@@ -216,6 +535,7 @@ impl Engine {
         async move { receiver.await.unwrap() }
     }
 
+    /// This returns how many spans match the query, ignoring its limit.
     // The query is executed even if the returned future is not awaited
     pub fn query_span_count(&self, query: Query) -> impl Future<Output = usize> {
         let (sender, receiver) = oneshot::channel();
@@ -225,6 +545,45 @@ impl Engine {
         async move { receiver.await.unwrap() }
     }
 
+    /// This returns the direct children of the span, ordered by when they
+    /// were created.
+    // The query is executed even if the returned future is not awaited
+    pub fn query_span_children(&self, parent_key: SpanKey) -> impl Future<Output = Vec<SpanView>> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .query_sender
+            .send(EngineCommand::QuerySpanChildren(parent_key, sender));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// This looks up the key of a span from the ID its client gave it.
+    // The query is executed even if the returned future is not awaited
+    pub fn get_span_key(
+        &self,
+        connection_id: ConnectionId,
+        span_id: SpanId,
+    ) -> impl Future<Output = Option<SpanKey>> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .query_sender
+            .send(EngineCommand::GetSpanKey(connection_id, span_id, sender));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// This returns duration statistics of the spans matching the query.
+    // The query is executed even if the returned future is not awaited
+    pub fn query_span_duration_stats(
+        &self,
+        query: Query,
+    ) -> impl Future<Output = DurationStatsView> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .query_sender
+            .send(EngineCommand::QuerySpanDurationStats(query, sender));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// This is not yet implemented; the engine will panic if it is called.
     // The query is executed even if the returned future is not awaited
     pub fn query_span_event(&self, query: Query) -> impl Future<Output = Vec<SpanEvent>> {
         let (sender, receiver) = oneshot::channel();
@@ -234,6 +593,7 @@ impl Engine {
         async move { receiver.await.unwrap() }
     }
 
+    /// This returns the events matching the query, up to its limit.
     // The query is executed even if the returned future is not awaited
     pub fn query_event(&self, query: Query) -> impl Future<Output = Vec<EventView>> {
         let (sender, receiver) = oneshot::channel();
@@ -243,6 +603,7 @@ impl Engine {
         async move { receiver.await.unwrap() }
     }
 
+    /// This returns how many events match the query, ignoring its limit.
     // The query is executed even if the returned future is not awaited
     pub fn query_event_count(&self, query: Query) -> impl Future<Output = usize> {
         let (sender, receiver) = oneshot::channel();
@@ -252,153 +613,822 @@ impl Engine {
         async move { receiver.await.unwrap() }
     }
 
+    /// This returns how the query would be executed without executing it.
     // The query is executed even if the returned future is not awaited
-    pub fn query_stats(&self) -> impl Future<Output = StatsView> {
+    pub fn explain_event_query(&self, query: Query) -> impl Future<Output = QueryPlanView> {
         let (sender, receiver) = oneshot::channel();
-        let _ = self.query_sender.send(EngineCommand::QueryStats(sender));
+        let _ = self
+            .query_sender
+            .send(EngineCommand::ExplainEventQuery(query, sender));
         async move { receiver.await.unwrap() }
     }
 
-    pub fn insert_connection(
-        &self,
-        connection: NewConnection,
-    ) -> impl Future<Output = Result<ConnectionKey, EngineInsertError>> {
+    /// This returns the event with the given key, if it exists.
+    // The query is executed even if the returned future is not awaited
+    pub fn get_event(&self, event_key: EventKey) -> impl Future<Output = Option<EventView>> {
         let (sender, receiver) = oneshot::channel();
         let _ = self
-            .insert_sender
-            .send(EngineCommand::InsertConnection(connection, sender));
+            .query_sender
+            .send(EngineCommand::GetEvent(event_key, sender));
         async move { receiver.await.unwrap() }
     }
 
-    pub fn disconnect_connection(
-        &self,
-        id: ConnectionId,
-    ) -> impl Future<Output = Result<(), EngineInsertError>> {
+    /// This returns the span with the given key, if it exists.
+    // The query is executed even if the returned future is not awaited
+    pub fn get_span(&self, span_key: SpanKey) -> impl Future<Output = Option<SpanView>> {
         let (sender, receiver) = oneshot::channel();
         let _ = self
-            .insert_sender
-            .send(EngineCommand::DisconnectConnection(id, sender));
+            .query_sender
+            .send(EngineCommand::GetSpan(span_key, sender));
         async move { receiver.await.unwrap() }
     }
 
-    pub fn insert_span_event(
+    /// This returns everything that happened to the span in order: when it
+    /// was created, entered and exited, updated, linked, and closed, along
+    /// with the events directly within it. It is empty if the span doesn't
+    /// exist.
+    // The query is executed even if the returned future is not awaited
+    pub fn query_span_timeline(
         &self,
-        span_event: NewSpanEvent,
-    ) -> impl Future<Output = Result<SpanKey, EngineInsertError>> {
+        span_key: SpanKey,
+    ) -> impl Future<Output = Vec<SpanTimelineEntry>> {
         let (sender, receiver) = oneshot::channel();
         let _ = self
-            .insert_sender
-            .send(EngineCommand::InsertSpanEvent(span_event, sender));
+            .query_sender
+            .send(EngineCommand::QuerySpanTimeline(span_key, sender));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// This queries for events that occurred while the span was open, which
+    /// are not necessarily within the span. The query's timeframe is narrowed
+    /// to the span's lifetime and, if `same_connection` is set, only events
+    /// from the span's connection are included.
+    // The query is executed even if the returned future is not awaited
+    pub fn query_event_during_span(
+        &self,
+        span_key: SpanKey,
+        query: Query,
+        same_connection: bool,
+    ) -> impl Future<Output = Vec<EventView>> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self.query_sender.send(EngineCommand::QueryEventDuringSpan(
+            span_key,
+            query,
+            same_connection,
+            sender,
+        ));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// This splits the query's timeframe into buckets of `bucket_size` and
+    /// returns the `k` buckets with the most matching events or spans.
+    // The query is executed even if the returned future is not awaited
+    pub fn peak_buckets(
+        &self,
+        kind: EventsOrSpans,
+        query: Query,
+        bucket_size: u64,
+        k: usize,
+    ) -> impl Future<Output = Vec<BucketView>> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self.query_sender.send(EngineCommand::QueryPeakBuckets(
+            kind,
+            query,
+            bucket_size,
+            k,
+            sender,
+        ));
         async move { receiver.await.unwrap() }
     }
 
-    pub fn insert_event(
+    /// This returns how many events matching the query fall into each bucket
+    /// of `bucket_size` across its timeframe.
+    // The query is executed even if the returned future is not awaited
+    pub fn query_event_histogram(
         &self,
-        event: NewEvent,
-    ) -> impl Future<Output = Result<(), EngineInsertError>> {
+        query: Query,
+        bucket_size: u64,
+    ) -> impl Future<Output = Vec<BucketView>> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self.query_sender.send(EngineCommand::QueryEventHistogram(
+            query,
+            bucket_size,
+            sender,
+        ));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// This returns the distinct attribute names seen on events or spans, in
+    /// sorted order.
+    // The query is executed even if the returned future is not awaited
+    pub fn list_attribute_keys(&self, kind: EventsOrSpans) -> impl Future<Output = Vec<String>> {
         let (sender, receiver) = oneshot::channel();
         let _ = self
-            .insert_sender
-            .send(EngineCommand::InsertEvent(event, sender));
+            .query_sender
+            .send(EngineCommand::ListAttributeKeys(kind, sender));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// This returns up to `limit` distinct values of the attribute on events or
+    /// spans, with the most common values first.
+    // The query is executed even if the returned future is not awaited
+    pub fn list_attribute_values(
+        &self,
+        kind: EventsOrSpans,
+        attribute: String,
+        limit: usize,
+    ) -> impl Future<Output = Vec<String>> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self.query_sender.send(EngineCommand::ListAttributeValues(
+            kind, attribute, limit, sender,
+        ));
         async move { receiver.await.unwrap() }
     }
 
-    pub fn delete(&self, filter: DeleteFilter) -> impl Future<Output = DeleteMetrics> {
+    /// This writes the events matching the query as newline-delimited JSON,
+    /// one `EventView` per line, and returns how many were written.
+    // The query is executed even if the returned future is not awaited
+    pub fn export_events(
+        &self,
+        query: Query,
+        writer: Box<dyn Write + Send>,
+    ) -> impl Future<Output = Result<usize, IoError>> {
         let (sender, receiver) = oneshot::channel();
         let _ = self
-            .insert_sender
-            .send(EngineCommand::Delete(filter, sender));
+            .query_sender
+            .send(EngineCommand::ExportEvents(query, writer, sender));
         async move { receiver.await.unwrap() }
     }
 
-    pub fn subscribe_to_events(
+    /// This writes the events matching the query as CSV with a header row and
+    /// returns how many were written. A column is either an inherent property
+    /// (`timestamp`, `level`, `target`, `name`, or `file`) or an attribute
+    /// prefixed with `@`. Attributes are resolved through the event's spans
+    /// and connection, and are left empty if missing.
+    // The query is executed even if the returned future is not awaited
+    pub fn export_events_csv(
         &self,
-        filter: Vec<FilterPredicate>,
-    ) -> impl Future<Output = (SubscriptionId, UnboundedReceiver<EventView>)> {
+        query: Query,
+        columns: Vec<String>,
+        writer: Box<dyn Write + Send>,
+    ) -> impl Future<Output = Result<usize, IoError>> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self.query_sender.send(EngineCommand::ExportEventsCsv(
+            query, columns, writer, sender,
+        ));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// This writes the spans matching the query in the Chrome trace event
+    /// format, which can be opened in Perfetto, and returns how many were
+    /// written.
+    // The query is executed even if the returned future is not awaited
+    pub fn export_chrome_trace(
+        &self,
+        query: Query,
+        writer: Box<dyn Write + Send>,
+    ) -> impl Future<Output = Result<usize, IoError>> {
         let (sender, receiver) = oneshot::channel();
         let _ = self
             .query_sender
-            .send(EngineCommand::EventSubscribe(filter, sender));
+            .send(EngineCommand::ExportChromeTrace(query, writer, sender));
         async move { receiver.await.unwrap() }
     }
 
-    pub fn unsubscribe_from_events(&self, id: SubscriptionId) -> impl Future<Output = ()> {
+    /// This writes the spans matching the query as Jaeger JSON, which can be
+    /// opened in the Jaeger UI, and returns how many were written.
+    // The query is executed even if the returned future is not awaited
+    pub fn export_jaeger(
+        &self,
+        query: Query,
+        writer: Box<dyn Write + Send>,
+    ) -> impl Future<Output = Result<usize, IoError>> {
         let (sender, receiver) = oneshot::channel();
         let _ = self
             .query_sender
-            .send(EngineCommand::EventUnsubscribe(id, sender));
+            .send(EngineCommand::ExportJaeger(query, writer, sender));
         async move { receiver.await.unwrap() }
     }
 
-    pub fn copy_dataset(&self, to: Box<dyn Storage + Send>) -> impl Future<Output = ()> {
+    /// This returns the overall counts and timeframe of the stored data.
+    // The query is executed even if the returned future is not awaited
+    pub fn query_stats(&self) -> impl Future<Output = StatsView> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self.query_sender.send(EngineCommand::QueryStats(sender));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// Saves the filter text under the name so it can be used again later,
+    /// replacing any filter already saved with that name. The text must parse
+    /// as a filter. Filters are persisted if the storage supports it.
+    // The command is executed even if the returned future is not awaited
+    pub fn save_filter(
+        &self,
+        name: String,
+        filter: String,
+    ) -> impl Future<Output = Result<SavedFilterView, SaveFilterError>> {
         let (sender, receiver) = oneshot::channel();
         let _ = self
             .query_sender
-            .send(EngineCommand::CopyDataset(to, sender));
+            .send(EngineCommand::SaveFilter(name, filter, sender));
         async move { receiver.await.unwrap() }
     }
 
-    pub fn get_status(&self) -> impl Future<Output = EngineStatusView> {
+    /// This returns the saved filters ordered by name.
+    // The query is executed even if the returned future is not awaited
+    pub fn list_filters(&self) -> impl Future<Output = Vec<SavedFilterView>> {
         let (sender, receiver) = oneshot::channel();
-        let _ = self.query_sender.send(EngineCommand::GetStatus(sender));
+        let _ = self.query_sender.send(EngineCommand::ListFilters(sender));
         async move { receiver.await.unwrap() }
     }
-}
 
-enum EngineCommand {
-    QueryConnection(Query, OneshotSender<Vec<ConnectionView>>),
-    QueryConnectionCount(Query, OneshotSender<usize>),
-    QuerySpan(Query, OneshotSender<Vec<SpanView>>),
-    QuerySpanCount(Query, OneshotSender<usize>),
-    QuerySpanEvent(Query, OneshotSender<Vec<SpanEvent>>),
-    QueryEvent(Query, OneshotSender<Vec<EventView>>),
-    QueryEventCount(Query, OneshotSender<usize>),
-    QueryStats(OneshotSender<StatsView>),
-    InsertConnection(
-        NewConnection,
-        OneshotSender<Result<ConnectionKey, EngineInsertError>>,
-    ),
-    DisconnectConnection(ConnectionId, OneshotSender<Result<(), EngineInsertError>>),
-    InsertSpanEvent(
-        NewSpanEvent,
-        OneshotSender<Result<SpanKey, EngineInsertError>>,
-    ),
-    InsertEvent(NewEvent, OneshotSender<Result<(), EngineInsertError>>),
-    Delete(DeleteFilter, OneshotSender<DeleteMetrics>),
+    /// Removes the filter saved with the name and returns it, if there was one.
+    // The command is executed even if the returned future is not awaited
+    pub fn delete_filter(&self, name: String) -> impl Future<Output = Option<SavedFilterView>> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .query_sender
+            .send(EngineCommand::DeleteFilter(name, sender));
+        async move { receiver.await.unwrap() }
+    }
 
-    EventSubscribe(
-        Vec<FilterPredicate>,
-        OneshotSender<(SubscriptionId, UnboundedReceiver<EventView>)>,
-    ),
-    EventUnsubscribe(SubscriptionId, OneshotSender<()>),
+    /// Registers a new connection. Its key is needed to insert its spans and
+    /// events.
+    pub async fn insert_connection(
+        &self,
+        connection: NewConnection,
+    ) -> Result<ConnectionKey, EngineInsertError> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .insert_sender
+            .send(EngineCommand::InsertConnection(connection, sender))
+            .await;
+        receiver.await.unwrap_or(Err(EngineInsertError::Shutdown))
+    }
 
-    CopyDataset(Box<dyn Storage + Send>, OneshotSender<()>),
-    GetStatus(OneshotSender<EngineStatusView>),
-}
+    /// Marks the connection as disconnected as of now.
+    pub async fn disconnect_connection(&self, id: ConnectionId) -> Result<(), EngineInsertError> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .insert_sender
+            .send(EngineCommand::DisconnectConnection(id, sender))
+            .await;
+        receiver.await.unwrap_or(Err(EngineInsertError::Shutdown))
+    }
 
-pub struct EngineStatusView {
-    pub load: f64,
-}
+    /// Creates, updates, closes, or links a span according to the event's
+    /// kind. This returns the key of the span it applied to.
+    ///
+    /// If the engine was built with a [reorder window] and the span (or its
+    /// parent) hasn't been created yet, the result is not known until it has
+    /// been or the window has passed.
+    ///
+    /// If it has a sequence number that isn't more than the last one applied
+    /// for the connection's id, it is skipped as a retransmission and this
+    /// returns [`AlreadyApplied`](EngineInsertError::AlreadyApplied).
+    ///
+    /// [reorder window]: EngineBuilder::reorder_window
+    pub async fn insert_span_event(
+        &self,
+        span_event: NewSpanEvent,
+    ) -> Result<SpanKey, EngineInsertError> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .insert_sender
+            .send(EngineCommand::InsertSpanEvent(span_event, Some(sender)))
+            .await;
+        receiver.await.unwrap_or(Err(EngineInsertError::Shutdown))
+    }
 
-pub struct DeleteFilter {
-    pub start: Timestamp,
-    pub end: Timestamp,
-    pub inside: bool,
-    pub dry_run: bool,
-}
+    /// Queues the span event like [`insert_span_event`](Engine::insert_span_event)
+    /// but only waits for room in the insert queue, not for the result. This
+    /// lets a client stream inserts without a round-trip for each one.
+    pub async fn queue_span_event(&self, span_event: NewSpanEvent) {
+        let _ = self
+            .insert_sender
+            .send(EngineCommand::InsertSpanEvent(span_event, None))
+            .await;
+    }
 
-pub struct DeleteMetrics {
-    pub connections: usize,
-    pub spans: usize,
-    pub span_events: usize,
-    pub events: usize,
-}
+    /// Inserts an event into the connection given by its key and, if set,
+    /// within the span given by its ID.
+    ///
+    /// Like [`insert_span_event`](Engine::insert_span_event), this may wait
+    /// for the span to be created if the engine has a reorder window and skips
+    /// it if its sequence number was already applied.
+    pub async fn insert_event(&self, event: NewEvent) -> Result<EventKey, EngineInsertError> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .insert_sender
+            .send(EngineCommand::InsertEvent(event, Some(sender)))
+            .await;
+        receiver.await.unwrap_or(Err(EngineInsertError::Shutdown))
+    }
 
-struct RawEngine<S> {
-    storage: S,
-    keys: KeyCache,
+    /// Queues the event like [`insert_event`](Engine::insert_event) but only
+    /// waits for room in the insert queue, not for the result.
+    pub async fn queue_event(&self, event: NewEvent) {
+        let _ = self
+            .insert_sender
+            .send(EngineCommand::InsertEvent(event, None))
+            .await;
+    }
+
+    /// Inserts an event without needing a connection first. It is recorded
+    /// under a disconnected connection with the event's connection fields,
+    /// which is shared by all one-shot events with the same fields, so it can
+    /// be queried like any other.
+    pub async fn insert_oneshot_event(
+        &self,
+        event: NewOneShotEvent,
+    ) -> Result<EventKey, EngineInsertError> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .insert_sender
+            .send(EngineCommand::InsertOneShotEvent(event, sender))
+            .await;
+        receiver.await.unwrap_or(Err(EngineInsertError::Shutdown))
+    }
+
+    /// Removes the data selected by the filter. See [`DeleteFilter`].
+    pub async fn delete(&self, filter: DeleteFilter) -> DeleteMetrics {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .insert_sender
+            .send(EngineCommand::Delete(filter, sender))
+            .await;
+        receiver.await.unwrap()
+    }
+
+    /// Removes the connection and everything recorded through it. Connections
+    /// that are still connected can't be deleted.
+    pub async fn delete_connection(
+        &self,
+        connection_id: ConnectionId,
+    ) -> Result<DeleteMetrics, DeleteConnectionError> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .insert_sender
+            .send(EngineCommand::DeleteConnection(connection_id, sender))
+            .await;
+        receiver.await.unwrap()
+    }
+
+    /// This inserts the events from newline-delimited JSON as written by
+    /// [`export_events`](Engine::export_events). See [`ImportMetrics`].
+    pub async fn import_ndjson(&self, reader: Box<dyn BufRead + Send>) -> ImportMetrics {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .insert_sender
+            .send(EngineCommand::ImportNdjson(reader, sender))
+            .await;
+        receiver.await.unwrap()
+    }
+
+    /// Removes all data from the engine and its storage. Active subscriptions
+    /// are ended since they would otherwise refer to removed data.
+    pub async fn clear(&self) {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self.insert_sender.send(EngineCommand::Clear(sender)).await;
+        receiver.await.unwrap()
+    }
+
+    /// Makes everything inserted before this call durable. Since it goes
+    /// through the insert queue, all prior inserts are written to storage
+    /// first. For file storage this then checkpoints the write-ahead log into
+    /// the database file and syncs it to disk, so the data survives a crash
+    /// or power loss. Inserts are otherwise only guaranteed to survive the
+    /// application exiting, not the system going down.
+    pub async fn flush(&self) {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self.insert_sender.send(EngineCommand::Flush(sender)).await;
+        receiver.await.unwrap()
+    }
+
+    /// Rewrites the storage to give back the space left over from deleted data
+    /// and returns its size before and after, or `None` if the storage has
+    /// nothing to compact. This can take a while for a large file and the
+    /// engine does nothing else meanwhile, so it should be done on request
+    /// rather than regularly.
+    pub async fn compact(&self) -> Option<CompactMetrics> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .insert_sender
+            .send(EngineCommand::Compact(sender))
+            .await;
+        receiver.await.unwrap()
+    }
+
+    /// Stops the engine once everything inserted before this call has been
+    /// written and flushed (see [`flush`](Engine::flush)). This returns after
+    /// the storage has been dropped, so a file can be reopened afterwards.
+    /// Shutting down again does nothing. Inserts afterwards fail with
+    /// [`Shutdown`](EngineInsertError::Shutdown) and any other use panics.
+    pub async fn shutdown(&self) {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .insert_sender
+            .send(EngineCommand::Shutdown(sender))
+            .await;
+
+        // the engine may have already been shut down
+        let _ = receiver.await;
+    }
+
+    /// Removes data that ended before the cutoff. See [`DeleteFilter::before`].
+    pub async fn delete_before(&self, at: Timestamp) -> DeleteMetrics {
+        self.delete(DeleteFilter::before(at)).await
+    }
+
+    /// Subscribes to events matching the filter. Each event is sent once as it
+    /// is inserted.
+    pub fn subscribe_to_events(
+        &self,
+        filter: Vec<FilterPredicate>,
+    ) -> impl Future<Output = (SubscriptionId, UnboundedReceiver<EventView>)> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .query_sender
+            .send(EngineCommand::EventSubscribe(filter, sender));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// Stops the subscription and closes its receiver.
+    pub fn unsubscribe_from_events(&self, id: SubscriptionId) -> impl Future<Output = ()> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .query_sender
+            .send(EngineCommand::EventUnsubscribe(id, sender));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// Subscribes to spans matching the filter. A span is sent when it is
+    /// created and again whenever it is updated or closed (if it still matches)
+    /// so that listeners can replace their previous copy.
+    pub fn subscribe_to_spans(
+        &self,
+        filter: Vec<FilterPredicate>,
+    ) -> impl Future<Output = (SubscriptionId, UnboundedReceiver<SpanView>)> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .query_sender
+            .send(EngineCommand::SpanSubscribe(filter, sender));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// Stops the subscription and closes its receiver.
+    pub fn unsubscribe_from_spans(&self, id: SubscriptionId) -> impl Future<Output = ()> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .query_sender
+            .send(EngineCommand::SpanUnsubscribe(id, sender));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// Subscribes to connections matching the filter. A connection is sent
+    /// when it connects and again when it disconnects.
+    pub fn subscribe_to_connections(
+        &self,
+        filter: Vec<FilterPredicate>,
+    ) -> impl Future<Output = (SubscriptionId, UnboundedReceiver<ConnectionView>)> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .query_sender
+            .send(EngineCommand::ConnectionSubscribe(filter, sender));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// Stops the subscription and closes its receiver.
+    pub fn unsubscribe_from_connections(&self, id: SubscriptionId) -> impl Future<Output = ()> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .query_sender
+            .send(EngineCommand::ConnectionUnsubscribe(id, sender));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// This copies all the stored connections, spans, and events into another
+    /// storage.
+    pub fn copy_dataset(&self, to: Box<dyn Storage + Send>) -> impl Future<Output = ()> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .query_sender
+            .send(EngineCommand::CopyDataset(to, sender));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// This creates a new in-memory engine with only the events and spans that
+    /// match the filter. Their ancestor spans and connections are included
+    /// as well so that the fork renders them the same way.
+    ///
+    /// The fork runs on a thread of its own, which ends once the fork and its
+    /// clones are dropped or it is [shut down](Engine::shutdown).
+    ///
+    /// # Panics
+    ///
+    /// Like the other queries, this panics if this engine was shut down.
+    pub fn fork_filtered(&self, filter: Vec<FilterPredicate>) -> impl Future<Output = Engine> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .query_sender
+            .send(EngineCommand::ForkFiltered(filter, sender));
+        async move { Engine::new(receiver.await.unwrap()) }
+    }
+
+    /// This returns how many inserts are waiting in the queue.
+    pub fn insert_queue_depth(&self) -> usize {
+        // the queue is only visible from this side
+        self.insert_sender.max_capacity() - self.insert_sender.capacity()
+    }
+
+    /// This returns how long the queries executed since the engine started
+    /// have taken.
+    pub fn query_latency(&self) -> impl Future<Output = QueryLatencyView> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self
+            .query_sender
+            .send(EngineCommand::GetQueryLatency(sender));
+        async move { receiver.await.unwrap() }
+    }
+
+    /// This returns how busy the engine has been since the last check and how
+    /// many inserts are waiting in the queue.
+    pub fn get_status(&self) -> impl Future<Output = EngineStatusView> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self.query_sender.send(EngineCommand::GetStatus(sender));
+
+        let insert_queue_depth = self.insert_queue_depth();
+
+        async move {
+            let mut status = receiver.await.unwrap();
+            status.insert_queue_depth = insert_queue_depth;
+            status
+        }
+    }
+}
+
+enum EngineCommand {
+    QueryConnection(Query, OneshotSender<Vec<ConnectionView>>),
+    QueryConnectionCount(Query, OneshotSender<usize>),
+    QuerySpan(Query, OneshotSender<Vec<SpanView>>),
+    QuerySpanCount(Query, OneshotSender<usize>),
+    QuerySpanChildren(SpanKey, OneshotSender<Vec<SpanView>>),
+    GetSpanKey(ConnectionId, SpanId, OneshotSender<Option<SpanKey>>),
+    QuerySpanDurationStats(Query, OneshotSender<DurationStatsView>),
+    QuerySpanEvent(Query, OneshotSender<Vec<SpanEvent>>),
+    QueryEvent(Query, OneshotSender<Vec<EventView>>),
+    QueryEventCount(Query, OneshotSender<usize>),
+    ExplainEventQuery(Query, OneshotSender<QueryPlanView>),
+    GetEvent(EventKey, OneshotSender<Option<EventView>>),
+    GetSpan(SpanKey, OneshotSender<Option<SpanView>>),
+    QuerySpanTimeline(SpanKey, OneshotSender<Vec<SpanTimelineEntry>>),
+    QueryEventDuringSpan(SpanKey, Query, bool, OneshotSender<Vec<EventView>>),
+    QueryPeakBuckets(
+        EventsOrSpans,
+        Query,
+        u64,
+        usize,
+        OneshotSender<Vec<BucketView>>,
+    ),
+    QueryEventHistogram(Query, u64, OneshotSender<Vec<BucketView>>),
+    ListAttributeKeys(EventsOrSpans, OneshotSender<Vec<String>>),
+    ListAttributeValues(EventsOrSpans, String, usize, OneshotSender<Vec<String>>),
+    ExportEvents(
+        Query,
+        Box<dyn Write + Send>,
+        OneshotSender<Result<usize, IoError>>,
+    ),
+    ExportEventsCsv(
+        Query,
+        Vec<String>,
+        Box<dyn Write + Send>,
+        OneshotSender<Result<usize, IoError>>,
+    ),
+    ExportChromeTrace(
+        Query,
+        Box<dyn Write + Send>,
+        OneshotSender<Result<usize, IoError>>,
+    ),
+    ExportJaeger(
+        Query,
+        Box<dyn Write + Send>,
+        OneshotSender<Result<usize, IoError>>,
+    ),
+    QueryStats(OneshotSender<StatsView>),
+    SaveFilter(
+        String,
+        String,
+        OneshotSender<Result<SavedFilterView, SaveFilterError>>,
+    ),
+    ListFilters(OneshotSender<Vec<SavedFilterView>>),
+    DeleteFilter(String, OneshotSender<Option<SavedFilterView>>),
+    InsertConnection(
+        NewConnection,
+        OneshotSender<Result<ConnectionKey, EngineInsertError>>,
+    ),
+    DisconnectConnection(ConnectionId, OneshotSender<Result<(), EngineInsertError>>),
+    InsertSpanEvent(NewSpanEvent, SpanEventReply),
+    InsertEvent(NewEvent, EventReply),
+    InsertOneShotEvent(
+        NewOneShotEvent,
+        OneshotSender<Result<EventKey, EngineInsertError>>,
+    ),
+    ImportNdjson(Box<dyn BufRead + Send>, OneshotSender<ImportMetrics>),
+    Delete(DeleteFilter, OneshotSender<DeleteMetrics>),
+    DeleteConnection(
+        ConnectionId,
+        OneshotSender<Result<DeleteMetrics, DeleteConnectionError>>,
+    ),
+    Clear(OneshotSender<()>),
+    Flush(OneshotSender<()>),
+    Compact(OneshotSender<Option<CompactMetrics>>),
+    ExpirePending,
+    Shutdown(OneshotSender<()>),
+
+    EventSubscribe(
+        Vec<FilterPredicate>,
+        OneshotSender<(SubscriptionId, UnboundedReceiver<EventView>)>,
+    ),
+    EventUnsubscribe(SubscriptionId, OneshotSender<()>),
+    SpanSubscribe(
+        Vec<FilterPredicate>,
+        OneshotSender<(SubscriptionId, UnboundedReceiver<SpanView>)>,
+    ),
+    SpanUnsubscribe(SubscriptionId, OneshotSender<()>),
+    ConnectionSubscribe(
+        Vec<FilterPredicate>,
+        OneshotSender<(SubscriptionId, UnboundedReceiver<ConnectionView>)>,
+    ),
+    ConnectionUnsubscribe(SubscriptionId, OneshotSender<()>),
+
+    CopyDataset(Box<dyn Storage + Send>, OneshotSender<()>),
+    ForkFiltered(Vec<FilterPredicate>, OneshotSender<TransientStorage>),
+    GetStatus(OneshotSender<EngineStatusView>),
+    GetQueryLatency(OneshotSender<QueryLatencyView>),
+}
+
+impl EngineCommand {
+    // Whether this reads the dataset on behalf of a user, as opposed to
+    // changing it or managing the engine.
+    fn is_query(&self) -> bool {
+        // every command is listed so that new ones must be classified
+        match self {
+            EngineCommand::QueryConnection(..)
+            | EngineCommand::QueryConnectionCount(..)
+            | EngineCommand::QuerySpan(..)
+            | EngineCommand::QuerySpanCount(..)
+            | EngineCommand::QuerySpanChildren(..)
+            | EngineCommand::GetSpanKey(..)
+            | EngineCommand::QuerySpanDurationStats(..)
+            | EngineCommand::QuerySpanEvent(..)
+            | EngineCommand::QueryEvent(..)
+            | EngineCommand::QueryEventCount(..)
+            | EngineCommand::ExplainEventQuery(..)
+            | EngineCommand::GetEvent(..)
+            | EngineCommand::GetSpan(..)
+            | EngineCommand::QuerySpanTimeline(..)
+            | EngineCommand::QueryEventDuringSpan(..)
+            | EngineCommand::QueryPeakBuckets(..)
+            | EngineCommand::QueryEventHistogram(..)
+            | EngineCommand::ListAttributeKeys(..)
+            | EngineCommand::ListAttributeValues(..)
+            | EngineCommand::ExportEvents(..)
+            | EngineCommand::ExportEventsCsv(..)
+            | EngineCommand::ExportChromeTrace(..)
+            | EngineCommand::ExportJaeger(..)
+            | EngineCommand::QueryStats(..) => true,
+            EngineCommand::SaveFilter(..)
+            | EngineCommand::ListFilters(..)
+            | EngineCommand::DeleteFilter(..)
+            | EngineCommand::InsertConnection(..)
+            | EngineCommand::DisconnectConnection(..)
+            | EngineCommand::InsertSpanEvent(..)
+            | EngineCommand::InsertEvent(..)
+            | EngineCommand::InsertOneShotEvent(..)
+            | EngineCommand::ImportNdjson(..)
+            | EngineCommand::Delete(..)
+            | EngineCommand::DeleteConnection(..)
+            | EngineCommand::Clear(..)
+            | EngineCommand::Flush(..)
+            | EngineCommand::Compact(..)
+            | EngineCommand::ExpirePending
+            | EngineCommand::Shutdown(..)
+            | EngineCommand::EventSubscribe(..)
+            | EngineCommand::EventUnsubscribe(..)
+            | EngineCommand::SpanSubscribe(..)
+            | EngineCommand::SpanUnsubscribe(..)
+            | EngineCommand::ConnectionSubscribe(..)
+            | EngineCommand::ConnectionUnsubscribe(..)
+            | EngineCommand::CopyDataset(..)
+            | EngineCommand::ForkFiltered(..)
+            | EngineCommand::GetStatus(..)
+            | EngineCommand::GetQueryLatency(..) => false,
+        }
+    }
+}
+
+pub struct EngineStatusView {
+    pub load: f64,
+    pub insert_queue_depth: usize,
+}
+
+/// How long queries have taken, counted the way a Prometheus histogram is.
+#[derive(Debug, Clone)]
+pub struct QueryLatencyView {
+    /// The upper bound of each bucket in seconds along with how many queries
+    /// took at most that long (so each includes the ones before it).
+    pub buckets: Vec<(f64, u64)>,
+    /// The time taken by all queries in seconds.
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl QueryLatencyView {
+    fn new() -> QueryLatencyView {
+        QueryLatencyView {
+            buckets: QUERY_LATENCY_BOUNDS.iter().map(|b| (*b, 0)).collect(),
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn record(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        for (bound, count) in &mut self.buckets {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+pub struct DeleteFilter {
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub inside: bool,
+    pub dry_run: bool,
+}
+
+impl DeleteFilter {
+    /// This creates a filter for pruning everything that finished before the
+    /// given time: disconnected connections, closed root spans (with their
+    /// descendents), and root events. Spans that are still open are kept even
+    /// if they were created before the cutoff.
+    pub fn before(at: Timestamp) -> DeleteFilter {
+        DeleteFilter {
+            start: at,
+            end: Timestamp::MAX,
+            inside: false,
+            dry_run: false,
+        }
+    }
+}
+
+pub struct DeleteMetrics {
+    pub connections: usize,
+    pub spans: usize,
+    pub span_events: usize,
+    pub events: usize,
+}
+
+/// The size in bytes of the storage before and after compacting it.
+pub struct CompactMetrics {
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+/// The counts of what was created by an import. Lines that could not be read
+/// or parsed are logged and skipped rather than failing the whole import.
+pub struct ImportMetrics {
+    pub connections: usize,
+    pub spans: usize,
+    pub events: usize,
+    pub skipped_lines: usize,
+}
+
+struct RawEngine<S> {
+    storage: S,
+    read_only: bool,
+    options: EngineOptions,
+    #[cfg(feature = "parallel")]
+    parallel_search: Option<ParallelSearch<S>>,
+    pending: PendingInserts,
+    connection_keys: KeyAllocator,
+    keys: KeyAllocator,
     connection_key_map: HashMap<ConnectionId, ConnectionKey>,
     connections: BTreeMap<ConnectionKey, Connection>,
+    connection_indexes: ConnectionIndexes,
+    // the last sequence number applied for each connection id, which carries
+    // over when a client reconnects with the same id
+    last_seqs: HashMap<ConnectionId, u64>,
+    // the connection shared by one-shot events with the same connection
+    // fields, keyed by the fields as JSON
+    oneshot_connections: HashMap<String, ConnectionKey>,
+    next_oneshot_id: ConnectionId,
+    saved_filters: BTreeMap<String, String>,
     span_key_map: HashMap<(ConnectionKey, SpanId), SpanKey>,
     span_id_map: HashMap<SpanKey, SpanId>,
     span_indexes: SpanIndexes,
@@ -408,15 +1438,34 @@ struct RawEngine<S> {
 
     next_subscriber_id: usize,
     event_subscribers: HashMap<usize, (BasicEventFilter, UnboundedSender<EventView>)>,
+    span_subscribers: HashMap<usize, (BasicSpanFilter, UnboundedSender<SpanView>)>,
+    connection_subscribers:
+        HashMap<usize, (BasicConnectionFilter, UnboundedSender<ConnectionView>)>,
 }
 
 impl<S: Storage> RawEngine<S> {
+    #[cfg(test)]
     fn new(storage: S) -> RawEngine<S> {
+        RawEngine::with_options(storage, EngineOptions::default())
+    }
+
+    fn with_options(storage: S, options: EngineOptions) -> RawEngine<S> {
         let mut engine = RawEngine {
+            read_only: storage.is_read_only(),
             storage,
-            keys: KeyCache::new(),
+            #[cfg(feature = "parallel")]
+            parallel_search: None,
+            pending: PendingInserts::new(options.reorder_window),
+            options,
+            connection_keys: KeyAllocator::new(),
+            keys: KeyAllocator::new(),
             connection_key_map: HashMap::new(),
             connections: BTreeMap::new(),
+            connection_indexes: ConnectionIndexes::new(),
+            last_seqs: HashMap::new(),
+            oneshot_connections: HashMap::new(),
+            next_oneshot_id: ONESHOT_CONNECTION_IDS,
+            saved_filters: BTreeMap::new(),
             span_key_map: HashMap::new(),
             span_id_map: HashMap::new(),
             span_indexes: SpanIndexes::new(),
@@ -426,8 +1475,12 @@ impl<S: Storage> RawEngine<S> {
 
             next_subscriber_id: 0,
             event_subscribers: HashMap::new(),
+            span_subscribers: HashMap::new(),
+            connection_subscribers: HashMap::new(),
         };
 
+        engine.saved_filters = engine.storage.load_saved_filters().into_iter().collect();
+
         let connections = engine.storage.get_all_connections().collect::<Vec<_>>();
 
         let mut connections_not_disconnected = vec![];
@@ -439,28 +1492,50 @@ impl<S: Storage> RawEngine<S> {
             engine.insert_connection_bookeeping(&connection);
         }
 
-        let spans = engine.storage.get_all_spans().collect::<Vec<_>>();
+        if !engine.options.persist_indexes || !engine.load_indexes() {
+            let spans = engine.storage.get_all_spans().collect::<Vec<_>>();
 
-        let mut spans_not_closed = vec![];
-        for span in spans {
-            if span.closed_at.is_none() {
-                spans_not_closed.push(span.key());
+            for span in spans {
+                engine.insert_span_bookeeping(&span);
             }
-            engine.insert_span_bookeeping(&span);
-        }
 
-        let span_events = engine.storage.get_all_span_events().collect::<Vec<_>>();
+            let span_events = engine.storage.get_all_span_events().collect::<Vec<_>>();
 
-        for span_event in span_events {
-            engine.insert_span_event_bookeeping(&span_event);
-        }
+            for span_event in span_events {
+                engine.insert_span_event_bookeeping(&span_event);
+            }
 
-        let events = engine.storage.get_all_events().collect::<Vec<_>>();
+            let events = engine.storage.get_all_events().collect::<Vec<_>>();
 
-        for event in events {
-            engine.insert_event_bookeeping(&event);
+            for event in events {
+                engine.insert_event_bookeeping(&event);
+            }
+        }
+
+        // keys continue after the newest one so a restart does not hand out
+        // the same keys again
+        let last_connection_key = engine.connections.keys().next_back().copied();
+        engine.connection_keys = KeyAllocator::starting_after(last_connection_key);
+
+        let last_key = [
+            engine.span_indexes.all.last(),
+            engine.span_event_ids.last(),
+            engine.event_indexes.all.last(),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+        .copied();
+        engine.keys = KeyAllocator::starting_after(last_key);
+
+        // with read-only storage, these are likely still being written by
+        // another process so they are left open
+        if engine.read_only {
+            return engine;
         }
 
+        let spans_not_closed = engine.span_indexes.durations.open.clone();
+
         if !connections_not_disconnected.is_empty() || !spans_not_closed.is_empty() {
             let last_event = engine.event_indexes.all.last();
             let last_span_event = engine.span_event_ids.last();
@@ -472,8 +1547,15 @@ impl<S: Storage> RawEngine<S> {
             };
 
             let at = last_at.saturating_add(1);
+            engine.keys.reserve(at);
 
             for connection_key in connections_not_disconnected {
+                if let Some(connection) = engine.connections.get_mut(&connection_key) {
+                    connection.disconnected_at = Some(at);
+                }
+                engine
+                    .connection_indexes
+                    .update_with_disconnected(connection_key);
                 engine
                     .storage
                     .update_connection_disconnected(connection_key, at);
@@ -488,67 +1570,54 @@ impl<S: Storage> RawEngine<S> {
         engine
     }
 
-    pub fn query_connection(&self, query: Query) -> Vec<ConnectionView> {
-        let limit = query.limit;
-
-        let mut filter = BasicConnectionFilter::And(
-            query
-                .filter
-                .into_iter()
-                .map(|p| BasicConnectionFilter::from_predicate(p).unwrap())
-                .collect(),
-        );
-        filter.simplify();
-
-        let (start, end) = match query.order {
-            Order::Asc => {
-                let start = query
-                    .previous
-                    .map(|p| p.saturating_add(1))
-                    .unwrap_or(Timestamp::MIN);
-                let end = query.end;
+    /// This restores the indexes saved by [`flush`](RawEngine::flush) if the
+    /// storage still has them. It returns `false` if they must be rebuilt.
+    fn load_indexes(&mut self) -> bool {
+        let Some(data) = self.storage.load_indexes() else {
+            return false;
+        };
 
-                (start, end)
-            }
-            Order::Desc => {
-                let start = Timestamp::MIN;
-                let end = query
-                    .previous
-                    .map(|p| Timestamp::new(p.get() - 1).unwrap())
-                    .unwrap_or(query.end);
+        // the format is checked first since a different layout may still
+        // deserialize successfully
+        if bincode::deserialize::<u32>(&data).ok() != Some(SAVED_INDEXES_VERSION) {
+            return false;
+        }
 
-                (start, end)
+        let saved = match bincode::deserialize::<(u32, SavedIndexes)>(&data) {
+            Ok((_, saved)) => saved,
+            Err(err) => {
+                eprintln!("rebuilding indexes since saved indexes could not be read: {err}");
+                return false;
             }
         };
 
-        let mut connections = self
-            .connections
-            .range(start..=end)
-            .map(|(_key, connection)| connection)
-            .collect::<Vec<_>>();
+        self.span_key_map = saved.span_key_map;
+        self.span_id_map = saved.span_id_map;
+        self.span_indexes = saved.span_indexes;
+        self.span_event_ids = saved.span_event_ids;
+        self.span_events_by_span_ids = saved.span_events_by_span_ids;
+        self.event_indexes = saved.event_indexes;
 
-        if query.order == Order::Desc {
-            connections.reverse();
-        }
+        true
+    }
 
-        connections
-            .into_iter()
-            .filter(|connection| {
-                connection.connected_at <= query.end
-                    && connection
-                        .disconnected_at
-                        .map(|d| d >= query.start)
-                        .unwrap_or(true)
-            })
-            .filter(|connection| filter.matches(&self.storage, connection.key()))
+    pub(crate) fn query_connection(&self, query: Query) -> Vec<ConnectionView> {
+        let limit = query.limit.min(self.options.max_query_limit);
+
+        self.query_connection_iter(query)
             .take(limit)
             .map(|connection| self.render_connection(connection))
             .collect()
     }
 
-    pub fn query_connection_count(&self, query: Query) -> usize {
-        // TODO: make this better
-        self.query_connection(query).len()
+    pub(crate) fn query_connection_count(&self, query: Query) -> usize {
+        self.query_connection_iter(query).count()
+    }
+
+    /// This yields the connections matching the query in order, ignoring the
+    /// limit.
+    fn query_connection_iter(&self, query: Query) -> impl Iterator<Item = &Connection> + '_ {
+        IndexedConnectionFilterIterator::new(query, self).map(|key| &self.connections[&key])
     }
 
     fn render_connection(&self, connection: &Connection) -> ConnectionView {
@@ -558,6 +1627,8 @@ impl<S: Storage> RawEngine<S> {
             id: connection_id.to_string(),
             connected_at: connection.connected_at,
             disconnected_at: connection.disconnected_at,
+            duration: connection.duration(),
+            schema: connection.schema,
             attributes: connection
                 .fields
                 .iter()
@@ -573,8 +1644,8 @@ impl<S: Storage> RawEngine<S> {
         }
     }
 
-    pub fn query_event(&self, query: Query) -> Vec<EventView> {
-        let limit = query.limit;
+    pub(crate) fn query_event(&self, query: Query) -> Vec<EventView> {
+        let limit = query.limit.min(self.options.max_query_limit);
         IndexedEventFilterIterator::new(query, self)
             .take(limit)
             .map(|event_key| self.storage.get_event(event_key).unwrap())
@@ -582,17 +1653,401 @@ impl<S: Storage> RawEngine<S> {
             .collect()
     }
 
-    pub fn query_event_count(&self, query: Query) -> usize {
-        let event_iter = IndexedEventFilterIterator::new(query, self);
+    pub(crate) fn query_event_during_span(
+        &self,
+        span_key: SpanKey,
+        mut query: Query,
+        same_connection: bool,
+    ) -> Vec<EventView> {
+        let Some(span) = self.storage.get_span(span_key) else {
+            return Vec::new();
+        };
 
-        match event_iter.size_hint() {
-            (min, Some(max)) if min == max => min,
-            _ => event_iter.count(),
+        query.start = query.start.max(span.created_at);
+        query.end = query.end.min(span.closed_at.unwrap_or(Timestamp::MAX));
+        if query.start > query.end {
+            return Vec::new();
         }
-    }
 
-    fn render_event(&self, event: &Event) -> EventView {
-        let connection = self.connections.get(&event.connection_key).unwrap();
+        if same_connection {
+            let connection_id = self.connections[&span.connection_key].id;
+            query
+                .filter
+                .push(FilterPredicate::Single(FilterPredicateSingle {
+                    property_kind: Some(FilterPropertyKind::Inherent),
+                    property: "connection".to_owned(),
+                    value: ValuePredicate::Comparison(ValueOperator::Eq, connection_id.to_string()),
+                }));
+        }
+
+        self.query_event(query)
+    }
+
+    pub(crate) fn explain_event_query(&self, query: Query) -> QueryPlanView {
+        IndexedEventFilterIterator::new(query, self).explain()
+    }
+
+    pub(crate) fn query_event_count(&self, query: Query) -> usize {
+        let event_iter = IndexedEventFilterIterator::new(query, self);
+
+        match event_iter.size_hint() {
+            (min, Some(max)) if min == max => min,
+            _ => event_iter.count(),
+        }
+    }
+
+    pub(crate) fn export_events(
+        &self,
+        query: Query,
+        writer: &mut dyn Write,
+    ) -> Result<usize, IoError> {
+        let limit = query.limit;
+        let mut count = 0;
+        for event_key in IndexedEventFilterIterator::new(query, self).take(limit) {
+            let event = self.storage.get_event(event_key).unwrap();
+            serde_json::to_writer(&mut *writer, &self.render_event(&event))?;
+            writer.write_all(b"\n")?;
+            count += 1;
+        }
+
+        writer.flush()?;
+
+        Ok(count)
+    }
+
+    pub(crate) fn export_events_csv(
+        &self,
+        query: Query,
+        columns: &[String],
+        writer: &mut dyn Write,
+    ) -> Result<usize, IoError> {
+        for column in columns {
+            let known = matches!(
+                column.as_str(),
+                "timestamp" | "level" | "target" | "name" | "file"
+            );
+            if !known && !column.starts_with('@') {
+                let message = format!("unknown column: {column}");
+                return Err(IoError::new(IoErrorKind::InvalidInput, message));
+            }
+        }
+
+        let header = columns.iter().map(|column| encode_csv_value(column));
+        writeln!(writer, "{}", header.collect::<Vec<_>>().join(","))?;
+
+        let limit = query.limit;
+        let mut count = 0;
+        for event_key in IndexedEventFilterIterator::new(query, self).take(limit) {
+            let context = EventContext::new(event_key, &self.storage);
+            let event = context.event();
+
+            let row = columns.iter().map(|column| {
+                let value = match column.as_str() {
+                    "timestamp" => event.timestamp.to_string(),
+                    "level" => match event.level {
+                        Level::Trace => "TRACE".to_owned(),
+                        Level::Debug => "DEBUG".to_owned(),
+                        Level::Info => "INFO".to_owned(),
+                        Level::Warn => "WARN".to_owned(),
+                        Level::Error => "ERROR".to_owned(),
+                    },
+                    "target" => event.target.clone(),
+                    "name" => event.name.clone(),
+                    "file" => match (&event.file_name, event.file_line) {
+                        (None, _) => String::new(),
+                        (Some(name), None) => name.clone(),
+                        (Some(name), Some(line)) => format!("{name}:{line}"),
+                    },
+                    attribute => context
+                        .attribute(&attribute[1..])
+                        .map(Value::to_string)
+                        .unwrap_or_default(),
+                };
+
+                encode_csv_value(&value)
+            });
+
+            writeln!(writer, "{}", row.collect::<Vec<_>>().join(","))?;
+            count += 1;
+        }
+
+        writer.flush()?;
+
+        Ok(count)
+    }
+
+    /// The connections and spans referenced by the imported events are created
+    /// with fresh ids so they do not collide with existing data. An export only
+    /// carries the names of spans, so they take the target and level of the
+    /// first event seen in them. Connections and spans are closed at the last
+    /// event seen in them.
+    pub(crate) fn import_ndjson(&mut self, reader: &mut dyn BufRead) -> ImportMetrics {
+        let mut metrics = ImportMetrics {
+            connections: 0,
+            spans: 0,
+            events: 0,
+            skipped_lines: 0,
+        };
+
+        if self.read_only {
+            return metrics;
+        }
+
+        let mut connection_keys = HashMap::<ConnectionIdView, ConnectionKey>::new();
+        let mut span_keys = HashMap::<FullSpanIdView, SpanKey>::new();
+        let mut next_span_ids = HashMap::<ConnectionKey, u64>::new();
+        let mut last_seen = HashMap::<Timestamp, Timestamp>::new();
+        let mut events = Vec::new();
+
+        let mut next_connection_id = self
+            .connection_key_map
+            .keys()
+            .max()
+            .and_then(|id| id.checked_add(1))
+            .unwrap_or(1);
+
+        for (idx, line) in reader.lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) if err.kind() == IoErrorKind::InvalidData => {
+                    eprintln!("skipping line {} of import: {err}", idx + 1);
+                    metrics.skipped_lines += 1;
+                    continue;
+                }
+                Err(err) => {
+                    eprintln!("stopping import at line {}: {err}", idx + 1);
+                    break;
+                }
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let view = match serde_json::from_str::<EventView>(&line) {
+                Ok(view) => view,
+                Err(err) => {
+                    eprintln!("skipping line {} of import: {err}", idx + 1);
+                    metrics.skipped_lines += 1;
+                    continue;
+                }
+            };
+
+            let Ok(level) = Level::try_from(view.level) else {
+                eprintln!("skipping line {} of import: unknown level", idx + 1);
+                metrics.skipped_lines += 1;
+                continue;
+            };
+
+            let mut fields = BTreeMap::new();
+            let mut connection_fields = BTreeMap::new();
+            let mut span_fields = HashMap::<FullSpanIdView, BTreeMap<String, Value>>::new();
+            for attribute in view.attributes {
+                let value = parse_attribute_value(attribute.typ, attribute.value);
+                match attribute.source {
+                    AttributeSourceView::Inherent => {
+                        fields.insert(attribute.name, value);
+                    }
+                    AttributeSourceView::Connection { .. } => {
+                        connection_fields.insert(attribute.name, value);
+                    }
+                    AttributeSourceView::Span { span_id } => {
+                        span_fields
+                            .entry(span_id)
+                            .or_default()
+                            .insert(attribute.name, value);
+                    }
+                }
+            }
+
+            let connection_key = match connection_keys.get(&view.connection_id) {
+                Some(connection_key) => *connection_key,
+                None => {
+                    let Some(connection_id) = self.free_connection_id(next_connection_id) else {
+                        eprintln!("skipping line {} of import: no connection id left", idx + 1);
+                        metrics.skipped_lines += 1;
+                        continue;
+                    };
+
+                    let connection = Connection {
+                        id: connection_id,
+                        connected_at: self.import_key(view.timestamp),
+                        disconnected_at: None,
+                        schema: 1,
+                        fields: connection_fields,
+                    };
+
+                    let connection_key = connection.key();
+                    next_connection_id = connection_id.checked_add(1).unwrap_or(1);
+
+                    self.insert_connection_bookeeping(&connection);
+                    self.storage.insert_connection(connection);
+
+                    connection_keys.insert(view.connection_id, connection_key);
+                    metrics.connections += 1;
+                    connection_key
+                }
+            };
+
+            let mut seen_keys = vec![connection_key];
+            let mut parent_key = None;
+            for ancestor in view.ancestors {
+                let span_key = match span_keys.get(&ancestor.id) {
+                    Some(span_key) => *span_key,
+                    None => {
+                        let next_span_id = next_span_ids.entry(connection_key).or_insert(1);
+                        let span_id = SpanId::new(*next_span_id).unwrap();
+                        *next_span_id += 1;
+
+                        let span_key = self.import_key(view.timestamp);
+                        let fields = span_fields.remove(&ancestor.id).unwrap_or_default();
+
+                        let span = Span {
+                            connection_key,
+                            id: span_id,
+                            created_at: span_key,
+                            client_created_at: None,
+                            closed_at: None,
+                            parent_key,
+                            follows: Vec::new(),
+                            target: view.target.clone(),
+                            name: ancestor.name.clone(),
+                            level,
+                            file_name: None,
+                            file_line: None,
+                            fields: fields.clone(),
+                            busy: 0,
+                            entered_at: None,
+                        };
+
+                        let span_event = SpanEvent {
+                            connection_key,
+                            timestamp: span_key,
+                            span_key,
+                            kind: SpanEventKind::Create(CreateSpanEvent {
+                                parent_key,
+                                target: view.target.clone(),
+                                name: ancestor.name,
+                                level,
+                                file_name: None,
+                                file_line: None,
+                                fields,
+                            }),
+                        };
+
+                        self.insert_span_bookeeping(&span);
+                        self.storage.insert_span(span);
+
+                        self.insert_span_event_bookeeping(&span_event);
+                        self.storage.insert_span_event(span_event);
+
+                        span_keys.insert(ancestor.id, span_key);
+                        metrics.spans += 1;
+                        span_key
+                    }
+                };
+
+                seen_keys.push(span_key);
+                parent_key = Some(span_key);
+            }
+
+            let (file_name, file_line) = match view.file {
+                None => (None, None),
+                Some(file) => match file.rsplit_once(':') {
+                    Some((name, line)) if line.parse::<u32>().is_ok() => {
+                        (Some(name.to_owned()), line.parse().ok())
+                    }
+                    _ => (Some(file), None),
+                },
+            };
+
+            let event = Event {
+                connection_key,
+                timestamp: self.import_key(view.timestamp),
+                client_timestamp: view.client_timestamp,
+                span_key: parent_key,
+                name: view.name,
+                target: view.target,
+                level,
+                file_name,
+                file_line,
+                fields,
+            };
+
+            for key in seen_keys {
+                let seen = last_seen.entry(key).or_insert(event.timestamp);
+                *seen = (*seen).max(event.timestamp);
+            }
+
+            self.insert_event_bookeeping(&event);
+            self.notify_event_subscribers(&event);
+            events.push(event);
+            metrics.events += 1;
+
+            if events.len() >= MAX_EVENT_INSERT_BATCH {
+                self.storage.insert_events(std::mem::take(&mut events));
+            }
+        }
+
+        self.storage.insert_events(events);
+
+        for span_key in span_keys.into_values() {
+            let closed_at = last_seen.get(&span_key).copied().unwrap_or(span_key);
+            self.span_indexes.update_with_closed(span_key, closed_at);
+            self.storage.update_span_closed(span_key, closed_at);
+            self.notify_span_subscribers(span_key);
+        }
+
+        for connection_key in connection_keys.into_values() {
+            let disconnected_at = last_seen[&connection_key];
+            if let Some(connection) = self.connections.get_mut(&connection_key) {
+                connection.disconnected_at = Some(disconnected_at);
+            }
+            self.connection_indexes
+                .update_with_disconnected(connection_key);
+            self.storage
+                .update_connection_disconnected(connection_key, disconnected_at);
+            self.notify_connection_subscribers(connection_key);
+        }
+
+        metrics
+    }
+
+    // This returns a key as close to `desired` as possible that is not used by
+    // any connection, span, span event, or event. Unlike live inserts, imported
+    // data keeps its original timestamps.
+    fn import_key(&self, desired: Timestamp) -> Timestamp {
+        let now = now();
+        let mut key = desired;
+        loop {
+            let taken = self.connections.contains_key(&key)
+                || self.span_indexes.all.binary_search(&key).is_ok()
+                || self.span_event_ids.binary_search(&key).is_ok()
+                || self.event_indexes.all.binary_search(&key).is_ok();
+
+            if taken {
+                key = key.saturating_add(1);
+                continue;
+            }
+
+            // live inserts can be given keys this recent, so it is reserved
+            // for them to continue after
+            if key >= saturating_sub(now, 10000000) {
+                self.connection_keys.reserve(key);
+                self.keys.reserve(key);
+            }
+
+            return key;
+        }
+    }
+
+    pub(crate) fn get_event(&self, event_key: EventKey) -> Option<EventView> {
+        let event = self.storage.get_event(event_key)?;
+        Some(self.render_event(&event))
+    }
+
+    fn render_event(&self, event: &Event) -> EventView {
+        let connection = self.connections.get(&event.connection_key).unwrap();
         let connection_id = connection.id;
 
         let context = EventContext::with_event(event, &self.storage);
@@ -659,7 +2114,12 @@ impl<S: Storage> RawEngine<S> {
                 ancestors.reverse();
                 ancestors
             },
+            span_id: event
+                .span_key
+                .and_then(|span_key| self.span_id_map.get(&span_key))
+                .map(|span_id| format!("{connection_id}-{span_id}")),
             timestamp: event.timestamp,
+            client_timestamp: event.client_timestamp,
             level: event.level as i32,
             target: event.target.clone(),
             name: event.name.clone(),
@@ -680,16 +2140,38 @@ impl<S: Storage> RawEngine<S> {
         }
     }
 
-    pub fn query_span(&self, query: Query) -> Vec<SpanView> {
-        let limit = query.limit;
+    pub(crate) fn query_span(&self, query: Query) -> Vec<SpanView> {
+        let limit = query.limit.min(self.options.max_query_limit);
+        let end = query.end.min(now());
         IndexedSpanFilterIterator::new(query, self)
             .take(limit)
             .map(|span_key| self.storage.get_span(span_key).unwrap())
-            .map(|span| self.render_span(&span))
+            .map(|span| self.render_span(&span, Some(end)))
+            .collect()
+    }
+
+    pub(crate) fn query_span_children(&self, parent_key: SpanKey) -> Vec<SpanView> {
+        self.span_indexes
+            .children
+            .get(&parent_key)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .map(|span_key| self.storage.get_span(*span_key).unwrap())
+            .map(|span| self.render_span(&span, None))
             .collect()
     }
 
-    pub fn query_span_count(&self, query: Query) -> usize {
+    pub(crate) fn get_span_key(
+        &self,
+        connection_id: ConnectionId,
+        span_id: SpanId,
+    ) -> Option<SpanKey> {
+        let connection_key = self.connection_key_map.get(&connection_id)?;
+        self.span_key_map.get(&(*connection_key, span_id)).copied()
+    }
+
+    pub(crate) fn query_span_count(&self, query: Query) -> usize {
         let span_iter = IndexedSpanFilterIterator::new(query, self);
 
         match span_iter.size_hint() {
@@ -698,7 +2180,275 @@ impl<S: Storage> RawEngine<S> {
         }
     }
 
-    fn render_span(&self, span: &Span) -> SpanView {
+    /// This computes duration statistics over all the spans matching the
+    /// query, ignoring the limit. Spans that are still open have no duration
+    /// and are only counted in `still_open`.
+    pub(crate) fn query_span_duration_stats(&self, mut query: Query) -> DurationStatsView {
+        // the order and pagination are irrelevant for statistics
+        query.order = Order::Asc;
+        query.previous = None;
+
+        let mut still_open = 0;
+        let mut durations = vec![];
+        for span_key in IndexedSpanFilterIterator::new(query, self) {
+            let Some(span) = self.storage.get_span(span_key) else {
+                continue;
+            };
+
+            match span.duration() {
+                Some(duration) => durations.push(duration),
+                None => still_open += 1,
+            }
+        }
+
+        durations.sort_unstable();
+
+        // uses the nearest-rank method
+        let percentile = |p: usize| -> Option<u64> {
+            let rank = (p * durations.len()).div_ceil(100).max(1);
+            durations.get(rank - 1).copied()
+        };
+
+        let mean = (!durations.is_empty()).then(|| {
+            let total = durations.iter().map(|d| *d as u128).sum::<u128>();
+            (total / durations.len() as u128) as u64
+        });
+
+        DurationStatsView {
+            count: durations.len(),
+            still_open,
+            min: durations.first().copied(),
+            max: durations.last().copied(),
+            mean,
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+        }
+    }
+
+    /// Each span is written as a complete ("X") event. Connections are used as
+    /// processes and span trees as threads, so nested spans stack. Spans that
+    /// are still open are given a synthetic end at the end of the query.
+    pub(crate) fn export_chrome_trace(
+        &self,
+        query: Query,
+        writer: &mut dyn Write,
+    ) -> Result<usize, IoError> {
+        let limit = query.limit;
+        let end = query.end;
+
+        writer.write_all(b"[")?;
+
+        let mut count = 0;
+        for span_key in IndexedSpanFilterIterator::new(query, self).take(limit) {
+            let span = self.storage.get_span(span_key).unwrap();
+            let view = self.render_span(&span, Some(end));
+
+            let connection_id = self.connections[&span.connection_key].id;
+            let root_id = SpanContext::with_span(&span, &self.storage)
+                .parents()
+                .last()
+                .map(|root| root.id)
+                .unwrap_or(span.id);
+
+            let closed_at = span.closed_at.unwrap_or(end);
+            let args = view
+                .attributes
+                .into_iter()
+                .map(|attribute| (attribute.name, serde_json::Value::String(attribute.value)))
+                .collect::<serde_json::Map<_, _>>();
+
+            let trace_event = serde_json::json!({
+                "ph": "X",
+                "name": view.name,
+                "cat": view.target,
+                "ts": span.created_at.get(),
+                "dur": closed_at.get().saturating_sub(span.created_at.get()),
+                "pid": connection_id,
+                "tid": root_id,
+                "args": args,
+            });
+
+            if count > 0 {
+                writer.write_all(b",")?;
+            }
+            writer.write_all(b"\n")?;
+            serde_json::to_writer(&mut *writer, &trace_event)?;
+            count += 1;
+        }
+
+        writer.write_all(b"\n]\n")?;
+        writer.flush()?;
+
+        Ok(count)
+    }
+
+    /// Spans are grouped into traces by their root span, with the root's
+    /// connection as the trace's process. Parents become `CHILD_OF` references
+    /// and follows become `FOLLOWS_FROM` ones (even if the span followed wasn't
+    /// exported). Events directly within a span are its logs. Spans that are
+    /// still open are given a synthetic end at the end of the query.
+    pub(crate) fn export_jaeger(
+        &self,
+        query: Query,
+        writer: &mut dyn Write,
+    ) -> Result<usize, IoError> {
+        let limit = query.limit;
+        let end = query.end;
+
+        // keyed by the root span so traces are written in the order they began
+        let mut traces = BTreeMap::<SpanKey, (String, Vec<serde_json::Value>)>::new();
+
+        let mut count = 0;
+        for span_key in IndexedSpanFilterIterator::new(query, self).take(limit) {
+            let span = self.storage.get_span(span_key).unwrap();
+            let (root_key, trace_id) = self.jaeger_trace_id(&span);
+
+            let mut references = Vec::new();
+            if let Some(parent_key) = span.parent_key {
+                references.push(serde_json::json!({
+                    "refType": "CHILD_OF",
+                    "traceID": trace_id,
+                    "spanID": format!("{:016x}", parent_key.get()),
+                }));
+            }
+            for follows_key in &span.follows {
+                if let Some(follows) = self.storage.get_span(*follows_key) {
+                    let (_, follows_trace_id) = self.jaeger_trace_id(&follows);
+                    references.push(serde_json::json!({
+                        "refType": "FOLLOWS_FROM",
+                        "traceID": follows_trace_id,
+                        "spanID": format!("{:016x}", follows_key.get()),
+                    }));
+                }
+            }
+
+            let mut tags = vec![
+                jaeger_tag(
+                    "level",
+                    &Value::Str(format!("{:?}", span.level).to_uppercase()),
+                ),
+                jaeger_tag("target", &Value::Str(span.target.clone())),
+            ];
+            tags.extend(
+                span.fields
+                    .iter()
+                    .map(|(name, value)| jaeger_tag(name, value)),
+            );
+
+            let logs = self
+                .event_indexes
+                .descendents
+                .get(&span_key)
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|event_key| self.storage.get_event(*event_key))
+                .filter(|event| event.span_key == Some(span_key))
+                .map(|event| {
+                    let mut fields = vec![
+                        jaeger_tag("event", &Value::Str(event.name.clone())),
+                        jaeger_tag(
+                            "level",
+                            &Value::Str(format!("{:?}", event.level).to_uppercase()),
+                        ),
+                    ];
+                    fields.extend(
+                        event
+                            .fields
+                            .iter()
+                            .map(|(name, value)| jaeger_tag(name, value)),
+                    );
+
+                    serde_json::json!({
+                        "timestamp": event.timestamp.get(),
+                        "fields": fields,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let closed_at = span.closed_at.unwrap_or(end);
+            let jaeger_span = serde_json::json!({
+                "traceID": trace_id,
+                "spanID": format!("{:016x}", span_key.get()),
+                "operationName": span.name,
+                "references": references,
+                "startTime": span.created_at.get(),
+                "duration": closed_at.get().saturating_sub(span.created_at.get()),
+                "tags": tags,
+                "logs": logs,
+                "processID": "p1",
+            });
+
+            traces
+                .entry(root_key)
+                .or_insert_with(|| (trace_id, Vec::new()))
+                .1
+                .push(jaeger_span);
+            count += 1;
+        }
+
+        let data = traces
+            .into_iter()
+            .map(|(root_key, (trace_id, spans))| {
+                let root = self.storage.get_span(root_key).unwrap();
+                let connection = &self.connections[&root.connection_key];
+
+                // the usual attribute for this in OpenTelemetry and a common
+                // one for `venator` clients
+                let service_name = connection
+                    .fields
+                    .get("service.name")
+                    .or_else(|| connection.fields.get("service"))
+                    .map(|value| value.to_string())
+                    .unwrap_or_else(|| format!("connection {}", connection.id));
+
+                let process_tags = connection
+                    .fields
+                    .iter()
+                    .map(|(name, value)| jaeger_tag(name, value))
+                    .collect::<Vec<_>>();
+
+                serde_json::json!({
+                    "traceID": trace_id,
+                    "spans": spans,
+                    "processes": {
+                        "p1": {
+                            "serviceName": service_name,
+                            "tags": process_tags,
+                        },
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+
+        serde_json::to_writer(&mut *writer, &serde_json::json!({ "data": data }))?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+
+        Ok(count)
+    }
+
+    // The ids are made from span keys since clients reuse their span ids once
+    // a span is closed. Jaeger's trace id is 128 bits and is made from the key
+    // of the root span.
+    fn jaeger_trace_id(&self, span: &Span) -> (SpanKey, String) {
+        let root_key = SpanContext::with_span(span, &self.storage)
+            .parents()
+            .last()
+            .map(|root| root.key())
+            .unwrap_or(span.key());
+
+        (root_key, format!("{:032x}", root_key.get()))
+    }
+
+    pub(crate) fn get_span(&self, span_key: SpanKey) -> Option<SpanView> {
+        let span = self.storage.get_span(span_key)?;
+        Some(self.render_span(&span, None))
+    }
+
+    // `end` is where a span that is currently entered is considered busy until
+    fn render_span(&self, span: &Span, end: Option<Timestamp>) -> SpanView {
         let connection = self.connections.get(&span.connection_key).unwrap();
         let connection_id = connection.id;
 
@@ -766,8 +2516,16 @@ impl<S: Storage> RawEngine<S> {
                 ancestors.reverse();
                 ancestors
             },
+            follows: span
+                .follows
+                .iter()
+                .filter_map(|follows_key| self.span_id_map.get(follows_key))
+                .map(|follows_id| format!("{connection_id}-{follows_id}"))
+                .collect(),
             created_at: span.created_at,
+            client_created_at: span.client_created_at,
             closed_at: span.closed_at,
+            busy: span.busy(end),
             level: span.level as i32,
             target: span.target.clone(),
             name: span.name.clone(),
@@ -788,97 +2546,670 @@ impl<S: Storage> RawEngine<S> {
         }
     }
 
-    pub fn query_span_event(&self, _query: Query) -> Vec<SpanEvent> {
+    pub(crate) fn query_span_event(&self, _query: Query) -> Vec<SpanEvent> {
         unimplemented!()
     }
 
-    pub fn query_stats(&self) -> StatsView {
-        StatsView {
-            start: self.event_indexes.all.first().copied(),
-            end: self.event_indexes.all.last().copied(),
-            total_events: self.event_indexes.all.len(),
-            total_spans: self.span_indexes.all.len(),
-        }
-    }
-
-    pub fn insert_connection(
-        &mut self,
-        connection: NewConnection,
-    ) -> Result<ConnectionKey, EngineInsertError> {
-        if self.connection_key_map.contains_key(&connection.id) {
-            return Err(EngineInsertError::DuplicateConnectionId);
-        }
-
-        let now = now();
-        let connection_key = self.keys.register(now, now);
-        let connection = Connection {
-            id: connection.id,
-            connected_at: connection_key,
-            disconnected_at: None,
-            fields: connection.fields,
+    pub(crate) fn query_span_timeline(&self, span_key: SpanKey) -> Vec<SpanTimelineEntry> {
+        let Some(span) = self.storage.get_span(span_key) else {
+            return Vec::new();
         };
 
-        self.insert_connection_bookeeping(&connection);
-        self.storage.insert_connection(connection);
+        let connection_id = self.connections[&span.connection_key].id;
 
-        Ok(connection_key)
-    }
+        let span_event_keys = self
+            .span_events_by_span_ids
+            .get(&span_key)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let mut timeline: Vec<SpanTimelineEntry> = Vec::with_capacity(span_event_keys.len());
+        // the entries for enters that haven't been exited yet
+        let mut entered = Vec::new();
+        for span_event_key in span_event_keys {
+            let Some(span_event) = self.storage.get_span_event(*span_event_key) else {
+                continue;
+            };
 
-    fn insert_connection_bookeeping(&mut self, connection: &Connection) {
-        self.connection_key_map
-            .insert(connection.id, connection.key());
-        self.connections
-            .insert(connection.key(), connection.clone());
-    }
+            let kind = match &span_event.kind {
+                SpanEventKind::Create(_) => SpanTimelineEntryKind::Create,
+                SpanEventKind::Update(update) => SpanTimelineEntryKind::Update {
+                    attributes: update
+                        .fields
+                        .iter()
+                        .map(|(name, value)| AttributeView {
+                            name: name.clone(),
+                            value: value.to_string(),
+                            typ: value.to_type_view(),
+                            source: AttributeSourceView::Inherent,
+                        })
+                        .collect(),
+                },
+                SpanEventKind::Follows(follows) => {
+                    let Some(follows_id) = self.span_id_map.get(&follows.follows) else {
+                        continue;
+                    };
+
+                    SpanTimelineEntryKind::Follows {
+                        follows: format!("{connection_id}-{follows_id}"),
+                    }
+                }
+                SpanEventKind::Enter => {
+                    entered.push(timeline.len());
+                    SpanTimelineEntryKind::Busy { exited_at: None }
+                }
+                SpanEventKind::Exit => {
+                    if let Some(idx) = entered.pop() {
+                        timeline[idx].kind = SpanTimelineEntryKind::Busy {
+                            exited_at: Some(span_event.timestamp),
+                        };
+                    }
+                    continue;
+                }
+                SpanEventKind::Close => SpanTimelineEntryKind::Close,
+            };
 
-    pub fn disconnect_connection(
-        &mut self,
-        connection_id: ConnectionId,
-    ) -> Result<(), EngineInsertError> {
-        let now = now();
-        let at = self.keys.register(now, now);
+            timeline.push(SpanTimelineEntry {
+                timestamp: span_event.timestamp,
+                kind,
+            });
+        }
 
-        let connection_key = *self
-            .connection_key_map
-            .get(&connection_id)
-            .ok_or(EngineInsertError::UnknownConnectionId)?;
+        // the descendent index includes events in child spans as well
+        let event_keys = self
+            .event_indexes
+            .descendents
+            .get(&span_key)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        for event_key in event_keys {
+            let Some(event) = self.storage.get_event(*event_key) else {
+                continue;
+            };
 
-        let connection = self.storage.get_connection(connection_key).unwrap();
+            if event.span_key != Some(span_key) {
+                continue;
+            }
 
-        if connection.disconnected_at.is_some() {
-            return Err(EngineInsertError::AlreadyDisconnected);
+            timeline.push(SpanTimelineEntry {
+                timestamp: event.timestamp,
+                kind: SpanTimelineEntryKind::Event {
+                    name: event.name.clone(),
+                    level: event.level as i32,
+                },
+            });
         }
 
-        let filter = IndexedSpanFilter::And(vec![
-            IndexedSpanFilter::Single(&self.span_indexes.durations.open, None),
-            IndexedSpanFilter::Single(
-                self.span_indexes
-                    .connections
-                    .get(&connection_key)
-                    .map(Vec::as_slice)
-                    .unwrap_or_default(),
-                None,
-            ),
-        ]);
+        timeline.sort_by_key(|entry| entry.timestamp);
+        timeline
+    }
 
-        let open_spans = IndexedSpanFilterIterator::new_internal(filter, self).collect::<Vec<_>>();
+    /// This splits the query's timeframe into buckets of `bucket_size`
+    /// (starting from `query.start`) and returns the `k` buckets with the most
+    /// events or spans. Spans are bucketed by when they were created. Buckets
+    /// with equal counts are ordered earliest first.
+    pub(crate) fn query_peak_buckets(
+        &self,
+        kind: EventsOrSpans,
+        mut query: Query,
+        bucket_size: u64,
+        k: usize,
+    ) -> Vec<BucketView> {
+        let bucket_size = bucket_size.max(1);
+        let start = query.start;
+        let end = query.end;
+
+        // the order and pagination are irrelevant for bucketing
+        query.order = Order::Asc;
+        query.previous = None;
+
+        let timestamps: Box<dyn Iterator<Item = Timestamp> + '_> = match kind {
+            EventsOrSpans::Events => Box::new(IndexedEventFilterIterator::new(query, self)),
+            EventsOrSpans::Spans => Box::new(IndexedSpanFilterIterator::new(query, self)),
+        };
 
-        for span_key in open_spans {
-            self.span_indexes.update_with_closed(span_key, at);
-            self.storage.update_span_closed(span_key, at);
+        let mut counts = BTreeMap::<u64, usize>::new();
+        for timestamp in timestamps {
+            // spans that overlap the timeframe may have been created before it
+            if timestamp < start {
+                continue;
+            }
+
+            let bucket = (timestamp.get() - start.get()) / bucket_size;
+            *counts.entry(bucket).or_default() += 1;
         }
 
-        self.storage
-            .update_connection_disconnected(connection_key, at);
+        let mut buckets = counts.into_iter().collect::<Vec<_>>();
 
-        Ok(())
+        // the sort is stable, so buckets with equal counts stay earliest first
+        buckets.sort_by(|(_, a), (_, b)| b.cmp(a));
+        buckets.truncate(k);
+
+        buckets
+            .into_iter()
+            .map(|(bucket, count)| {
+                let bucket_start = start.saturating_add(bucket * bucket_size);
+                let bucket_end = bucket_start.saturating_add(bucket_size - 1).min(end);
+
+                BucketView {
+                    start: bucket_start,
+                    end: bucket_end,
+                    count,
+                }
+            })
+            .collect()
     }
 
-    pub fn insert_span_event(
+    /// This counts the events matching the query into consecutive buckets of
+    /// `bucket_size` microseconds from the query's `start` to `end`. Every
+    /// bucket is returned, including empty ones. The bucket size is increased
+    /// if needed so that no more than `MAX_HISTOGRAM_BUCKETS` are returned.
+    pub(crate) fn query_event_histogram(
+        &self,
+        mut query: Query,
+        bucket_size: u64,
+    ) -> Vec<BucketView> {
+        let start = query.start;
+        let end = query.end;
+
+        let span = end.get().saturating_sub(start.get());
+        let bucket_size = bucket_size
+            .max(1)
+            .max(span / MAX_HISTOGRAM_BUCKETS as u64 + 1);
+        let bucket_count = (span / bucket_size + 1) as usize;
+
+        // the order and pagination are irrelevant for bucketing
+        query.order = Order::Asc;
+        query.previous = None;
+
+        let mut counts = vec![0; bucket_count];
+        for event_key in IndexedEventFilterIterator::new(query, self) {
+            let bucket = (event_key.get() - start.get()) / bucket_size;
+            counts[bucket as usize] += 1;
+        }
+
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(bucket, count)| {
+                let bucket_start = start.saturating_add(bucket as u64 * bucket_size);
+                let bucket_end = bucket_start.saturating_add(bucket_size - 1).min(end);
+
+                BucketView {
+                    start: bucket_start,
+                    end: bucket_end,
+                    count,
+                }
+            })
+            .collect()
+    }
+
+    pub(crate) fn list_attribute_keys(&self, kind: EventsOrSpans) -> Vec<String> {
+        let attributes = match kind {
+            EventsOrSpans::Events => &self.event_indexes.attributes,
+            EventsOrSpans::Spans => &self.span_indexes.attributes,
+        };
+
+        attributes.keys().cloned().collect()
+    }
+
+    pub(crate) fn list_attribute_values(
+        &self,
+        kind: EventsOrSpans,
+        attribute: &str,
+        limit: usize,
+    ) -> Vec<String> {
+        let attributes = match kind {
+            EventsOrSpans::Events => &self.event_indexes.attributes,
+            EventsOrSpans::Spans => &self.span_indexes.attributes,
+        };
+
+        let Some(index) = attributes.get(attribute) else {
+            return Vec::new();
+        };
+
+        let mut counts = index.value_counts().into_iter().collect::<HashMap<_, _>>();
+
+        // numeric values are not bucketed, so only a bounded number of them
+        // are looked up
+        for key in index.numeric_entries().take(limit) {
+            let value = match kind {
+                EventsOrSpans::Events => EventContext::new(key, &self.storage)
+                    .attribute(attribute)
+                    .map(Value::to_string),
+                EventsOrSpans::Spans => SpanContext::new(key, &self.storage)
+                    .attribute(attribute)
+                    .map(Value::to_string),
+            };
+
+            if let Some(value) = value {
+                *counts.entry(value).or_default() += 1;
+            }
+        }
+
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+        counts.truncate(limit);
+
+        counts.into_iter().map(|(value, _)| value).collect()
+    }
+
+    pub(crate) fn query_stats(&self) -> StatsView {
+        let connected_connections = self
+            .connections
+            .values()
+            .filter(|connection| connection.disconnected_at.is_none())
+            .count();
+
+        StatsView {
+            start: self.event_indexes.all.first().copied(),
+            end: self.event_indexes.all.last().copied(),
+            total_events: self.event_indexes.all.len(),
+            total_spans: self.span_indexes.all.len(),
+            events_per_level: self.event_indexes.levels.each_ref().map(Vec::len),
+            spans_per_level: self.span_indexes.levels.each_ref().map(Vec::len),
+            connected_connections,
+            disconnected_connections: self.connections.len() - connected_connections,
+        }
+    }
+
+    pub(crate) fn save_filter(
+        &mut self,
+        name: String,
+        filter: String,
+    ) -> Result<SavedFilterView, SaveFilterError> {
+        if self.read_only {
+            return Err(SaveFilterError::ReadOnly);
+        }
+
+        // checked here so that a filter that can't be used is never kept; it
+        // may be meant for either events or spans so it only has to be valid
+        // as one of them
+        let predicates = FilterPredicate::parse(&filter).map_err(SaveFilterError::Syntax)?;
+        let first_invalid =
+            |validate: fn(FilterPredicate) -> Result<FallibleFilterPredicate, InputError>| {
+                predicates.iter().find_map(|predicate| {
+                    first_filter_error(
+                        validate(predicate.clone()).map_err(|e| (e, predicate.to_string())),
+                    )
+                })
+            };
+
+        if let (Some((err, text)), Some(_)) = (
+            first_invalid(BasicEventFilter::validate),
+            first_invalid(BasicSpanFilter::validate),
+        ) {
+            return Err(SaveFilterError::Invalid(err, text));
+        }
+
+        self.storage.save_filter(&name, &filter);
+        self.saved_filters.insert(name.clone(), filter.clone());
+
+        Ok(SavedFilterView { name, filter })
+    }
+
+    pub(crate) fn list_filters(&self) -> Vec<SavedFilterView> {
+        self.saved_filters
+            .iter()
+            .map(|(name, filter)| SavedFilterView {
+                name: name.clone(),
+                filter: filter.clone(),
+            })
+            .collect()
+    }
+
+    pub(crate) fn delete_filter(&mut self, name: &str) -> Option<SavedFilterView> {
+        let (name, filter) = self.saved_filters.remove_entry(name)?;
+        self.storage.delete_saved_filter(&name);
+
+        Some(SavedFilterView { name, filter })
+    }
+
+    pub(crate) fn insert_connection(
+        &mut self,
+        connection: NewConnection,
+    ) -> Result<ConnectionKey, EngineInsertError> {
+        if self.read_only {
+            return Err(EngineInsertError::ReadOnly);
+        }
+
+        // A connection id can be reused once the previous connection with
+        // that id has disconnected. The id then refers to the newest one.
+        if let Some(key) = self.connection_key_map.get(&connection.id) {
+            if self.connections[key].disconnected_at.is_none() {
+                return Err(EngineInsertError::DuplicateConnectionId);
+            }
+        }
+
+        let now = now();
+        let connection_key = self.connection_keys.register(now, now);
+        let connection = Connection {
+            id: connection.id,
+            connected_at: connection_key,
+            disconnected_at: None,
+            schema: connection.schema,
+            fields: connection.fields,
+        };
+
+        self.insert_connection_bookeeping(&connection);
+        self.storage.insert_connection(connection);
+
+        self.notify_connection_subscribers(connection_key);
+
+        Ok(connection_key)
+    }
+
+    // This finds a connection id that isn't in use, starting from `from` and
+    // wrapping around to the lowest ids once the highest is reached.
+    fn free_connection_id(&self, from: ConnectionId) -> Option<ConnectionId> {
+        (from..=ConnectionId::MAX)
+            .chain(1..from)
+            .find(|id| !self.connection_key_map.contains_key(id))
+    }
+
+    fn insert_connection_bookeeping(&mut self, connection: &Connection) {
+        self.connection_key_map
+            .insert(connection.id, connection.key());
+        self.connections
+            .insert(connection.key(), connection.clone());
+        self.connection_indexes
+            .update_with_new_connection(connection);
+    }
+
+    pub(crate) fn disconnect_connection(
+        &mut self,
+        connection_id: ConnectionId,
+    ) -> Result<(), EngineInsertError> {
+        if self.read_only {
+            return Err(EngineInsertError::ReadOnly);
+        }
+
+        let now = now();
+        let at = self.connection_keys.register(now, now);
+
+        let connection_key = *self
+            .connection_key_map
+            .get(&connection_id)
+            .ok_or(EngineInsertError::UnknownConnectionId)?;
+
+        let connection = self.storage.get_connection(connection_key).unwrap();
+
+        if connection.disconnected_at.is_some() {
+            return Err(EngineInsertError::AlreadyDisconnected);
+        }
+
+        let filter = IndexedSpanFilter::And(vec![
+            IndexedSpanFilter::Single(&self.span_indexes.durations.open, None),
+            IndexedSpanFilter::Single(
+                self.span_indexes
+                    .connections
+                    .get(&connection_key)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default(),
+                None,
+            ),
+        ]);
+
+        let open_spans = IndexedSpanFilterIterator::new_internal(filter, self).collect::<Vec<_>>();
+
+        for span_key in open_spans {
+            self.span_indexes.update_with_closed(span_key, at);
+            self.storage.update_span_closed(span_key, at);
+            self.notify_span_subscribers(span_key);
+        }
+
+        if let Some(connection) = self.connections.get_mut(&connection_key) {
+            connection.disconnected_at = Some(at);
+        }
+        self.connection_indexes
+            .update_with_disconnected(connection_key);
+
+        self.storage
+            .update_connection_disconnected(connection_key, at);
+
+        self.notify_connection_subscribers(connection_key);
+
+        Ok(())
+    }
+
+    fn notify_connection_subscribers(&mut self, connection_key: ConnectionKey) {
+        let Some(connection) = self.connections.get(&connection_key) else {
+            return;
+        };
+
+        let mut remove = vec![];
+        for (id, (filter, sender)) in &self.connection_subscribers {
+            if filter.matches(&self.storage, connection_key) {
+                let send_result = sender.send(self.render_connection(connection));
+                if send_result.is_err() {
+                    remove.push(*id);
+                }
+            }
+        }
+
+        for id in remove {
+            self.connection_subscribers.remove(&id);
+        }
+    }
+
+    /// This inserts the span event unless it references a span that hasn't
+    /// been created yet and the engine has a reorder window, in which case it
+    /// is held until that span is created or the window passes. The result is
+    /// sent once the span event is actually inserted or rejected.
+    pub(crate) fn insert_span_event_or_hold(
+        &mut self,
+        new_span_event: NewSpanEvent,
+        reply: SpanEventReply,
+    ) {
+        if self.is_retransmitted(new_span_event.connection_key, new_span_event.seq) {
+            if let Some(reply) = reply {
+                let _ = reply.send(Err(EngineInsertError::AlreadyApplied));
+            }
+            return;
+        }
+
+        match self.missing_span_id(&new_span_event) {
+            Some(span_id) => {
+                let evicted = self.pending.hold(
+                    new_span_event.connection_key,
+                    span_id,
+                    PendingInsert::SpanEvent(new_span_event, reply),
+                );
+                self.force_pending(evicted.into_iter().collect());
+            }
+            None => self.insert_span_event_and_reply(new_span_event, reply),
+        }
+    }
+
+    fn insert_span_event_and_reply(&mut self, new_span_event: NewSpanEvent, reply: SpanEventReply) {
+        let created = match &new_span_event.kind {
+            NewSpanEventKind::Create(_) => {
+                Some((new_span_event.connection_key, new_span_event.span_id))
+            }
+            _ => None,
+        };
+        let (connection_key, seq) = (new_span_event.connection_key, new_span_event.seq);
+
+        let res = self.insert_span_event(new_span_event);
+        match &res {
+            Ok(_) => self.record_seq(connection_key, seq),
+            Err(err) => eprintln!("rejecting span event insert due to: {err:?}"),
+        }
+
+        let inserted = res.is_ok();
+        if let Some(reply) = reply {
+            let _ = reply.send(res);
+        }
+
+        if let (true, Some((connection_key, span_id))) = (inserted, created) {
+            for insert in self.pending.take(connection_key, span_id) {
+                match insert {
+                    PendingInsert::SpanEvent(new_span_event, reply) => {
+                        self.insert_span_event_or_hold(new_span_event, reply);
+                    }
+                    PendingInsert::Event(new_event, reply) => {
+                        self.insert_events_or_hold(vec![(new_event, reply)]);
+                    }
+                }
+            }
+        }
+    }
+
+    // This returns true if the message's sequence number was already applied
+    // for its connection id. Messages without one are always applied.
+    fn is_retransmitted(&self, connection_key: ConnectionKey, seq: Option<u64>) -> bool {
+        let Some(seq) = seq else {
+            return false;
+        };
+
+        let Some(connection) = self.connections.get(&connection_key) else {
+            return false;
+        };
+
+        self.last_seqs
+            .get(&connection.id)
+            .is_some_and(|last_seq| seq <= *last_seq)
+    }
+
+    // This records the sequence number of a message that was inserted, so
+    // that a message that failed can still be applied if it is sent again.
+    fn record_seq(&mut self, connection_key: ConnectionKey, seq: Option<u64>) {
+        let Some(seq) = seq else {
+            return;
+        };
+
+        let Some(connection) = self.connections.get(&connection_key) else {
+            return;
+        };
+
+        let last_seq = self.last_seqs.entry(connection.id).or_insert(seq);
+        *last_seq = (*last_seq).max(seq);
+    }
+
+    // This returns the span that the span event needs but that doesn't exist,
+    // if it should be held for it.
+    fn missing_span_id(&self, new_span_event: &NewSpanEvent) -> Option<SpanId> {
+        if !self.pending.is_enabled() || self.read_only {
+            return None;
+        }
+
+        let connection_key = new_span_event.connection_key;
+        let exists = |span_id| self.span_key_map.contains_key(&(connection_key, span_id));
+
+        let span_id = match &new_span_event.kind {
+            NewSpanEventKind::Create(create) => create.parent_id?,
+            NewSpanEventKind::Follows(follows) if exists(new_span_event.span_id) => follows.follows,
+            _ => new_span_event.span_id,
+        };
+
+        (!exists(span_id)).then_some(span_id)
+    }
+
+    /// This is the same as [`insert_span_event_or_hold`] but for events, which
+    /// are held if their span hasn't been created yet.
+    ///
+    /// [`insert_span_event_or_hold`]: RawEngine::insert_span_event_or_hold
+    pub(crate) fn insert_events_or_hold(&mut self, new_events: Vec<(NewEvent, EventReply)>) {
+        let mut inserts = Vec::with_capacity(new_events.len());
+        for (new_event, reply) in new_events {
+            if self.is_retransmitted(new_event.connection_key, new_event.seq) {
+                if let Some(reply) = reply {
+                    let _ = reply.send(Err(EngineInsertError::AlreadyApplied));
+                }
+                continue;
+            }
+
+            let missing_span_id = new_event.span_id.filter(|span_id| {
+                self.pending.is_enabled()
+                    && !self.read_only
+                    && !self
+                        .span_key_map
+                        .contains_key(&(new_event.connection_key, *span_id))
+            });
+
+            match missing_span_id {
+                Some(span_id) => {
+                    let evicted = self.pending.hold(
+                        new_event.connection_key,
+                        span_id,
+                        PendingInsert::Event(new_event, reply),
+                    );
+                    self.force_pending(evicted.into_iter().collect());
+                }
+                None => inserts.push((new_event, reply)),
+            }
+        }
+
+        self.insert_events_and_reply(inserts);
+    }
+
+    fn insert_events_and_reply(&mut self, new_events: Vec<(NewEvent, EventReply)>) {
+        if new_events.is_empty() {
+            return;
+        }
+
+        let (new_events, replies): (Vec<_>, Vec<_>) = new_events.into_iter().unzip();
+        let seqs = new_events
+            .iter()
+            .map(|new_event| (new_event.connection_key, new_event.seq))
+            .collect::<Vec<_>>();
+
+        let results = self.insert_events(new_events);
+        for ((res, reply), (connection_key, seq)) in results.into_iter().zip(replies).zip(seqs) {
+            match &res {
+                Ok(_) => self.record_seq(connection_key, seq),
+                Err(err) => eprintln!("rejecting event insert due to: {err:?}"),
+            }
+            if let Some(reply) = reply {
+                let _ = reply.send(res);
+            }
+        }
+    }
+
+    /// This does the held inserts that have waited longer than the reorder
+    /// window, which fail if their span still hasn't been created.
+    pub(crate) fn expire_pending(&mut self) {
+        let expired = self.pending.take_expired(Instant::now());
+        self.force_pending(expired);
+    }
+
+    /// This does all the held inserts regardless of how long they've waited.
+    pub(crate) fn resolve_pending(&mut self) {
+        let held = self.pending.take_all();
+        self.force_pending(held);
+    }
+
+    fn force_pending(&mut self, inserts: Vec<PendingInsert>) {
+        for insert in inserts {
+            match insert {
+                PendingInsert::SpanEvent(new_span_event, reply) => {
+                    self.insert_span_event_and_reply(new_span_event, reply);
+                }
+                PendingInsert::Event(new_event, reply) => {
+                    self.insert_events_and_reply(vec![(new_event, reply)]);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn insert_span_event(
         &mut self,
         mut new_span_event: NewSpanEvent,
     ) -> Result<SpanEventKey, EngineInsertError> {
-        let span_event_key = self.keys.register(now(), new_span_event.timestamp);
+        if self.read_only {
+            return Err(EngineInsertError::ReadOnly);
+        }
+
+        if !self
+            .connections
+            .contains_key(&new_span_event.connection_key)
+        {
+            return Err(EngineInsertError::UnknownConnection);
+        }
+
+        let client_timestamp = new_span_event.timestamp;
+        let span_event_key = self.keys.register(now(), client_timestamp);
         new_span_event.timestamp = span_event_key;
 
         match new_span_event.kind {
@@ -906,6 +3237,8 @@ impl<S: Storage> RawEngine<S> {
                     connection_key: new_span_event.connection_key,
                     id: new_span_event.span_id,
                     created_at: new_span_event.timestamp,
+                    client_created_at: (client_timestamp != span_event_key)
+                        .then_some(client_timestamp),
                     closed_at: None,
                     parent_key,
                     follows: Vec::new(),
@@ -918,6 +3251,8 @@ impl<S: Storage> RawEngine<S> {
                     file_name: new_create_event.file_name.clone(),
                     file_line: new_create_event.file_line,
                     fields: new_create_event.fields.clone(),
+                    busy: 0,
+                    entered_at: None,
                 };
 
                 let span_event = SpanEvent {
@@ -938,11 +3273,15 @@ impl<S: Storage> RawEngine<S> {
                     }),
                 };
 
+                let span_key = span.created_at;
+
                 self.insert_span_bookeeping(&span);
                 self.storage.insert_span(span);
 
                 self.insert_span_event_bookeeping(&span_event);
                 self.storage.insert_span_event(span_event);
+
+                self.notify_span_subscribers(span_key);
             }
             NewSpanEventKind::Update(new_update_event) => {
                 let span_key = self
@@ -999,6 +3338,8 @@ impl<S: Storage> RawEngine<S> {
 
                 self.insert_span_event_bookeeping(&span_event);
                 self.storage.insert_span_event(span_event);
+
+                self.notify_span_subscribers(span_key);
             }
             NewSpanEventKind::Follows(new_follows_event) => {
                 let span_key = self
@@ -1029,6 +3370,8 @@ impl<S: Storage> RawEngine<S> {
 
                 self.insert_span_event_bookeeping(&span_event);
                 self.storage.insert_span_event(span_event);
+
+                self.notify_span_subscribers(span_key);
             }
             NewSpanEventKind::Enter => {
                 let span_key = self
@@ -1044,6 +3387,17 @@ impl<S: Storage> RawEngine<S> {
                     kind: SpanEventKind::Enter,
                 };
 
+                // the span can be missing if its storage failed to read it
+                let span = self
+                    .storage
+                    .get_span(span_key)
+                    .ok_or(EngineInsertError::UnknownSpanId)?;
+                if span.entered_at.is_none() {
+                    let entered_at = span.at_client_time(client_timestamp);
+                    self.storage
+                        .update_span_busy(span_key, span.busy, Some(entered_at));
+                }
+
                 self.insert_span_event_bookeeping(&span_event);
                 self.storage.insert_span_event(span_event);
             }
@@ -1061,6 +3415,17 @@ impl<S: Storage> RawEngine<S> {
                     kind: SpanEventKind::Exit,
                 };
 
+                let span = self
+                    .storage
+                    .get_span(span_key)
+                    .ok_or(EngineInsertError::UnknownSpanId)?;
+                if let Some(entered_at) = span.entered_at {
+                    let exited_at = span.at_client_time(client_timestamp);
+                    let busy = exited_at.get().saturating_sub(entered_at.get());
+                    self.storage
+                        .update_span_busy(span_key, span.busy + busy, None);
+                }
+
                 self.insert_span_event_bookeeping(&span_event);
                 self.storage.insert_span_event(span_event);
             }
@@ -1078,34 +3443,64 @@ impl<S: Storage> RawEngine<S> {
                     kind: SpanEventKind::Close,
                 };
 
-                self.span_indexes
-                    .update_with_closed(span_key, new_span_event.timestamp);
+                let span = self
+                    .storage
+                    .get_span(span_key)
+                    .ok_or(EngineInsertError::UnknownSpanId)?;
+                let closed_at = span.at_client_time(client_timestamp);
 
-                self.storage
-                    .update_span_closed(span_key, new_span_event.timestamp);
+                self.span_indexes.update_with_closed(span_key, closed_at);
+                self.storage.update_span_closed(span_key, closed_at);
 
                 self.insert_span_event_bookeeping(&span_event);
                 self.storage.insert_span_event(span_event);
+
+                self.notify_span_subscribers(span_key);
             }
         }
 
         Ok(span_event_key)
     }
 
-    fn insert_span_bookeeping(&mut self, span: &Span) {
-        let span_key = span.created_at;
-
-        self.span_key_map
-            .insert((span.connection_key, span.id), span_key);
-        self.span_id_map.insert(span_key, span.id);
-        self.span_indexes
-            .update_with_new_span(&SpanContext::with_span(span, &self.storage));
-    }
+    fn notify_span_subscribers(&mut self, span_key: SpanKey) {
+        if self.span_subscribers.is_empty() {
+            return;
+        }
 
-    fn insert_span_event_bookeeping(&mut self, span_event: &SpanEvent) {
-        let timestamp = span_event.timestamp;
-        let idx = self.span_event_ids.upper_bound_via_expansion(&timestamp);
-        self.span_event_ids.insert(idx, timestamp);
+        let Some(span) = self.storage.get_span(span_key) else {
+            return;
+        };
+
+        let mut remove = vec![];
+        let context = SpanContext::with_span(&span, &self.storage);
+        for (id, (filter, sender)) in &self.span_subscribers {
+            if filter.matches(&context) {
+                let send_result = sender.send(self.render_span(&span, None));
+                if send_result.is_err() {
+                    remove.push(*id);
+                }
+            }
+        }
+
+        for id in remove {
+            self.span_subscribers.remove(&id);
+        }
+    }
+
+    fn insert_span_bookeeping(&mut self, span: &Span) {
+        let span_key = span.created_at;
+
+        self.span_key_map
+            .insert((span.connection_key, span.id), span_key);
+        self.span_id_map.insert(span_key, span.id);
+        self.span_indexes
+            .update_with_new_span(&SpanContext::with_span(span, &self.storage));
+    }
+
+    fn insert_span_event_bookeeping(&mut self, span_event: &SpanEvent) {
+        let timestamp = span_event.timestamp;
+        let idx = self.span_event_ids.upper_bound_via_expansion(&timestamp);
+        self.span_event_ids.insert(idx, timestamp);
 
         let by_span_index = self
             .span_events_by_span_ids
@@ -1115,7 +3510,144 @@ impl<S: Storage> RawEngine<S> {
         by_span_index.insert(idx, timestamp);
     }
 
-    pub fn insert_event(&mut self, mut new_event: NewEvent) -> Result<(), EngineInsertError> {
+    #[cfg(test)]
+    pub(crate) fn insert_event(
+        &mut self,
+        new_event: NewEvent,
+    ) -> Result<EventKey, EngineInsertError> {
+        self.insert_events(vec![new_event]).pop().unwrap()
+    }
+
+    /// This inserts many events, writing them to storage in a single batch.
+    /// Each event is validated independently, so one being rejected does not
+    /// keep the others from being inserted.
+    pub(crate) fn insert_events(
+        &mut self,
+        new_events: Vec<NewEvent>,
+    ) -> Vec<Result<EventKey, EngineInsertError>> {
+        if self.read_only {
+            return new_events
+                .iter()
+                .map(|_| Err(EngineInsertError::ReadOnly))
+                .collect();
+        }
+
+        let mut results = Vec::with_capacity(new_events.len());
+        let mut events = Vec::with_capacity(new_events.len());
+
+        for new_event in new_events {
+            match self.prepare_event(new_event) {
+                Ok(event) => {
+                    self.insert_event_bookeeping(&event);
+                    self.notify_event_subscribers(&event);
+                    results.push(Ok(event.key()));
+                    events.push(event);
+                }
+                Err(err) => results.push(Err(err)),
+            }
+        }
+
+        self.storage.insert_events(events);
+
+        results
+    }
+
+    pub(crate) fn insert_oneshot_event(
+        &mut self,
+        new_event: NewOneShotEvent,
+    ) -> Result<EventKey, EngineInsertError> {
+        if self.read_only {
+            return Err(EngineInsertError::ReadOnly);
+        }
+
+        // this is checked up front so a rejected event doesn't leave behind
+        // an empty connection
+        let _: Level = new_event
+            .level
+            .try_into()
+            .map_err(|_| EngineInsertError::UnknownLevel)?;
+
+        // events with the same connection fields share a connection, so that
+        // scripts sending them don't each leave a connection behind
+        let fields_key = serde_json::to_string(&new_event.connection_fields).unwrap();
+        let existing = self
+            .oneshot_connections
+            .get(&fields_key)
+            .copied()
+            .filter(|connection_key| self.connections.contains_key(connection_key));
+
+        let connection_key = match existing {
+            Some(connection_key) => connection_key,
+            None => {
+                let connection_id = self
+                    .free_connection_id(self.next_oneshot_id)
+                    .ok_or(EngineInsertError::DuplicateConnectionId)?;
+                self.next_oneshot_id = connection_id
+                    .checked_add(1)
+                    .unwrap_or(ONESHOT_CONNECTION_IDS);
+
+                self.insert_connection(NewConnection::new(
+                    connection_id,
+                    new_event.connection_fields,
+                ))?
+            }
+        };
+
+        let result = self
+            .insert_events(vec![NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: new_event.timestamp,
+                span_id: None,
+                name: new_event.name,
+                target: new_event.target,
+                level: new_event.level,
+                file_name: new_event.file_name,
+                file_line: new_event.file_line,
+                fields: new_event.fields,
+            }])
+            .pop()
+            .unwrap();
+
+        let connection_id = self.connections[&connection_key].id;
+        match (&result, existing) {
+            (Ok(_), Some(_)) => self.extend_disconnected(connection_key),
+            (Ok(_), None) => {
+                self.disconnect_connection(connection_id)?;
+                self.oneshot_connections.insert(fields_key, connection_key);
+            }
+            (Err(_), Some(_)) => {}
+            (Err(_), None) => {
+                // a connection made for an event that was rejected is removed
+                // so it isn't left empty
+                self.disconnect_connection(connection_id)?;
+                let _ = self.delete_connection(connection_id);
+            }
+        }
+
+        result
+    }
+
+    // This moves the disconnect of a connection that was already disconnected
+    // up to now, so it covers what was inserted into it since.
+    fn extend_disconnected(&mut self, connection_key: ConnectionKey) {
+        let now = now();
+        let at = self.connection_keys.register(now, now);
+
+        if let Some(connection) = self.connections.get_mut(&connection_key) {
+            connection.disconnected_at = Some(at);
+        }
+        self.storage
+            .update_connection_disconnected(connection_key, at);
+
+        self.notify_connection_subscribers(connection_key);
+    }
+
+    fn prepare_event(&mut self, new_event: NewEvent) -> Result<Event, EngineInsertError> {
+        if !self.connections.contains_key(&new_event.connection_key) {
+            return Err(EngineInsertError::UnknownConnection);
+        }
+
         let span_key = new_event
             .span_id
             .map(|span_id| {
@@ -1126,32 +3658,34 @@ impl<S: Storage> RawEngine<S> {
             })
             .transpose()?;
 
-        let event_key = self.keys.register(now(), new_event.timestamp);
-        new_event.timestamp = event_key;
+        let level = new_event
+            .level
+            .try_into()
+            .map_err(|_| EngineInsertError::UnknownLevel)?;
+
+        let client_timestamp = new_event.timestamp;
+        let event_key = self.keys.register(now(), client_timestamp);
 
-        let event = Event {
+        Ok(Event {
             connection_key: new_event.connection_key,
-            timestamp: new_event.timestamp,
+            timestamp: event_key,
+            client_timestamp: (client_timestamp != event_key).then_some(client_timestamp),
             span_key,
             name: new_event.name,
             target: new_event.target,
-            level: new_event
-                .level
-                .try_into()
-                .map_err(|_| EngineInsertError::UnknownLevel)?,
+            level,
             file_name: new_event.file_name,
             file_line: new_event.file_line,
             fields: new_event.fields,
-        };
-
-        self.insert_event_bookeeping(&event);
-        self.storage.insert_event(event.clone());
+        })
+    }
 
+    fn notify_event_subscribers(&mut self, event: &Event) {
         let mut remove = vec![];
-        let context = EventContext::with_event(&event, &self.storage);
+        let context = EventContext::with_event(event, &self.storage);
         for (id, (filter, sender)) in &self.event_subscribers {
             if filter.matches(&context) {
-                let send_result = sender.send(self.render_event(&event));
+                let send_result = sender.send(self.render_event(event));
                 if send_result.is_err() {
                     remove.push(*id);
                 }
@@ -1161,8 +3695,6 @@ impl<S: Storage> RawEngine<S> {
         for id in remove {
             self.event_subscribers.remove(&id);
         }
-
-        Ok(())
     }
 
     fn insert_event_bookeeping(&mut self, event: &Event) {
@@ -1170,7 +3702,71 @@ impl<S: Storage> RawEngine<S> {
             .update_with_new_event(&EventContext::with_event(event, &self.storage));
     }
 
-    pub fn delete(&mut self, filter: DeleteFilter) -> DeleteMetrics {
+    pub(crate) fn compact(&mut self) -> Option<CompactMetrics> {
+        if self.read_only {
+            return None;
+        }
+
+        self.storage.compact()
+    }
+
+    /// This also saves the indexes so they needn't be rebuilt on startup if
+    /// nothing else is written.
+    pub(crate) fn flush(&mut self) {
+        if self.read_only {
+            return;
+        }
+
+        if !self.options.persist_indexes {
+            self.storage.flush();
+            return;
+        }
+
+        let indexes = SavedIndexesRef {
+            span_key_map: &self.span_key_map,
+            span_id_map: &self.span_id_map,
+            span_indexes: &self.span_indexes,
+            span_event_ids: &self.span_event_ids,
+            span_events_by_span_ids: &self.span_events_by_span_ids,
+            event_indexes: &self.event_indexes,
+        };
+
+        self.storage
+            .save_indexes(&|| bincode::serialize(&(SAVED_INDEXES_VERSION, &indexes)).unwrap());
+        self.storage.flush();
+    }
+
+    pub(crate) fn clear(&mut self) {
+        if self.read_only {
+            return;
+        }
+
+        // held inserts are answered first so they don't outlive the data they
+        // were waiting on
+        self.resolve_pending();
+        self.storage.clear();
+
+        self.connection_keys = KeyAllocator::new();
+        self.keys = KeyAllocator::new();
+        self.connection_key_map.clear();
+        self.connections.clear();
+        self.connection_indexes = ConnectionIndexes::new();
+        self.span_key_map.clear();
+        self.span_id_map.clear();
+        self.span_indexes = SpanIndexes::new();
+        self.span_event_ids.clear();
+        self.span_events_by_span_ids.clear();
+        self.event_indexes = EventIndexes::new();
+        self.last_seqs.clear();
+        self.oneshot_connections.clear();
+
+        // dropping the senders closes the subscriptions
+        self.event_subscribers.clear();
+        self.span_subscribers.clear();
+        self.connection_subscribers.clear();
+    }
+
+    pub(crate) fn delete(&mut self, filter: DeleteFilter) -> DeleteMetrics {
         let connections =
             self.get_connections_in_range_filter(filter.start, filter.end, filter.inside);
         let root_spans =
@@ -1223,12 +3819,74 @@ impl<S: Storage> RawEngine<S> {
             };
         }
 
-        let mut connections_to_delete = connections;
-        let mut spans_to_delete = spans_from_root_spans;
-        let mut span_events_to_delete = span_events;
-        let mut events_to_delete = root_events;
-        events_to_delete.extend(events_from_root_spans);
+        if self.read_only {
+            return DeleteMetrics {
+                connections: 0,
+                spans: 0,
+                span_events: 0,
+                events: 0,
+            };
+        }
+
+        let mut events = root_events;
+        events.extend(events_from_root_spans);
+
+        self.delete_entities(connections, spans_from_root_spans, span_events, events)
+    }
+
+    /// Removes a connection along with all of its spans, span events, and
+    /// events. Nothing belonging to other connections is affected.
+    pub(crate) fn delete_connection(
+        &mut self,
+        connection_id: ConnectionId,
+    ) -> Result<DeleteMetrics, DeleteConnectionError> {
+        if self.read_only {
+            return Err(DeleteConnectionError::ReadOnly);
+        }
+
+        let connection_key = *self
+            .connection_key_map
+            .get(&connection_id)
+            .ok_or(DeleteConnectionError::UnknownConnectionId)?;
+
+        if self.connections[&connection_key].disconnected_at.is_none() {
+            return Err(DeleteConnectionError::StillConnected);
+        }
+
+        let spans = self
+            .span_indexes
+            .connections
+            .get(&connection_key)
+            .cloned()
+            .unwrap_or_default();
+        let events = self
+            .event_indexes
+            .connections
+            .get(&connection_key)
+            .cloned()
+            .unwrap_or_default();
+        let span_events = spans
+            .iter()
+            .flat_map(|span| {
+                self.span_events_by_span_ids
+                    .get(span)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default()
+                    .iter()
+                    .cloned()
+            })
+            .collect::<Vec<SpanEventKey>>();
+
+        Ok(self.delete_entities(vec![connection_key], spans, span_events, events))
+    }
 
+    fn delete_entities(
+        &mut self,
+        mut connections_to_delete: Vec<ConnectionKey>,
+        mut spans_to_delete: Vec<SpanKey>,
+        mut span_events_to_delete: Vec<SpanEventKey>,
+        mut events_to_delete: Vec<EventKey>,
+    ) -> DeleteMetrics {
         connections_to_delete.sort(); // this should already be sorted in theory
         spans_to_delete.sort();
         span_events_to_delete.sort();
@@ -1257,7 +3915,7 @@ impl<S: Storage> RawEngine<S> {
         }
     }
 
-    pub fn get_connections_in_range_filter(
+    pub(crate) fn get_connections_in_range_filter(
         &self,
         start: Timestamp,
         end: Timestamp,
@@ -1278,7 +3936,7 @@ impl<S: Storage> RawEngine<S> {
             .collect()
     }
 
-    pub fn get_root_spans_in_range_filter(
+    pub(crate) fn get_root_spans_in_range_filter(
         &self,
         start: Timestamp,
         end: Timestamp,
@@ -1306,7 +3964,7 @@ impl<S: Storage> RawEngine<S> {
         iter.collect()
     }
 
-    pub fn get_root_events_in_range_filter(
+    pub(crate) fn get_root_events_in_range_filter(
         &self,
         start: Timestamp,
         end: Timestamp,
@@ -1342,6 +4000,7 @@ impl<S: Storage> RawEngine<S> {
         self.connection_key_map
             .retain(|_, key| !connections.contains(key));
 
+        self.connection_indexes.remove_connections(connections);
         self.span_indexes.remove_connections(connections);
         self.event_indexes.remove_connections(connections);
     }
@@ -1373,7 +4032,7 @@ impl<S: Storage> RawEngine<S> {
         self.event_indexes.remove_events(events);
     }
 
-    pub fn copy_dataset(&self, mut to: Box<dyn Storage + Send>) {
+    pub(crate) fn copy_dataset(&self, mut to: Box<dyn Storage + Send>) {
         let connections = self.storage.get_all_connections().collect::<Vec<_>>();
 
         for connection in connections {
@@ -1399,7 +4058,114 @@ impl<S: Storage> RawEngine<S> {
         }
     }
 
-    pub fn subscribe_to_events(
+    pub(crate) fn fork_filtered(&self, filter: Vec<FilterPredicate>) -> TransientStorage {
+        let query = || Query {
+            filter: filter.clone(),
+            order: Order::Asc,
+            limit: usize::MAX,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        };
+
+        // the filter may only be valid for one kind of entity (`#duration` is
+        // only for spans for example) so each kind is only searched if valid
+        let is_valid_for_events = filter.iter().all(|p| {
+            BasicEventFilter::from_predicate(
+                p.clone(),
+                &self.connection_key_map,
+                &self.span_key_map,
+            )
+            .is_ok()
+        });
+        let is_valid_for_spans = filter.iter().all(|p| {
+            BasicSpanFilter::from_predicate(p.clone(), &self.connection_key_map, &self.span_key_map)
+                .is_ok()
+        });
+
+        let event_keys = if is_valid_for_events {
+            IndexedEventFilterIterator::new(query(), self).collect()
+        } else {
+            vec![]
+        };
+
+        let mut span_keys = if is_valid_for_spans {
+            IndexedSpanFilterIterator::new(query(), self).collect()
+        } else {
+            BTreeSet::new()
+        };
+
+        let events = event_keys
+            .into_iter()
+            .map(|key| self.storage.get_event(key).unwrap())
+            .collect::<Vec<_>>();
+
+        span_keys.extend(events.iter().filter_map(|event| event.span_key));
+
+        // include all ancestors so the copied entities keep their hierarchy
+        let mut ancestor_keys = vec![];
+        for span_key in &span_keys {
+            let mut parent_key = self.storage.get_span(*span_key).unwrap().parent_key;
+            while let Some(key) = parent_key {
+                if span_keys.contains(&key) {
+                    break;
+                }
+
+                ancestor_keys.push(key);
+                parent_key = self.storage.get_span(key).unwrap().parent_key;
+            }
+        }
+        span_keys.extend(ancestor_keys);
+
+        let spans = span_keys
+            .iter()
+            .map(|key| self.storage.get_span(*key).unwrap())
+            .collect::<Vec<_>>();
+
+        let connection_keys = events
+            .iter()
+            .map(|event| event.connection_key)
+            .chain(spans.iter().map(|span| span.connection_key))
+            .collect::<BTreeSet<_>>();
+
+        let mut storage = TransientStorage::new();
+
+        for connection_key in connection_keys {
+            let connection = self.storage.get_connection(connection_key).unwrap();
+            storage.insert_connection((*connection).clone());
+        }
+
+        for span in spans {
+            let mut span = (*span).clone();
+            span.follows.retain(|key| span_keys.contains(key));
+
+            for span_event_key in self
+                .span_events_by_span_ids
+                .get(&span.key())
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+            {
+                let span_event = self.storage.get_span_event(*span_event_key).unwrap();
+                if let SpanEventKind::Follows(follows) = &span_event.kind {
+                    if !span_keys.contains(&follows.follows) {
+                        continue;
+                    }
+                }
+
+                storage.insert_span_event((*span_event).clone());
+            }
+
+            storage.insert_span(span);
+        }
+
+        for event in events {
+            storage.insert_event((*event).clone());
+        }
+
+        storage
+    }
+
+    pub(crate) fn subscribe_to_events(
         &mut self,
         filter: Vec<FilterPredicate>,
     ) -> (SubscriptionId, UnboundedReceiver<EventView>) {
@@ -1428,116 +4194,340 @@ impl<S: Storage> RawEngine<S> {
         (id, receiver)
     }
 
-    pub fn unsubscribe_from_events(&mut self, id: SubscriptionId) {
+    pub(crate) fn unsubscribe_from_events(&mut self, id: SubscriptionId) {
         self.event_subscribers.remove(&id);
     }
-}
 
-struct KeyCache {
-    keys: Cell<VecDeque<Timestamp>>,
-}
+    pub(crate) fn subscribe_to_spans(
+        &mut self,
+        filter: Vec<FilterPredicate>,
+    ) -> (SubscriptionId, UnboundedReceiver<SpanView>) {
+        let mut filter = BasicSpanFilter::And(
+            filter
+                .into_iter()
+                .map(|p| {
+                    BasicSpanFilter::from_predicate(p, &self.connection_key_map, &self.span_key_map)
+                        .unwrap()
+                })
+                .collect(),
+        );
+        filter.simplify();
 
-impl KeyCache {
-    fn new() -> KeyCache {
-        KeyCache {
-            keys: Cell::new(VecDeque::new()),
-        }
+        let id = self.next_subscriber_id;
+        self.next_subscriber_id += 1;
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        self.span_subscribers.insert(id, (filter, sender));
+
+        (id, receiver)
     }
 
-    fn register(&self, now: Timestamp, desired: Timestamp) -> Timestamp {
-        let mut keys = self.keys.take();
+    pub(crate) fn unsubscribe_from_spans(&mut self, id: SubscriptionId) {
+        self.span_subscribers.remove(&id);
+    }
 
-        // only keep 10s of cached keys and limit to 1s in the future
-        let min = saturating_sub(now, 10000000);
-        let max = now.saturating_add(1000000);
+    pub(crate) fn subscribe_to_connections(
+        &mut self,
+        filter: Vec<FilterPredicate>,
+    ) -> (SubscriptionId, UnboundedReceiver<ConnectionView>) {
+        let mut filter = BasicConnectionFilter::And(
+            filter
+                .into_iter()
+                .map(|p| BasicConnectionFilter::from_predicate(p).unwrap())
+                .collect(),
+        );
+        filter.simplify();
 
-        let mut desired = desired.max(min).min(max);
+        let id = self.next_subscriber_id;
+        self.next_subscriber_id += 1;
 
-        let idx = keys.partition_point(|key| *key < min);
-        keys.drain(..idx);
+        let (sender, receiver) = mpsc::unbounded_channel();
 
-        let mut idx = keys.partition_point(|key| *key < desired);
-        while idx < keys.len() {
-            if keys[idx] != desired {
-                break;
-            } else {
-                idx += 1;
-                desired = desired.saturating_add(1);
-            }
-        }
+        self.connection_subscribers.insert(id, (filter, sender));
 
-        keys.insert(idx, desired);
+        (id, receiver)
+    }
 
-        self.keys.set(keys);
-        desired
+    pub(crate) fn unsubscribe_from_connections(&mut self, id: SubscriptionId) {
+        self.connection_subscribers.remove(&id);
     }
 }
 
-fn saturating_sub(a: Timestamp, b: u64) -> Timestamp {
-    Timestamp::new(a.get().saturating_sub(b)).unwrap_or(Timestamp::MIN)
+/// The version of the [`SavedIndexes`] layout. This must be bumped whenever it
+/// or any of the indexes change so that older saves are rebuilt instead.
+const SAVED_INDEXES_VERSION: u32 = 1;
+
+/// This is the part of the engine that is derived from spans, span events, and
+/// events. Connections are few so they are always loaded from storage.
+#[derive(Deserialize)]
+struct SavedIndexes {
+    span_key_map: HashMap<(ConnectionKey, SpanId), SpanKey>,
+    span_id_map: HashMap<SpanKey, SpanId>,
+    span_indexes: SpanIndexes,
+    span_event_ids: Vec<Timestamp>,
+    span_events_by_span_ids: HashMap<SpanKey, Vec<Timestamp>>,
+    event_indexes: EventIndexes,
 }
 
-struct EventContext<'a, S> {
-    event_key: EventKey,
-    storage: &'a S,
-    event: RefOrDeferredArc<'a, Event>,
-    parents: OnceCell<Vec<Arc<Span>>>,
-    connection: OnceCell<Arc<Connection>>,
+// This must have the same fields in the same order as `SavedIndexes`.
+#[derive(Serialize)]
+struct SavedIndexesRef<'a> {
+    span_key_map: &'a HashMap<(ConnectionKey, SpanId), SpanKey>,
+    span_id_map: &'a HashMap<SpanKey, SpanId>,
+    span_indexes: &'a SpanIndexes,
+    span_event_ids: &'a Vec<Timestamp>,
+    span_events_by_span_ids: &'a HashMap<SpanKey, Vec<Timestamp>>,
+    event_indexes: &'a EventIndexes,
 }
 
-impl<'a, S> EventContext<'a, S>
-where
-    S: Storage,
-{
-    fn new(event_key: EventKey, storage: &'a S) -> EventContext<'a, S> {
-        EventContext {
-            event_key,
-            storage,
-            event: RefOrDeferredArc::Deferred(OnceCell::new()),
-            parents: OnceCell::new(),
-            connection: OnceCell::new(),
+/// This hands out the keys for connections, spans, span events, and events.
+/// Keys are timestamps so they follow the client's clock where possible, but
+/// each is greater than the last so they are unique and in the order the
+/// engine received them regardless of how well the client's clock behaves.
+struct KeyAllocator {
+    last: Cell<Option<Timestamp>>,
+}
+
+impl KeyAllocator {
+    fn new() -> KeyAllocator {
+        KeyAllocator {
+            last: Cell::new(None),
         }
     }
 
-    fn with_event(event: &'a Event, storage: &'a S) -> EventContext<'a, S> {
-        EventContext {
-            event_key: event.key(),
-            storage,
-            event: RefOrDeferredArc::Ref(event),
-            parents: OnceCell::new(),
-            connection: OnceCell::new(),
+    // This continues after the given key, for when keys were handed out by a
+    // previous run of the engine.
+    fn starting_after(last: Option<Timestamp>) -> KeyAllocator {
+        KeyAllocator {
+            last: Cell::new(last),
         }
     }
 
-    fn key(&self) -> EventKey {
-        self.event_key
+    // This returns a key as close to `desired` as possible. Timestamps more
+    // than 10s in the past or 1s in the future are assumed to be from a bad
+    // clock and are clamped, and the next key after the last one is used if
+    // `desired` is not after it. Keys are only for identity and ordering, so
+    // durations are measured from the client's timestamps instead.
+    fn register(&self, now: Timestamp, desired: Timestamp) -> Timestamp {
+        let min = saturating_sub(now, 10000000);
+        let max = now.saturating_add(1000000);
+
+        let desired = desired.max(min).min(max);
+        let key = match self.last.get() {
+            Some(last) if desired <= last => last.saturating_add(1),
+            _ => desired,
+        };
+
+        self.last.set(Some(key));
+        key
     }
 
-    fn event(&self) -> &Event {
-        match &self.event {
-            RefOrDeferredArc::Ref(event) => event,
-            RefOrDeferredArc::Deferred(deferred) => {
+    // This makes sure a key that was chosen elsewhere (like for imported
+    // data) is not handed out again.
+    fn reserve(&self, key: Timestamp) {
+        if self.last.get().is_none_or(|last| last < key) {
+            self.last.set(Some(key));
+        }
+    }
+}
+
+// This quotes the value if it has any characters that are special to CSV.
+fn encode_csv_value(value: &str) -> String {
+    if value.contains(['"', ',', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+// Jaeger tags carry their type, but only have 64-bit integers so larger values
+// are kept as strings.
+fn jaeger_tag(key: &str, value: &Value) -> serde_json::Value {
+    let (typ, value) = match value {
+        Value::F64(value) => ("float64", serde_json::json!(value)),
+        Value::I64(value) => ("int64", serde_json::json!(value)),
+        Value::U64(value) => match i64::try_from(*value) {
+            Ok(value) => ("int64", serde_json::json!(value)),
+            Err(_) => ("string", serde_json::json!(value.to_string())),
+        },
+        Value::I128(value) => ("string", serde_json::json!(value.to_string())),
+        Value::U128(value) => ("string", serde_json::json!(value.to_string())),
+        Value::Bool(value) => ("bool", serde_json::json!(value)),
+        Value::Str(value) => ("string", serde_json::json!(value)),
+    };
+
+    serde_json::json!({ "key": key, "type": typ, "value": value })
+}
+
+// This reverses the rendering of an attribute value, falling back to a string
+// if it does not parse as its type.
+fn parse_attribute_value(typ: AttributeTypeView, value: String) -> Value {
+    let parsed = match typ {
+        AttributeTypeView::F64 => value.parse().ok().map(Value::F64),
+        AttributeTypeView::I64 => value.parse().ok().map(Value::I64),
+        AttributeTypeView::U64 => value.parse().ok().map(Value::U64),
+        AttributeTypeView::I128 => value.parse().ok().map(Value::I128),
+        AttributeTypeView::U128 => value.parse().ok().map(Value::U128),
+        AttributeTypeView::Bool => value.parse().ok().map(Value::Bool),
+        AttributeTypeView::String => None,
+    };
+
+    parsed.unwrap_or(Value::Str(value))
+}
+
+fn saturating_sub(a: Timestamp, b: u64) -> Timestamp {
+    Timestamp::new(a.get().saturating_sub(b)).unwrap_or(Timestamp::MIN)
+}
+
+// This returns the first invalid predicate in the validation result along with
+// its text.
+fn first_filter_error(
+    result: Result<FallibleFilterPredicate, (InputError, String)>,
+) -> Option<(InputError, String)> {
+    match result {
+        Ok(FallibleFilterPredicate::Single(_)) => None,
+        Ok(FallibleFilterPredicate::And(predicates) | FallibleFilterPredicate::Or(predicates)) => {
+            predicates.into_iter().find_map(first_filter_error)
+        }
+        Err(err) => Some(err),
+    }
+}
+
+/// This keeps what was resolved for an event while a query checks it against
+/// its filters. Each filter makes its own `EventContext`, so without this the
+/// ancestors would be walked again for every attribute predicate. Events are
+/// checked one at a time, so only the most recent one is kept.
+#[derive(Default)]
+struct EventContextCache {
+    event_key: Cell<Option<EventKey>>,
+    parents: RefCell<Option<Vec<Arc<Span>>>>,
+    attributes: RefCell<HashMap<String, Option<Value>>>,
+}
+
+impl EventContextCache {
+    fn new() -> EventContextCache {
+        EventContextCache::default()
+    }
+
+    fn reset_for(&self, event_key: EventKey) {
+        if self.event_key.get() != Some(event_key) {
+            self.event_key.set(Some(event_key));
+            self.parents.borrow_mut().take();
+            self.attributes.borrow_mut().clear();
+        }
+    }
+
+    fn parents(
+        &self,
+        event_key: EventKey,
+        load: impl FnOnce() -> Vec<Arc<Span>>,
+    ) -> Vec<Arc<Span>> {
+        self.reset_for(event_key);
+        self.parents.borrow_mut().get_or_insert_with(load).clone()
+    }
+
+    fn attribute<R>(
+        &self,
+        event_key: EventKey,
+        attr: &str,
+        resolve: impl FnOnce() -> Option<Value>,
+        f: impl FnOnce(Option<&Value>) -> R,
+    ) -> R {
+        self.reset_for(event_key);
+        if let Some(value) = self.attributes.borrow().get(attr) {
+            return f(value.as_ref());
+        }
+
+        let value = resolve();
+        let result = f(value.as_ref());
+        self.attributes.borrow_mut().insert(attr.to_owned(), value);
+        result
+    }
+}
+
+struct EventContext<'a, S> {
+    event_key: EventKey,
+    storage: &'a S,
+    event: RefOrDeferredArc<'a, Event>,
+    parents: OnceCell<Vec<Arc<Span>>>,
+    connection: OnceCell<Arc<Connection>>,
+    cache: Option<&'a EventContextCache>,
+}
+
+impl<'a, S> EventContext<'a, S>
+where
+    S: Storage,
+{
+    fn new(event_key: EventKey, storage: &'a S) -> EventContext<'a, S> {
+        EventContext {
+            event_key,
+            storage,
+            event: RefOrDeferredArc::Deferred(OnceCell::new()),
+            parents: OnceCell::new(),
+            connection: OnceCell::new(),
+            cache: None,
+        }
+    }
+
+    fn with_cache(
+        event_key: EventKey,
+        storage: &'a S,
+        cache: &'a EventContextCache,
+    ) -> EventContext<'a, S> {
+        EventContext {
+            event_key,
+            storage,
+            event: RefOrDeferredArc::Deferred(OnceCell::new()),
+            parents: OnceCell::new(),
+            connection: OnceCell::new(),
+            cache: Some(cache),
+        }
+    }
+
+    fn with_event(event: &'a Event, storage: &'a S) -> EventContext<'a, S> {
+        EventContext {
+            event_key: event.key(),
+            storage,
+            event: RefOrDeferredArc::Ref(event),
+            parents: OnceCell::new(),
+            connection: OnceCell::new(),
+            cache: None,
+        }
+    }
+
+    fn key(&self) -> EventKey {
+        self.event_key
+    }
+
+    fn event(&self) -> &Event {
+        match &self.event {
+            RefOrDeferredArc::Ref(event) => event,
+            RefOrDeferredArc::Deferred(deferred) => {
                 deferred.get_or_init(|| self.storage.get_event(self.event_key).unwrap())
             }
         }
     }
 
     fn parents(&self) -> impl Iterator<Item = &Span> {
-        let event = self.event();
+        let load = || {
+            let mut parents = vec![];
+            let mut parent_key_next = self.event().span_key;
 
-        self.parents
-            .get_or_init(|| {
-                let mut parents = vec![];
-                let mut parent_key_next = event.span_key;
+            while let Some(parent_key) = parent_key_next {
+                let parent = self.storage.get_span(parent_key).unwrap();
 
-                while let Some(parent_key) = parent_key_next {
-                    let parent = self.storage.get_span(parent_key).unwrap();
+                parent_key_next = parent.parent_key;
+                parents.push(parent);
+            }
 
-                    parent_key_next = parent.parent_key;
-                    parents.push(parent);
-                }
+            parents
+        };
 
-                parents
+        self.parents
+            .get_or_init(|| match self.cache {
+                Some(cache) => cache.parents(self.event_key, load),
+                None => load(),
             })
             .iter()
             .map(|p| &**p)
@@ -1572,6 +4562,17 @@ where
         None
     }
 
+    // This resolves the attribute like `attribute`, but only once per event
+    // if the context was given a cache.
+    fn with_attribute<R>(&self, attr: &str, f: impl FnOnce(Option<&Value>) -> R) -> R {
+        match self.cache {
+            Some(cache) => {
+                cache.attribute(self.event_key, attr, || self.attribute(attr).cloned(), f)
+            }
+            None => f(self.attribute(attr)),
+        }
+    }
+
     fn attribute_with_key(&self, attr: &str) -> Option<(&Value, Timestamp)> {
         let event = self.event();
         if let Some(v) = event.fields.get(attr) {
@@ -1593,7 +4594,6 @@ where
         None
     }
 
-    #[allow(unused)]
     fn attributes(&self) -> impl Iterator<Item = (&str, &Value)> {
         let mut attributes = BTreeMap::new();
 
@@ -1781,25 +4781,135 @@ fn now() -> Timestamp {
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use filter::Order;
     use models::{NewCreateSpanEvent, NewUpdateSpanEvent};
 
     use super::*;
 
+    fn simple_event(
+        connection_key: ConnectionKey,
+        timestamp: u64,
+        span_id: Option<u64>,
+    ) -> NewEvent {
+        NewEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(timestamp).unwrap(),
+            span_id: span_id.map(|id| id.try_into().unwrap()),
+            name: "event".to_owned(),
+            target: "crate::storage::tests".to_owned(),
+            level: 2,
+            file_name: None,
+            file_line: None,
+            fields: BTreeMap::new(),
+        }
+    }
+
+    fn simple_open(
+        connection_key: ConnectionKey,
+        open: u64,
+        parent_id: Option<u64>,
+    ) -> NewSpanEvent {
+        named_open(connection_key, open, parent_id, "test")
+    }
+
+    fn named_open(
+        connection_key: ConnectionKey,
+        open: u64,
+        parent_id: Option<u64>,
+        name: &str,
+    ) -> NewSpanEvent {
+        NewSpanEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(open).unwrap(),
+            span_id: open.try_into().unwrap(),
+            kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                parent_id: parent_id.map(|id| id.try_into().unwrap()),
+                target: "crate::storage::tests".to_owned(),
+                name: name.to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            }),
+        }
+    }
+
+    fn simple_close(connection_key: ConnectionKey, open: u64, close: u64) -> NewSpanEvent {
+        NewSpanEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(close).unwrap(),
+            span_id: open.try_into().unwrap(),
+            kind: NewSpanEventKind::Close,
+        }
+    }
+
+    fn query(filter: &str) -> Query {
+        Query {
+            filter: FilterPredicate::parse(filter).unwrap(),
+            order: Order::Asc,
+            limit: 5,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        }
+    }
+
     #[test]
-    fn test_event_filters() {
+    fn connection_found_by_schema() {
         let mut engine = RawEngine::new(TransientStorage::new());
 
-        let connection_key = engine
+        engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+        engine
+            .insert_connection(NewConnection {
+                id: 2,
+                schema: 2,
+                fields: BTreeMap::new(),
+            })
+            .unwrap();
+        engine
             .insert_connection(NewConnection {
-                id: 1,
+                id: 3,
+                schema: 3,
                 fields: BTreeMap::new(),
             })
             .unwrap();
 
+        let connections = engine.query_connection(query("#schema: 2"));
+        assert_eq!(connections.len(), 1);
+        assert_eq!(connections[0].id, "2");
+        assert_eq!(connections[0].schema, 2);
+
+        let connections = engine.query_connection(query("#schema: >=2"));
+        assert_eq!(connections.len(), 2);
+
+        let connections = engine.query_connection(query("#schema: !1"));
+        assert_eq!(connections.len(), 2);
+
+        assert!(BasicConnectionFilter::validate(
+            FilterPredicate::parse("#schema: v2").unwrap().remove(0)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_event_filters() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
         let simple = |id: u64, level: i32, attribute1: &str, attribute2: &str| -> NewEvent {
             NewEvent {
                 connection_key,
+                seq: None,
                 timestamp: id.try_into().unwrap(),
                 span_id: None,
                 name: "event".to_owned(),
@@ -1824,21 +4934,3199 @@ mod tests {
         engine.insert_event(simple(8, 4, "test", "C")).unwrap(); // excluded by "C"
         engine.insert_event(simple(9, 4, "test", "A")).unwrap(); // excluded by timestamp
 
-        let events = engine.query_event(Query {
-            filter: FilterPredicate::parse(
-                "#level: >=WARN @\"attribute1\": test @\"attribute2\": A",
-            )
-            .unwrap(),
-            order: Order::Asc,
-            limit: 3,
-            start: Timestamp::new(2).unwrap(),
-            end: Timestamp::new(8).unwrap(),
+        let events = engine.query_event(Query {
+            filter: FilterPredicate::parse(
+                "#level: >=WARN @\"attribute1\": test @\"attribute2\": A",
+            )
+            .unwrap(),
+            order: Order::Asc,
+            limit: 3,
+            start: Timestamp::new(2).unwrap(),
+            end: Timestamp::new(8).unwrap(),
+            previous: None,
+        });
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].timestamp, Timestamp::new(4).unwrap());
+        assert_eq!(events[1].timestamp, Timestamp::new(5).unwrap());
+    }
+
+    #[test]
+    fn event_found_by_level_range() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        for level in 0..5 {
+            engine
+                .insert_event(NewEvent {
+                    level,
+                    ..simple_event(connection_key, level as u64 + 1, None)
+                })
+                .unwrap();
+        }
+
+        let events = engine.query_event(query("#level: <=DEBUG"));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].level, 0);
+        assert_eq!(events[1].level, 1);
+
+        let events = engine.query_event(query("#level: DEBUG..WARN"));
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].level, 1);
+        assert_eq!(events[2].level, 3);
+
+        let events = engine.query_event(query("#level: WARN..WARN"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].level, 3);
+
+        let validate = |filter: &str| {
+            BasicEventFilter::validate(FilterPredicate::parse(filter).unwrap().remove(0))
+        };
+
+        assert!(validate("#level: <=WARN").is_ok());
+        assert!(validate("#level: DEBUG..WARN").is_ok());
+        assert!(validate("#level: WARN..DEBUG").is_err());
+        assert!(validate("#level: >=DEBUG..WARN").is_err());
+        assert!(validate("#level: <WARN").is_err());
+    }
+
+    #[test]
+    fn event_found_by_attribute_existence() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let simple = |id: u64, fields: &[(&str, Value)]| -> NewEvent {
+            NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: id.try_into().unwrap(),
+                span_id: None,
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: fields
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.clone()))
+                    .collect(),
+            }
+        };
+
+        engine
+            .insert_event(simple(1, &[("error.code", Value::I64(404))]))
+            .unwrap();
+        engine
+            .insert_event(simple(2, &[("message", Value::Str("A".to_owned()))]))
+            .unwrap();
+        engine
+            .insert_event(simple(3, &[("error.code", Value::Str("E1".to_owned()))]))
+            .unwrap();
+        engine
+            .insert_event(simple(4, &[("error.code", Value::Bool(false))]))
+            .unwrap();
+
+        let events = engine.query_event(query("@error.code: *"));
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].timestamp, Timestamp::new(1).unwrap());
+        assert_eq!(events[1].timestamp, Timestamp::new(3).unwrap());
+        assert_eq!(events[2].timestamp, Timestamp::new(4).unwrap());
+
+        let events = engine.query_event(query("@error.code: !*"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].timestamp, Timestamp::new(2).unwrap());
+
+        let events = engine.query_event(query("@missing: *"));
+        assert_eq!(events.len(), 0);
+    }
+
+    #[test]
+    fn event_found_by_attribute_contains() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let simple = |id: u64, user_agent: Value| -> NewEvent {
+            NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: id.try_into().unwrap(),
+                span_id: None,
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::from_iter([("user_agent".to_owned(), user_agent)]),
+            }
+        };
+
+        engine
+            .insert_event(simple(1, Value::Str("curl/8.4.0".to_owned())))
+            .unwrap();
+        engine
+            .insert_event(simple(2, Value::Str("Mozilla/5.0".to_owned())))
+            .unwrap();
+        engine
+            .insert_event(simple(3, Value::Str("libcurl agent".to_owned())))
+            .unwrap();
+        engine.insert_event(simple(4, Value::I64(123))).unwrap();
+
+        let events = engine.query_event(query("@user_agent: ~=curl"));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].timestamp, Timestamp::new(1).unwrap());
+        assert_eq!(events[1].timestamp, Timestamp::new(3).unwrap());
+
+        let events = engine.query_event(query("@user_agent: ~=\"/5.\""));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].timestamp, Timestamp::new(2).unwrap());
+
+        let events = engine.query_event(query("@user_agent: !~=curl"));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].timestamp, Timestamp::new(2).unwrap());
+        assert_eq!(events[1].timestamp, Timestamp::new(4).unwrap());
+    }
+
+    #[test]
+    fn event_and_span_found_by_list() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let simple = |id: u64, status: u64| -> NewEvent {
+            NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: id.try_into().unwrap(),
+                span_id: None,
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::from_iter([("status".to_owned(), Value::U64(status))]),
+            }
+        };
+
+        let simple_open = |open: u64, region: &str| -> NewSpanEvent {
+            NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(open).unwrap(),
+                span_id: open.try_into().unwrap(),
+                kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: None,
+                    target: "crate::storage::tests".to_owned(),
+                    name: "test".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::from_iter([(
+                        "region".to_owned(),
+                        Value::Str(region.to_owned()),
+                    )]),
+                }),
+            }
+        };
+
+        engine.insert_event(simple(1, 200)).unwrap();
+        engine.insert_event(simple(2, 500)).unwrap();
+        engine.insert_event(simple(3, 503)).unwrap();
+        engine.insert_event(simple(4, 404)).unwrap();
+
+        engine.insert_span_event(simple_open(5, "us-east")).unwrap();
+        engine.insert_span_event(simple_open(6, "eu-west")).unwrap();
+        engine.insert_span_event(simple_open(7, "us-west")).unwrap();
+
+        let events = engine.query_event(query("@status: (500|502|503)"));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].timestamp, Timestamp::new(2).unwrap());
+        assert_eq!(events[1].timestamp, Timestamp::new(3).unwrap());
+
+        let events = engine.query_event(query("@status: [200, 404]"));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].timestamp, Timestamp::new(1).unwrap());
+        assert_eq!(events[1].timestamp, Timestamp::new(4).unwrap());
+
+        let spans = engine.query_span(query("@region: [us-east,us-west]"));
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].created_at, Timestamp::new(5).unwrap());
+        assert_eq!(spans[1].created_at, Timestamp::new(7).unwrap());
+
+        let result =
+            BasicEventFilter::validate(FilterPredicate::parse("@status: []").unwrap()[0].clone());
+        assert!(matches!(result, Err(InputError::InvalidListValue)));
+    }
+
+    #[test]
+    fn event_found_by_case_insensitive_attribute() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let simple = |id: u64, env: &str| -> NewEvent {
+            NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: id.try_into().unwrap(),
+                span_id: None,
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::from_iter([("env".to_owned(), Value::Str(env.to_owned()))]),
+            }
+        };
+
+        engine.insert_event(simple(1, "Production")).unwrap();
+        engine.insert_event(simple(2, "production")).unwrap();
+        engine.insert_event(simple(3, "staging")).unwrap();
+
+        let events = engine.query_event(query("@env: production"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].timestamp, Timestamp::new(2).unwrap());
+
+        let events = engine.query_event(query("@env: PRODUCTION/i"));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].timestamp, Timestamp::new(1).unwrap());
+        assert_eq!(events[1].timestamp, Timestamp::new(2).unwrap());
+
+        let result = BasicEventFilter::validate(
+            FilterPredicate::parse("#target: crate/i").unwrap()[0].clone(),
+        );
+        assert!(matches!(
+            result,
+            Err(InputError::InvalidCaseInsensitiveValue)
+        ));
+    }
+
+    #[test]
+    fn span_connection_filter_rejects_operators() {
+        let predicate = |filter: &str| FilterPredicate::parse(filter).unwrap().remove(0);
+
+        assert!(BasicSpanFilter::validate(predicate("#connection: 123")).is_ok());
+        assert!(matches!(
+            BasicSpanFilter::validate(predicate("#connection: >123")),
+            Err(InputError::InvalidConnectionOperator)
+        ));
+
+        // validating must agree with what building the filter would do
+        assert!(matches!(
+            BasicSpanFilter::from_predicate(
+                predicate("#connection: >123"),
+                &HashMap::new(),
+                &HashMap::new()
+            ),
+            Err(InputError::InvalidConnectionOperator)
+        ));
+    }
+
+    #[test]
+    fn event_found_by_bool_attribute() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let simple = |id: u64, cache_hit: Value| -> NewEvent {
+            NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: id.try_into().unwrap(),
+                span_id: None,
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::from_iter([("cache_hit".to_owned(), cache_hit)]),
+            }
+        };
+
+        engine.insert_event(simple(1, Value::Bool(true))).unwrap();
+        engine.insert_event(simple(2, Value::Bool(false))).unwrap();
+        engine
+            .insert_event(simple(3, Value::Str("true".to_owned())))
+            .unwrap();
+        engine.insert_event(simple(4, Value::F64(1.0))).unwrap();
+
+        let events = engine.query_event(query("@cache_hit: true"));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].timestamp, Timestamp::new(1).unwrap());
+        assert_eq!(events[1].timestamp, Timestamp::new(3).unwrap());
+
+        let events = engine.query_event(query("@cache_hit: false"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].timestamp, Timestamp::new(2).unwrap());
+
+        let events = engine.query_event(query("@cache_hit: 1"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].timestamp, Timestamp::new(4).unwrap());
+    }
+
+    #[test]
+    fn event_and_span_found_by_file_and_line() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let simple = |id: u64, file: Option<(&str, u32)>| -> NewEvent {
+            NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: id.try_into().unwrap(),
+                span_id: None,
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: file.map(|(name, _)| name.to_owned()),
+                file_line: file.map(|(_, line)| line),
+                fields: BTreeMap::new(),
+            }
+        };
+
+        engine
+            .insert_event(simple(1, Some(("src/main.rs", 42))))
+            .unwrap();
+        engine
+            .insert_event(simple(2, Some(("src/lib.rs", 10))))
+            .unwrap();
+        engine
+            .insert_event(simple(3, Some(("build.rs", 50))))
+            .unwrap();
+        engine.insert_event(simple(4, None)).unwrap();
+
+        engine
+            .insert_span_event(NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(5).unwrap(),
+                span_id: 5.try_into().unwrap(),
+                kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: None,
+                    target: "crate::storage::tests".to_owned(),
+                    name: "test".to_owned(),
+                    level: 2,
+                    file_name: Some("src/main.rs".to_owned()),
+                    file_line: Some(12),
+                    fields: BTreeMap::new(),
+                }),
+            })
+            .unwrap();
+
+        let events = engine.query_event(query("#file: src/main.rs"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].timestamp, Timestamp::new(1).unwrap());
+
+        let events = engine.query_event(query("#file: src/*"));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].timestamp, Timestamp::new(1).unwrap());
+        assert_eq!(events[1].timestamp, Timestamp::new(2).unwrap());
+
+        let events = engine.query_event(query("#line: >=42"));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].timestamp, Timestamp::new(1).unwrap());
+        assert_eq!(events[1].timestamp, Timestamp::new(3).unwrap());
+
+        let events = engine.query_event(query("#line: 10"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].timestamp, Timestamp::new(2).unwrap());
+
+        let spans = engine.query_span(query("#file: src/main.rs #line: <20"));
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].created_at, Timestamp::new(5).unwrap());
+
+        let result =
+            BasicEventFilter::validate(FilterPredicate::parse("#line: abc").unwrap()[0].clone());
+        assert!(matches!(result, Err(InputError::InvalidLineValue)));
+    }
+
+    #[test]
+    fn event_found_by_search() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(
+                1,
+                BTreeMap::from_iter([("host".to_owned(), Value::Str("db-01".to_owned()))]),
+            ))
+            .unwrap();
+
+        let simple = |id: u64, name: &str, target: &str, fields: &[(&str, Value)]| NewEvent {
+            connection_key,
+            seq: None,
+            timestamp: id.try_into().unwrap(),
+            span_id: None,
+            name: name.to_owned(),
+            target: target.to_owned(),
+            level: 2,
+            file_name: None,
+            file_line: None,
+            fields: fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+        };
+
+        engine
+            .insert_event(simple(1, "deadlock detected", "crate::a", &[]))
+            .unwrap();
+        engine
+            .insert_event(simple(2, "event", "crate::deadlock", &[]))
+            .unwrap();
+        engine
+            .insert_event(simple(
+                3,
+                "event",
+                "crate::b",
+                &[("message", Value::Str("possible deadlock".to_owned()))],
+            ))
+            .unwrap();
+        engine
+            .insert_event(simple(
+                4,
+                "event",
+                "crate::c",
+                &[("retries", Value::U64(4271))],
+            ))
+            .unwrap();
+
+        let events = engine.query_event(query("#search: deadlock"));
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].timestamp, Timestamp::new(1).unwrap());
+        assert_eq!(events[1].timestamp, Timestamp::new(2).unwrap());
+        assert_eq!(events[2].timestamp, Timestamp::new(3).unwrap());
+
+        let events = engine.query_event(query("#search: 427"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].timestamp, Timestamp::new(4).unwrap());
+
+        // attributes from the connection are searched too
+        let events = engine.query_event(query("#search: db-01"));
+        assert_eq!(events.len(), 4);
+
+        let events = engine.query_event(query("#search: !deadlock"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].timestamp, Timestamp::new(4).unwrap());
+
+        let result =
+            BasicEventFilter::validate(FilterPredicate::parse("#search: >abc").unwrap()[0].clone());
+        assert!(matches!(result, Err(InputError::InvalidSearchOperator)));
+    }
+
+    #[test]
+    fn colliding_timestamps_get_unique_keys() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let first = engine
+            .insert_event(simple_event(connection_key, 5, None))
+            .unwrap();
+        let second = engine
+            .insert_event(simple_event(connection_key, 5, None))
+            .unwrap();
+        let third = engine
+            .insert_event(simple_event(connection_key, 5, None))
+            .unwrap();
+
+        assert_eq!(first, Timestamp::new(5).unwrap());
+        assert_eq!(second, Timestamp::new(6).unwrap());
+        assert_eq!(third, Timestamp::new(7).unwrap());
+
+        let span = engine
+            .insert_span_event(simple_open(connection_key, 6, None))
+            .unwrap();
+
+        assert_eq!(span, Timestamp::new(8).unwrap());
+
+        let events = engine.query_event(Query {
+            filter: vec![],
+            order: Order::Asc,
+            limit: 5,
+            start: Timestamp::new(1).unwrap(),
+            end: Timestamp::new(10).unwrap(),
+            previous: None,
+        });
+        assert_eq!(events.len(), 3);
+    }
+
+    #[test]
+    fn out_of_order_timestamps_get_later_keys() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let first = engine
+            .insert_event(simple_event(connection_key, 5, None))
+            .unwrap();
+        let second = engine
+            .insert_event(simple_event(connection_key, 3, None))
+            .unwrap();
+
+        assert_eq!(first, Timestamp::new(5).unwrap());
+        assert_eq!(second, Timestamp::new(6).unwrap());
+
+        // the client's timestamp is kept only where it differs
+        let event = engine.get_event(first).unwrap();
+        assert_eq!(event.client_timestamp, None);
+        let event = engine.get_event(second).unwrap();
+        assert_eq!(event.client_timestamp, Some(Timestamp::new(3).unwrap()));
+
+        // keys continue after the existing ones when restarted, including
+        // the disconnect that is recorded at 7 for the open connection
+        let mut engine = RawEngine::new(engine.storage);
+        let third = engine
+            .insert_event(simple_event(connection_key, 4, None))
+            .unwrap();
+        assert_eq!(third, Timestamp::new(8).unwrap());
+    }
+
+    #[test]
+    fn span_durations_use_client_timestamps() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let span_event = |timestamp: u64, id: u64, kind: NewSpanEventKind| NewSpanEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(timestamp).unwrap(),
+            span_id: id.try_into().unwrap(),
+            kind,
+        };
+        let create = |timestamp: u64, id: u64| {
+            span_event(
+                timestamp,
+                id,
+                NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: None,
+                    target: "crate::storage::tests".to_owned(),
+                    name: "test".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                }),
+            )
+        };
+
+        // a batch sent late is keyed after what was already received
+        engine.insert_span_event(create(500, 1)).unwrap();
+        let batched_key = engine.insert_span_event(create(100, 2)).unwrap();
+        for (timestamp, kind) in [
+            (120, NewSpanEventKind::Enter),
+            (150, NewSpanEventKind::Exit),
+            (200, NewSpanEventKind::Close),
+        ] {
+            engine
+                .insert_span_event(span_event(timestamp, 2, kind))
+                .unwrap();
+        }
+
+        // a timestamp from a clock far ahead is clamped but still measured
+        let clamped_key = engine.insert_span_event(create(5000000, 3)).unwrap();
+        engine
+            .insert_span_event(span_event(5000300, 3, NewSpanEventKind::Close))
+            .unwrap();
+
+        let span = engine.storage.get_span(batched_key).unwrap();
+        assert_eq!(span.created_at, Timestamp::new(501).unwrap());
+        assert_eq!(span.duration(), Some(100));
+        assert_eq!(span.busy(None), Some(30));
+
+        let span = engine.storage.get_span(clamped_key).unwrap();
+        assert_eq!(span.created_at, Timestamp::new(1001000).unwrap());
+        assert_eq!(span.duration(), Some(300));
+    }
+
+    #[test]
+    fn held_event_is_inserted_once_its_span_is_created() {
+        let mut engine = RawEngine::with_options(
+            TransientStorage::new(),
+            EngineOptions {
+                reorder_window: Duration::from_secs(60),
+                ..Default::default()
+            },
+        );
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let (sender, mut receiver) = oneshot::channel();
+        engine.insert_events_or_hold(vec![(
+            NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(3).unwrap(),
+                span_id: Some(1.try_into().unwrap()),
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            },
+            Some(sender),
+        )]);
+
+        assert!(receiver.try_recv().is_err());
+
+        engine.insert_span_event_or_hold(
+            NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(2).unwrap(),
+                span_id: 1.try_into().unwrap(),
+                kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: None,
+                    target: "crate::storage::tests".to_owned(),
+                    name: "test".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                }),
+            },
+            None,
+        );
+
+        let event_key = receiver.try_recv().unwrap().unwrap();
+        let event = engine.get_event(event_key).unwrap();
+        assert_eq!(event.ancestors.len(), 1);
+        assert_eq!(event.span_id.as_deref(), Some("1-1"));
+    }
+
+    #[test]
+    fn held_event_fails_after_the_window() {
+        let mut engine = RawEngine::with_options(
+            TransientStorage::new(),
+            EngineOptions {
+                reorder_window: Duration::from_millis(1),
+                ..Default::default()
+            },
+        );
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let (sender, mut receiver) = oneshot::channel();
+        engine.insert_events_or_hold(vec![(
+            NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(3).unwrap(),
+                span_id: Some(1.try_into().unwrap()),
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            },
+            Some(sender),
+        )]);
+
+        engine.expire_pending();
+        std::thread::sleep(Duration::from_millis(5));
+        engine.expire_pending();
+
+        assert!(matches!(
+            receiver.try_recv().unwrap(),
+            Err(EngineInsertError::UnknownParentSpanId)
+        ));
+    }
+
+    #[test]
+    fn oldest_held_event_is_inserted_when_too_many_are_held() {
+        let mut engine = RawEngine::with_options(
+            TransientStorage::new(),
+            EngineOptions {
+                reorder_window: Duration::from_secs(60),
+                ..Default::default()
+            },
+        );
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let mut receivers = vec![];
+        for i in 0..=pending::MAX_PENDING_INSERTS as u64 {
+            let (sender, receiver) = oneshot::channel();
+            engine.insert_events_or_hold(vec![(
+                NewEvent {
+                    connection_key,
+                    seq: None,
+                    timestamp: Timestamp::new(i + 3).unwrap(),
+                    span_id: Some((i + 1).try_into().unwrap()),
+                    name: "event".to_owned(),
+                    target: "crate::storage::tests".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                },
+                Some(sender),
+            )]);
+            receivers.push(receiver);
+        }
+
+        assert!(matches!(
+            receivers[0].try_recv().unwrap(),
+            Err(EngineInsertError::UnknownParentSpanId)
+        ));
+        assert!(receivers[1].try_recv().is_err());
+        assert!(receivers.last_mut().unwrap().try_recv().is_err());
+    }
+
+    #[test]
+    fn retransmitted_events_are_skipped_after_reconnecting() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connect = |engine: &mut RawEngine<TransientStorage>| {
+            engine
+                .insert_connection(NewConnection::new(1, BTreeMap::new()))
+                .unwrap()
+        };
+
+        let simple = |connection_key: ConnectionKey, seq: u64| -> (NewEvent, EventReply) {
+            let event = NewEvent {
+                connection_key,
+                seq: Some(seq),
+                timestamp: Timestamp::new(seq).unwrap(),
+                span_id: None,
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            };
+            (event, None)
+        };
+
+        let connection_key = connect(&mut engine);
+        engine.insert_events_or_hold(vec![simple(connection_key, 1), simple(connection_key, 2)]);
+        engine.disconnect_connection(1).unwrap();
+
+        let connection_key = connect(&mut engine);
+        let (event, _) = simple(connection_key, 2);
+        let (sender, mut receiver) = oneshot::channel();
+        engine.insert_events_or_hold(vec![(event, Some(sender)), simple(connection_key, 3)]);
+
+        assert!(matches!(
+            receiver.try_recv().unwrap(),
+            Err(EngineInsertError::AlreadyApplied)
+        ));
+
+        let events = engine.query_event(Query {
+            filter: vec![],
+            order: Order::Asc,
+            limit: 5,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        });
+        assert_eq!(events.len(), 3);
+    }
+
+    #[test]
+    fn rejected_events_can_be_retransmitted() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let event = || NewEvent {
+            seq: Some(1),
+            ..simple_event(connection_key, 3, Some(1))
+        };
+
+        // the span doesn't exist yet, so this is rejected
+        let (sender, mut receiver) = oneshot::channel();
+        engine.insert_events_or_hold(vec![(event(), Some(sender))]);
+        assert!(matches!(
+            receiver.try_recv().unwrap(),
+            Err(EngineInsertError::UnknownParentSpanId)
+        ));
+
+        engine
+            .insert_span_event(simple_open(connection_key, 1, None))
+            .unwrap();
+
+        let (sender, mut receiver) = oneshot::channel();
+        engine.insert_events_or_hold(vec![(event(), Some(sender))]);
+        assert!(receiver.try_recv().unwrap().is_ok());
+
+        let (sender, mut receiver) = oneshot::channel();
+        engine.insert_events_or_hold(vec![(event(), Some(sender))]);
+        assert!(matches!(
+            receiver.try_recv().unwrap(),
+            Err(EngineInsertError::AlreadyApplied)
+        ));
+    }
+
+    #[test]
+    fn delete_before_keeps_open_spans() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        engine
+            .insert_span_event(simple_open(connection_key, 1, None))
+            .unwrap(); // closed before cutoff
+        engine
+            .insert_event(simple_event(connection_key, 2, Some(1)))
+            .unwrap();
+        engine
+            .insert_span_event(simple_close(connection_key, 1, 3))
+            .unwrap();
+        engine
+            .insert_span_event(simple_open(connection_key, 4, None))
+            .unwrap(); // still open
+        engine
+            .insert_event(simple_event(connection_key, 5, Some(4)))
+            .unwrap();
+        engine
+            .insert_event(simple_event(connection_key, 6, None))
+            .unwrap(); // before cutoff
+        engine
+            .insert_event(simple_event(connection_key, 20, None))
+            .unwrap();
+
+        let metrics = engine.delete(DeleteFilter::before(Timestamp::new(10).unwrap()));
+        assert_eq!(metrics.spans, 1);
+        assert_eq!(metrics.events, 2);
+
+        let spans = engine.query_span(query(""));
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].created_at, Timestamp::new(4).unwrap());
+
+        let events = engine.query_event(query(""));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].timestamp, Timestamp::new(5).unwrap());
+        assert_eq!(events[1].timestamp, Timestamp::new(20).unwrap());
+
+        assert!(engine
+            .storage
+            .get_span(Timestamp::new(1).unwrap())
+            .is_none());
+        assert!(engine
+            .storage
+            .get_event(Timestamp::new(2).unwrap())
+            .is_none());
+    }
+
+    #[test]
+    fn connection_found_by_disconnected_none() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        for (id, region) in [(1, "east"), (2, "west"), (3, "east")] {
+            engine
+                .insert_connection(NewConnection::new(
+                    id,
+                    BTreeMap::from_iter([("region".to_owned(), Value::Str(region.to_owned()))]),
+                ))
+                .unwrap();
+        }
+        engine.disconnect_connection(1).unwrap();
+
+        let query = |filter: &str, order: Order| Query {
+            filter: FilterPredicate::parse(filter).unwrap(),
+            order,
+            limit: 5,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        };
+
+        let ids = |connections: Vec<ConnectionView>| {
+            connections
+                .into_iter()
+                .map(|connection| connection.id)
+                .collect::<Vec<_>>()
+        };
+
+        let connections = engine.query_connection(query("#disconnected: none", Order::Asc));
+        assert_eq!(ids(connections), ["2", "3"]);
+
+        let connections =
+            engine.query_connection(query("#disconnected: none @region: east", Order::Desc));
+        assert_eq!(ids(connections), ["3"]);
+
+        let connections = engine.query_connection(query("@region: east", Order::Desc));
+        assert_eq!(ids(connections), ["3", "1"]);
+
+        let connections = engine.query_connection(query("#disconnected: !none", Order::Asc));
+        assert_eq!(ids(connections), ["1"]);
+
+        assert!(BasicConnectionFilter::validate(
+            FilterPredicate::parse("#disconnected: >none")
+                .unwrap()
+                .remove(0)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn oneshot_events_get_their_own_connection() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let oneshot = |tool: &str, level: i32| NewOneShotEvent {
+            connection_fields: BTreeMap::from_iter([(
+                "tool".to_owned(),
+                Value::Str(tool.to_owned()),
+            )]),
+            timestamp: Timestamp::new(1).unwrap(),
+            name: "deployed".to_owned(),
+            target: "deploy".to_owned(),
+            level,
+            file_name: None,
+            file_line: None,
+            fields: BTreeMap::new(),
+        };
+
+        engine
+            .insert_oneshot_event(oneshot("deploy.sh", 2))
+            .unwrap();
+        engine
+            .insert_oneshot_event(oneshot("backup.sh", 2))
+            .unwrap();
+        assert!(matches!(
+            engine.insert_oneshot_event(oneshot("backup.sh", 7)),
+            Err(EngineInsertError::UnknownLevel)
+        ));
+
+        let events = engine.query_event(query("@tool: deploy.sh"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].name, "deployed");
+
+        // the rejected event didn't leave an empty connection behind
+        let connections = engine.query_connection(query("#disconnected: !none"));
+        assert_eq!(connections.len(), 2);
+        assert!(engine
+            .query_connection(query("#disconnected: none"))
+            .is_empty());
+        assert!(connections
+            .iter()
+            .all(|c| c.id.parse::<ConnectionId>().unwrap() >= ONESHOT_CONNECTION_IDS));
+
+        // events with the same fields share their connection, which is kept
+        // disconnected after the latest of them
+        let disconnected_at = connections[0].disconnected_at.unwrap();
+        engine
+            .insert_oneshot_event(oneshot("deploy.sh", 3))
+            .unwrap();
+        assert_eq!(engine.query_event(query("@tool: deploy.sh")).len(), 2);
+        let connections = engine.query_connection(query("@tool: deploy.sh"));
+        assert_eq!(connections.len(), 1);
+        assert!(connections[0].disconnected_at.unwrap() > disconnected_at);
+    }
+
+    #[test]
+    fn connection_count_ignores_limit() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let mut connection_keys = vec![];
+        for id in 1..=7 {
+            let connection_key = engine
+                .insert_connection(NewConnection::new(id, BTreeMap::new()))
+                .unwrap();
+            connection_keys.push(connection_key);
+        }
+
+        let query = |previous: Option<Timestamp>| Query {
+            filter: vec![],
+            order: Order::Asc,
+            limit: 5,
+            start: Timestamp::new(1).unwrap(),
+            end: Timestamp::new(2000).unwrap(),
+            previous,
+        };
+
+        assert_eq!(engine.query_connection(query(None)).len(), 5);
+        assert_eq!(engine.query_connection_count(query(None)), 7);
+        assert_eq!(
+            engine.query_connection_count(query(Some(connection_keys[1]))),
+            5
+        );
+    }
+
+    #[test]
+    fn query_limit_is_capped_by_options() {
+        let options = EngineOptions {
+            max_query_limit: 3,
+            ..EngineOptions::default()
+        };
+        let mut engine = RawEngine::with_options(TransientStorage::new(), options);
+
+        for id in 1..=7 {
+            engine
+                .insert_connection(NewConnection::new(id, BTreeMap::new()))
+                .unwrap();
+        }
+
+        let query = |limit: usize| Query {
+            filter: vec![],
+            order: Order::Asc,
+            limit,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        };
+
+        assert_eq!(engine.query_connection(query(2)).len(), 2);
+        assert_eq!(engine.query_connection(query(5)).len(), 3);
+        assert_eq!(engine.query_connection_count(query(5)), 7);
+    }
+
+    #[test]
+    fn span_subscription_receives_creates_and_closes() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let (_id, mut receiver) =
+            engine.subscribe_to_spans(FilterPredicate::parse("#name: request").unwrap());
+
+        engine
+            .insert_span_event(named_open(connection_key, 1, None, "request"))
+            .unwrap();
+        engine
+            .insert_span_event(named_open(connection_key, 2, None, "other"))
+            .unwrap();
+        engine
+            .insert_span_event(simple_close(connection_key, 2, 3))
+            .unwrap();
+        engine
+            .insert_span_event(simple_close(connection_key, 1, 4))
+            .unwrap();
+
+        let span = receiver.try_recv().unwrap();
+        assert_eq!(span.created_at, Timestamp::new(1).unwrap());
+        assert_eq!(span.closed_at, None);
+
+        let span = receiver.try_recv().unwrap();
+        assert_eq!(span.created_at, Timestamp::new(1).unwrap());
+        assert_eq!(span.closed_at, Some(Timestamp::new(4).unwrap()));
+
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn connection_subscription_receives_connects_and_disconnects() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let (id, mut receiver) = engine.subscribe_to_connections(vec![]);
+
+        engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+        engine.disconnect_connection(1).unwrap();
+
+        let connection = receiver.try_recv().unwrap();
+        assert_eq!(connection.id, "1");
+        assert_eq!(connection.disconnected_at, None);
+
+        let connection = receiver.try_recv().unwrap();
+        assert_eq!(connection.id, "1");
+        assert!(connection.disconnected_at.is_some());
+
+        // unsubscribing drops the sender which closes the channel
+        engine.unsubscribe_from_connections(id);
+        assert!(matches!(
+            receiver.try_recv(),
+            Err(tokio::sync::mpsc::error::TryRecvError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn connection_id_can_be_reused_after_disconnect() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let new_connection = || NewConnection::new(1, BTreeMap::new());
+
+        let first_key = engine.insert_connection(new_connection()).unwrap();
+
+        let result = engine.insert_connection(new_connection());
+        assert!(matches!(
+            result,
+            Err(EngineInsertError::DuplicateConnectionId)
+        ));
+
+        engine.disconnect_connection(1).unwrap();
+
+        let second_key = engine.insert_connection(new_connection()).unwrap();
+        assert_ne!(first_key, second_key);
+
+        let connections = engine.query_connection(Query {
+            filter: vec![],
+            order: Order::Asc,
+            limit: 5,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        });
+
+        assert_eq!(connections.len(), 2);
+        assert!(connections[0].disconnected_at.is_some());
+        assert_eq!(connections[1].disconnected_at, None);
+        assert!(connections[0].duration.is_some());
+        assert_eq!(connections[1].duration, None);
+
+        // the id now refers to the newest connection
+        engine.disconnect_connection(1).unwrap();
+        assert!(matches!(
+            engine.disconnect_connection(1),
+            Err(EngineInsertError::AlreadyDisconnected)
+        ));
+    }
+
+    #[test]
+    fn span_duration_stats() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        // spans with durations 1 through 10
+        for i in 1..=10 {
+            let open = i * 50;
+            engine
+                .insert_span_event(named_open(connection_key, open, None, "handle_request"))
+                .unwrap();
+            engine
+                .insert_span_event(simple_close(connection_key, open, open + i))
+                .unwrap();
+        }
+        engine
+            .insert_span_event(named_open(connection_key, 600, None, "handle_request"))
+            .unwrap(); // still open
+        engine
+            .insert_span_event(named_open(connection_key, 601, None, "other"))
+            .unwrap();
+        engine
+            .insert_span_event(simple_close(connection_key, 601, 900))
+            .unwrap(); // excluded by name
+
+        let stats = engine.query_span_duration_stats(query("#name: handle_request"));
+        assert_eq!(stats.count, 10);
+        assert_eq!(stats.still_open, 1);
+        assert_eq!(stats.min, Some(1));
+        assert_eq!(stats.max, Some(10));
+        assert_eq!(stats.mean, Some(5));
+        assert_eq!(stats.p50, Some(5));
+        assert_eq!(stats.p90, Some(9));
+        assert_eq!(stats.p99, Some(10));
+
+        let stats = engine.query_span_duration_stats(query("#name: missing"));
+        assert_eq!(stats.count, 0);
+        assert_eq!(stats.min, None);
+        assert_eq!(stats.p50, None);
+    }
+
+    #[test]
+    fn export_events_writes_matches_as_lines() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        for level in 0..5 {
+            engine
+                .insert_event(NewEvent {
+                    connection_key,
+                    seq: None,
+                    timestamp: (level as u64 + 1).try_into().unwrap(),
+                    span_id: None,
+                    name: "event".to_owned(),
+                    target: "crate::storage::tests".to_owned(),
+                    level,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                })
+                .unwrap();
+        }
+
+        let mut output = Vec::new();
+        let count = engine
+            .export_events(
+                Query {
+                    filter: FilterPredicate::parse("#level: >=INFO").unwrap(),
+                    order: Order::Asc,
+                    limit: usize::MAX,
+                    start: Timestamp::MIN,
+                    end: Timestamp::MAX,
+                    previous: None,
+                },
+                &mut output,
+            )
+            .unwrap();
+
+        assert_eq!(count, 3);
+
+        let output = String::from_utf8(output).unwrap();
+        let levels = output
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["level"].clone())
+            .collect::<Vec<_>>();
+
+        assert_eq!(levels, [2, 3, 4]);
+    }
+
+    #[test]
+    fn import_ndjson_round_trips_an_export() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(
+                1,
+                BTreeMap::from_iter([("service".to_owned(), Value::Str("a".to_owned()))]),
+            ))
+            .unwrap();
+
+        engine
+            .insert_span_event(NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(5).unwrap(),
+                span_id: 1.try_into().unwrap(),
+                kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: None,
+                    target: "crate::storage::tests".to_owned(),
+                    name: "request".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::from_iter([("status".to_owned(), Value::U64(200))]),
+                }),
+            })
+            .unwrap();
+
+        for timestamp in [6, 7] {
+            engine
+                .insert_event(NewEvent {
+                    connection_key,
+                    seq: None,
+                    timestamp: Timestamp::new(timestamp).unwrap(),
+                    span_id: Some(1.try_into().unwrap()),
+                    name: "event".to_owned(),
+                    target: "crate::storage::tests".to_owned(),
+                    level: 3,
+                    file_name: Some("src/main.rs".to_owned()),
+                    file_line: Some(42),
+                    fields: BTreeMap::from_iter([("ok".to_owned(), Value::Bool(true))]),
+                })
+                .unwrap();
+        }
+
+        let query = || Query {
+            filter: vec![],
+            order: Order::Asc,
+            limit: usize::MAX,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        };
+
+        let mut output = Vec::new();
+        engine.export_events(query(), &mut output).unwrap();
+        output.extend_from_slice(b"{not json\n");
+
+        // importing into the same engine must not collide with its data
+        let metrics = engine.import_ndjson(&mut output.as_slice());
+        assert_eq!(metrics.connections, 1);
+        assert_eq!(metrics.spans, 1);
+        assert_eq!(metrics.events, 2);
+        assert_eq!(metrics.skipped_lines, 1);
+
+        let events = engine.query_event(query());
+        assert_eq!(events.len(), 4);
+        // the imported keys are moved past the ones already in use
+        let keys = events
+            .iter()
+            .map(|e| (e.connection_id.as_str(), e.timestamp.get()))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, [("1", 6), ("1", 7), ("2", 10), ("2", 11)]);
+
+        let imported = &events[3];
+        assert_eq!(imported.file.as_deref(), Some("src/main.rs:42"));
+        assert_eq!(imported.ancestors[0].id, "2-1");
+        assert_eq!(imported.ancestors[0].name, "request");
+        let attributes = imported
+            .attributes
+            .iter()
+            .map(|a| (a.name.as_str(), a.value.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            attributes,
+            [("ok", "true"), ("service", "a"), ("status", "200")]
+        );
+
+        let spans = engine.query_span(Query {
+            filter: FilterPredicate::parse("#connection: 2").unwrap(),
+            ..query()
+        });
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].created_at, Timestamp::new(9).unwrap());
+        assert_eq!(spans[0].closed_at, Some(Timestamp::new(11).unwrap()));
+    }
+
+    #[test]
+    fn import_ndjson_wraps_around_connection_ids() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(ConnectionId::MAX, BTreeMap::new()))
+            .unwrap();
+
+        engine
+            .insert_event(NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(5).unwrap(),
+                span_id: None,
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            })
+            .unwrap();
+
+        let query = || Query {
+            filter: vec![],
+            order: Order::Asc,
+            limit: usize::MAX,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        };
+
+        let mut output = Vec::new();
+        engine.export_events(query(), &mut output).unwrap();
+
+        let metrics = engine.import_ndjson(&mut output.as_slice());
+        assert_eq!(metrics.connections, 1);
+        assert_eq!(metrics.events, 1);
+
+        let ids = engine
+            .query_event(query())
+            .iter()
+            .map(|e| e.connection_id.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [ConnectionId::MAX.to_string(), "1".to_owned()]);
+    }
+
+    #[test]
+    fn export_chrome_trace_nests_spans_by_root() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(7, BTreeMap::new()))
+            .unwrap();
+
+        let create = |timestamp: u64, id: u64, parent_id: Option<u64>| NewSpanEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(timestamp).unwrap(),
+            span_id: id.try_into().unwrap(),
+            kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                parent_id: parent_id.map(|id| id.try_into().unwrap()),
+                target: "crate::storage::tests".to_owned(),
+                name: format!("span{id}"),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::from_iter([("id".to_owned(), Value::U64(id))]),
+            }),
+        };
+
+        engine.insert_span_event(create(10, 1, None)).unwrap();
+        engine.insert_span_event(create(20, 2, Some(1))).unwrap();
+        engine
+            .insert_span_event(NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(30).unwrap(),
+                span_id: 2.try_into().unwrap(),
+                kind: NewSpanEventKind::Close,
+            })
+            .unwrap();
+
+        let mut output = Vec::new();
+        let count = engine
+            .export_chrome_trace(
+                Query {
+                    filter: vec![],
+                    order: Order::Asc,
+                    limit: usize::MAX,
+                    start: Timestamp::MIN,
+                    end: Timestamp::new(100).unwrap(),
+                    previous: None,
+                },
+                &mut output,
+            )
+            .unwrap();
+
+        assert_eq!(count, 2);
+
+        let trace: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(
+            trace,
+            serde_json::json!([
+                {
+                    "ph": "X",
+                    "name": "span1",
+                    "cat": "crate::storage::tests",
+                    "ts": 10,
+                    "dur": 90,
+                    "pid": 7,
+                    "tid": 1,
+                    "args": { "id": "1" },
+                },
+                {
+                    "ph": "X",
+                    "name": "span2",
+                    "cat": "crate::storage::tests",
+                    "ts": 20,
+                    "dur": 10,
+                    "pid": 7,
+                    "tid": 1,
+                    "args": { "id": "2" },
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn export_jaeger_groups_spans_into_traces() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(
+                7,
+                BTreeMap::from_iter([("service.name".to_owned(), Value::Str("api".to_owned()))]),
+            ))
+            .unwrap();
+
+        let create = |timestamp: u64, id: u64, parent_id: Option<u64>| NewSpanEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(timestamp).unwrap(),
+            span_id: id.try_into().unwrap(),
+            kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                parent_id: parent_id.map(|id| id.try_into().unwrap()),
+                target: "crate::storage::tests".to_owned(),
+                name: format!("span{id}"),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::from_iter([("id".to_owned(), Value::U64(id))]),
+            }),
+        };
+
+        engine.insert_span_event(create(10, 1, None)).unwrap();
+        engine.insert_span_event(create(20, 2, Some(1))).unwrap();
+        engine
+            .insert_event(NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(25).unwrap(),
+                span_id: Some(2.try_into().unwrap()),
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 3,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            })
+            .unwrap();
+        engine
+            .insert_span_event(NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(30).unwrap(),
+                span_id: 2.try_into().unwrap(),
+                kind: NewSpanEventKind::Close,
+            })
+            .unwrap();
+        engine.insert_span_event(create(40, 3, None)).unwrap();
+
+        let mut output = Vec::new();
+        let count = engine
+            .export_jaeger(
+                Query {
+                    filter: vec![],
+                    order: Order::Asc,
+                    limit: usize::MAX,
+                    start: Timestamp::MIN,
+                    end: Timestamp::new(100).unwrap(),
+                    previous: None,
+                },
+                &mut output,
+            )
+            .unwrap();
+
+        assert_eq!(count, 3);
+
+        let export: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        let traces = export["data"].as_array().unwrap();
+        assert_eq!(traces.len(), 2);
+
+        let trace = &traces[0];
+        assert_eq!(trace["traceID"], "0000000000000000000000000000000a");
+        assert_eq!(trace["processes"]["p1"]["serviceName"], "api");
+        assert_eq!(
+            trace["spans"][1],
+            serde_json::json!({
+                "traceID": "0000000000000000000000000000000a",
+                "spanID": "0000000000000014",
+                "operationName": "span2",
+                "references": [{
+                    "refType": "CHILD_OF",
+                    "traceID": "0000000000000000000000000000000a",
+                    "spanID": "000000000000000a",
+                }],
+                "startTime": 20,
+                "duration": 10,
+                "tags": [
+                    { "key": "level", "type": "string", "value": "INFO" },
+                    { "key": "target", "type": "string", "value": "crate::storage::tests" },
+                    { "key": "id", "type": "int64", "value": 2 },
+                ],
+                "logs": [{
+                    "timestamp": 25,
+                    "fields": [
+                        { "key": "event", "type": "string", "value": "event" },
+                        { "key": "level", "type": "string", "value": "WARN" },
+                    ],
+                }],
+                "processID": "p1",
+            })
+        );
+        assert_eq!(trace["spans"][0]["duration"], 90);
+
+        assert_eq!(traces[1]["traceID"], "00000000000000000000000000000028");
+    }
+
+    #[test]
+    fn export_jaeger_keeps_reused_span_ids_apart() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection = || NewConnection::new(1, BTreeMap::new());
+        let create = |connection_key: ConnectionKey, timestamp: u64| NewSpanEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(timestamp).unwrap(),
+            span_id: 1.try_into().unwrap(),
+            kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                parent_id: None,
+                target: "crate::storage::tests".to_owned(),
+                name: "span".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            }),
+        };
+
+        // a client that reconnects with the same id starts its span ids over,
+        // so the span id is reused once the first span is closed
+        let connection_key = engine.insert_connection(connection()).unwrap();
+        engine
+            .insert_span_event(create(connection_key, 10))
+            .unwrap();
+        engine.disconnect_connection(1).unwrap();
+
+        let connection_key = engine.insert_connection(connection()).unwrap();
+        engine
+            .insert_span_event(create(connection_key, 2000))
+            .unwrap();
+
+        let mut output = Vec::new();
+        engine
+            .export_jaeger(
+                Query {
+                    filter: vec![],
+                    order: Order::Asc,
+                    limit: usize::MAX,
+                    start: Timestamp::MIN,
+                    end: Timestamp::new(3000).unwrap(),
+                    previous: None,
+                },
+                &mut output,
+            )
+            .unwrap();
+
+        let export: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        let traces = export["data"].as_array().unwrap();
+        assert_eq!(traces.len(), 2);
+        assert_ne!(traces[0]["traceID"], traces[1]["traceID"]);
+        assert_ne!(
+            traces[0]["spans"][0]["spanID"],
+            traces[1]["spans"][0]["spanID"]
+        );
+    }
+
+    #[test]
+    fn export_events_csv_resolves_columns() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(
+                1,
+                BTreeMap::from_iter([("host".to_owned(), Value::Str("a,b".to_owned()))]),
+            ))
+            .unwrap();
+
+        for (timestamp, status) in [(1, Some(200)), (2, None)] {
+            engine
+                .insert_event(NewEvent {
+                    connection_key,
+                    seq: None,
+                    timestamp: Timestamp::new(timestamp).unwrap(),
+                    span_id: None,
+                    name: "request".to_owned(),
+                    target: "crate::storage::tests".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: status
+                        .map(|status| ("status".to_owned(), Value::U64(status)))
+                        .into_iter()
+                        .collect(),
+                })
+                .unwrap();
+        }
+
+        let query = || Query {
+            filter: vec![],
+            order: Order::Asc,
+            limit: usize::MAX,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        };
+
+        let columns = ["timestamp", "level", "name", "@status", "@host"].map(str::to_owned);
+        let mut output = Vec::new();
+        let count = engine
+            .export_events_csv(query(), &columns, &mut output)
+            .unwrap();
+
+        assert_eq!(count, 2);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "timestamp,level,name,@status,@host\n\
+             1,INFO,request,200,\"a,b\"\n\
+             2,INFO,request,,\"a,b\"\n"
+        );
+
+        let columns = ["status".to_owned()];
+        let result = engine.export_events_csv(query(), &columns, &mut Vec::new());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn event_and_span_found_by_key() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let span_key = engine
+            .insert_span_event(NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(5).unwrap(),
+                span_id: 1.try_into().unwrap(),
+                kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: None,
+                    target: "crate::storage::tests".to_owned(),
+                    name: "request".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::from_iter([("status".to_owned(), Value::U64(200))]),
+                }),
+            })
+            .unwrap();
+
+        let event_key = engine
+            .insert_event(NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(6).unwrap(),
+                span_id: Some(1.try_into().unwrap()),
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            })
+            .unwrap();
+
+        let event = engine.get_event(event_key).unwrap();
+        assert_eq!(event.timestamp, event_key);
+        assert_eq!(event.ancestors.len(), 1);
+        assert_eq!(event.ancestors[0].id, "1-1");
+        assert_eq!(event.attributes.len(), 1);
+        assert_eq!(event.attributes[0].name, "status");
+
+        let span = engine.get_span(span_key).unwrap();
+        assert_eq!(span.id, "1-1");
+        assert_eq!(span.name, "request");
+
+        assert!(engine.get_event(span_key).is_none());
+        assert!(engine.get_span(event_key).is_none());
+    }
+
+    #[test]
+    fn span_children_are_direct_and_ordered() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let create = |timestamp: u64, id: u64, parent_id: Option<u64>| NewSpanEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(timestamp).unwrap(),
+            span_id: id.try_into().unwrap(),
+            kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                parent_id: parent_id.map(|id| id.try_into().unwrap()),
+                target: "crate::storage::tests".to_owned(),
+                name: format!("span{id}"),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            }),
+        };
+
+        engine.insert_span_event(create(1, 1, None)).unwrap();
+        engine.insert_span_event(create(2, 2, Some(1))).unwrap();
+        engine.insert_span_event(create(3, 3, Some(2))).unwrap();
+        engine.insert_span_event(create(4, 4, Some(1))).unwrap();
+
+        let root_key = engine.get_span_key(1, 1.try_into().unwrap()).unwrap();
+        let children = engine.query_span_children(root_key);
+        let ids = children.iter().map(|s| s.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["1-2", "1-4"]);
+
+        let leaf_key = engine.get_span_key(1, 3.try_into().unwrap()).unwrap();
+        assert!(engine.query_span_children(leaf_key).is_empty());
+        assert!(engine.get_span_key(2, 1.try_into().unwrap()).is_none());
+    }
+
+    #[test]
+    fn span_parent_filter_uses_children_index() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection = |id: u64| NewConnection::new(id, BTreeMap::new());
+        let connection_key_1 = engine.insert_connection(connection(1)).unwrap();
+        let connection_key_2 = engine.insert_connection(connection(2)).unwrap();
+
+        let create =
+            |connection_key, timestamp: u64, id: u64, parent_id: Option<u64>| NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(timestamp).unwrap(),
+                span_id: id.try_into().unwrap(),
+                kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: parent_id.map(|id| id.try_into().unwrap()),
+                    target: "crate::storage::tests".to_owned(),
+                    name: format!("span{id}"),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                }),
+            };
+
+        engine
+            .insert_span_event(create(connection_key_1, 2, 1, None))
+            .unwrap();
+        engine
+            .insert_span_event(create(connection_key_1, 3, 2, Some(1)))
+            .unwrap();
+        engine
+            .insert_span_event(create(connection_key_1, 4, 3, Some(2)))
+            .unwrap();
+        engine
+            .insert_span_event(create(connection_key_1, 5, 4, Some(1)))
+            .unwrap();
+        engine
+            .insert_span_event(create(connection_key_2, 6, 1, None))
+            .unwrap();
+        engine
+            .insert_span_event(create(connection_key_2, 7, 2, Some(1)))
+            .unwrap();
+
+        let ids = |engine: &RawEngine<TransientStorage>, filter: &str, order: Order| {
+            engine
+                .query_span(Query {
+                    filter: FilterPredicate::parse(filter).unwrap(),
+                    order,
+                    limit: 5,
+                    start: Timestamp::MIN,
+                    end: Timestamp::MAX,
+                    previous: None,
+                })
+                .into_iter()
+                .map(|span| span.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(&engine, "#parent: 1-1", Order::Asc), ["1-2", "1-4"]);
+        assert_eq!(ids(&engine, "#parent: 1-1", Order::Desc), ["1-4", "1-2"]);
+        assert_eq!(ids(&engine, "#parent: 1-2", Order::Asc), ["1-3"]);
+        assert!(ids(&engine, "#parent: 1-3", Order::Asc).is_empty());
+
+        let parent_key = engine.get_span_key(1, 1.try_into().unwrap()).unwrap();
+        let filter = IndexedSpanFilter::build(
+            Some(BasicSpanFilter::Parent(parent_key)),
+            &engine.span_indexes,
+        );
+        assert!(matches!(filter, IndexedSpanFilter::Single(index, None) if index.len() == 2));
+
+        // deleting a connection leaves no children behind for its spans
+        engine.disconnect_connection(2).unwrap();
+        engine.delete_connection(2).unwrap();
+        let mut parents = engine
+            .span_indexes
+            .children
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        parents.sort();
+        assert_eq!(
+            parents,
+            [
+                parent_key,
+                engine.get_span_key(1, 2.try_into().unwrap()).unwrap()
+            ]
+        );
+        assert_eq!(ids(&engine, "#parent: 1-1", Order::Asc), ["1-2", "1-4"]);
+    }
+
+    #[test]
+    fn span_follows_are_rendered() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let create = |timestamp: u64, id: u64| NewSpanEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(timestamp).unwrap(),
+            span_id: id.try_into().unwrap(),
+            kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                parent_id: None,
+                target: "crate::storage::tests".to_owned(),
+                name: format!("span{id}"),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            }),
+        };
+
+        let first_key = engine.insert_span_event(create(1, 1)).unwrap();
+        let second_key = engine.insert_span_event(create(2, 2)).unwrap();
+        engine
+            .insert_span_event(NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(3).unwrap(),
+                span_id: 2.try_into().unwrap(),
+                kind: NewSpanEventKind::Follows(NewFollowsSpanEvent {
+                    follows: 1.try_into().unwrap(),
+                }),
+            })
+            .unwrap();
+
+        let second = engine.get_span(second_key).unwrap();
+        assert_eq!(second.follows, ["1-1"]);
+
+        let first = engine.get_span(first_key).unwrap();
+        assert!(first.follows.is_empty());
+    }
+
+    #[test]
+    fn span_busy_sums_entered_time() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let span_event = |timestamp: u64, id: u64, kind: NewSpanEventKind| NewSpanEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(timestamp).unwrap(),
+            span_id: id.try_into().unwrap(),
+            kind,
+        };
+        let create = |timestamp: u64, id: u64| {
+            span_event(
+                timestamp,
+                id,
+                NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: None,
+                    target: "crate::storage::tests".to_owned(),
+                    name: format!("span{id}"),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                }),
+            )
+        };
+
+        let entered_key = engine.insert_span_event(create(10, 1)).unwrap();
+        let idle_key = engine.insert_span_event(create(15, 2)).unwrap();
+        let open_key = engine.insert_span_event(create(30, 3)).unwrap();
+        for (timestamp, id, kind) in [
+            (20, 1, NewSpanEventKind::Enter),
+            (40, 3, NewSpanEventKind::Enter),
+            (50, 1, NewSpanEventKind::Exit),
+            (60, 1, NewSpanEventKind::Enter),
+            (70, 1, NewSpanEventKind::Exit),
+        ] {
+            engine
+                .insert_span_event(span_event(timestamp, id, kind))
+                .unwrap();
+        }
+
+        assert_eq!(engine.get_span(entered_key).unwrap().busy, Some(40));
+        assert_eq!(engine.get_span(idle_key).unwrap().busy, Some(0));
+        assert_eq!(engine.get_span(open_key).unwrap().busy, None);
+
+        // open spans are busy up to the end of the query
+        let query_before_100 = |filter: &str| Query {
+            end: Timestamp::new(100).unwrap(),
+            ..query(filter)
+        };
+
+        let spans = engine.query_span(query_before_100("#level: >=TRACE"));
+        let busy = spans.iter().map(|span| span.busy).collect::<Vec<_>>();
+        assert_eq!(busy, [Some(40), Some(0), Some(60)]);
+
+        let spans = engine.query_span(query_before_100("#busy: >35us"));
+        let ids = spans
+            .iter()
+            .map(|span| span.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["1-1", "1-3"]);
+
+        // the entered span is only busy until the end of the query, same as
+        // the value it's shown with
+        let spans = engine.query_span(query_before_100("#busy: <100us"));
+        let busy = spans.iter().map(|span| span.busy).collect::<Vec<_>>();
+        assert_eq!(busy, [Some(40), Some(0), Some(60)]);
+    }
+
+    #[test]
+    fn span_timeline_pairs_enters_with_exits() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let span_event = |timestamp: u64, id: u64, kind: NewSpanEventKind| NewSpanEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(timestamp).unwrap(),
+            span_id: id.try_into().unwrap(),
+            kind,
+        };
+        let create = |timestamp: u64, id: u64, parent_id: Option<u64>| {
+            span_event(
+                timestamp,
+                id,
+                NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: parent_id.map(|id| id.try_into().unwrap()),
+                    target: "crate::storage::tests".to_owned(),
+                    name: format!("span{id}"),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                }),
+            )
+        };
+        let event = |timestamp: u64, span_id: u64| NewEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(timestamp).unwrap(),
+            span_id: Some(span_id.try_into().unwrap()),
+            name: format!("event{timestamp}"),
+            target: "crate::storage::tests".to_owned(),
+            level: 2,
+            file_name: None,
+            file_line: None,
+            fields: BTreeMap::new(),
+        };
+
+        let span_key = engine.insert_span_event(create(10, 1, None)).unwrap();
+        engine
+            .insert_span_event(span_event(20, 1, NewSpanEventKind::Enter))
+            .unwrap();
+        engine.insert_event(event(25, 1)).unwrap();
+        engine.insert_span_event(create(30, 2, Some(1))).unwrap();
+        engine.insert_event(event(35, 2)).unwrap();
+        engine
+            .insert_span_event(span_event(40, 1, NewSpanEventKind::Exit))
+            .unwrap();
+        engine
+            .insert_span_event(span_event(
+                50,
+                1,
+                NewSpanEventKind::Update(NewUpdateSpanEvent {
+                    fields: BTreeMap::from_iter([("a".to_owned(), Value::U64(1))]),
+                }),
+            ))
+            .unwrap();
+        engine
+            .insert_span_event(span_event(60, 1, NewSpanEventKind::Enter))
+            .unwrap();
+
+        let timeline = engine.query_span_timeline(span_key);
+        let entries = timeline
+            .iter()
+            .map(|entry| {
+                let kind = match &entry.kind {
+                    SpanTimelineEntryKind::Create => "create".to_owned(),
+                    SpanTimelineEntryKind::Update { attributes } => {
+                        format!("update {}", attributes[0].name)
+                    }
+                    SpanTimelineEntryKind::Follows { follows } => format!("follows {follows}"),
+                    SpanTimelineEntryKind::Busy { exited_at } => {
+                        format!("busy until {exited_at:?}")
+                    }
+                    SpanTimelineEntryKind::Event { name, .. } => name.clone(),
+                    SpanTimelineEntryKind::Close => "close".to_owned(),
+                };
+                (entry.timestamp.get(), kind)
+            })
+            .collect::<Vec<_>>();
+
+        let expected = [
+            (10, "create".to_owned()),
+            (20, "busy until Some(40)".to_owned()),
+            (25, "event25".to_owned()),
+            (50, "update a".to_owned()),
+            (60, "busy until None".to_owned()),
+        ];
+        assert_eq!(entries, expected);
+
+        assert!(engine
+            .query_span_timeline(Timestamp::new(5).unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn attribute_keys_are_listed_by_kind() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(
+                1,
+                BTreeMap::from_iter([("service".to_owned(), Value::Str("a".to_owned()))]),
+            ))
+            .unwrap();
+
+        engine
+            .insert_span_event(NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(1).unwrap(),
+                span_id: 1.try_into().unwrap(),
+                kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: None,
+                    target: "crate::storage::tests".to_owned(),
+                    name: "span".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::from_iter([("user".to_owned(), Value::U64(5))]),
+                }),
+            })
+            .unwrap();
+        engine
+            .insert_event(NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(2).unwrap(),
+                span_id: None,
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::from_iter([
+                    ("status".to_owned(), Value::U64(200)),
+                    ("method".to_owned(), Value::Str("GET".to_owned())),
+                ]),
+            })
+            .unwrap();
+
+        assert_eq!(
+            engine.list_attribute_keys(EventsOrSpans::Events),
+            ["method", "service", "status"]
+        );
+        assert_eq!(
+            engine.list_attribute_keys(EventsOrSpans::Spans),
+            ["service", "user"]
+        );
+    }
+
+    #[test]
+    fn attribute_values_are_listed_by_frequency() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let event = |timestamp: u64, attribute: &str, value: Value| NewEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(timestamp).unwrap(),
+            span_id: None,
+            name: "event".to_owned(),
+            target: "crate::storage::tests".to_owned(),
+            level: 2,
+            file_name: None,
+            file_line: None,
+            fields: BTreeMap::from_iter([(attribute.to_owned(), value)]),
+        };
+
+        let envs = ["prod", "dev", "prod", "staging", "dev", "prod"];
+        for (i, env) in envs.into_iter().enumerate() {
+            let value = Value::Str(env.to_owned());
+            engine
+                .insert_event(event(i as u64 + 1, "env", value))
+                .unwrap();
+        }
+        for (i, status) in [200, 404, 200].into_iter().enumerate() {
+            let value = Value::U64(status);
+            engine
+                .insert_event(event(i as u64 + 10, "status", value))
+                .unwrap();
+        }
+
+        assert_eq!(
+            engine.list_attribute_values(EventsOrSpans::Events, "env", 5),
+            ["prod", "dev", "staging"]
+        );
+        assert_eq!(
+            engine.list_attribute_values(EventsOrSpans::Events, "env", 2),
+            ["prod", "dev"]
+        );
+        assert_eq!(
+            engine.list_attribute_values(EventsOrSpans::Events, "status", 5),
+            ["200", "404"]
+        );
+        assert!(engine
+            .list_attribute_values(EventsOrSpans::Spans, "env", 5)
+            .is_empty());
+    }
+
+    #[test]
+    fn explain_event_query_reports_scans() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        for (timestamp, level, path) in [(1, 2, "/api/a"), (2, 3, "/api/b"), (3, 3, "/home")] {
+            engine
+                .insert_event(NewEvent {
+                    connection_key,
+                    seq: None,
+                    timestamp: Timestamp::new(timestamp).unwrap(),
+                    span_id: None,
+                    name: "event".to_owned(),
+                    target: "crate::storage::tests".to_owned(),
+                    level,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::from_iter([("path".to_owned(), Value::Str(path.to_owned()))]),
+                })
+                .unwrap();
+        }
+
+        let plan = engine.explain_event_query(query("#level: WARN"));
+        assert_eq!(plan.node, "index");
+        assert_eq!(plan.estimate_count, 2);
+        assert!(plan.indexed);
+
+        let plan = engine.explain_event_query(query("#level: WARN @path: \"/api/*\""));
+        assert_eq!(plan.node, "and");
+        assert!(!plan.indexed);
+        assert!(plan.children[0].indexed);
+
+        let attribute_plan = &plan.children[1].children[0];
+        assert_eq!(attribute_plan.estimate_count, 3);
+        assert_eq!(attribute_plan.scan.as_deref(), Some("@path"));
+    }
+
+    #[test]
+    fn target_filters_use_target_index() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let targets = ["lib::x", "app::db", "app::http", "app::db"];
+        for (timestamp, target) in (2..).zip(targets) {
+            engine
+                .insert_event(NewEvent {
+                    connection_key,
+                    seq: None,
+                    timestamp: Timestamp::new(timestamp).unwrap(),
+                    span_id: None,
+                    name: "event".to_owned(),
+                    target: target.to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                })
+                .unwrap();
+        }
+        for (timestamp, target) in (10..).zip(targets) {
+            engine
+                .insert_span_event(NewSpanEvent {
+                    connection_key,
+                    seq: None,
+                    timestamp: Timestamp::new(timestamp).unwrap(),
+                    span_id: timestamp.try_into().unwrap(),
+                    kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                        parent_id: None,
+                        target: target.to_owned(),
+                        name: "span".to_owned(),
+                        level: 2,
+                        file_name: None,
+                        file_line: None,
+                        fields: BTreeMap::new(),
+                    }),
+                })
+                .unwrap();
+        }
+
+        let event_targets = |engine: &RawEngine<TransientStorage>, filter: &str| {
+            engine
+                .query_event(query(filter))
+                .into_iter()
+                .map(|event| event.target)
+                .collect::<Vec<_>>()
+        };
+        let span_targets = |engine: &RawEngine<TransientStorage>, filter: &str| {
+            engine
+                .query_span(query(filter))
+                .into_iter()
+                .map(|span| span.target)
+                .collect::<Vec<_>>()
+        };
+
+        let app = ["app::db", "app::http", "app::db"];
+        assert_eq!(event_targets(&engine, "#target: \"app::*\""), app);
+        assert_eq!(span_targets(&engine, "#target: \"app::*\""), app);
+        assert_eq!(event_targets(&engine, "#target: /^lib/"), ["lib::x"]);
+        assert_eq!(span_targets(&engine, "#target: /^lib/"), ["lib::x"]);
+        assert!(event_targets(&engine, "#target: \"none::*\"").is_empty());
+
+        let plan = engine.explain_event_query(query("#target: \"app::*\""));
+        assert!(plan.indexed);
+        assert_eq!(plan.estimate_count, 3);
+
+        // once the only event with a target is removed, so is its entry
+        engine.delete(DeleteFilter::before(Timestamp::new(3).unwrap()));
+        assert!(!engine.event_indexes.targets.contains_key("lib::x"));
+        assert!(engine.span_indexes.targets.contains_key("lib::x"));
+        assert!(event_targets(&engine, "#target: /^lib/").is_empty());
+    }
+
+    #[test]
+    fn unknown_attribute_filters_skip_scanning() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let fields = BTreeMap::from_iter([("path".to_owned(), Value::Str("API-a".to_owned()))]);
+        engine
+            .insert_span_event(NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(2).unwrap(),
+                span_id: 1.try_into().unwrap(),
+                kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: None,
+                    target: "crate::storage::tests".to_owned(),
+                    name: "span".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: fields.clone(),
+                }),
+            })
+            .unwrap();
+        engine
+            .insert_event(NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(3).unwrap(),
+                span_id: None,
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields,
+            })
+            .unwrap();
+
+        for filter in ["@path: *", "@path: ~=API", "@path: api-a/i"] {
+            assert_eq!(engine.query_event(query(filter)).len(), 1, "{filter}");
+
+            let plan = engine.explain_event_query(query(filter));
+            assert_eq!(plan.estimate_count, 1, "{filter}");
+        }
+        for filter in ["@path: ~=API", "@path: api-a/i"] {
+            assert_eq!(engine.query_span(query(filter)).len(), 1, "{filter}");
+        }
+
+        for filter in ["@pth: *", "@pth: ~=API", "@pth: api-a/i"] {
+            assert!(engine.query_event(query(filter)).is_empty(), "{filter}");
+
+            let plan = engine.explain_event_query(query(filter));
+            assert_eq!(plan.estimate_count, 0, "{filter}");
+            assert!(plan.indexed, "{filter}");
+        }
+        for filter in ["@pth: ~=API", "@pth: api-a/i"] {
+            assert!(engine.query_span(query(filter)).is_empty(), "{filter}");
+
+            let filter = BasicSpanFilter::from_predicate(
+                FilterPredicate::parse(filter).unwrap().remove(0),
+                &engine.connection_key_map,
+                &engine.span_key_map,
+            )
+            .unwrap();
+            let filter = IndexedSpanFilter::build(Some(filter), &engine.span_indexes);
+            assert!(matches!(filter, IndexedSpanFilter::Single([], None)));
+        }
+    }
+
+    #[test]
+    fn span_ancestors_filter() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let create = |timestamp: u64, id: u64, parent_id: Option<u64>| NewSpanEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(timestamp).unwrap(),
+            span_id: id.try_into().unwrap(),
+            kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                parent_id: parent_id.map(|id| id.try_into().unwrap()),
+                target: "crate::storage::tests".to_owned(),
+                name: format!("span{id}"),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            }),
+        };
+
+        engine.insert_span_event(create(1, 1, None)).unwrap();
+        engine.insert_span_event(create(2, 2, Some(1))).unwrap();
+        engine.insert_span_event(create(3, 3, None)).unwrap();
+        engine.insert_span_event(create(4, 4, Some(2))).unwrap();
+
+        let spans = engine.query_span(query("#ancestors: 1-4"));
+        let ids = spans
+            .iter()
+            .map(|span| span.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["1-1", "1-2"]);
+
+        assert!(engine.query_span(query("#ancestors: 1-1")).is_empty());
+        assert!(engine.query_span(query("#ancestors: 1-9")).is_empty());
+    }
+
+    #[test]
+    fn events_during_span_are_bounded_by_its_lifetime() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection = |id: u64| NewConnection::new(id, BTreeMap::new());
+        let first_key = engine.insert_connection(connection(1)).unwrap();
+        let second_key = engine.insert_connection(connection(2)).unwrap();
+
+        let span_key = engine
+            .insert_span_event(NewSpanEvent {
+                connection_key: first_key,
+                seq: None,
+                timestamp: Timestamp::new(5).unwrap(),
+                span_id: 1.try_into().unwrap(),
+                kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: None,
+                    target: "crate::storage::tests".to_owned(),
+                    name: "span".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                }),
+            })
+            .unwrap();
+
+        let event = |connection_key, timestamp: u64| NewEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(timestamp).unwrap(),
+            span_id: None,
+            name: "event".to_owned(),
+            target: "crate::storage::tests".to_owned(),
+            level: 2,
+            file_name: None,
+            file_line: None,
+            fields: BTreeMap::new(),
+        };
+        for (connection_key, timestamp) in [
+            (first_key, 3),
+            (first_key, 6),
+            (second_key, 7),
+            (first_key, 8),
+        ] {
+            engine
+                .insert_event(event(connection_key, timestamp))
+                .unwrap();
+        }
+
+        engine
+            .insert_span_event(NewSpanEvent {
+                connection_key: first_key,
+                seq: None,
+                timestamp: Timestamp::new(9).unwrap(),
+                span_id: 1.try_into().unwrap(),
+                kind: NewSpanEventKind::Close,
+            })
+            .unwrap();
+        engine.insert_event(event(first_key, 10)).unwrap();
+
+        let query = || Query {
+            filter: FilterPredicate::parse("#level: >=TRACE").unwrap(),
+            order: Order::Asc,
+            limit: 5,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        };
+
+        // the event sent at 3 was received after the span was created, so it
+        // is keyed within it and the rest are keyed one later than sent
+        let events = engine.query_event_during_span(span_key, query(), false);
+        let timestamps = events.iter().map(|e| e.timestamp.get()).collect::<Vec<_>>();
+        assert_eq!(timestamps, [6, 7, 8, 9]);
+
+        let events = engine.query_event_during_span(span_key, query(), true);
+        let timestamps = events.iter().map(|e| e.timestamp.get()).collect::<Vec<_>>();
+        assert_eq!(timestamps, [6, 7, 9]);
+    }
+
+    #[test]
+    fn clear_removes_everything() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection = || NewConnection::new(1, BTreeMap::new());
+        let connection_key = engine.insert_connection(connection()).unwrap();
+
+        engine
+            .insert_span_event(NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(1).unwrap(),
+                span_id: 1.try_into().unwrap(),
+                kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: None,
+                    target: "crate::storage::tests".to_owned(),
+                    name: "span".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                }),
+            })
+            .unwrap();
+        engine
+            .insert_event(NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(2).unwrap(),
+                span_id: Some(1.try_into().unwrap()),
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            })
+            .unwrap();
+
+        let (_, mut receiver) = engine.subscribe_to_events(vec![]);
+
+        engine.clear();
+
+        let stats = engine.query_stats();
+        assert_eq!(stats.total_events, 0);
+        assert_eq!(stats.total_spans, 0);
+        assert!(engine.storage.get_all_connections().next().is_none());
+        assert!(engine.storage.get_all_span_events().next().is_none());
+        assert!(engine
+            .span_indexes
+            .durations
+            .to_stratified_indexes()
+            .iter()
+            .all(|(index, _)| index.is_empty()));
+        assert!(matches!(
+            receiver.try_recv(),
+            Err(mpsc::error::TryRecvError::Disconnected)
+        ));
+
+        // a client that was connected before the clear is rejected instead of
+        // taking down the engine
+        let result = engine.insert_event(NewEvent {
+            connection_key,
+            seq: None,
+            timestamp: Timestamp::new(3).unwrap(),
+            span_id: None,
+            name: "event".to_owned(),
+            target: "crate::storage::tests".to_owned(),
+            level: 2,
+            file_name: None,
+            file_line: None,
+            fields: BTreeMap::new(),
+        });
+        assert!(matches!(result, Err(EngineInsertError::UnknownConnection)));
+
+        // the same connection id can be used again
+        engine.insert_connection(connection()).unwrap();
+    }
+
+    #[test]
+    fn event_context_cache_resolves_once_per_event() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        // a chain of three spans with the attributes at the root
+        for id in 1..=3u64 {
+            let fields = match id {
+                1 => BTreeMap::from_iter([
+                    ("user".to_owned(), Value::Str("alice".to_owned())),
+                    ("region".to_owned(), Value::Str("eu".to_owned())),
+                ]),
+                _ => BTreeMap::new(),
+            };
+
+            engine
+                .insert_span_event(NewSpanEvent {
+                    connection_key,
+                    seq: None,
+                    timestamp: Timestamp::new(id).unwrap(),
+                    span_id: id.try_into().unwrap(),
+                    kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                        parent_id: (id > 1).then(|| (id - 1).try_into().unwrap()),
+                        target: "crate::storage::tests".to_owned(),
+                        name: "span".to_owned(),
+                        level: 2,
+                        file_name: None,
+                        file_line: None,
+                        fields,
+                    }),
+                })
+                .unwrap();
+        }
+
+        let mut event_keys = vec![];
+        for (timestamp, span_id) in [(4, Some(3)), (5, None)] {
+            let event_key = engine
+                .insert_event(NewEvent {
+                    connection_key,
+                    seq: None,
+                    timestamp: Timestamp::new(timestamp).unwrap(),
+                    span_id: span_id.map(|id: u64| id.try_into().unwrap()),
+                    name: "event".to_owned(),
+                    target: "crate::storage::tests".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                })
+                .unwrap();
+            event_keys.push(event_key);
+        }
+
+        let cache = EventContextCache::new();
+        let user = EventContext::with_cache(event_keys[0], &engine.storage, &cache)
+            .with_attribute("user", |v| v.cloned());
+        assert_eq!(user, Some(Value::Str("alice".to_owned())));
+
+        // another context for the same event reuses what was resolved
+        let context = EventContext::with_cache(event_keys[0], &engine.storage, &cache);
+        let region = context.with_attribute("region", |v| v.cloned());
+        assert_eq!(region, Some(Value::Str("eu".to_owned())));
+        let parents = cache.parents(event_keys[0], || panic!("parents were loaded again"));
+        assert_eq!(parents.len(), 3);
+        assert!(cache.attributes.borrow().contains_key("user"));
+
+        // checking a different event starts over
+        let context = EventContext::with_cache(event_keys[1], &engine.storage, &cache);
+        assert_eq!(context.with_attribute("user", |v| v.cloned()), None);
+        assert!(!cache.attributes.borrow().contains_key("region"));
+
+        let events = engine.query_event(query("@user: alice @region: eu"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].timestamp, event_keys[0]);
+        assert!(engine
+            .query_event(query("@user: alice @region: us"))
+            .is_empty());
+    }
+
+    #[test]
+    fn delete_connection_keeps_other_connections() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let mut timestamp = 0;
+        for id in [1, 2] {
+            let connection_key = engine
+                .insert_connection(NewConnection::new(id, BTreeMap::new()))
+                .unwrap();
+
+            for (span_id, parent_id) in [(1, None), (2, Some(1))] {
+                timestamp += 1;
+                engine
+                    .insert_span_event(NewSpanEvent {
+                        connection_key,
+                        seq: None,
+                        timestamp: Timestamp::new(timestamp).unwrap(),
+                        span_id: span_id.try_into().unwrap(),
+                        kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                            parent_id: parent_id.map(|id: u64| id.try_into().unwrap()),
+                            target: "crate::storage::tests".to_owned(),
+                            name: format!("span{span_id}"),
+                            level: 2,
+                            file_name: None,
+                            file_line: None,
+                            fields: BTreeMap::new(),
+                        }),
+                    })
+                    .unwrap();
+            }
+
+            timestamp += 1;
+            engine
+                .insert_event(NewEvent {
+                    connection_key,
+                    seq: None,
+                    timestamp: Timestamp::new(timestamp).unwrap(),
+                    span_id: Some(2.try_into().unwrap()),
+                    name: "event".to_owned(),
+                    target: "crate::storage::tests".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                })
+                .unwrap();
+        }
+
+        assert!(matches!(
+            engine.delete_connection(1),
+            Err(DeleteConnectionError::StillConnected)
+        ));
+
+        engine.disconnect_connection(1).unwrap();
+        let metrics = engine.delete_connection(1).unwrap();
+        assert_eq!(metrics.connections, 1);
+        assert_eq!(metrics.spans, 2);
+        assert_eq!(metrics.span_events, 2);
+        assert_eq!(metrics.events, 1);
+
+        assert!(matches!(
+            engine.delete_connection(1),
+            Err(DeleteConnectionError::UnknownConnectionId)
+        ));
+
+        assert!(engine.query_event(query("#connection: 1")).is_empty());
+        assert!(engine.query_span(query("#stack: 1-1")).is_empty());
+
+        let events = engine.query_event(query("#connection: 2"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].connection_id, "2");
+
+        let events = engine.query_event(query("#stack: 2-2"));
+        assert_eq!(events.len(), 1);
+
+        let spans = engine.query_span(query("#stack: 2-1"));
+        let ids = spans
+            .iter()
+            .map(|span| span.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["2-1", "2-2"]);
+
+        let stats = engine.query_stats();
+        assert_eq!(stats.total_events, 1);
+        assert_eq!(stats.total_spans, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn engine_panics_when_used_after_shutdown() {
+        use futures::executor::block_on;
+
+        let engine = Engine::new(TransientStorage::new());
+        block_on(async {
+            engine.shutdown().await;
+            engine.shutdown().await;
+            engine.flush().await;
+        });
+    }
+
+    #[test]
+    fn inserts_fail_after_shutdown() {
+        use futures::executor::block_on;
+
+        let engine = Engine::new(TransientStorage::new());
+        let result = block_on(async {
+            engine.shutdown().await;
+            engine
+                .insert_connection(NewConnection::new(1, BTreeMap::new()))
+                .await
+        });
+
+        assert!(matches!(result, Err(EngineInsertError::Shutdown)));
+    }
+
+    #[cfg(feature = "persist")]
+    #[test]
+    fn shutdown_writes_queued_inserts() {
+        use futures::executor::block_on;
+
+        const COUNT: u64 = 500;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shutdown.db");
+
+        let engine = Engine::new(FileStorage::create(&path).unwrap());
+        block_on(async {
+            let connection_key = engine
+                .insert_connection(NewConnection::new(1, BTreeMap::new()))
+                .await
+                .unwrap();
+
+            // the inserts are queued without waiting on their results
+            for i in 1..=COUNT {
+                engine
+                    .queue_event(NewEvent {
+                        connection_key,
+                        seq: None,
+                        timestamp: Timestamp::new(i).unwrap(),
+                        span_id: None,
+                        name: "event".to_owned(),
+                        target: "crate::storage::tests".to_owned(),
+                        level: 2,
+                        file_name: None,
+                        file_line: None,
+                        fields: BTreeMap::new(),
+                    })
+                    .await;
+            }
+
+            engine.shutdown().await;
+        });
+
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(storage.get_all_events().count(), COUNT as usize);
+        drop(storage);
+    }
+
+    #[cfg(feature = "persist")]
+    #[test]
+    fn saved_filters_are_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("filters.db");
+
+        let mut engine = RawEngine::new(FileStorage::create(&path).unwrap());
+        engine
+            .save_filter("errors".to_owned(), "#level: ERROR".to_owned())
+            .unwrap();
+        engine
+            .save_filter("api".to_owned(), "@service: api".to_owned())
+            .unwrap();
+        engine
+            .save_filter("errors".to_owned(), "#level: >=WARN".to_owned())
+            .unwrap();
+        assert!(engine
+            .save_filter("broken".to_owned(), "#level: (".to_owned())
+            .is_err());
+        assert!(matches!(
+            engine.save_filter("invalid".to_owned(), "#level: loud".to_owned()),
+            Err(SaveFilterError::Invalid(InputError::InvalidLevelValue, _))
+        ));
+        // span-only predicates are fine too
+        engine
+            .save_filter("slow".to_owned(), "#duration: >1s".to_owned())
+            .unwrap();
+        engine.delete_filter("slow").unwrap();
+        engine.clear();
+        drop(engine);
+
+        let mut engine = RawEngine::new(FileStorage::open(&path).unwrap());
+        let filters = engine.list_filters();
+        assert_eq!(filters.len(), 2);
+        assert_eq!(filters[0].name, "api");
+        assert_eq!(filters[0].filter, "@service: api");
+        assert_eq!(filters[1].name, "errors");
+        assert_eq!(filters[1].filter, "#level: >=WARN");
+
+        let deleted = engine.delete_filter("api").unwrap();
+        assert_eq!(deleted.filter, "@service: api");
+        assert!(engine.delete_filter("api").is_none());
+        drop(engine);
+
+        let mut engine = RawEngine::new(FileStorage::open_read_only(&path).unwrap());
+        assert_eq!(engine.list_filters().len(), 1);
+        assert!(matches!(
+            engine.save_filter("api".to_owned(), "@service: api".to_owned()),
+            Err(SaveFilterError::ReadOnly)
+        ));
+        drop(engine);
+    }
+
+    #[cfg(feature = "persist")]
+    #[test]
+    fn indexes_are_not_saved_if_disabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("indexes.db");
+
+        let options = EngineOptions {
+            persist_indexes: false,
+            ..EngineOptions::default()
+        };
+        let mut engine = RawEngine::with_options(FileStorage::create(&path).unwrap(), options);
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+        engine
+            .insert_event(NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(2).unwrap(),
+                span_id: None,
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            })
+            .unwrap();
+        engine.flush();
+        drop(engine);
+
+        assert!(FileStorage::open(&path).unwrap().load_indexes().is_none());
+
+        let engine = RawEngine::new(FileStorage::open(&path).unwrap());
+        let query = Query {
+            filter: FilterPredicate::parse("#level: INFO").unwrap(),
+            order: Order::Asc,
+            limit: 5,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        };
+        assert_eq!(engine.query_event(query).len(), 1);
+        drop(engine);
+    }
+
+    #[cfg(feature = "persist")]
+    #[test]
+    fn indexes_are_reloaded_after_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("indexes.db");
+
+        let mut engine = RawEngine::new(FileStorage::create(&path).unwrap());
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+        engine
+            .insert_span_event(NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(1).unwrap(),
+                span_id: 1.try_into().unwrap(),
+                kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: None,
+                    target: "crate::storage::tests".to_owned(),
+                    name: "span".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::from_iter([(
+                        "user".to_owned(),
+                        Value::Str("alice".to_owned()),
+                    )]),
+                }),
+            })
+            .unwrap();
+        engine
+            .insert_event(NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(2).unwrap(),
+                span_id: Some(1.try_into().unwrap()),
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            })
+            .unwrap();
+        engine.flush();
+        drop(engine);
+
+        assert!(FileStorage::open(&path).unwrap().load_indexes().is_some());
+
+        let engine = RawEngine::new(FileStorage::open(&path).unwrap());
+
+        assert_eq!(engine.query_event(query("#stack: 1-1")).len(), 1);
+        assert_eq!(engine.query_span(query("@user: alice")).len(), 1);
+
+        // the span left open is closed on startup, so the saved indexes no
+        // longer match
+        assert!(engine.span_indexes.durations.open.is_empty());
+        assert!(engine.storage.load_indexes().is_none());
+        drop(engine);
+    }
+
+    #[test]
+    fn stats_are_counted_per_level_and_connection_state() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection = |id: u64| NewConnection::new(id, BTreeMap::new());
+        let connection_key = engine.insert_connection(connection(1)).unwrap();
+        engine.insert_connection(connection(2)).unwrap();
+        engine.disconnect_connection(2).unwrap();
+
+        engine
+            .insert_span_event(NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(1).unwrap(),
+                span_id: 1.try_into().unwrap(),
+                kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: None,
+                    target: "crate::storage::tests".to_owned(),
+                    name: "span".to_owned(),
+                    level: 1,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                }),
+            })
+            .unwrap();
+        for (timestamp, level) in [(2, 0), (3, 2), (4, 2), (5, 4)] {
+            engine
+                .insert_event(NewEvent {
+                    connection_key,
+                    seq: None,
+                    timestamp: Timestamp::new(timestamp).unwrap(),
+                    span_id: None,
+                    name: "event".to_owned(),
+                    target: "crate::storage::tests".to_owned(),
+                    level,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                })
+                .unwrap();
+        }
+
+        let stats = engine.query_stats();
+        assert_eq!(stats.events_per_level, [1, 0, 2, 0, 1]);
+        assert_eq!(stats.spans_per_level, [0, 1, 0, 0, 0]);
+        assert_eq!(stats.connected_connections, 1);
+        assert_eq!(stats.disconnected_connections, 1);
+    }
+
+    #[test]
+    fn event_peak_buckets() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        // buckets of 10 starting at 1: [1-10] has 2, [11-20] has 5, [21-30]
+        // has 2, and [31-40] has 1
+        for id in [2, 5, 11, 12, 13, 15, 19, 21, 29, 35] {
+            engine
+                .insert_event(simple_event(connection_key, id, None))
+                .unwrap();
+        }
+
+        let query = Query {
+            filter: FilterPredicate::parse("#level: >=TRACE").unwrap(),
+            order: Order::Desc,
+            limit: 5,
+            start: Timestamp::new(1).unwrap(),
+            end: Timestamp::new(40).unwrap(),
+            previous: None,
+        };
+
+        let buckets = engine.query_peak_buckets(EventsOrSpans::Events, query, 10, 3);
+
+        assert_eq!(buckets.len(), 3);
+        assert_eq!(buckets[0].start, Timestamp::new(11).unwrap());
+        assert_eq!(buckets[0].end, Timestamp::new(20).unwrap());
+        assert_eq!(buckets[0].count, 5);
+        assert_eq!(buckets[1].start, Timestamp::new(1).unwrap()); // tie, earliest first
+        assert_eq!(buckets[1].count, 2);
+        assert_eq!(buckets[2].start, Timestamp::new(21).unwrap());
+        assert_eq!(buckets[2].count, 2);
+    }
+
+    #[test]
+    fn span_peak_buckets() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        // the span created at 1 is still open, so it overlaps the timeframe
+        // but isn't counted. buckets of 10 starting at 5: [5-14] has 1,
+        // [15-24] has 3, and [25-34] has 2
+        for open in [1, 12, 15, 18, 21, 25, 34] {
+            engine
+                .insert_span_event(simple_open(connection_key, open, None))
+                .unwrap();
+        }
+
+        let query = Query {
+            filter: FilterPredicate::parse("#level: >=TRACE").unwrap(),
+            order: Order::Desc,
+            limit: 5,
+            start: Timestamp::new(5).unwrap(),
+            end: Timestamp::new(34).unwrap(),
+            previous: None,
+        };
+
+        let buckets = engine.query_peak_buckets(EventsOrSpans::Spans, query, 10, 2);
+
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].start, Timestamp::new(15).unwrap());
+        assert_eq!(buckets[0].end, Timestamp::new(24).unwrap());
+        assert_eq!(buckets[0].count, 3);
+        assert_eq!(buckets[1].start, Timestamp::new(25).unwrap());
+        assert_eq!(buckets[1].end, Timestamp::new(34).unwrap());
+        assert_eq!(buckets[1].count, 2);
+    }
+
+    #[test]
+    fn event_histogram() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        for id in [2, 5, 11, 12, 13, 35, 41] {
+            engine
+                .insert_event(simple_event(connection_key, id, None))
+                .unwrap();
+        }
+        engine
+            .insert_event(NewEvent {
+                level: 0, // excluded by level
+                ..simple_event(connection_key, 14, None)
+            })
+            .unwrap();
+
+        let query = |end: u64| Query {
+            filter: FilterPredicate::parse("#level: >=INFO").unwrap(),
+            order: Order::Desc,
+            limit: 5,
+            start: Timestamp::new(1).unwrap(),
+            end: Timestamp::new(end).unwrap(),
             previous: None,
-        });
+        };
 
-        assert_eq!(events.len(), 2);
-        assert_eq!(events[0].timestamp, Timestamp::new(4).unwrap());
-        assert_eq!(events[1].timestamp, Timestamp::new(5).unwrap());
+        let buckets = engine.query_event_histogram(query(35), 10);
+        let counts = buckets.iter().map(|b| b.count).collect::<Vec<_>>();
+        assert_eq!(counts, [2, 3, 0, 1]);
+        assert_eq!(buckets[3].start, Timestamp::new(31).unwrap());
+        assert_eq!(buckets[3].end, Timestamp::new(35).unwrap());
+
+        // the bucket size is raised to keep the bucket count bounded
+        let buckets = engine.query_event_histogram(query(u64::MAX), 1);
+        assert!(buckets.len() <= MAX_HISTOGRAM_BUCKETS);
+        assert_eq!(buckets.iter().map(|b| b.count).sum::<usize>(), 7);
     }
 
     #[test]
@@ -1846,16 +8134,14 @@ mod tests {
         let mut engine = RawEngine::new(TransientStorage::new());
 
         let connection_key = engine
-            .insert_connection(NewConnection {
-                id: 1,
-                fields: BTreeMap::new(),
-            })
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
             .unwrap();
 
         let simple_open =
             |open: u64, level: i32, attribute1: &str, attribute2: &str| -> NewSpanEvent {
                 NewSpanEvent {
                     connection_key,
+                    seq: None,
                     timestamp: Timestamp::new(open).unwrap(),
                     span_id: open.try_into().unwrap(),
                     kind: NewSpanEventKind::Create(NewCreateSpanEvent {
@@ -1876,6 +8162,7 @@ mod tests {
         let simple_close = |open: u64, close: u64| -> NewSpanEvent {
             NewSpanEvent {
                 connection_key,
+                seq: None,
                 timestamp: Timestamp::new(close).unwrap(),
                 span_id: open.try_into().unwrap(),
                 kind: NewSpanEventKind::Close,
@@ -1922,13 +8209,324 @@ mod tests {
             order: Order::Asc,
             limit: 5,
             start: Timestamp::new(2).unwrap(),
-            end: Timestamp::new(10).unwrap(),
+            end: Timestamp::new(14).unwrap(),
             previous: None,
         });
 
+        // the spans created after an earlier one closed are keyed after it
         assert_eq!(spans.len(), 2);
-        assert_eq!(spans[0].created_at, Timestamp::new(5).unwrap());
-        assert_eq!(spans[1].created_at, Timestamp::new(9).unwrap());
+        assert_eq!(spans[0].created_at, Timestamp::new(9).unwrap());
+        assert_eq!(spans[0].client_created_at, Some(Timestamp::new(5).unwrap()));
+        assert_eq!(spans[1].created_at, Timestamp::new(11).unwrap());
+        assert_eq!(spans[1].client_created_at, Some(Timestamp::new(9).unwrap()));
+    }
+
+    #[test]
+    fn span_found_by_closed_timestamp() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        engine
+            .insert_span_event(simple_open(connection_key, 1, None))
+            .unwrap();
+        engine
+            .insert_span_event(simple_close(connection_key, 1, 3))
+            .unwrap();
+        engine
+            .insert_span_event(simple_open(connection_key, 2, None))
+            .unwrap();
+        engine
+            .insert_span_event(simple_close(connection_key, 2, 6))
+            .unwrap();
+        engine
+            .insert_span_event(simple_open(connection_key, 4, None))
+            .unwrap(); // never closed
+
+        // the span sent at 2 is keyed at 4 since it arrived after the close
+        // at 3, and it stays open for the 4 the client measured so it is
+        // closed at 8
+        let spans = engine.query_span(query("#closed: >5"));
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].created_at, Timestamp::new(4).unwrap());
+        assert_eq!(spans[0].closed_at, Some(Timestamp::new(8).unwrap()));
+        assert_eq!(spans[1].created_at, Timestamp::new(7).unwrap());
+
+        let spans = engine.query_span(query("#closed: >=3"));
+        assert_eq!(spans.len(), 3);
+
+        let spans = engine.query_span(query("#closed: <6"));
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].created_at, Timestamp::new(1).unwrap());
+
+        let spans = engine.query_span(query("#closed: 8"));
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].created_at, Timestamp::new(4).unwrap());
+    }
+
+    #[test]
+    fn span_found_by_direct_parent() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        engine
+            .insert_span_event(simple_open(connection_key, 1, None))
+            .unwrap();
+        engine
+            .insert_span_event(simple_open(connection_key, 2, Some(1)))
+            .unwrap();
+        engine
+            .insert_span_event(simple_open(connection_key, 3, Some(2)))
+            .unwrap(); // grandchild
+        engine
+            .insert_span_event(simple_open(connection_key, 4, Some(1)))
+            .unwrap();
+
+        let spans = engine.query_span(query("#parent: 1-1"));
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].created_at, Timestamp::new(2).unwrap());
+        assert_eq!(spans[1].created_at, Timestamp::new(4).unwrap());
+
+        let spans = engine.query_span(query("#parent: none"));
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].created_at, Timestamp::new(1).unwrap());
+
+        let spans = engine.query_span(query("#stack: 1-1"));
+        assert_eq!(spans.len(), 4); // includes itself and the grandchild
+    }
+
+    #[test]
+    fn event_found_by_root() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        engine
+            .insert_span_event(simple_open(connection_key, 1, None))
+            .unwrap();
+
+        engine
+            .insert_event(simple_event(connection_key, 2, None))
+            .unwrap();
+        engine
+            .insert_event(simple_event(connection_key, 3, Some(1)))
+            .unwrap();
+        engine
+            .insert_event(simple_event(connection_key, 4, None))
+            .unwrap();
+
+        let events = engine.query_event(query("#root: true"));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].timestamp, Timestamp::new(2).unwrap());
+        assert_eq!(events[1].timestamp, Timestamp::new(4).unwrap());
+
+        let events = engine.query_event(query("#root: false"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].timestamp, Timestamp::new(3).unwrap());
+
+        let result =
+            BasicEventFilter::validate(FilterPredicate::parse("#root: maybe").unwrap()[0].clone());
+        assert!(matches!(result, Err(InputError::InvalidRootValue)));
+    }
+
+    #[test]
+    fn fork_filtered_matches_parent() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(
+                1,
+                BTreeMap::from_iter([("service".to_owned(), Value::Str("A".to_owned()))]),
+            ))
+            .unwrap();
+
+        let simple_open = |open: u64, parent: Option<u64>, attribute: &str| -> NewSpanEvent {
+            NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(open).unwrap(),
+                span_id: open.try_into().unwrap(),
+                kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: parent.map(|p| p.try_into().unwrap()),
+                    target: "crate::storage::tests".to_owned(),
+                    name: "test".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::from_iter([(
+                        "attribute1".to_owned(),
+                        Value::Str(attribute.to_owned()),
+                    )]),
+                }),
+            }
+        };
+
+        let simple = |id: u64, span: Option<u64>, level: i32| -> NewEvent {
+            NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: id.try_into().unwrap(),
+                span_id: span.map(|s| s.try_into().unwrap()),
+                name: "event".to_owned(),
+                target: "crate::storage::tests".to_owned(),
+                level,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            }
+        };
+
+        engine.insert_span_event(simple_open(1, None, "A")).unwrap();
+        engine
+            .insert_span_event(simple_open(2, Some(1), "B"))
+            .unwrap();
+        engine.insert_span_event(simple_open(3, None, "C")).unwrap();
+        engine.insert_event(simple(4, Some(2), 4)).unwrap();
+        engine.insert_event(simple(5, Some(3), 4)).unwrap();
+        engine.insert_event(simple(6, Some(2), 1)).unwrap();
+        engine.insert_event(simple(7, None, 4)).unwrap();
+
+        let query = |filter: &str| Query {
+            filter: FilterPredicate::parse(filter).unwrap(),
+            order: Order::Asc,
+            limit: 10,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        };
+
+        let filter = "#level: ERROR @\"attribute1\": B";
+        let fork = RawEngine::new(engine.fork_filtered(FilterPredicate::parse(filter).unwrap()));
+
+        let events = engine.query_event(query(filter));
+        let forked_events = fork.query_event(query(filter));
+
+        assert_eq!(forked_events.len(), 1);
+        assert_eq!(events.len(), forked_events.len());
+        assert_eq!(events[0].timestamp, forked_events[0].timestamp);
+        assert_eq!(
+            events[0].attributes.len(),
+            forked_events[0].attributes.len()
+        );
+
+        // the ancestry is kept intact
+        assert_eq!(forked_events[0].ancestors.len(), 2);
+        assert_eq!(forked_events[0].ancestors[0].id, "1-1");
+        assert_eq!(forked_events[0].ancestors[1].id, "1-2");
+
+        // only the matching event and its ancestors were copied
+        let stats = fork.query_stats();
+        assert_eq!(stats.total_events, 1);
+        assert_eq!(stats.total_spans, 2);
+    }
+
+    #[test]
+    fn event_and_span_found_by_target() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let simple_open = |open: u64, target: &str| -> NewSpanEvent {
+            NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(open).unwrap(),
+                span_id: open.try_into().unwrap(),
+                kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: None,
+                    target: target.to_owned(),
+                    name: "test".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::new(),
+                }),
+            }
+        };
+
+        let simple = |id: u64, target: &str| -> NewEvent {
+            NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: id.try_into().unwrap(),
+                span_id: None,
+                name: "event".to_owned(),
+                target: target.to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            }
+        };
+
+        engine
+            .insert_span_event(simple_open(1, "my_crate"))
+            .unwrap();
+        engine
+            .insert_span_event(simple_open(2, "my_crate::module"))
+            .unwrap();
+        engine.insert_event(simple(3, "my_crate::module")).unwrap();
+        engine.insert_event(simple(4, "my_crate")).unwrap();
+        engine.insert_event(simple(5, "my_crate::module")).unwrap();
+
+        let events = engine.query_event(query("#target: \"my_crate::module\""));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].timestamp, Timestamp::new(3).unwrap());
+        assert_eq!(events[1].timestamp, Timestamp::new(5).unwrap());
+
+        let spans = engine.query_span(query("#target: \"my_crate::module\""));
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].created_at, Timestamp::new(2).unwrap());
+
+        let spans = engine.query_span(query("#target: my_crate"));
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].created_at, Timestamp::new(1).unwrap());
+    }
+
+    #[test]
+    fn span_found_by_name_prefix() {
+        let mut engine = RawEngine::new(TransientStorage::new());
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        engine
+            .insert_span_event(named_open(connection_key, 1, None, "http-worker-1"))
+            .unwrap();
+        engine
+            .insert_span_event(named_open(connection_key, 2, None, "http-server"))
+            .unwrap();
+        engine
+            .insert_span_event(named_open(connection_key, 3, None, "http-worker-2"))
+            .unwrap();
+        engine
+            .insert_span_event(named_open(connection_key, 4, None, "worker"))
+            .unwrap();
+
+        let spans = engine.query_span(query("#name: http-worker-*"));
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].name, "http-worker-1");
+        assert_eq!(spans[1].name, "http-worker-2");
+
+        let spans = engine.query_span(query("#name: http-*"));
+        assert_eq!(spans.len(), 3);
+
+        let spans = engine.query_span(query("#name: ftp-*"));
+        assert_eq!(spans.len(), 0);
+
+        // not a prefix, so this is not index-backed but still works
+        let spans = engine.query_span(query("#name: *worker*"));
+        assert_eq!(spans.len(), 3);
     }
 
     #[test]
@@ -1936,16 +8534,17 @@ mod tests {
         let mut engine = RawEngine::new(TransientStorage::new());
 
         let connection_key = engine
-            .insert_connection(NewConnection {
-                id: 1,
-                fields: BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
-            })
+            .insert_connection(NewConnection::new(
+                1,
+                BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
+            ))
             .unwrap();
 
         let now = now();
         engine
             .insert_event(NewEvent {
                 connection_key,
+                seq: None,
                 timestamp: now.saturating_add(1),
                 span_id: None,
                 name: "event".to_owned(),
@@ -1985,16 +8584,17 @@ mod tests {
         let mut engine = RawEngine::new(TransientStorage::new());
 
         let connection_key = engine
-            .insert_connection(NewConnection {
-                id: 1,
-                fields: BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
-            })
+            .insert_connection(NewConnection::new(
+                1,
+                BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
+            ))
             .unwrap();
 
         let now = now();
         engine
             .insert_event(NewEvent {
                 connection_key,
+                seq: None,
                 timestamp: now.saturating_add(1),
                 span_id: None,
                 name: "event".to_owned(),
@@ -2034,16 +8634,17 @@ mod tests {
         let mut engine = RawEngine::new(TransientStorage::new());
 
         let connection_key = engine
-            .insert_connection(NewConnection {
-                id: 1,
-                fields: BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
-            })
+            .insert_connection(NewConnection::new(
+                1,
+                BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
+            ))
             .unwrap();
 
         let now = now();
         engine
             .insert_event(NewEvent {
                 connection_key,
+                seq: None,
                 timestamp: now.saturating_add(1),
                 span_id: None,
                 name: "event".to_owned(),
@@ -2083,16 +8684,17 @@ mod tests {
         let mut engine = RawEngine::new(TransientStorage::new());
 
         let connection_key = engine
-            .insert_connection(NewConnection {
-                id: 1,
-                fields: BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
-            })
+            .insert_connection(NewConnection::new(
+                1,
+                BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
+            ))
             .unwrap();
 
         let now = now();
         engine
             .insert_event(NewEvent {
                 connection_key,
+                seq: None,
                 timestamp: now.saturating_add(1),
                 span_id: None,
                 name: "event".to_owned(),
@@ -2132,15 +8734,16 @@ mod tests {
         let mut engine = RawEngine::new(TransientStorage::new());
 
         let connection_key = engine
-            .insert_connection(NewConnection {
-                id: 1,
-                fields: BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
-            })
+            .insert_connection(NewConnection::new(
+                1,
+                BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
+            ))
             .unwrap();
 
         engine
             .insert_span_event(NewSpanEvent {
                 connection_key,
+                seq: None,
                 timestamp: now(),
                 span_id: 1.try_into().unwrap(),
                 kind: NewSpanEventKind::Create(NewCreateSpanEvent {
@@ -2159,6 +8762,7 @@ mod tests {
         engine
             .insert_event(NewEvent {
                 connection_key,
+                seq: None,
                 timestamp: now.saturating_add(1),
                 span_id: SpanId::new(1),
                 name: "event".to_owned(),
@@ -2198,15 +8802,16 @@ mod tests {
         let mut engine = RawEngine::new(TransientStorage::new());
 
         let connection_key = engine
-            .insert_connection(NewConnection {
-                id: 1,
-                fields: BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
-            })
+            .insert_connection(NewConnection::new(
+                1,
+                BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
+            ))
             .unwrap();
 
         engine
             .insert_span_event(NewSpanEvent {
                 connection_key,
+                seq: None,
                 timestamp: now(),
                 span_id: 1.try_into().unwrap(),
                 kind: NewSpanEventKind::Create(NewCreateSpanEvent {
@@ -2225,6 +8830,7 @@ mod tests {
         engine
             .insert_event(NewEvent {
                 connection_key,
+                seq: None,
                 timestamp: now.saturating_add(1),
                 span_id: SpanId::new(1),
                 name: "event".to_owned(),
@@ -2264,15 +8870,16 @@ mod tests {
         let mut engine = RawEngine::new(TransientStorage::new());
 
         let connection_key = engine
-            .insert_connection(NewConnection {
-                id: 1,
-                fields: BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
-            })
+            .insert_connection(NewConnection::new(
+                1,
+                BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
+            ))
             .unwrap();
 
         engine
             .insert_span_event(NewSpanEvent {
                 connection_key,
+                seq: None,
                 timestamp: now(),
                 span_id: 1.try_into().unwrap(),
                 kind: NewSpanEventKind::Create(NewCreateSpanEvent {
@@ -2291,6 +8898,7 @@ mod tests {
         engine
             .insert_event(NewEvent {
                 connection_key,
+                seq: None,
                 timestamp: now.saturating_add(1),
                 span_id: SpanId::new(1),
                 name: "event".to_owned(),
@@ -2305,6 +8913,7 @@ mod tests {
         engine
             .insert_span_event(NewSpanEvent {
                 connection_key,
+                seq: None,
                 timestamp: super::now(),
                 span_id: 1.try_into().unwrap(),
                 kind: NewSpanEventKind::Update(NewUpdateSpanEvent {
@@ -2341,15 +8950,16 @@ mod tests {
         let mut engine = RawEngine::new(TransientStorage::new());
 
         let connection_key = engine
-            .insert_connection(NewConnection {
-                id: 1,
-                fields: BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
-            })
+            .insert_connection(NewConnection::new(
+                1,
+                BTreeMap::from_iter([("attr1".to_owned(), Value::Str("A".to_owned()))]),
+            ))
             .unwrap();
 
         engine
             .insert_span_event(NewSpanEvent {
                 connection_key,
+                seq: None,
                 timestamp: now(),
                 span_id: 1.try_into().unwrap(),
                 kind: NewSpanEventKind::Create(NewCreateSpanEvent {
@@ -2368,6 +8978,7 @@ mod tests {
         engine
             .insert_event(NewEvent {
                 connection_key,
+                seq: None,
                 timestamp: now.saturating_add(1),
                 span_id: SpanId::new(1),
                 name: "event".to_owned(),
@@ -2382,6 +8993,7 @@ mod tests {
         engine
             .insert_span_event(NewSpanEvent {
                 connection_key,
+                seq: None,
                 timestamp: super::now(),
                 span_id: 1.try_into().unwrap(),
                 kind: NewSpanEventKind::Update(NewUpdateSpanEvent {
@@ -2413,23 +9025,152 @@ mod tests {
         assert_eq!(events.len(), 1);
     }
 
+    fn assert_span_found_with_updated_attribute<S: Storage>(storage: S) -> RawEngine<S> {
+        let mut engine = RawEngine::new(storage);
+
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+
+        let span = |span_id: u64, parent_id: Option<u64>, fields: &[(&str, Value)]| {
+            NewSpanEventKind::Create(NewCreateSpanEvent {
+                parent_id: parent_id.map(|id| id.try_into().unwrap()),
+                target: "crate::storage::tests".to_owned(),
+                name: format!("span{span_id}"),
+                level: 4,
+                file_name: None,
+                file_line: None,
+                fields: fields
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.clone()))
+                    .collect(),
+            })
+        };
+        for (timestamp, span_id, kind) in [
+            (2, 1, span(1, None, &[("kept", Value::Bool(true))])),
+            (3, 2, span(2, Some(1), &[("retries", Value::I64(1))])),
+            (
+                4,
+                1,
+                NewSpanEventKind::Update(NewUpdateSpanEvent {
+                    fields: BTreeMap::from_iter([
+                        ("retries".to_owned(), Value::I64(5)),
+                        ("user".to_owned(), Value::Str("alice".to_owned())),
+                    ]),
+                }),
+            ),
+        ] {
+            engine
+                .insert_span_event(NewSpanEvent {
+                    connection_key,
+                    seq: None,
+                    timestamp: Timestamp::new(timestamp).unwrap(),
+                    span_id: span_id.try_into().unwrap(),
+                    kind,
+                })
+                .unwrap();
+        }
+
+        let names = |engine: &RawEngine<S>, filter: &str| {
+            engine
+                .query_span(Query {
+                    filter: FilterPredicate::parse(filter).unwrap(),
+                    order: Order::Asc,
+                    limit: 5,
+                    start: Timestamp::MIN,
+                    end: Timestamp::MAX,
+                    previous: None,
+                })
+                .into_iter()
+                .map(|span| span.name)
+                .collect::<Vec<_>>()
+        };
+
+        // the child inherits the new attribute but keeps its own value
+        assert_eq!(names(&engine, "@user: alice"), ["span1", "span2"]);
+        assert_eq!(names(&engine, "@retries: >3"), ["span1"]);
+        assert_eq!(names(&engine, "@retries: 1"), ["span2"]);
+        assert_eq!(names(&engine, "@kept: true"), ["span1", "span2"]);
+
+        engine
+    }
+
+    #[test]
+    fn span_found_with_updated_attribute() {
+        assert_span_found_with_updated_attribute(TransientStorage::new());
+    }
+
+    #[cfg(feature = "persist")]
+    #[test]
+    fn span_found_with_updated_attribute_in_file() {
+        let engine = assert_span_found_with_updated_attribute(FileStorage::new(
+            std::path::Path::new(":memory:"),
+        ));
+
+        // the stored fields are merged with their types intact, so rebuilding
+        // the indexes from storage finds the same spans
+        let engine = RawEngine::new(engine.storage);
+        let spans = engine.query_span(Query {
+            filter: FilterPredicate::parse("@retries: >3 @kept: true").unwrap(),
+            order: Order::Asc,
+            limit: 5,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        });
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, "span1");
+    }
+
+    #[test]
+    fn query_latency_buckets_are_cumulative() {
+        let mut latency = QueryLatencyView::new();
+        latency.record(Duration::from_micros(500));
+        latency.record(Duration::from_millis(20));
+        latency.record(Duration::from_secs(10));
+
+        let counts = latency
+            .buckets
+            .iter()
+            .map(|(_, count)| *count)
+            .collect::<Vec<_>>();
+        assert_eq!(counts, [1, 1, 1, 2, 2, 2, 2, 2]);
+        assert_eq!(latency.count, 3);
+    }
+
     #[test]
-    fn key_cache() {
-        let cache = KeyCache::new();
+    fn key_allocator() {
+        let keys = KeyAllocator::new();
 
         assert_eq!(
-            cache.register(1000.try_into().unwrap(), 1.try_into().unwrap()),
+            keys.register(1000.try_into().unwrap(), 1.try_into().unwrap()),
             Timestamp::new(1).unwrap()
         );
 
         assert_eq!(
-            cache.register(1000.try_into().unwrap(), 1.try_into().unwrap()),
+            keys.register(1000.try_into().unwrap(), 1.try_into().unwrap()),
             Timestamp::new(2).unwrap()
         );
 
         assert_eq!(
-            cache.register(20000000.try_into().unwrap(), 1.try_into().unwrap()),
+            keys.register(20000000.try_into().unwrap(), 1.try_into().unwrap()),
             Timestamp::new(10000000).unwrap()
         );
+
+        // an earlier timestamp still gets a later key
+        assert_eq!(
+            keys.register(20000000.try_into().unwrap(), 15000000.try_into().unwrap()),
+            Timestamp::new(15000000).unwrap()
+        );
+        assert_eq!(
+            keys.register(20000000.try_into().unwrap(), 12000000.try_into().unwrap()),
+            Timestamp::new(15000001).unwrap()
+        );
+
+        let keys = KeyAllocator::starting_after(Some(Timestamp::new(500).unwrap()));
+        assert_eq!(
+            keys.register(1000.try_into().unwrap(), 400.try_into().unwrap()),
+            Timestamp::new(501).unwrap()
+        );
     }
 }
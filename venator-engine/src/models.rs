@@ -31,10 +31,10 @@ pub type EventKey = NonZeroU64;
 /// side and is unique but only within that connection.
 pub type SpanId = NonZeroU64;
 
-pub type ConnectionIdView = String;
-pub type FullSpanIdView = String;
+pub(crate) type ConnectionIdView = String;
+pub(crate) type FullSpanIdView = String;
 
-pub type FullSpanId = (ConnectionId, SpanId);
+pub(crate) type FullSpanId = (ConnectionId, SpanId);
 
 pub type SubscriptionId = usize;
 
@@ -73,16 +73,44 @@ impl TryFrom<i32> for Level {
     }
 }
 
+impl Level {
+    /// Converts to the nearest level, for values that were never validated.
+    #[cfg(any(feature = "persist", feature = "postgres"))]
+    pub(crate) fn clamped(value: i32) -> Level {
+        match value {
+            ..=0 => Level::Trace,
+            1 => Level::Debug,
+            2 => Level::Info,
+            3 => Level::Warn,
+            4.. => Level::Error,
+        }
+    }
+}
+
 pub struct NewConnection {
     pub id: ConnectionId,
+    pub schema: u32,
     pub fields: BTreeMap<String, Value>,
 }
 
+impl NewConnection {
+    /// This creates a connection with the first schema version, which is what
+    /// clients that don't send one are using.
+    pub fn new(id: ConnectionId, fields: BTreeMap<String, Value>) -> NewConnection {
+        NewConnection {
+            id,
+            schema: 1,
+            fields,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Connection {
     pub id: ConnectionId,
     pub connected_at: Timestamp,
     pub disconnected_at: Option<Timestamp>,
+    pub schema: u32,
     pub fields: BTreeMap<String, Value>,
 }
 
@@ -106,11 +134,16 @@ pub struct ConnectionView {
     pub id: ConnectionIdView,
     pub connected_at: Timestamp,
     pub disconnected_at: Option<Timestamp>,
+    pub duration: Option<u64>, // in microseconds, none if still connected
+    pub schema: u32,
     pub attributes: Vec<AttributeView>,
 }
 
 pub struct NewSpanEvent {
     pub connection_key: ConnectionKey,
+    // a number the client increments with each message, so that one it sends
+    // again (like after reconnecting) is only applied once
+    pub seq: Option<u64>,
     pub timestamp: Timestamp,
     pub span_id: SpanId,
     pub kind: NewSpanEventKind,
@@ -182,8 +215,25 @@ pub struct FollowsSpanEvent {
     pub follows: SpanKey,
 }
 
+/// An event that is inserted without a connection registered beforehand. It
+/// is recorded under a disconnected connection with the given fields, which is
+/// shared with other one-shot events that have the same fields.
+pub struct NewOneShotEvent {
+    pub connection_fields: BTreeMap<String, Value>,
+    pub timestamp: Timestamp,
+    pub name: String,
+    pub target: String,
+    pub level: i32,
+    pub file_name: Option<String>,
+    pub file_line: Option<u32>,
+    pub fields: BTreeMap<String, Value>,
+}
+
 pub struct NewEvent {
     pub connection_key: ConnectionKey,
+    // a number the client increments with each message, so that one it sends
+    // again (like after reconnecting) is only applied once
+    pub seq: Option<u64>,
     pub timestamp: Timestamp,
     pub span_id: Option<SpanId>,
     pub name: String,
@@ -198,6 +248,8 @@ pub struct NewEvent {
 pub struct Event {
     pub connection_key: ConnectionKey,
     pub timestamp: Timestamp,
+    // the timestamp from the client if the key was assigned differently
+    pub client_timestamp: Option<Timestamp>,
     pub span_key: Option<SpanKey>,
     pub name: String,
     pub target: String,
@@ -213,11 +265,16 @@ impl Event {
     }
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct EventView {
     pub connection_id: ConnectionIdView,
     pub ancestors: Vec<AncestorView>, // in root-first order
+    // the span the event is directly within, which is the last ancestor
+    #[serde(default)]
+    pub span_id: Option<FullSpanIdView>,
     pub timestamp: Timestamp,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_timestamp: Option<Timestamp>,
     pub target: String,
     pub name: String,
     pub level: i32,
@@ -230,6 +287,8 @@ pub struct Span {
     pub connection_key: ConnectionKey,
     pub id: SpanId,
     pub created_at: Timestamp,
+    // the creation timestamp from the client if the key was assigned differently
+    pub client_created_at: Option<Timestamp>,
     pub closed_at: Option<Timestamp>,
     pub parent_key: Option<SpanKey>,
     pub follows: Vec<SpanKey>,
@@ -239,20 +298,40 @@ pub struct Span {
     pub file_name: Option<String>,
     pub file_line: Option<u32>,
     pub fields: BTreeMap<String, Value>,
+    // microseconds spent entered, not counting the current entry if any
+    pub busy: u64,
+    pub entered_at: Option<Timestamp>,
 }
 
 impl Span {
     pub fn key(&self) -> SpanKey {
         self.created_at
     }
+
+    // This places a timestamp from the client's clock relative to when the
+    // span was created, so that its distance from the client's creation time
+    // is kept regardless of the keys the engine assigned. Times that the
+    // span was closed or entered are measured this way.
+    pub(crate) fn at_client_time(&self, client_timestamp: Timestamp) -> Timestamp {
+        let client_created_at = self.client_created_at.unwrap_or(self.created_at);
+        let elapsed = client_timestamp
+            .get()
+            .saturating_sub(client_created_at.get());
+
+        self.created_at.saturating_add(elapsed)
+    }
 }
 
 #[derive(Serialize)]
 pub struct SpanView {
     pub id: FullSpanIdView,
     pub ancestors: Vec<AncestorView>, // in root-first order
+    pub follows: Vec<FullSpanIdView>,
     pub created_at: Timestamp,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_created_at: Option<Timestamp>,
     pub closed_at: Option<Timestamp>,
+    pub busy: Option<u64>, // in microseconds, none if entered with no end
     pub target: String,
     pub name: String,
     pub level: i32,
@@ -260,7 +339,28 @@ pub struct SpanView {
     pub attributes: Vec<AttributeView>,
 }
 
+/// A moment in a span's life or an event within it. These are returned in
+/// timestamp order, with each time the span was entered given by one entry.
+#[derive(Clone, Serialize)]
+pub struct SpanTimelineEntry {
+    pub timestamp: Timestamp,
+    #[serde(flatten)]
+    pub kind: SpanTimelineEntryKind,
+}
+
 #[derive(Clone, Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum SpanTimelineEntryKind {
+    Create,
+    Update { attributes: Vec<AttributeView> },
+    Follows { follows: FullSpanIdView },
+    // none if it is still entered
+    Busy { exited_at: Option<Timestamp> },
+    Event { name: String, level: i32 },
+    Close,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct AncestorView {
     pub id: FullSpanIdView,
     pub name: String,
@@ -268,6 +368,7 @@ pub struct AncestorView {
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub enum Value {
+    #[serde(with = "non_finite_f64")]
     F64(f64),
     I64(i64),
     U64(u64),
@@ -291,6 +392,49 @@ impl Value {
     }
 }
 
+/// JSON has no representation for NaN or infinities (`serde_json` writes them
+/// as `null` and then fails to read them back), so those are written as strings
+/// instead. Finite values are written as normal numbers.
+mod non_finite_f64 {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_nan() {
+            serializer.serialize_str("NaN")
+        } else if *value == f64::INFINITY {
+            serializer.serialize_str("inf")
+        } else if *value == f64::NEG_INFINITY {
+            serializer.serialize_str("-inf")
+        } else {
+            serializer.serialize_f64(*value)
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Number(f64),
+            // this is owned since deserializers like `serde_json::Value` and
+            // readers can't lend out strings
+            String(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Number(value) => Ok(value),
+            Repr::String(value) => match value.as_str() {
+                "NaN" => Ok(f64::NAN),
+                "inf" => Ok(f64::INFINITY),
+                "-inf" => Ok(f64::NEG_INFINITY),
+                other => Err(serde::de::Error::invalid_value(
+                    serde::de::Unexpected::Str(other),
+                    &"a number, \"NaN\", \"inf\", or \"-inf\"",
+                )),
+            },
+        }
+    }
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
         match self {
@@ -326,7 +470,7 @@ impl ValueOperator {
     }
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct AttributeView {
     pub name: String,
     pub value: String,
@@ -336,7 +480,7 @@ pub struct AttributeView {
     pub source: AttributeSourceView,
 }
 
-#[derive(Copy, Clone, Serialize)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum AttributeTypeView {
     F64,
@@ -348,7 +492,7 @@ pub enum AttributeTypeView {
     String,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case", tag = "source")]
 pub enum AttributeSourceView {
     Connection { connection_id: ConnectionIdView },
@@ -362,6 +506,62 @@ impl Span {
         self.closed_at
             .map(|closed_at| closed_at.get().saturating_sub(self.created_at.get()))
     }
+
+    // gets the time spent entered in microseconds, with a current entry being
+    // counted until the span closed or else until `end` if provided
+    pub fn busy(&self, end: Option<Timestamp>) -> Option<u64> {
+        let Some(entered_at) = self.entered_at else {
+            return Some(self.busy);
+        };
+
+        let end = self.closed_at.or(end)?;
+        Some(self.busy + end.get().saturating_sub(entered_at.get()))
+    }
+}
+
+/// This describes how a query is executed. Each node selects from an index or
+/// combines its children, and `scan` names the filter that must be checked on
+/// every element selected from the index when the index alone isn't enough.
+#[derive(Debug, Serialize)]
+pub struct QueryPlanView {
+    pub node: String,
+    pub estimate_count: usize,
+    pub indexed: bool,
+    pub scan: Option<String>,
+    pub children: Vec<QueryPlanView>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventsOrSpans {
+    Events,
+    Spans,
+}
+
+#[derive(Serialize)]
+pub struct BucketView {
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SavedFilterView {
+    pub name: String,
+    pub filter: String,
+}
+
+/// Durations are in microseconds and are `None` if no closed spans matched.
+#[derive(Debug, Serialize)]
+pub struct DurationStatsView {
+    pub count: usize,
+    pub still_open: usize,
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+    pub mean: Option<u64>,
+    pub p50: Option<u64>,
+    pub p90: Option<u64>,
+    pub p99: Option<u64>,
 }
 
 #[derive(Serialize)]
@@ -370,4 +570,31 @@ pub struct StatsView {
     pub end: Option<Timestamp>,
     pub total_spans: usize,
     pub total_events: usize,
+    pub events_per_level: [usize; 5], // indexed by `Level`
+    pub spans_per_level: [usize; 5],  // indexed by `Level`
+    pub connected_connections: usize,
+    pub disconnected_connections: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_finite_values_round_trip_through_json_values() {
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 1.5] {
+            let json = serde_json::to_value(Value::F64(value)).unwrap();
+
+            let Value::F64(parsed) = serde_json::from_value(json.clone()).unwrap() else {
+                panic!("expected an f64");
+            };
+            assert_eq!(parsed.to_bits(), value.to_bits());
+
+            let text = serde_json::to_string(&json).unwrap();
+            let Value::F64(parsed) = serde_json::from_reader(text.as_bytes()).unwrap() else {
+                panic!("expected an f64");
+            };
+            assert_eq!(parsed.to_bits(), value.to_bits());
+        }
+    }
 }
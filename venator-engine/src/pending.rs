@@ -0,0 +1,157 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot::Sender as OneshotSender;
+
+use crate::models::{ConnectionKey, EventKey, NewEvent, NewSpanEvent, SpanId, SpanKey};
+use crate::EngineInsertError;
+
+pub(crate) type SpanEventReply = Option<OneshotSender<Result<SpanKey, EngineInsertError>>>;
+pub(crate) type EventReply = Option<OneshotSender<Result<EventKey, EngineInsertError>>>;
+
+/// An insert that referenced a span that didn't exist yet, along with where to
+/// send its result once it is known.
+pub(crate) enum PendingInsert {
+    SpanEvent(NewSpanEvent, SpanEventReply),
+    Event(NewEvent, EventReply),
+}
+
+struct Held {
+    id: u64,
+    insert: PendingInsert,
+}
+
+/// The most inserts that are held at once. Past this, the oldest is given back
+/// early so that a client referencing spans that never arrive can't grow the
+/// held inserts without bound.
+pub(crate) const MAX_PENDING_INSERTS: usize = 10_000;
+
+/// This holds inserts that reference a span that hasn't been created, in case
+/// its create message arrives late. They are grouped by the span they wait on
+/// and are given back either when the span is created or once they have waited
+/// longer than the window.
+pub(crate) struct PendingInserts {
+    window: Duration,
+    held: HashMap<(ConnectionKey, SpanId), VecDeque<Held>>,
+    // every held insert in the order received, which may include ones that
+    // were already taken for their span
+    received: VecDeque<(u64, Instant, ConnectionKey, SpanId)>,
+    next_id: u64,
+    len: usize,
+}
+
+impl PendingInserts {
+    pub(crate) fn new(window: Duration) -> PendingInserts {
+        PendingInserts {
+            window,
+            held: HashMap::new(),
+            received: VecDeque::new(),
+            next_id: 0,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.window.is_zero()
+    }
+
+    /// This holds the insert and returns the oldest held insert if there were
+    /// already [`MAX_PENDING_INSERTS`].
+    pub(crate) fn hold(
+        &mut self,
+        connection_key: ConnectionKey,
+        span_id: SpanId,
+        insert: PendingInsert,
+    ) -> Option<PendingInsert> {
+        let evicted = if self.len >= MAX_PENDING_INSERTS {
+            self.pop_oldest(|_| true)
+        } else {
+            None
+        };
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.len += 1;
+
+        self.held
+            .entry((connection_key, span_id))
+            .or_default()
+            .push_back(Held { id, insert });
+        self.received
+            .push_back((id, Instant::now(), connection_key, span_id));
+
+        evicted
+    }
+
+    /// Returns the inserts that were waiting on the given span.
+    pub(crate) fn take(
+        &mut self,
+        connection_key: ConnectionKey,
+        span_id: SpanId,
+    ) -> Vec<PendingInsert> {
+        let Some(taken) = self.held.remove(&(connection_key, span_id)) else {
+            return Vec::new();
+        };
+
+        self.len -= taken.len();
+        taken.into_iter().map(|held| held.insert).collect()
+    }
+
+    /// Returns the inserts that have waited longer than the window.
+    pub(crate) fn take_expired(&mut self, now: Instant) -> Vec<PendingInsert> {
+        let window = self.window;
+
+        let mut expired = Vec::new();
+        while let Some(insert) = self.pop_oldest(|received| now.duration_since(received) >= window)
+        {
+            expired.push(insert);
+        }
+
+        expired
+    }
+
+    pub(crate) fn take_all(&mut self) -> Vec<PendingInsert> {
+        let mut all = Vec::with_capacity(self.len);
+        while let Some(insert) = self.pop_oldest(|_| true) {
+            all.push(insert);
+        }
+
+        all
+    }
+
+    // This removes the oldest held insert if it was received at a time that
+    // satisfies the predicate. Entries for inserts that were already taken are
+    // discarded along the way.
+    fn pop_oldest(&mut self, predicate: impl Fn(Instant) -> bool) -> Option<PendingInsert> {
+        while let Some(&(id, received, connection_key, span_id)) = self.received.front() {
+            let key = (connection_key, span_id);
+            let is_held = self
+                .held
+                .get(&key)
+                .and_then(VecDeque::front)
+                .is_some_and(|held| held.id == id);
+
+            if !is_held {
+                self.received.pop_front();
+                continue;
+            }
+
+            if !predicate(received) {
+                return None;
+            }
+
+            self.received.pop_front();
+            self.len -= 1;
+
+            let inserts = self.held.get_mut(&key).unwrap();
+            let held = inserts.pop_front().unwrap();
+            if inserts.is_empty() {
+                self.held.remove(&key);
+            }
+
+            return Some(held.insert);
+        }
+
+        None
+    }
+}
@@ -0,0 +1,511 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{self, BoxStream, StreamExt};
+use tokio::runtime::Handle;
+use tokio::task::spawn_blocking;
+
+use crate::models::{Connection, Event, Span, SpanEvent, Timestamp, Value};
+use crate::{CompactMetrics, SpanKey};
+
+use super::Storage;
+
+/// This is the asynchronous counterpart to [`Storage`] for backends that wait
+/// on the network or disk. The methods mirror those on [`Storage`] but return
+/// futures, and the *get all* methods return streams so that a large dataset
+/// can be loaded without holding all of it in memory at once.
+///
+/// The engine itself runs on a dedicated thread and reads storage while it
+/// searches, so it is given one of these through [`BlockOnStorage`]. A
+/// synchronous storage can be used where an `AsyncStorage` is expected through
+/// [`SpawnBlockingStorage`].
+pub trait AsyncStorage: Send + Sync {
+    fn get_connection(&self, at: Timestamp) -> BoxFuture<'_, Option<Arc<Connection>>>;
+    fn get_span(&self, at: Timestamp) -> BoxFuture<'_, Option<Arc<Span>>>;
+    fn get_span_event(&self, at: Timestamp) -> BoxFuture<'_, Option<Arc<SpanEvent>>>;
+    fn get_event(&self, at: Timestamp) -> BoxFuture<'_, Option<Arc<Event>>>;
+
+    fn get_all_connections(&self) -> BoxStream<'_, Arc<Connection>>;
+    fn get_all_spans(&self) -> BoxStream<'_, Arc<Span>>;
+    fn get_all_span_events(&self) -> BoxStream<'_, Arc<SpanEvent>>;
+    fn get_all_events(&self) -> BoxStream<'_, Arc<Event>>;
+
+    fn insert_connection(&mut self, connection: Connection) -> BoxFuture<'_, ()>;
+    fn insert_span(&mut self, span: Span) -> BoxFuture<'_, ()>;
+    fn insert_span_event(&mut self, span_event: SpanEvent) -> BoxFuture<'_, ()>;
+    fn insert_events(&mut self, events: Vec<Event>) -> BoxFuture<'_, ()>;
+
+    fn update_connection_disconnected(
+        &mut self,
+        at: Timestamp,
+        disconnected: Timestamp,
+    ) -> BoxFuture<'_, ()>;
+    fn update_span_closed(&mut self, at: Timestamp, closed: Timestamp) -> BoxFuture<'_, ()>;
+    /// See [`Storage::update_span_fields`].
+    fn update_span_fields(
+        &mut self,
+        at: Timestamp,
+        fields: BTreeMap<String, Value>,
+    ) -> BoxFuture<'_, ()>;
+    fn update_span_follows(&mut self, at: Timestamp, follows: SpanKey) -> BoxFuture<'_, ()>;
+    fn update_span_busy(
+        &mut self,
+        at: Timestamp,
+        busy: u64,
+        entered_at: Option<Timestamp>,
+    ) -> BoxFuture<'_, ()>;
+
+    fn drop_connections(&mut self, connections: Vec<Timestamp>) -> BoxFuture<'_, ()>;
+    fn drop_spans(&mut self, spans: Vec<Timestamp>) -> BoxFuture<'_, ()>;
+    fn drop_span_events(&mut self, span_events: Vec<Timestamp>) -> BoxFuture<'_, ()>;
+    fn drop_events(&mut self, events: Vec<Timestamp>) -> BoxFuture<'_, ()>;
+
+    /// Removes everything from storage.
+    fn clear(&mut self) -> BoxFuture<'_, ()>;
+
+    /// See [`Storage::is_read_only`]. This is a property of the storage
+    /// rather than something to wait on, so it isn't async.
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
+    /// See [`Storage::flush`].
+    fn flush(&mut self) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+
+    /// See [`Storage::compact`].
+    fn compact(&mut self) -> BoxFuture<'_, Option<CompactMetrics>> {
+        Box::pin(async { None })
+    }
+
+    /// See [`Storage::load_indexes`].
+    fn load_indexes(&self) -> BoxFuture<'_, Option<Vec<u8>>> {
+        Box::pin(async { None })
+    }
+
+    /// See [`Storage::save_indexes`]. Unlike there, the indexes are always
+    /// serialized up front since they can't be borrowed across an await.
+    fn save_indexes(&mut self, _indexes: Vec<u8>) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+
+    /// See [`Storage::load_saved_filters`].
+    fn load_saved_filters(&self) -> BoxFuture<'_, Vec<(String, String)>> {
+        Box::pin(async { Vec::new() })
+    }
+
+    /// See [`Storage::save_filter`].
+    fn save_filter(&mut self, _name: String, _filter: String) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+
+    /// See [`Storage::delete_saved_filter`].
+    fn delete_saved_filter(&mut self, _name: String) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+}
+
+/// This wraps a synchronous storage so it can be used as an [`AsyncStorage`].
+/// Each call is run on tokio's blocking thread pool so that disk I/O (like
+/// from a [`FileStorage`](crate::FileStorage)) doesn't stall the runtime.
+///
+/// Reads can run concurrently with each other but not with writes. The calls
+/// must be made from within a tokio runtime.
+pub struct SpawnBlockingStorage<S> {
+    inner: Arc<RwLock<S>>,
+}
+
+impl<S> SpawnBlockingStorage<S> {
+    pub fn new(storage: S) -> SpawnBlockingStorage<S> {
+        SpawnBlockingStorage {
+            inner: Arc::new(RwLock::new(storage)),
+        }
+    }
+
+    /// This returns the wrapped storage if no calls are still running.
+    pub fn into_inner(self) -> Option<S> {
+        Arc::into_inner(self.inner).map(|inner| inner.into_inner().unwrap())
+    }
+
+    fn read<T, F>(&self, f: F) -> BoxFuture<'_, T>
+    where
+        S: Sync + Send + 'static,
+        T: Send + 'static,
+        F: FnOnce(&S) -> T + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let task = spawn_blocking(move || f(&inner.read().unwrap()));
+            task.await.unwrap()
+        })
+    }
+
+    fn write<T, F>(&self, f: F) -> BoxFuture<'_, T>
+    where
+        S: Sync + Send + 'static,
+        T: Send + 'static,
+        F: FnOnce(&mut S) -> T + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let task = spawn_blocking(move || f(&mut inner.write().unwrap()));
+            task.await.unwrap()
+        })
+    }
+}
+
+impl<S> AsyncStorage for SpawnBlockingStorage<S>
+where
+    S: Storage + Send + Sync + 'static,
+{
+    fn get_connection(&self, at: Timestamp) -> BoxFuture<'_, Option<Arc<Connection>>> {
+        self.read(move |s| s.get_connection(at))
+    }
+
+    fn get_span(&self, at: Timestamp) -> BoxFuture<'_, Option<Arc<Span>>> {
+        self.read(move |s| s.get_span(at))
+    }
+
+    fn get_span_event(&self, at: Timestamp) -> BoxFuture<'_, Option<Arc<SpanEvent>>> {
+        self.read(move |s| s.get_span_event(at))
+    }
+
+    fn get_event(&self, at: Timestamp) -> BoxFuture<'_, Option<Arc<Event>>> {
+        self.read(move |s| s.get_event(at))
+    }
+
+    fn get_all_connections(&self) -> BoxStream<'_, Arc<Connection>> {
+        let all = self.read(|s| s.get_all_connections().collect::<Vec<_>>());
+        all.map(stream::iter).flatten_stream().boxed()
+    }
+
+    fn get_all_spans(&self) -> BoxStream<'_, Arc<Span>> {
+        let all = self.read(|s| s.get_all_spans().collect::<Vec<_>>());
+        all.map(stream::iter).flatten_stream().boxed()
+    }
+
+    fn get_all_span_events(&self) -> BoxStream<'_, Arc<SpanEvent>> {
+        let all = self.read(|s| s.get_all_span_events().collect::<Vec<_>>());
+        all.map(stream::iter).flatten_stream().boxed()
+    }
+
+    fn get_all_events(&self) -> BoxStream<'_, Arc<Event>> {
+        let all = self.read(|s| s.get_all_events().collect::<Vec<_>>());
+        all.map(stream::iter).flatten_stream().boxed()
+    }
+
+    fn insert_connection(&mut self, connection: Connection) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.insert_connection(connection))
+    }
+
+    fn insert_span(&mut self, span: Span) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.insert_span(span))
+    }
+
+    fn insert_span_event(&mut self, span_event: SpanEvent) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.insert_span_event(span_event))
+    }
+
+    fn insert_events(&mut self, events: Vec<Event>) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.insert_events(events))
+    }
+
+    fn update_connection_disconnected(
+        &mut self,
+        at: Timestamp,
+        disconnected: Timestamp,
+    ) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.update_connection_disconnected(at, disconnected))
+    }
+
+    fn update_span_closed(&mut self, at: Timestamp, closed: Timestamp) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.update_span_closed(at, closed))
+    }
+
+    fn update_span_fields(
+        &mut self,
+        at: Timestamp,
+        fields: BTreeMap<String, Value>,
+    ) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.update_span_fields(at, fields))
+    }
+
+    fn update_span_follows(&mut self, at: Timestamp, follows: SpanKey) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.update_span_follows(at, follows))
+    }
+
+    fn update_span_busy(
+        &mut self,
+        at: Timestamp,
+        busy: u64,
+        entered_at: Option<Timestamp>,
+    ) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.update_span_busy(at, busy, entered_at))
+    }
+
+    fn drop_connections(&mut self, connections: Vec<Timestamp>) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.drop_connections(&connections))
+    }
+
+    fn drop_spans(&mut self, spans: Vec<Timestamp>) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.drop_spans(&spans))
+    }
+
+    fn drop_span_events(&mut self, span_events: Vec<Timestamp>) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.drop_span_events(&span_events))
+    }
+
+    fn drop_events(&mut self, events: Vec<Timestamp>) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.drop_events(&events))
+    }
+
+    fn clear(&mut self) -> BoxFuture<'_, ()> {
+        self.write(|s| s.clear())
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.inner.read().unwrap().is_read_only()
+    }
+
+    fn flush(&mut self) -> BoxFuture<'_, ()> {
+        self.write(|s| s.flush())
+    }
+
+    fn compact(&mut self) -> BoxFuture<'_, Option<CompactMetrics>> {
+        self.write(|s| s.compact())
+    }
+
+    fn load_indexes(&self) -> BoxFuture<'_, Option<Vec<u8>>> {
+        self.read(|s| s.load_indexes())
+    }
+
+    fn save_indexes(&mut self, indexes: Vec<u8>) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.save_indexes(&|| indexes.clone()))
+    }
+
+    fn load_saved_filters(&self) -> BoxFuture<'_, Vec<(String, String)>> {
+        self.read(|s| s.load_saved_filters())
+    }
+
+    fn save_filter(&mut self, name: String, filter: String) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.save_filter(&name, &filter))
+    }
+
+    fn delete_saved_filter(&mut self, name: String) -> BoxFuture<'_, ()> {
+        self.write(move |s| s.delete_saved_filter(&name))
+    }
+}
+
+/// This wraps an [`AsyncStorage`] so it can be given to the
+/// [`Engine`](crate::Engine). Each call blocks the engine's thread on the
+/// given runtime until the future completes, which is fine since the engine
+/// never runs on the runtime itself. Calling it from within the runtime will
+/// panic. Backends that do I/O on the runtime (like a database connection)
+/// need it to be driven by its own threads, as a multi-threaded runtime is.
+pub struct BlockOnStorage<A> {
+    inner: A,
+    handle: Handle,
+}
+
+impl<A> BlockOnStorage<A> {
+    /// The handle is the runtime that the storage's futures are driven on; for
+    /// example [`Handle::current()`] from the task setting up the engine.
+    pub fn new(storage: A, handle: Handle) -> BlockOnStorage<A> {
+        BlockOnStorage {
+            inner: storage,
+            handle,
+        }
+    }
+
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+}
+
+impl<A> Storage for BlockOnStorage<A>
+where
+    A: AsyncStorage,
+{
+    fn get_connection(&self, at: Timestamp) -> Option<Arc<Connection>> {
+        self.handle.block_on(self.inner.get_connection(at))
+    }
+
+    fn get_span(&self, at: Timestamp) -> Option<Arc<Span>> {
+        self.handle.block_on(self.inner.get_span(at))
+    }
+
+    fn get_span_event(&self, at: Timestamp) -> Option<Arc<SpanEvent>> {
+        self.handle.block_on(self.inner.get_span_event(at))
+    }
+
+    fn get_event(&self, at: Timestamp) -> Option<Arc<Event>> {
+        self.handle.block_on(self.inner.get_event(at))
+    }
+
+    fn get_all_connections(&self) -> Box<dyn Iterator<Item = Arc<Connection>> + '_> {
+        let mut all = self.inner.get_all_connections();
+        Box::new(std::iter::from_fn(move || self.handle.block_on(all.next())))
+    }
+
+    fn get_all_spans(&self) -> Box<dyn Iterator<Item = Arc<Span>> + '_> {
+        let mut all = self.inner.get_all_spans();
+        Box::new(std::iter::from_fn(move || self.handle.block_on(all.next())))
+    }
+
+    fn get_all_span_events(&self) -> Box<dyn Iterator<Item = Arc<SpanEvent>> + '_> {
+        let mut all = self.inner.get_all_span_events();
+        Box::new(std::iter::from_fn(move || self.handle.block_on(all.next())))
+    }
+
+    fn get_all_events(&self) -> Box<dyn Iterator<Item = Arc<Event>> + '_> {
+        let mut all = self.inner.get_all_events();
+        Box::new(std::iter::from_fn(move || self.handle.block_on(all.next())))
+    }
+
+    fn insert_connection(&mut self, connection: Connection) {
+        self.handle
+            .block_on(self.inner.insert_connection(connection))
+    }
+
+    fn insert_span(&mut self, span: Span) {
+        self.handle.block_on(self.inner.insert_span(span))
+    }
+
+    fn insert_span_event(&mut self, span_event: SpanEvent) {
+        self.handle
+            .block_on(self.inner.insert_span_event(span_event))
+    }
+
+    fn insert_event(&mut self, event: Event) {
+        self.handle.block_on(self.inner.insert_events(vec![event]))
+    }
+
+    fn insert_events(&mut self, events: Vec<Event>) {
+        self.handle.block_on(self.inner.insert_events(events))
+    }
+
+    fn update_connection_disconnected(&mut self, at: Timestamp, disconnected: Timestamp) {
+        self.handle
+            .block_on(self.inner.update_connection_disconnected(at, disconnected))
+    }
+
+    fn update_span_closed(&mut self, at: Timestamp, closed: Timestamp) {
+        self.handle
+            .block_on(self.inner.update_span_closed(at, closed))
+    }
+
+    fn update_span_fields(&mut self, at: Timestamp, fields: BTreeMap<String, Value>) {
+        self.handle
+            .block_on(self.inner.update_span_fields(at, fields))
+    }
+
+    fn update_span_follows(&mut self, at: Timestamp, follows: SpanKey) {
+        self.handle
+            .block_on(self.inner.update_span_follows(at, follows))
+    }
+
+    fn update_span_busy(&mut self, at: Timestamp, busy: u64, entered_at: Option<Timestamp>) {
+        self.handle
+            .block_on(self.inner.update_span_busy(at, busy, entered_at))
+    }
+
+    fn drop_connections(&mut self, connections: &[Timestamp]) {
+        self.handle
+            .block_on(self.inner.drop_connections(connections.to_vec()))
+    }
+
+    fn drop_spans(&mut self, spans: &[Timestamp]) {
+        self.handle.block_on(self.inner.drop_spans(spans.to_vec()))
+    }
+
+    fn drop_span_events(&mut self, span_events: &[Timestamp]) {
+        self.handle
+            .block_on(self.inner.drop_span_events(span_events.to_vec()))
+    }
+
+    fn drop_events(&mut self, events: &[Timestamp]) {
+        self.handle
+            .block_on(self.inner.drop_events(events.to_vec()))
+    }
+
+    fn clear(&mut self) {
+        self.handle.block_on(self.inner.clear())
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.inner.is_read_only()
+    }
+
+    fn flush(&mut self) {
+        self.handle.block_on(self.inner.flush())
+    }
+
+    fn compact(&mut self) -> Option<CompactMetrics> {
+        self.handle.block_on(self.inner.compact())
+    }
+
+    fn load_indexes(&self) -> Option<Vec<u8>> {
+        self.handle.block_on(self.inner.load_indexes())
+    }
+
+    fn save_indexes(&mut self, serialize: &dyn Fn() -> Vec<u8>) {
+        self.handle.block_on(self.inner.save_indexes(serialize()))
+    }
+
+    fn load_saved_filters(&self) -> Vec<(String, String)> {
+        self.handle.block_on(self.inner.load_saved_filters())
+    }
+
+    fn save_filter(&mut self, name: &str, filter: &str) {
+        self.handle
+            .block_on(self.inner.save_filter(name.to_owned(), filter.to_owned()))
+    }
+
+    fn delete_saved_filter(&mut self, name: &str) {
+        self.handle
+            .block_on(self.inner.delete_saved_filter(name.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Builder;
+
+    use super::*;
+    use crate::{ConnectionKey, TransientStorage};
+
+    fn make_event(key: u64) -> Event {
+        Event {
+            connection_key: ConnectionKey::new(1).unwrap(),
+            timestamp: Timestamp::new(key).unwrap(),
+            client_timestamp: None,
+            span_key: None,
+            target: "crate::storage::asynchronous::tests".to_owned(),
+            name: "event".to_owned(),
+            level: crate::models::Level::Info,
+            file_name: None,
+            file_line: None,
+            fields: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn sync_storage_round_trips_through_async() {
+        let runtime = Builder::new_current_thread().build().unwrap();
+
+        let storage = SpawnBlockingStorage::new(TransientStorage::new());
+        let mut storage = BlockOnStorage::new(storage, runtime.handle().clone());
+
+        storage.insert_events(vec![make_event(1), make_event(2), make_event(3)]);
+        storage.drop_events(&[Timestamp::new(2).unwrap()]);
+
+        assert!(storage.get_event(Timestamp::new(1).unwrap()).is_some());
+        assert!(storage.get_event(Timestamp::new(2).unwrap()).is_none());
+        assert_eq!(storage.get_all_events().count(), 2);
+
+        let storage = storage.into_inner().into_inner().unwrap();
+        assert_eq!(storage.get_all_events().count(), 2);
+    }
+}
@@ -1,20 +1,24 @@
+#[cfg(not(feature = "parallel"))]
 use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::num::NonZeroUsize;
+use std::ops::DerefMut;
 use std::sync::Arc;
+#[cfg(feature = "parallel")]
+use std::sync::Mutex;
 
 use lru::LruCache;
 
-use crate::{Connection, Event, Span, SpanEvent, SpanKey, Timestamp, Value};
+use crate::{CompactMetrics, Connection, Event, Span, SpanEvent, SpanKey, Timestamp, Value};
 
 use super::Storage;
 
 /// This storage wraps another storage implementation to keep some in memory.
 pub struct CachedStorage<S> {
-    connections: RefCell<LruCache<Timestamp, Arc<Connection>>>,
-    spans: RefCell<LruCache<Timestamp, Arc<Span>>>,
-    // span_events: RefCell<LruCache<Timestamp, Arc<SpanEvent>>>,
-    events: RefCell<LruCache<Timestamp, Arc<Event>>>,
+    connections: Cache<Connection>,
+    spans: Cache<Span>,
+    // span_events: Cache<SpanEvent>,
+    events: Cache<Event>,
     inner: S,
 }
 
@@ -23,9 +27,9 @@ impl<S> CachedStorage<S> {
         let capacity = NonZeroUsize::new(capacity).unwrap();
 
         CachedStorage {
-            connections: RefCell::new(LruCache::new(capacity)),
-            spans: RefCell::new(LruCache::new(capacity)),
-            events: RefCell::new(LruCache::new(capacity)),
+            connections: Cache::new(capacity),
+            spans: Cache::new(capacity),
+            events: Cache::new(capacity),
             inner: storage,
         }
     }
@@ -110,6 +114,10 @@ where
         self.inner.insert_event(event)
     }
 
+    fn insert_events(&mut self, events: Vec<Event>) {
+        self.inner.insert_events(events)
+    }
+
     fn update_connection_disconnected(&mut self, at: Timestamp, disconnected: Timestamp) {
         self.connections.borrow_mut().pop(&at);
         self.inner.update_connection_disconnected(at, disconnected);
@@ -130,6 +138,11 @@ where
         self.inner.update_span_follows(at, follows);
     }
 
+    fn update_span_busy(&mut self, at: Timestamp, busy: u64, entered_at: Option<Timestamp>) {
+        self.spans.borrow_mut().pop(&at);
+        self.inner.update_span_busy(at, busy, entered_at);
+    }
+
     fn drop_connections(&mut self, connections: &[Timestamp]) {
         for c in connections {
             self.connections.borrow_mut().pop(c);
@@ -157,4 +170,74 @@ where
 
         self.inner.drop_events(events);
     }
+
+    fn clear(&mut self) {
+        self.connections.borrow_mut().clear();
+        self.spans.borrow_mut().clear();
+        self.events.borrow_mut().clear();
+        self.inner.clear();
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.inner.is_read_only()
+    }
+
+    fn flush(&mut self) {
+        self.inner.flush();
+    }
+
+    fn compact(&mut self) -> Option<CompactMetrics> {
+        self.inner.compact()
+    }
+
+    fn load_indexes(&self) -> Option<Vec<u8>> {
+        self.inner.load_indexes()
+    }
+
+    fn save_indexes(&mut self, serialize: &dyn Fn() -> Vec<u8>) {
+        self.inner.save_indexes(serialize);
+    }
+
+    fn load_saved_filters(&self) -> Vec<(String, String)> {
+        self.inner.load_saved_filters()
+    }
+
+    fn save_filter(&mut self, name: &str, filter: &str) {
+        self.inner.save_filter(name, filter);
+    }
+
+    fn delete_saved_filter(&mut self, name: &str) {
+        self.inner.delete_saved_filter(name);
+    }
+}
+
+// The caches are updated when reading, so they need interior mutability. They
+// are only read from multiple threads with the `parallel` feature, so that is
+// the only time they need a mutex.
+#[cfg(feature = "parallel")]
+struct Cache<T>(Mutex<LruCache<Timestamp, Arc<T>>>);
+
+#[cfg(not(feature = "parallel"))]
+struct Cache<T>(RefCell<LruCache<Timestamp, Arc<T>>>);
+
+impl<T> Cache<T> {
+    #[cfg(feature = "parallel")]
+    fn new(capacity: NonZeroUsize) -> Cache<T> {
+        Cache(Mutex::new(LruCache::new(capacity)))
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn new(capacity: NonZeroUsize) -> Cache<T> {
+        Cache(RefCell::new(LruCache::new(capacity)))
+    }
+
+    #[cfg(feature = "parallel")]
+    fn borrow_mut(&self) -> impl DerefMut<Target = LruCache<Timestamp, Arc<T>>> + '_ {
+        self.0.lock().unwrap()
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn borrow_mut(&self) -> impl DerefMut<Target = LruCache<Timestamp, Arc<T>>> + '_ {
+        self.0.borrow_mut()
+    }
 }
@@ -1,40 +1,238 @@
 use std::collections::BTreeMap;
-use std::path::Path;
-use std::sync::Arc;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{
+    Connection as DbConnection, Error as DbError, OpenFlags, OptionalExtension, Params, Row,
+};
+
+use crate::models::{Level, Value};
+use crate::{
+    CompactMetrics, Connection, Event, Span, SpanEvent, SpanEventKind, SpanId, SpanKey, Timestamp,
+};
 
-use rusqlite::{Connection as DbConnection, Error as DbError, Params, Row};
+use super::Storage;
 
-use crate::models::Value;
-use crate::{Connection, Event, Span, SpanEvent, SpanEventKind, SpanId, SpanKey, Timestamp};
+/// How much of the written data is synced to disk. Writes always go through
+/// the write-ahead log, so the process crashing does not lose data in any mode;
+/// this only matters if the OS crashes or the machine loses power.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Durability {
+    /// Nothing is synced until the storage is flushed, so the database can be
+    /// corrupted by a power loss. This is the fastest.
+    #[default]
+    Off,
+    /// The log is synced when it is checkpointed, so the database stays
+    /// consistent but the latest transactions can be lost.
+    Normal,
+    /// Every transaction is synced before it completes.
+    Full,
+}
 
-use super::Storage;
+impl Durability {
+    fn synchronous(self) -> &'static str {
+        match self {
+            Durability::Off => "OFF",
+            Durability::Normal => "NORMAL",
+            Durability::Full => "FULL",
+        }
+    }
+}
+
+impl FromStr for Durability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Durability::Off),
+            "normal" => Ok(Durability::Normal),
+            "full" => Ok(Durability::Full),
+            _ => Err(format!(
+                "unknown durability {s:?}, expected \"off\", \"normal\", or \"full\""
+            )),
+        }
+    }
+}
 
+#[derive(Debug, Default, Clone)]
+pub struct FileStorageOptions {
+    pub durability: Durability,
+    /// Whether the `fields` of connections, spans, and events are compressed
+    /// with zstd before being written. This makes attribute-heavy datasets
+    /// smaller (about a quarter for typical request attributes) at the cost of
+    /// some CPU on every insert and lookup. Fields are read the same either
+    /// way, so this can be changed for an existing file; only new writes are
+    /// affected.
+    pub compress_fields: bool,
+}
+
+/// Inserts and updates go through a single writer connection while reads are
+/// served from a pool of read-only connections, so lookups from other threads
+/// do not wait on each other or on the writer. An in-memory database cannot be
+/// shared between connections, so it serves reads from the writer instead.
+///
+/// Saved indexes are kept in the `indexes` table and are removed by the first
+/// write after saving them, so they are never loaded for different data.
+///
+/// A file opened with [`open_read_only`](FileStorage::open_read_only) has no
+/// writer at all and panics if asked to modify the dataset.
 pub struct FileStorage {
-    connection: DbConnection,
+    connection: Mutex<DbConnection>,
+    path: Option<PathBuf>,
+    readers: Mutex<Vec<DbConnection>>,
+    has_saved_indexes: bool,
+    read_only: bool,
+    options: FileStorageOptions,
 }
 
 impl FileStorage {
+    /// This opens the file if it already contains a dataset, or initializes a
+    /// new one otherwise. It panics if the file cannot be used.
     pub fn new(path: &Path) -> FileStorage {
+        FileStorage::new_with_options(path, FileStorageOptions::default())
+    }
+
+    /// This is the same as [`new`](FileStorage::new) but with the options.
+    pub fn new_with_options(path: &Path, options: FileStorageOptions) -> FileStorage {
         let connection = DbConnection::open(path).unwrap();
 
-        connection
-            .execute_batch(r#"PRAGMA synchronous = OFF; PRAGMA journal_mode = OFF;"#)
-            .unwrap();
+        let result = if has_any_table(&connection).unwrap() {
+            FileStorage::open_connection(connection, path, options)
+        } else {
+            FileStorage::create_connection(connection, path, options)
+        };
+
+        match result {
+            Ok(storage) => storage,
+            Err(err) => panic!("failed to load {}: {err}", path.display()),
+        }
+    }
+
+    /// This opens a file with an existing dataset. The tables are validated
+    /// to have the expected columns.
+    pub fn open(path: &Path) -> Result<FileStorage, FileStorageError> {
+        FileStorage::open_with_options(path, FileStorageOptions::default())
+    }
+
+    /// This is the same as [`open`](FileStorage::open) but with the options.
+    pub fn open_with_options(
+        path: &Path,
+        options: FileStorageOptions,
+    ) -> Result<FileStorage, FileStorageError> {
+        let connection = DbConnection::open(path)?;
+
+        FileStorage::open_connection(connection, path, options)
+    }
+
+    /// This opens a file with an existing dataset for reading only, without
+    /// taking the write lock, so it can be inspected while another process is
+    /// writing to it. The dataset is read as it was when opened; the engine
+    /// doesn't see what is written afterwards.
+    ///
+    /// Since it can't be migrated, this fails if the file was written by an
+    /// older version and hasn't been opened for writing since.
+    pub fn open_read_only(path: &Path) -> Result<FileStorage, FileStorageError> {
+        let connection = open_reader(path)?;
+        connection.execute("PRAGMA query_only = ON", ())?;
+
+        let version = schema_version(&connection)?;
+        if version > SCHEMA_VERSION {
+            return Err(FileStorageError::UnsupportedVersion(version));
+        }
+        if version < SCHEMA_VERSION {
+            return Err(FileStorageError::OutdatedVersion(version));
+        }
+
+        check_columns(&connection)?;
+
+        let has_saved_indexes = has_saved_indexes(&connection)?;
+
+        let mut storage = FileStorage::with_writer(
+            connection,
+            path,
+            has_saved_indexes,
+            FileStorageOptions::default(),
+        );
+        storage.read_only = true;
+
+        Ok(storage)
+    }
+
+    /// This initializes a new dataset in the file. It fails if the file
+    /// already has any of the tables.
+    pub fn create(path: &Path) -> Result<FileStorage, FileStorageError> {
+        FileStorage::create_with_options(path, FileStorageOptions::default())
+    }
+
+    /// This is the same as [`create`](FileStorage::create) but with the
+    /// options.
+    pub fn create_with_options(
+        path: &Path,
+        options: FileStorageOptions,
+    ) -> Result<FileStorage, FileStorageError> {
+        let connection = DbConnection::open(path)?;
+
+        FileStorage::create_connection(connection, path, options)
+    }
+
+    fn open_connection(
+        mut connection: DbConnection,
+        path: &Path,
+        options: FileStorageOptions,
+    ) -> Result<FileStorage, FileStorageError> {
+        set_pragmas(&connection, options.durability)?;
 
-        let _ = connection.execute(
+        let version = schema_version(&connection)?;
+        if version > SCHEMA_VERSION {
+            return Err(FileStorageError::UnsupportedVersion(version));
+        }
+
+        // check this before migrating so unrelated files are left untouched
+        for (table, _) in TABLES {
+            if table_columns(&connection, table)?.is_empty() {
+                return Err(FileStorageError::MissingTable(table));
+            }
+        }
+
+        migrate(&mut connection, version)?;
+        check_columns(&connection)?;
+
+        let has_saved_indexes = has_saved_indexes(&connection)?;
+
+        Ok(FileStorage::with_writer(
+            connection,
+            path,
+            has_saved_indexes,
+            options,
+        ))
+    }
+
+    fn create_connection(
+        connection: DbConnection,
+        path: &Path,
+        options: FileStorageOptions,
+    ) -> Result<FileStorage, FileStorageError> {
+        set_pragmas(&connection, options.durability)?;
+
+        connection.execute(
             r#"
             CREATE TABLE connections (
                 key             INT8 NOT NULL,
                 id              INT8,
                 disconnected_at INT8,
                 fields          TEXT,
+                schema          INT4 NOT NULL DEFAULT 1,
 
                 CONSTRAINT connections_pk PRIMARY KEY (key)
             );"#,
             (),
-        );
+        )?;
 
-        let _ = connection.execute(
+        connection.execute(
             r#"
             CREATE TABLE spans (
                 key        INT8 NOT NULL,
@@ -49,13 +247,16 @@ impl FileStorage {
                 file_name  TEXT,
                 file_line  INTEGER,
                 fields     TEXT,
+                busy       INT8 NOT NULL DEFAULT 0,
+                entered_at INT8,
+                client_created_at INT8,
 
                 CONSTRAINT spans_pk PRIMARY KEY (key)
             );"#,
             (),
-        );
+        )?;
 
-        let _ = connection.execute(
+        connection.execute(
             r#"
             CREATE TABLE span_events (
                 key        INT8 NOT NULL,
@@ -67,9 +268,9 @@ impl FileStorage {
                 CONSTRAINT span_events_pk PRIMARY KEY (key)
             );"#,
             (),
-        );
+        )?;
 
-        let _ = connection.execute(
+        connection.execute(
             r#"
             CREATE TABLE events (
                 key        INT8 NOT NULL,
@@ -81,144 +282,514 @@ impl FileStorage {
                 file_name  TEXT,
                 file_line  INTEGER,
                 fields     TEXT,
+                client_timestamp INT8,
 
                 CONSTRAINT events_pk PRIMARY KEY (key)
             );"#,
             (),
-        );
+        )?;
+
+        connection.execute(
+            r#"
+            CREATE TABLE meta (
+                key   TEXT NOT NULL,
+                value INT8,
+
+                CONSTRAINT meta_pk PRIMARY KEY (key)
+            );"#,
+            (),
+        )?;
+
+        connection.execute(INDEXES_TABLE, ())?;
+        connection.execute(SAVED_FILTERS_TABLE, ())?;
+
+        set_schema_version(&connection, SCHEMA_VERSION)?;
 
-        FileStorage { connection }
+        Ok(FileStorage::with_writer(connection, path, false, options))
+    }
+
+    fn with_writer(
+        connection: DbConnection,
+        path: &Path,
+        has_saved_indexes: bool,
+        options: FileStorageOptions,
+    ) -> FileStorage {
+        let in_memory = path.as_os_str().is_empty() || path == Path::new(":memory:");
+
+        FileStorage {
+            connection: Mutex::new(connection),
+            path: (!in_memory).then(|| path.to_owned()),
+            readers: Mutex::new(Vec::new()),
+            has_saved_indexes,
+            read_only: false,
+            options,
+        }
+    }
+
+    /// This returns the connection for modifying the dataset. Any saved indexes
+    /// are removed first since they would no longer match.
+    fn writer(&mut self) -> &mut DbConnection {
+        assert!(!self.read_only, "the dataset was opened read-only");
+
+        let connection = self.connection.get_mut().unwrap();
+
+        if self.has_saved_indexes {
+            connection.execute("DELETE FROM indexes", ()).unwrap();
+            self.has_saved_indexes = false;
+        }
+
+        connection
+    }
+
+    /// This runs the query on an idle reader connection, opening a new one if
+    /// all of them are in use. Errors are logged and yield `None` since the
+    /// `Storage` getters have no way to report them.
+    fn read<T>(&self, query: impl FnOnce(&DbConnection) -> Result<T, DbError>) -> Option<T> {
+        let Some(path) = &self.path else {
+            let result = query(&self.connection.lock().unwrap());
+            return result
+                .inspect_err(|err| eprintln!("failed to read from file storage: {err}"))
+                .ok();
+        };
+
+        let reader = self.readers.lock().unwrap().pop();
+        let reader = match reader {
+            Some(reader) => reader,
+            None => match open_reader(path) {
+                Ok(reader) => reader,
+                Err(err) => {
+                    eprintln!("failed to open file storage reader: {err}");
+                    return None;
+                }
+            },
+        };
+
+        let result = query(&reader);
+        self.readers.lock().unwrap().push(reader);
+        result
+            .inspect_err(|err| eprintln!("failed to read from file storage: {err}"))
+            .ok()
     }
 }
 
-impl Storage for FileStorage {
-    fn get_connection(&self, at: Timestamp) -> Option<Arc<Connection>> {
-        let mut stmt = self
-            .connection
-            .prepare_cached("SELECT * FROM connections WHERE key = ?1")
-            .unwrap();
+/// The version of the table layout and encoding that this build writes. This
+/// must be bumped with a new step in [`migrate`] whenever either changes.
+///
+/// - 1: the original layout, files from before the `meta` table existed
+/// - 2: added the `schema` column to `connections` and the `meta` table
+/// - 3: added the `busy` and `entered_at` columns to `spans`
+/// - 4: added the `indexes` table
+/// - 5: `fields` columns may hold compressed blobs (see [`encode_fields`])
+/// - 6: added the `client_created_at` column to `spans` and the
+///   `client_timestamp` column to `events`
+/// - 7: added the `saved_filters` table
+const SCHEMA_VERSION: u32 = 7;
+
+/// This holds at most one row with the engine's serialized indexes and the row
+/// counts of the other tables when they were saved.
+const INDEXES_TABLE: &str = r#"
+    CREATE TABLE indexes (
+        connections INT8 NOT NULL,
+        spans       INT8 NOT NULL,
+        span_events INT8 NOT NULL,
+        events      INT8 NOT NULL,
+        data        BLOB NOT NULL
+    );"#;
+
+/// This holds the named filters, which are kept apart from the dataset.
+const SAVED_FILTERS_TABLE: &str = r#"
+    CREATE TABLE saved_filters (
+        name   TEXT NOT NULL,
+        filter TEXT NOT NULL,
+
+        CONSTRAINT saved_filters_pk PRIMARY KEY (name)
+    );"#;
+
+/// This upgrades the file from the given version to [`SCHEMA_VERSION`] one step
+/// at a time. All steps are done in a single transaction so a failure does not
+/// leave the file partially migrated.
+fn migrate(connection: &mut DbConnection, from: u32) -> Result<(), DbError> {
+    if from == SCHEMA_VERSION {
+        return Ok(());
+    }
 
-        let result = stmt.query_row((at,), connection_from_row);
+    let tx = connection.transaction()?;
+
+    if from < 2 {
+        // files created before the `meta` table may or may not have the
+        // schema column already
+        let columns = table_columns(&tx, "connections")?;
+        if !columns.is_empty() && !columns.iter().any(|c| c == "schema") {
+            tx.execute(
+                r#"ALTER TABLE connections ADD COLUMN schema INT4 NOT NULL DEFAULT 1;"#,
+                (),
+            )?;
+        }
 
-        Some(Arc::new(result.unwrap()))
+        tx.execute(
+            r#"
+            CREATE TABLE meta (
+                key   TEXT NOT NULL,
+                value INT8,
+
+                CONSTRAINT meta_pk PRIMARY KEY (key)
+            );"#,
+            (),
+        )?;
     }
 
-    fn get_span(&self, at: Timestamp) -> Option<Arc<Span>> {
-        let mut stmt = self
-            .connection
-            .prepare_cached("SELECT * FROM spans WHERE key = ?1")
-            .unwrap();
+    if from < 3 {
+        tx.execute(
+            r#"ALTER TABLE spans ADD COLUMN busy INT8 NOT NULL DEFAULT 0;"#,
+            (),
+        )?;
+        tx.execute(r#"ALTER TABLE spans ADD COLUMN entered_at INT8;"#, ())?;
+
+        // recover the busy time of existing spans from their enter/exit events
+        let mut busy = BTreeMap::<Timestamp, (u64, Option<Timestamp>)>::new();
+        let mut stmt = tx.prepare(
+            "SELECT key, span_id, kind FROM span_events WHERE kind IN ('enter', 'exit') ORDER BY key",
+        )?;
+        let mut rows = stmt.query(())?;
+        while let Some(row) = rows.next()? {
+            let at: Timestamp = row.get(0)?;
+            let span_key: Timestamp = row.get(1)?;
+            let kind: String = row.get(2)?;
+
+            let (busy, entered_at) = busy.entry(span_key).or_default();
+            match (kind.as_str(), *entered_at) {
+                ("enter", None) => *entered_at = Some(at),
+                ("exit", Some(entered)) => {
+                    *busy += at.get().saturating_sub(entered.get());
+                    *entered_at = None;
+                }
+                _ => {}
+            }
+        }
+        drop(rows);
+        drop(stmt);
 
-        let result = stmt.query_row((at,), span_from_row);
+        let mut stmt = tx.prepare("UPDATE spans SET busy = ?2, entered_at = ?3 WHERE key = ?1")?;
+        for (span_key, (busy, entered_at)) in busy {
+            stmt.execute((span_key, busy as i64, entered_at))?;
+        }
+    }
 
-        Some(Arc::new(result.unwrap()))
+    if from < 4 {
+        tx.execute(INDEXES_TABLE, ())?;
     }
 
-    fn get_span_event(&self, at: Timestamp) -> Option<Arc<SpanEvent>> {
-        let mut stmt = self
-            .connection
-            .prepare_cached("SELECT * FROM span_events WHERE key = ?1")
-            .unwrap();
+    // version 5 only allows a new encoding of fields, so existing rows are
+    // left as they are
 
-        let result = stmt.query_row((at,), span_event_from_row);
+    if from < 6 {
+        // existing rows were keyed by the client's timestamp
+        tx.execute(
+            r#"ALTER TABLE spans ADD COLUMN client_created_at INT8;"#,
+            (),
+        )?;
+        tx.execute(
+            r#"ALTER TABLE events ADD COLUMN client_timestamp INT8;"#,
+            (),
+        )?;
+    }
 
-        Some(Arc::new(result.unwrap()))
+    if from < 7 {
+        tx.execute(SAVED_FILTERS_TABLE, ())?;
     }
 
-    fn get_event(&self, at: Timestamp) -> Option<Arc<Event>> {
-        let mut stmt = self
-            .connection
-            .prepare_cached("SELECT * FROM events WHERE key = ?1")
-            .unwrap();
+    set_schema_version(&tx, SCHEMA_VERSION)?;
 
-        let result = stmt.query_row((at,), event_from_row);
+    tx.commit()
+}
 
-        Some(Arc::new(result.unwrap()))
+/// This returns the version recorded in the `meta` table, or 1 if the file was
+/// created before the table existed.
+fn schema_version(connection: &DbConnection) -> Result<u32, DbError> {
+    if table_columns(connection, "meta")?.is_empty() {
+        return Ok(1);
     }
 
-    fn get_all_connections(&self) -> Box<dyn Iterator<Item = Arc<Connection>> + '_> {
-        let mut stmt = self
-            .connection
-            .prepare_cached("SELECT * FROM connections ORDER BY key")
-            .unwrap();
+    let version: Option<u32> = connection
+        .query_row(
+            "SELECT value FROM meta WHERE key = 'schema_version'",
+            (),
+            |row| row.get(0),
+        )
+        .optional()?;
 
-        let connections = stmt
-            .query_map((), connection_from_row)
-            .unwrap()
-            .map(|result| result.unwrap())
-            .collect::<Vec<_>>();
+    Ok(version.unwrap_or(1))
+}
+
+fn set_schema_version(connection: &DbConnection, version: u32) -> Result<(), DbError> {
+    connection.execute(
+        "INSERT OR REPLACE INTO meta VALUES ('schema_version', ?1)",
+        (version,),
+    )?;
+
+    Ok(())
+}
+
+/// The tables and columns that are expected to exist in an opened file.
+const TABLES: &[(&str, &[&str])] = &[
+    (
+        "connections",
+        &["key", "id", "disconnected_at", "fields", "schema"],
+    ),
+    (
+        "spans",
+        &[
+            "key",
+            "connection",
+            "id",
+            "closed_at",
+            "parent_id",
+            "follows",
+            "target",
+            "name",
+            "level",
+            "file_name",
+            "file_line",
+            "fields",
+            "busy",
+            "entered_at",
+            "client_created_at",
+        ],
+    ),
+    (
+        "span_events",
+        &["key", "connection", "span_id", "kind", "data"],
+    ),
+    (
+        "events",
+        &[
+            "key",
+            "connection",
+            "span_id",
+            "target",
+            "name",
+            "level",
+            "file_name",
+            "file_line",
+            "fields",
+            "client_timestamp",
+        ],
+    ),
+];
+
+// write-ahead logging lets readers proceed while the writer is inserting
+fn set_pragmas(connection: &DbConnection, durability: Durability) -> Result<(), DbError> {
+    connection.execute_batch(&format!(
+        "PRAGMA synchronous = {}; PRAGMA journal_mode = WAL;",
+        durability.synchronous()
+    ))
+}
+
+fn open_reader(path: &Path) -> Result<DbConnection, DbError> {
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY
+        | OpenFlags::SQLITE_OPEN_URI
+        | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+
+    let connection = DbConnection::open_with_flags(path, flags)?;
+    connection.busy_timeout(Duration::from_secs(5))?;
+
+    Ok(connection)
+}
+
+fn check_columns(connection: &DbConnection) -> Result<(), FileStorageError> {
+    for (table, expected_columns) in TABLES {
+        let columns = table_columns(connection, table)?;
+        for column in *expected_columns {
+            if !columns.iter().any(|c| c == column) {
+                return Err(FileStorageError::MissingColumn(table, column));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn has_saved_indexes(connection: &DbConnection) -> Result<bool, DbError> {
+    let count: i64 = connection.query_row("SELECT COUNT(*) FROM indexes", (), |row| row.get(0))?;
 
-        Box::new(connections.into_iter().map(Arc::new))
+    Ok(count > 0)
+}
+
+fn table_counts(connection: &DbConnection) -> Result<[i64; 4], DbError> {
+    let mut counts = [0; 4];
+    for (count, table) in counts
+        .iter_mut()
+        .zip(["connections", "spans", "span_events", "events"])
+    {
+        *count = connection.query_row(&format!("SELECT COUNT(*) FROM {table}"), (), |row| {
+            row.get(0)
+        })?;
     }
 
-    fn get_all_spans(&self) -> Box<dyn Iterator<Item = Arc<Span>> + '_> {
-        let mut stmt = self
-            .connection
-            .prepare_cached("SELECT * FROM spans ORDER BY key")
-            .unwrap();
+    Ok(counts)
+}
 
-        let spans = stmt
-            .query_map((), span_from_row)
-            .unwrap()
-            .map(|result| result.unwrap())
-            .collect::<Vec<_>>();
+fn has_any_table(connection: &DbConnection) -> Result<bool, DbError> {
+    let count: i64 = connection.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'",
+        (),
+        |row| row.get(0),
+    )?;
+
+    Ok(count > 0)
+}
+
+/// This returns the column names of the table, or an empty list if the table
+/// does not exist.
+fn table_columns(connection: &DbConnection, table: &str) -> Result<Vec<String>, DbError> {
+    let mut stmt = connection.prepare(&format!("PRAGMA table_info({table})"))?;
+    let columns = stmt
+        .query_map((), |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?;
 
-        Box::new(spans.into_iter().map(Arc::new))
+    Ok(columns)
+}
+
+#[derive(Debug)]
+pub enum FileStorageError {
+    Database(DbError),
+    MissingTable(&'static str),
+    MissingColumn(&'static str, &'static str),
+    UnsupportedVersion(u32),
+    OutdatedVersion(u32),
+}
+
+impl Display for FileStorageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            FileStorageError::Database(err) => write!(f, "database error: {err}"),
+            FileStorageError::MissingTable(table) => write!(f, "missing table {table:?}"),
+            FileStorageError::MissingColumn(table, column) => {
+                write!(f, "missing column {column:?} in table {table:?}")
+            }
+            FileStorageError::UnsupportedVersion(version) => write!(
+                f,
+                "file has schema version {version} but only up to {SCHEMA_VERSION} is supported"
+            ),
+            FileStorageError::OutdatedVersion(version) => write!(
+                f,
+                "file has schema version {version} and must be opened for writing to migrate it"
+            ),
+        }
     }
+}
 
-    fn get_all_span_events(&self) -> Box<dyn Iterator<Item = Arc<SpanEvent>> + '_> {
-        let mut stmt = self
-            .connection
-            .prepare_cached("SELECT * FROM span_events ORDER BY key")
-            .unwrap();
+impl std::error::Error for FileStorageError {}
 
-        let span_events = stmt
-            .query_map((), span_event_from_row)
-            .unwrap()
-            .map(|result| result.unwrap())
-            .collect::<Vec<_>>();
+impl From<DbError> for FileStorageError {
+    fn from(err: DbError) -> Self {
+        FileStorageError::Database(err)
+    }
+}
 
-        Box::new(span_events.into_iter().map(Arc::new))
+impl Storage for FileStorage {
+    fn get_connection(&self, at: Timestamp) -> Option<Arc<Connection>> {
+        self.read(|connection| {
+            let mut stmt = connection.prepare_cached("SELECT * FROM connections WHERE key = ?1")?;
+            stmt.query_row((at,), connection_from_row).optional()
+        })
+        .flatten()
+        .map(Arc::new)
     }
 
-    fn get_all_events(&self) -> Box<dyn Iterator<Item = Arc<Event>> + '_> {
-        let mut stmt = self
-            .connection
-            .prepare_cached("SELECT * FROM events ORDER BY key")
-            .unwrap();
+    fn get_span(&self, at: Timestamp) -> Option<Arc<Span>> {
+        self.read(|connection| {
+            let mut stmt = connection.prepare_cached("SELECT * FROM spans WHERE key = ?1")?;
+            stmt.query_row((at,), span_from_row).optional()
+        })
+        .flatten()
+        .map(Arc::new)
+    }
 
-        let events = stmt
-            .query_map((), event_from_row)
-            .unwrap()
-            .map(|result| result.unwrap())
-            .collect::<Vec<_>>();
+    fn get_span_event(&self, at: Timestamp) -> Option<Arc<SpanEvent>> {
+        self.read(|connection| {
+            let mut stmt = connection.prepare_cached("SELECT * FROM span_events WHERE key = ?1")?;
+            stmt.query_row((at,), span_event_from_row).optional()
+        })
+        .flatten()
+        .map(Arc::new)
+    }
 
-        Box::new(events.into_iter().map(Arc::new))
+    fn get_event(&self, at: Timestamp) -> Option<Arc<Event>> {
+        self.read(|connection| {
+            let mut stmt = connection.prepare_cached("SELECT * FROM events WHERE key = ?1")?;
+            stmt.query_row((at,), event_from_row).optional()
+        })
+        .flatten()
+        .map(Arc::new)
+    }
+
+    fn get_all_connections(&self) -> Box<dyn Iterator<Item = Arc<Connection>> + '_> {
+        let connections = self.read(|connection| {
+            let mut stmt = connection.prepare_cached("SELECT * FROM connections ORDER BY key")?;
+            let rows = stmt.query_map((), connection_from_row)?;
+            rows.collect::<Result<Vec<_>, _>>()
+        });
+
+        Box::new(connections.unwrap_or_default().into_iter().map(Arc::new))
+    }
+
+    fn get_all_spans(&self) -> Box<dyn Iterator<Item = Arc<Span>> + '_> {
+        let spans = self.read(|connection| {
+            let mut stmt = connection.prepare_cached("SELECT * FROM spans ORDER BY key")?;
+            let rows = stmt.query_map((), span_from_row)?;
+            rows.collect::<Result<Vec<_>, _>>()
+        });
+
+        Box::new(spans.unwrap_or_default().into_iter().map(Arc::new))
+    }
+
+    fn get_all_span_events(&self) -> Box<dyn Iterator<Item = Arc<SpanEvent>> + '_> {
+        let span_events = self.read(|connection| {
+            let mut stmt = connection.prepare_cached("SELECT * FROM span_events ORDER BY key")?;
+            let rows = stmt.query_map((), span_event_from_row)?;
+            rows.collect::<Result<Vec<_>, _>>()
+        });
+
+        Box::new(span_events.unwrap_or_default().into_iter().map(Arc::new))
+    }
+
+    fn get_all_events(&self) -> Box<dyn Iterator<Item = Arc<Event>> + '_> {
+        let events = self.read(|connection| {
+            let mut stmt = connection.prepare_cached("SELECT * FROM events ORDER BY key")?;
+            let rows = stmt.query_map((), event_from_row)?;
+            rows.collect::<Result<Vec<_>, _>>()
+        });
+
+        Box::new(events.unwrap_or_default().into_iter().map(Arc::new))
     }
 
     fn insert_connection(&mut self, connection: Connection) {
+        let compress = self.options.compress_fields;
         let mut stmt = self
-            .connection
-            .prepare_cached("INSERT INTO connections VALUES (?1, ?2, ?3, ?4)")
+            .writer()
+            .prepare_cached("INSERT INTO connections VALUES (?1, ?2, ?3, ?4, ?5)")
             .unwrap();
 
-        stmt.execute(connection_to_params(connection)).unwrap();
+        stmt.execute(connection_to_params(connection, compress))
+            .unwrap();
     }
 
     fn insert_span(&mut self, span: Span) {
+        let compress = self.options.compress_fields;
         let mut stmt = self
-            .connection
+            .writer()
             .prepare_cached(
-                "INSERT INTO spans VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                "INSERT INTO spans VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
             )
             .unwrap();
 
-        stmt.execute(span_to_params(span)).unwrap();
+        stmt.execute(span_to_params(span, compress)).unwrap();
     }
 
     fn insert_span_event(&mut self, span_event: SpanEvent) {
         let mut stmt = self
-            .connection
+            .writer()
             .prepare_cached("INSERT INTO span_events VALUES (?1, ?2, ?3, ?4, ?5)")
             .unwrap();
 
@@ -226,17 +797,34 @@ impl Storage for FileStorage {
     }
 
     fn insert_event(&mut self, event: Event) {
+        let compress = self.options.compress_fields;
         let mut stmt = self
-            .connection
-            .prepare_cached("INSERT INTO events VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)")
+            .writer()
+            .prepare_cached("INSERT INTO events VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)")
+            .unwrap();
+
+        stmt.execute(event_to_params(event, compress)).unwrap();
+    }
+
+    fn insert_events(&mut self, events: Vec<Event>) {
+        let compress = self.options.compress_fields;
+        let tx = self.writer().transaction().unwrap();
+
+        let mut stmt = tx
+            .prepare_cached("INSERT INTO events VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)")
             .unwrap();
 
-        stmt.execute(event_to_params(event)).unwrap();
+        for event in events {
+            stmt.execute(event_to_params(event, compress)).unwrap();
+        }
+
+        drop(stmt);
+        tx.commit().unwrap();
     }
 
     fn update_connection_disconnected(&mut self, at: Timestamp, disconnected: Timestamp) {
         let mut stmt = self
-            .connection
+            .writer()
             .prepare_cached("UPDATE connections SET disconnected_at = ?2 WHERE key = ?1")
             .unwrap();
 
@@ -245,7 +833,7 @@ impl Storage for FileStorage {
 
     fn update_span_closed(&mut self, at: Timestamp, closed: Timestamp) {
         let mut stmt = self
-            .connection
+            .writer()
             .prepare_cached("UPDATE spans SET closed_at = ?2 WHERE key = ?1")
             .unwrap();
 
@@ -253,8 +841,10 @@ impl Storage for FileStorage {
     }
 
     fn update_span_fields(&mut self, at: Timestamp, fields: BTreeMap<String, Value>) {
-        let mut stmt = self
-            .connection
+        let compress = self.options.compress_fields;
+        let connection = self.writer();
+
+        let mut stmt = connection
             .prepare_cached("SELECT * FROM spans WHERE spans.key = ?1")
             .unwrap();
 
@@ -266,10 +856,9 @@ impl Storage for FileStorage {
             new_fields.extend(fields);
             new_fields
         };
-        let fields = serde_json::to_string(&fields).unwrap();
+        let fields = encode_fields(&fields, compress);
 
-        let mut stmt = self
-            .connection
+        let mut stmt = connection
             .prepare_cached("UPDATE spans SET fields = ?2 WHERE key = ?1")
             .unwrap();
 
@@ -277,8 +866,9 @@ impl Storage for FileStorage {
     }
 
     fn update_span_follows(&mut self, at: Timestamp, follows: SpanKey) {
-        let mut stmt = self
-            .connection
+        let connection = self.writer();
+
+        let mut stmt = connection
             .prepare_cached("SELECT * FROM spans WHERE spans.key = ?1")
             .unwrap();
 
@@ -292,16 +882,24 @@ impl Storage for FileStorage {
         };
         let fields = serde_json::to_string(&follows).unwrap();
 
-        let mut stmt = self
-            .connection
+        let mut stmt = connection
             .prepare_cached("UPDATE spans SET follows = ?2 WHERE key = ?1")
             .unwrap();
 
         stmt.execute((at, fields)).unwrap();
     }
 
+    fn update_span_busy(&mut self, at: Timestamp, busy: u64, entered_at: Option<Timestamp>) {
+        let mut stmt = self
+            .writer()
+            .prepare_cached("UPDATE spans SET busy = ?2, entered_at = ?3 WHERE key = ?1")
+            .unwrap();
+
+        stmt.execute((at, busy as i64, entered_at)).unwrap();
+    }
+
     fn drop_connections(&mut self, connections: &[Timestamp]) {
-        let tx = self.connection.transaction().unwrap();
+        let tx = self.writer().transaction().unwrap();
 
         let mut stmt = tx
             .prepare_cached("DELETE FROM connections WHERE connections.key = ?1")
@@ -316,7 +914,7 @@ impl Storage for FileStorage {
     }
 
     fn drop_spans(&mut self, spans: &[Timestamp]) {
-        let tx = self.connection.transaction().unwrap();
+        let tx = self.writer().transaction().unwrap();
 
         let mut stmt = tx
             .prepare_cached("DELETE FROM spans WHERE spans.key = ?1")
@@ -331,7 +929,7 @@ impl Storage for FileStorage {
     }
 
     fn drop_span_events(&mut self, span_events: &[Timestamp]) {
-        let tx = self.connection.transaction().unwrap();
+        let tx = self.writer().transaction().unwrap();
 
         let mut stmt = tx
             .prepare_cached("DELETE FROM span_events WHERE span_events.key = ?1")
@@ -346,7 +944,7 @@ impl Storage for FileStorage {
     }
 
     fn drop_events(&mut self, events: &[Timestamp]) {
-        let tx = self.connection.transaction().unwrap();
+        let tx = self.writer().transaction().unwrap();
 
         let mut stmt = tx
             .prepare_cached("DELETE FROM events WHERE events.key = ?1")
@@ -359,34 +957,218 @@ impl Storage for FileStorage {
         drop(stmt);
         tx.commit().unwrap();
     }
+
+    fn clear(&mut self) {
+        let tx = self.writer().transaction().unwrap();
+
+        for table in ["connections", "spans", "span_events", "events"] {
+            tx.execute(&format!("DELETE FROM {table}"), ()).unwrap();
+        }
+
+        tx.commit().unwrap();
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn flush(&mut self) {
+        if self.read_only {
+            return;
+        }
+
+        // writes may not be synced to disk, so a checkpoint is done with full
+        // syncing to move the write-ahead log into the database file
+        self.connection
+            .get_mut()
+            .unwrap()
+            .execute_batch(&format!(
+                "PRAGMA synchronous = FULL;
+                PRAGMA wal_checkpoint(TRUNCATE);
+                PRAGMA synchronous = {};",
+                self.options.durability.synchronous()
+            ))
+            .unwrap();
+    }
+
+    fn compact(&mut self) -> Option<CompactMetrics> {
+        if self.read_only {
+            return None;
+        }
+
+        let connection = self.connection.get_mut().unwrap();
+        let size = |connection: &DbConnection| -> u64 {
+            connection
+                .query_row(
+                    "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+                    (),
+                    |row| row.get::<_, i64>(0),
+                )
+                .unwrap() as u64
+        };
+
+        let size_before = size(connection);
+
+        // the vacuumed copy is written through the log, so it is checkpointed
+        // to actually shrink the file
+        connection
+            .execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")
+            .unwrap();
+
+        let size_after = size(connection);
+
+        Some(CompactMetrics {
+            size_before,
+            size_after,
+        })
+    }
+
+    fn load_indexes(&self) -> Option<Vec<u8>> {
+        if !self.has_saved_indexes {
+            return None;
+        }
+
+        self.read(|connection| {
+            let saved = connection
+                .query_row(
+                    "SELECT connections, spans, span_events, events, data FROM indexes",
+                    (),
+                    |row| {
+                        let counts: [i64; 4] = [row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?];
+                        let data: Vec<u8> = row.get(4)?;
+                        Ok((counts, data))
+                    },
+                )
+                .optional()?;
+
+            // this shouldn't happen, but the indexes can't be trusted if the
+            // tables were changed by something else
+            match saved {
+                Some((counts, data)) if counts == table_counts(connection)? => Ok(Some(data)),
+                _ => Ok(None),
+            }
+        })
+        .flatten()
+    }
+
+    fn save_indexes(&mut self, serialize: &dyn Fn() -> Vec<u8>) {
+        if self.read_only {
+            return;
+        }
+
+        let data = serialize();
+
+        let tx = self.connection.get_mut().unwrap().transaction().unwrap();
+        let [connections, spans, span_events, events] = table_counts(&tx).unwrap();
+
+        tx.execute("DELETE FROM indexes", ()).unwrap();
+        tx.execute(
+            "INSERT INTO indexes VALUES (?1, ?2, ?3, ?4, ?5)",
+            (connections, spans, span_events, events, data),
+        )
+        .unwrap();
+
+        tx.commit().unwrap();
+        self.has_saved_indexes = true;
+    }
+
+    fn load_saved_filters(&self) -> Vec<(String, String)> {
+        self.read(|connection| {
+            let mut stmt = connection.prepare("SELECT name, filter FROM saved_filters")?;
+            let filters = stmt
+                .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(filters)
+        })
+        .unwrap_or_default()
+    }
+
+    // these don't go through `writer` since the filters don't change the
+    // dataset, so saved indexes are still valid
+    fn save_filter(&mut self, name: &str, filter: &str) {
+        if self.read_only {
+            return;
+        }
+
+        self.connection
+            .get_mut()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO saved_filters VALUES (?1, ?2)",
+                (name, filter),
+            )
+            .unwrap();
+    }
+
+    fn delete_saved_filter(&mut self, name: &str) {
+        if self.read_only {
+            return;
+        }
+
+        self.connection
+            .get_mut()
+            .unwrap()
+            .execute("DELETE FROM saved_filters WHERE name = ?1", (name,))
+            .unwrap();
+    }
+}
+
+/// Compressed fields are stored as a blob starting with this byte followed by
+/// the zstd-compressed JSON. Uncompressed fields are stored as JSON text, so
+/// the two can be told apart regardless of the current options.
+const COMPRESSED_FIELDS: u8 = 1;
+
+fn encode_fields(fields: &BTreeMap<String, Value>, compress: bool) -> SqlValue {
+    let json = serde_json::to_string(fields).unwrap();
+    if !compress {
+        return SqlValue::Text(json);
+    }
+
+    let mut data = vec![COMPRESSED_FIELDS];
+    zstd::stream::copy_encode(json.as_bytes(), &mut data, 0).unwrap();
+    SqlValue::Blob(data)
+}
+
+fn decode_fields(value: ValueRef<'_>) -> BTreeMap<String, Value> {
+    match value {
+        ValueRef::Text(json) => serde_json::from_slice(json).unwrap(),
+        ValueRef::Blob([COMPRESSED_FIELDS, data @ ..]) => {
+            let json = zstd::stream::decode_all(data).unwrap();
+            serde_json::from_slice(&json).unwrap()
+        }
+        _ => panic!("unknown fields encoding"),
+    }
 }
 
-fn connection_to_params(connection: Connection) -> impl Params {
+fn connection_to_params(connection: Connection, compress: bool) -> impl Params {
     let key = connection.key();
     let id = connection.id;
     let disconnected_at = connection.disconnected_at;
-    let fields = serde_json::to_string(&connection.fields).unwrap();
+    let fields = encode_fields(&connection.fields, compress);
+    let schema = connection.schema;
 
-    (key, id as i64, disconnected_at, fields)
+    (key, id as i64, disconnected_at, fields, schema)
 }
 
 fn connection_from_row(row: &Row<'_>) -> Result<Connection, DbError> {
     let key = row.get(0)?;
     let id: i64 = row.get(1)?;
     let disconnected_at = row.get(2)?;
-    let fields: String = row.get(3)?;
-    let fields = serde_json::from_str(&fields).unwrap();
+    let fields = decode_fields(row.get_ref(3)?);
+    let schema = row.get(4)?;
 
     Ok(Connection {
         id: id as u64,
         connected_at: key,
         disconnected_at,
+        schema,
         fields,
     })
 }
 
 #[rustfmt::skip]
-fn span_to_params(span: Span) -> impl Params {
+fn span_to_params(span: Span, compress: bool) -> impl Params {
     let key = span.created_at;
     let connection_key = span.connection_key;
     let id = span.id.get() as i64;
@@ -398,9 +1180,12 @@ fn span_to_params(span: Span) -> impl Params {
     let level = span.level as i32;
     let file_name = span.file_name;
     let file_line = span.file_line;
-    let fields = serde_json::to_string(&span.fields).unwrap();
+    let fields = encode_fields(&span.fields, compress);
+    let busy = span.busy as i64;
+    let entered_at = span.entered_at;
+    let client_created_at = span.client_created_at;
 
-    (key, connection_key, id, closed_at, parent_id, follows, target, name, level, file_name, file_line, fields)
+    (key, connection_key, id, closed_at, parent_id, follows, target, name, level, file_name, file_line, fields, busy, entered_at, client_created_at)
 }
 
 fn span_from_row(row: &Row<'_>) -> Result<Span, DbError> {
@@ -416,11 +1201,14 @@ fn span_from_row(row: &Row<'_>) -> Result<Span, DbError> {
     let level: i32 = row.get(8)?;
     let file_name = row.get(9)?;
     let file_line = row.get(10)?;
-    let fields: String = row.get(11)?;
-    let fields = serde_json::from_str(&fields).unwrap();
+    let fields = decode_fields(row.get_ref(11)?);
+    let busy: i64 = row.get(12)?;
+    let entered_at = row.get(13)?;
+    let client_created_at = row.get(14)?;
 
     Ok(Span {
         created_at: key,
+        client_created_at,
         connection_key,
         id: SpanId::new(id as u64).unwrap(),
         closed_at,
@@ -428,10 +1216,12 @@ fn span_from_row(row: &Row<'_>) -> Result<Span, DbError> {
         follows,
         target,
         name,
-        level: level.try_into().unwrap(),
+        level: Level::clamped(level),
         file_name,
         file_line,
         fields,
+        busy: busy as u64,
+        entered_at,
     })
 }
 
@@ -548,7 +1338,7 @@ fn span_event_from_row(row: &Row<'_>) -> Result<SpanEvent, DbError> {
 }
 
 #[rustfmt::skip]
-fn event_to_params(event: Event) -> impl Params {
+fn event_to_params(event: Event, compress: bool) -> impl Params {
     let key = event.timestamp;
     let connection_key = event.connection_key;
     let span_key = event.span_key;
@@ -557,9 +1347,10 @@ fn event_to_params(event: Event) -> impl Params {
     let level = event.level as i32;
     let file_name = event.file_name;
     let file_line = event.file_line;
-    let fields = serde_json::to_string(&event.fields).unwrap();
+    let fields = encode_fields(&event.fields, compress);
+    let client_timestamp = event.client_timestamp;
 
-    (key, connection_key, span_key, target, name, level, file_name, file_line, fields)
+    (key, connection_key, span_key, target, name, level, file_name, file_line, fields, client_timestamp)
 }
 
 fn event_from_row(row: &Row<'_>) -> Result<Event, DbError> {
@@ -571,18 +1362,405 @@ fn event_from_row(row: &Row<'_>) -> Result<Event, DbError> {
     let level: i32 = row.get(5)?;
     let file_name = row.get(6)?;
     let file_line = row.get(7)?;
-    let fields: String = row.get(8)?;
-    let fields = serde_json::from_str(&fields).unwrap();
+    let fields = decode_fields(row.get_ref(8)?);
+    let client_timestamp = row.get(9)?;
 
     Ok(Event {
         timestamp: key,
+        client_timestamp,
         connection_key,
         span_key,
         target,
         name,
-        level: level.try_into().unwrap(),
+        level: Level::clamped(level),
         file_name,
         file_line,
         fields,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_keys_return_none() {
+        let storage = FileStorage::new(Path::new(":memory:"));
+        let at = Timestamp::new(1).unwrap();
+
+        assert!(storage.get_connection(at).is_none());
+        assert!(storage.get_span(at).is_none());
+        assert!(storage.get_span_event(at).is_none());
+        assert!(storage.get_event(at).is_none());
+    }
+
+    #[test]
+    fn read_errors_return_none() {
+        let mut storage = FileStorage::new(Path::new(":memory:"));
+        storage.insert_events(make_events(3));
+        let at = Timestamp::new(1).unwrap();
+        assert!(storage.get_event(at).is_some());
+
+        storage.writer().execute("DROP TABLE events", ()).unwrap();
+
+        assert!(storage.get_event(at).is_none());
+        assert_eq!(storage.get_all_events().count(), 0);
+    }
+
+    #[test]
+    fn open_validates_tables() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let path = dir.path().join("empty.db");
+        assert!(matches!(
+            FileStorage::open(&path),
+            Err(FileStorageError::MissingTable("connections"))
+        ));
+
+        let path = dir.path().join("valid.db");
+        let mut storage = FileStorage::create(&path).unwrap();
+        storage.insert_events(make_events(3));
+        drop(storage);
+
+        assert!(FileStorage::create(&path).is_err());
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(storage.get_all_events().count(), 3);
+        drop(storage);
+
+        let path = dir.path().join("partial.db");
+        let mut storage = FileStorage::create(&path).unwrap();
+        storage
+            .writer()
+            .execute("ALTER TABLE events DROP COLUMN file_line", ())
+            .unwrap();
+        drop(storage);
+        assert!(matches!(
+            FileStorage::open(&path),
+            Err(FileStorageError::MissingColumn("events", "file_line"))
+        ));
+    }
+
+    #[test]
+    fn open_migrates_old_versions() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // a version 1 file has no meta table and no connection schema column
+        let path = dir.path().join("v1.db");
+        let connection = DbConnection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                r#"
+                CREATE TABLE connections (key INT8 NOT NULL, id INT8, disconnected_at INT8, fields TEXT, CONSTRAINT connections_pk PRIMARY KEY (key));
+                CREATE TABLE spans (key INT8 NOT NULL, connection INT8, id INT8, closed_at INT8, parent_id INT8, follows TEXT, target TEXT, name TEXT, level INT, file_name TEXT, file_line INTEGER, fields TEXT, CONSTRAINT spans_pk PRIMARY KEY (key));
+                CREATE TABLE span_events (key INT8 NOT NULL, connection INT8, span_id INT8, kind TEXT, data TEXT, CONSTRAINT span_events_pk PRIMARY KEY (key));
+                CREATE TABLE events (key INT8 NOT NULL, connection INT8, span_id INT8, target TEXT, name TEXT, level INT, file_name TEXT, file_line INTEGER, fields TEXT, CONSTRAINT events_pk PRIMARY KEY (key));
+                INSERT INTO connections VALUES (1, 7, NULL, '{}');
+                "#,
+            )
+            .unwrap();
+        drop(connection);
+
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(
+            schema_version(&storage.connection.lock().unwrap()).unwrap(),
+            SCHEMA_VERSION
+        );
+        let connection = storage.get_connection(Timestamp::new(1).unwrap()).unwrap();
+        assert_eq!(connection.id, 7);
+        assert_eq!(connection.schema, 1);
+        drop(storage);
+
+        // a file from a newer build is refused
+        set_schema_version(&DbConnection::open(&path).unwrap(), SCHEMA_VERSION + 1).unwrap();
+        assert!(matches!(
+            FileStorage::open(&path),
+            Err(FileStorageError::UnsupportedVersion(v)) if v == SCHEMA_VERSION + 1
+        ));
+    }
+
+    fn make_events(count: u64) -> Vec<Event> {
+        (1..=count)
+            .map(|i| Event {
+                connection_key: Timestamp::new(1).unwrap(),
+                timestamp: Timestamp::new(i).unwrap(),
+                client_timestamp: None,
+                span_key: None,
+                name: "event".to_owned(),
+                target: "crate::module".to_owned(),
+                level: Level::Info,
+                file_name: Some("src/main.rs".to_owned()),
+                file_line: Some(42),
+                fields: BTreeMap::from_iter([(
+                    "message".to_owned(),
+                    Value::Str("test".to_owned()),
+                )]),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn field_values_keep_their_types() {
+        let mut storage = FileStorage::new(Path::new(":memory:"));
+
+        let fields = BTreeMap::from_iter([
+            ("f64".to_owned(), Value::F64(1.5)),
+            ("nan".to_owned(), Value::F64(f64::NAN)),
+            ("inf".to_owned(), Value::F64(f64::INFINITY)),
+            ("neg_inf".to_owned(), Value::F64(f64::NEG_INFINITY)),
+            ("i64".to_owned(), Value::I64(-3)),
+            ("u64".to_owned(), Value::U64(u64::MAX)),
+            ("i128".to_owned(), Value::I128(i128::MIN)),
+            ("u128".to_owned(), Value::U128(u128::MAX)),
+            ("bool".to_owned(), Value::Bool(true)),
+            ("str".to_owned(), Value::Str("4".to_owned())),
+        ]);
+
+        let mut event = make_events(1).pop().unwrap();
+        event.fields = fields.clone();
+        storage.insert_event(event);
+
+        let event = storage.get_event(Timestamp::new(1).unwrap()).unwrap();
+        assert_eq!(event.fields.len(), fields.len());
+        for (name, value) in &fields {
+            match (value, &event.fields[name]) {
+                (Value::F64(a), Value::F64(b)) if a.is_nan() => assert!(b.is_nan()),
+                (a, b) => assert_eq!(a, b),
+            }
+        }
+    }
+
+    #[test]
+    fn reads_run_concurrently_with_inserts() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let path = dir.path().join("concurrent.db");
+        let mut writer = FileStorage::create(&path).unwrap();
+        let storage = FileStorage::open(&path).unwrap();
+
+        let events = make_events(1000);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for chunk in events.chunks(100) {
+                    writer.insert_events(chunk.to_vec());
+                }
+            });
+
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut last_count = 0;
+                    for _ in 0..20 {
+                        let count = storage.get_all_events().count();
+                        assert!(count >= last_count);
+                        assert_eq!(count % 100, 0);
+                        last_count = count;
+                    }
+                });
+            }
+        });
+
+        assert_eq!(storage.get_all_events().count(), 1000);
+        drop(storage);
+        drop(writer);
+    }
+
+    #[test]
+    fn flush_checkpoints_the_log() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let path = dir.path().join("flush.db");
+        let mut storage = FileStorage::create(&path).unwrap();
+        storage.insert_events(make_events(100));
+
+        let wal_path = dir.path().join("flush.db-wal");
+        assert!(std::fs::metadata(&wal_path).unwrap().len() > 0);
+
+        storage.flush();
+        assert_eq!(std::fs::metadata(&wal_path).unwrap().len(), 0);
+
+        // the data is in the database file itself
+        std::fs::copy(&path, dir.path().join("copy.db")).unwrap();
+        let copy = FileStorage::open(&dir.path().join("copy.db")).unwrap();
+        assert_eq!(copy.get_all_events().count(), 100);
+        drop(copy);
+        drop(storage);
+    }
+
+    #[test]
+    fn read_only_storage_reads_while_another_writes() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let path = dir.path().join("read-only.db");
+        let mut writer = FileStorage::create(&path).unwrap();
+        writer.insert_connection(Connection {
+            id: 1,
+            connected_at: Timestamp::new(1).unwrap(),
+            disconnected_at: None,
+            schema: 1,
+            fields: BTreeMap::new(),
+        });
+        writer.insert_events(make_events(100));
+
+        let storage = FileStorage::open_read_only(&path).unwrap();
+        assert!(storage.is_read_only());
+        assert_eq!(storage.get_all_events().count(), 100);
+        assert!(storage.get_event(Timestamp::new(50).unwrap()).is_some());
+
+        // the writer isn't blocked by the reader
+        writer.insert_events(make_events(200).split_off(100));
+        assert_eq!(storage.get_all_events().count(), 200);
+
+        let mut engine = crate::RawEngine::new(storage);
+        let result = engine.insert_connection(crate::NewConnection::new(1, BTreeMap::new()));
+        assert!(matches!(result, Err(crate::EngineInsertError::ReadOnly)));
+        drop(engine);
+        drop(writer);
+    }
+
+    #[test]
+    fn compact_shrinks_the_file_after_dropping() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let path = dir.path().join("compact.db");
+        let mut storage = FileStorage::create(&path).unwrap();
+        storage.insert_events(make_events(10_000));
+
+        let dropped: Vec<Timestamp> = (1..=9_000).map(|i| Timestamp::new(i).unwrap()).collect();
+        storage.drop_events(&dropped);
+        storage.flush();
+        let file_size = std::fs::metadata(&path).unwrap().len();
+
+        let metrics = storage.compact().unwrap();
+        assert_eq!(metrics.size_before, file_size);
+        assert!(metrics.size_after < metrics.size_before);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), metrics.size_after);
+        assert_eq!(storage.get_all_events().count(), 1_000);
+        drop(storage);
+    }
+
+    #[test]
+    fn durability_sets_synchronous_mode() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for durability in [Durability::Off, Durability::Normal, Durability::Full] {
+            let path = dir.path().join(format!("{durability:?}.db"));
+            let options = FileStorageOptions {
+                durability,
+                ..FileStorageOptions::default()
+            };
+            let mut storage = FileStorage::create_with_options(&path, options).unwrap();
+
+            let synchronous: i64 = storage
+                .connection
+                .get_mut()
+                .unwrap()
+                .query_row("PRAGMA synchronous", (), |row| row.get(0))
+                .unwrap();
+            // sqlite reports OFF, NORMAL, and FULL as 0, 1, and 2
+            assert_eq!(synchronous, durability as i64);
+        }
+    }
+
+    fn make_request_events(count: u64) -> Vec<Event> {
+        let methods = ["GET", "POST", "PUT", "DELETE"];
+        let paths = ["/api/users", "/api/orders", "/api/orders/items", "/health"];
+        let agents = [
+            "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko)",
+            "curl/8.5.0",
+            "okhttp/4.12.0",
+        ];
+
+        make_events(count)
+            .into_iter()
+            .map(|mut event| {
+                let i = event.timestamp.get();
+                event.name = "request completed".to_owned();
+                event.fields = BTreeMap::from_iter([
+                    (
+                        "message".to_owned(),
+                        Value::Str(format!("handled request {i} in {}ms", i % 250)),
+                    ),
+                    (
+                        "http.method".to_owned(),
+                        Value::Str(methods[i as usize % 4].to_owned()),
+                    ),
+                    (
+                        "http.route".to_owned(),
+                        Value::Str(paths[i as usize % 4].to_owned()),
+                    ),
+                    ("http.status_code".to_owned(), Value::U64(200 + i % 3 * 100)),
+                    (
+                        "http.user_agent".to_owned(),
+                        Value::Str(agents[i as usize % 3].to_owned()),
+                    ),
+                    (
+                        "request_id".to_owned(),
+                        Value::Str(format!("{:032x}", i.wrapping_mul(0x9e3779b97f4a7c15))),
+                    ),
+                    ("user_id".to_owned(), Value::U64(i % 97)),
+                    ("latency_ms".to_owned(), Value::F64((i % 250) as f64 * 1.25)),
+                    ("cache_hit".to_owned(), Value::Bool(i % 2 == 0)),
+                    (
+                        "service.name".to_owned(),
+                        Value::Str("checkout-api".to_owned()),
+                    ),
+                ]);
+                event
+            })
+            .collect()
+    }
+
+    #[test]
+    fn compressed_fields_are_smaller_on_disk() {
+        const COUNT: u64 = 1_000;
+
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut sizes = vec![];
+        for compress_fields in [false, true] {
+            let path = dir.path().join(format!("compress-{compress_fields}.db"));
+            let options = FileStorageOptions {
+                compress_fields,
+                ..FileStorageOptions::default()
+            };
+
+            let mut storage = FileStorage::create_with_options(&path, options).unwrap();
+            storage.insert_events(make_request_events(COUNT));
+            storage.flush();
+            drop(storage);
+
+            sizes.push(std::fs::metadata(&path).unwrap().len());
+        }
+
+        let [plain, compressed] = sizes[..] else {
+            unreachable!()
+        };
+        assert!(compressed < plain);
+    }
+
+    #[test]
+    fn compressed_and_plain_fields_can_be_mixed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mixed.db");
+
+        let mut events = make_request_events(4);
+        let later = events.split_off(2);
+
+        let mut storage = FileStorage::create(&path).unwrap();
+        storage.insert_events(events.clone());
+        drop(storage);
+
+        let options = FileStorageOptions {
+            compress_fields: true,
+            ..FileStorageOptions::default()
+        };
+        let mut storage = FileStorage::open_with_options(&path, options).unwrap();
+        storage.insert_events(later.clone());
+
+        let stored = storage.get_all_events().collect::<Vec<_>>();
+        assert_eq!(stored.len(), 4);
+        for (stored, event) in stored.iter().zip(events.iter().chain(&later)) {
+            assert_eq!(stored.fields, event.fields);
+        }
+        drop(storage);
+    }
+}
@@ -1,26 +1,36 @@
 use std::collections::BTreeMap;
 use std::sync::Arc;
 
+mod asynchronous;
 mod cached;
 #[cfg(feature = "persist")]
 mod file;
+#[cfg(feature = "postgres")]
+mod postgres;
 mod transient;
 
 use crate::models::{Connection, Event, Span, SpanEvent, Timestamp, Value};
-use crate::SpanKey;
+use crate::{CompactMetrics, SpanKey};
 
+pub use asynchronous::{AsyncStorage, BlockOnStorage, SpawnBlockingStorage};
 pub use cached::CachedStorage;
 #[cfg(feature = "persist")]
-pub use file::FileStorage;
+pub use file::{Durability, FileStorage, FileStorageError, FileStorageOptions};
+#[cfg(feature = "postgres")]
+pub use postgres::{PostgresStorage, PostgresStorageError};
 pub use transient::TransientStorage;
 
-/// This reflects the backing storage of spans, events, and span events. This
-/// (currently) does not store indexes; they are re-created on startup.
+/// This reflects the backing storage of spans, events, and span events. The
+/// indexes are re-created on startup unless the storage can keep a copy of
+/// them (see [`load_indexes`](Storage::load_indexes)).
 ///
 /// This interface enforces that elements are directly accessible by their
 /// `timestamp` (`created_at` for spans) and that those timestamps are unique.
 ///
 /// The *get all* methods are used to load on startup, and backfill new indexes.
+///
+/// Backends that are asynchronous can implement [`AsyncStorage`] instead and
+/// be given to the engine through [`BlockOnStorage`].
 pub trait Storage {
     fn get_connection(&self, at: Timestamp) -> Option<Arc<Connection>>;
     fn get_span(&self, at: Timestamp) -> Option<Arc<Span>>;
@@ -37,13 +47,86 @@ pub trait Storage {
     fn insert_span_event(&mut self, span_event: SpanEvent);
     fn insert_event(&mut self, event: Event);
 
+    /// Inserts many events at once. Implementations that have a per-write
+    /// overhead (like a transaction) should override this to pay it once.
+    fn insert_events(&mut self, events: Vec<Event>) {
+        for event in events {
+            self.insert_event(event);
+        }
+    }
+
     fn update_connection_disconnected(&mut self, at: Timestamp, disconnected: Timestamp);
     fn update_span_closed(&mut self, at: Timestamp, closed: Timestamp);
+    /// Merges the fields into the span's existing fields by name, replacing
+    /// the values of any that are already set. The engine updates its
+    /// attribute indexes before calling this since it reads the old values.
     fn update_span_fields(&mut self, at: Timestamp, fields: BTreeMap<String, Value>);
     fn update_span_follows(&mut self, at: Timestamp, follows: SpanKey);
+    fn update_span_busy(&mut self, at: Timestamp, busy: u64, entered_at: Option<Timestamp>);
 
     fn drop_connections(&mut self, connections: &[Timestamp]);
     fn drop_spans(&mut self, spans: &[Timestamp]);
     fn drop_span_events(&mut self, span_events: &[Timestamp]);
     fn drop_events(&mut self, events: &[Timestamp]);
+
+    /// Removes everything from storage.
+    fn clear(&mut self);
+
+    /// Whether the storage can only be read, like a file opened while another
+    /// process is writing to it. The engine refuses anything that would modify
+    /// such storage, so the methods above that write are never called.
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
+    /// Makes sure everything written so far is durable. Implementations that
+    /// don't persist anything have nothing to do.
+    fn flush(&mut self) {}
+
+    /// Rewrites the storage to give back the space left over from removed
+    /// data and returns its size in bytes before and after. Implementations
+    /// that have nothing to give back return `None`.
+    fn compact(&mut self) -> Option<CompactMetrics> {
+        None
+    }
+
+    /// Returns the indexes given to the last [`save_indexes`] call if nothing
+    /// has been written since. Implementations that can't keep them always
+    /// return `None` and the indexes are rebuilt from the *get all* methods.
+    ///
+    /// [`save_indexes`]: Storage::save_indexes
+    fn load_indexes(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Keeps a serialized copy of the indexes to be loaded on the next startup.
+    /// The indexes are only serialized if the implementation can keep them.
+    fn save_indexes(&mut self, _serialize: &dyn Fn() -> Vec<u8>) {}
+
+    /// Returns the named filters kept by [`save_filter`], as name and filter
+    /// text pairs. Implementations that can't keep them return nothing and the
+    /// filters only last as long as the engine.
+    ///
+    /// [`save_filter`]: Storage::save_filter
+    fn load_saved_filters(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// Keeps the filter text under the name, replacing any filter already
+    /// saved with that name. These aren't part of the dataset so they are left
+    /// alone by [`clear`](Storage::clear).
+    fn save_filter(&mut self, _name: &str, _filter: &str) {}
+
+    /// Removes the filter saved with the name, if any.
+    fn delete_saved_filter(&mut self, _name: &str) {}
 }
+
+/// Storage that can be read from multiple threads at once, which is needed to
+/// search it in parallel (see [`EngineBuilder::parallel_search`]).
+///
+/// [`EngineBuilder::parallel_search`]: crate::EngineBuilder::parallel_search
+#[cfg(feature = "parallel")]
+pub trait ParallelStorage: Storage + Sync {}
+
+#[cfg(feature = "parallel")]
+impl<T: Storage + Sync> ParallelStorage for T {}
@@ -0,0 +1,814 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, StreamExt};
+use tokio_postgres::types::{Json, ToSql};
+use tokio_postgres::{Client, Error as DbError, NoTls, Row};
+
+use crate::models::{Level, SpanEventKind, Value};
+use crate::{Connection, Event, Span, SpanEvent, SpanId, SpanKey, Timestamp};
+
+use super::AsyncStorage;
+
+/// This storage keeps everything in a Postgres database so that it can be
+/// shared and outlive the machine running the engine. The tables mirror those
+/// of [`FileStorage`](crate::FileStorage) except that the `fields` columns are
+/// `jsonb`.
+///
+/// It is asynchronous, so it is given to the engine through a
+/// [`BlockOnStorage`](crate::BlockOnStorage):
+///
+/// ```no_run
+/// # async fn example() {
+/// use tokio::runtime::Handle;
+/// use venator_engine::{BlockOnStorage, Engine, PostgresStorage};
+///
+/// let storage = PostgresStorage::connect("host=localhost user=venator")
+///     .await
+///     .unwrap();
+///
+/// let engine = Engine::new(BlockOnStorage::new(storage, Handle::current()));
+/// # }
+/// ```
+///
+/// Saved indexes are kept in the `indexes` table and are removed by the first
+/// write after saving them, so they are never loaded for different data.
+pub struct PostgresStorage {
+    client: Client,
+    has_saved_indexes: bool,
+}
+
+impl PostgresStorage {
+    /// This connects to the database described by the configuration string
+    /// (see [`tokio_postgres::Config`]) and creates the tables if they don't
+    /// exist yet. It must be called within a tokio runtime, which drives the
+    /// connection from then on.
+    pub async fn connect(config: &str) -> Result<PostgresStorage, PostgresStorageError> {
+        let (client, connection) = tokio_postgres::connect(config, NoTls).await?;
+
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                eprintln!("postgres connection error: {err}");
+            }
+        });
+
+        PostgresStorage::with_client(client).await
+    }
+
+    /// This uses an already established client, for example one that is
+    /// connected with TLS. The tables are created if they don't exist yet.
+    pub async fn with_client(client: Client) -> Result<PostgresStorage, PostgresStorageError> {
+        client.batch_execute(TABLES).await?;
+
+        let saved: i64 = client
+            .query_one("SELECT COUNT(*) FROM indexes", &[])
+            .await?
+            .get(0);
+
+        Ok(PostgresStorage {
+            client,
+            has_saved_indexes: saved > 0,
+        })
+    }
+
+    /// This returns the client for modifying the dataset. Any saved indexes are
+    /// removed first since they would no longer match.
+    async fn writer(&mut self) -> &Client {
+        if self.has_saved_indexes {
+            self.client
+                .execute("DELETE FROM indexes", &[])
+                .await
+                .unwrap();
+            self.has_saved_indexes = false;
+        }
+
+        &self.client
+    }
+
+    async fn get<T>(&self, query: &str, at: Timestamp, from_row: fn(&Row) -> T) -> Option<Arc<T>> {
+        let row = self.client.query_opt(query, &[&to_db(at)]).await.unwrap();
+
+        row.map(|row| Arc::new(from_row(&row)))
+    }
+
+    /// This streams the rows rather than collecting them so that the whole
+    /// table needn't be held in memory when rebuilding the indexes.
+    fn get_all<T: Send + 'static>(
+        &self,
+        query: &'static str,
+        from_row: fn(&Row) -> T,
+    ) -> BoxStream<'_, Arc<T>> {
+        let rows = async move {
+            self.client
+                .query_raw(query, std::iter::empty::<i64>())
+                .await
+                .unwrap()
+        };
+
+        stream::once(rows)
+            .flatten()
+            .map(move |row| Arc::new(from_row(&row.unwrap())))
+            .boxed()
+    }
+
+    async fn execute(&mut self, query: &str, params: &[&(dyn ToSql + Sync)]) {
+        self.writer().await.execute(query, params).await.unwrap();
+    }
+
+    async fn drop_keys(&mut self, query: &str, keys: Vec<Timestamp>) {
+        let keys = keys.into_iter().map(to_db).collect::<Vec<_>>();
+        self.execute(query, &[&keys]).await;
+    }
+}
+
+/// The tables are created if they don't exist so that connecting to a database
+/// that was already set up is the same as connecting to a new one.
+const TABLES: &str = r#"
+    CREATE TABLE IF NOT EXISTS connections (
+        key             INT8 NOT NULL,
+        id              INT8,
+        disconnected_at INT8,
+        fields          JSONB,
+        schema          INT8 NOT NULL DEFAULT 1,
+
+        CONSTRAINT connections_pk PRIMARY KEY (key)
+    );
+
+    CREATE TABLE IF NOT EXISTS spans (
+        key        INT8 NOT NULL,
+        connection INT8,
+        id         INT8,
+        closed_at  INT8,
+        parent_id  INT8,
+        follows    JSONB,
+        target     TEXT,
+        name       TEXT,
+        level      INT4,
+        file_name  TEXT,
+        file_line  INT8,
+        fields     JSONB,
+        busy       INT8 NOT NULL DEFAULT 0,
+        entered_at INT8,
+        client_created_at INT8,
+
+        CONSTRAINT spans_pk PRIMARY KEY (key)
+    );
+
+    CREATE TABLE IF NOT EXISTS span_events (
+        key        INT8 NOT NULL,
+        connection INT8,
+        span_id    INT8,
+        kind       TEXT,
+        data       JSONB,
+
+        CONSTRAINT span_events_pk PRIMARY KEY (key)
+    );
+
+    CREATE TABLE IF NOT EXISTS events (
+        key        INT8 NOT NULL,
+        connection INT8,
+        span_id    INT8,
+        target     TEXT,
+        name       TEXT,
+        level      INT4,
+        file_name  TEXT,
+        file_line  INT8,
+        fields     JSONB,
+        client_timestamp INT8,
+
+        CONSTRAINT events_pk PRIMARY KEY (key)
+    );
+
+    -- these were added after the tables were first created
+    ALTER TABLE spans ADD COLUMN IF NOT EXISTS client_created_at INT8;
+    ALTER TABLE events ADD COLUMN IF NOT EXISTS client_timestamp INT8;
+
+    CREATE TABLE IF NOT EXISTS indexes (
+        connections INT8 NOT NULL,
+        spans       INT8 NOT NULL,
+        span_events INT8 NOT NULL,
+        events      INT8 NOT NULL,
+        data        BYTEA NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS saved_filters (
+        name   TEXT NOT NULL,
+        filter TEXT NOT NULL,
+
+        CONSTRAINT saved_filters_pk PRIMARY KEY (name)
+    );"#;
+
+const TABLE_COUNTS: &str = "SELECT
+    (SELECT COUNT(*) FROM connections),
+    (SELECT COUNT(*) FROM spans),
+    (SELECT COUNT(*) FROM span_events),
+    (SELECT COUNT(*) FROM events)";
+
+#[derive(Debug)]
+pub enum PostgresStorageError {
+    Database(DbError),
+}
+
+impl Display for PostgresStorageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            PostgresStorageError::Database(err) => write!(f, "database error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for PostgresStorageError {}
+
+impl From<DbError> for PostgresStorageError {
+    fn from(err: DbError) -> Self {
+        PostgresStorageError::Database(err)
+    }
+}
+
+impl AsyncStorage for PostgresStorage {
+    fn get_connection(&self, at: Timestamp) -> BoxFuture<'_, Option<Arc<Connection>>> {
+        Box::pin(self.get(
+            "SELECT * FROM connections WHERE key = $1",
+            at,
+            connection_from_row,
+        ))
+    }
+
+    fn get_span(&self, at: Timestamp) -> BoxFuture<'_, Option<Arc<Span>>> {
+        Box::pin(self.get("SELECT * FROM spans WHERE key = $1", at, span_from_row))
+    }
+
+    fn get_span_event(&self, at: Timestamp) -> BoxFuture<'_, Option<Arc<SpanEvent>>> {
+        Box::pin(self.get(
+            "SELECT * FROM span_events WHERE key = $1",
+            at,
+            span_event_from_row,
+        ))
+    }
+
+    fn get_event(&self, at: Timestamp) -> BoxFuture<'_, Option<Arc<Event>>> {
+        Box::pin(self.get("SELECT * FROM events WHERE key = $1", at, event_from_row))
+    }
+
+    fn get_all_connections(&self) -> BoxStream<'_, Arc<Connection>> {
+        self.get_all(
+            "SELECT * FROM connections ORDER BY key",
+            connection_from_row,
+        )
+    }
+
+    fn get_all_spans(&self) -> BoxStream<'_, Arc<Span>> {
+        self.get_all("SELECT * FROM spans ORDER BY key", span_from_row)
+    }
+
+    fn get_all_span_events(&self) -> BoxStream<'_, Arc<SpanEvent>> {
+        self.get_all(
+            "SELECT * FROM span_events ORDER BY key",
+            span_event_from_row,
+        )
+    }
+
+    fn get_all_events(&self) -> BoxStream<'_, Arc<Event>> {
+        self.get_all("SELECT * FROM events ORDER BY key", event_from_row)
+    }
+
+    fn insert_connection(&mut self, connection: Connection) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            let key = to_db(connection.key());
+            let id = connection.id as i64;
+            let disconnected_at = connection.disconnected_at.map(to_db);
+            let fields = Json(connection.fields);
+            let schema = connection.schema as i64;
+
+            self.execute(
+                "INSERT INTO connections VALUES ($1, $2, $3, $4, $5)",
+                &[&key, &id, &disconnected_at, &fields, &schema],
+            )
+            .await;
+        })
+    }
+
+    fn insert_span(&mut self, span: Span) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            let key = to_db(span.created_at);
+            let connection_key = to_db(span.connection_key);
+            let id = span.id.get() as i64;
+            let closed_at = span.closed_at.map(to_db);
+            let parent_id = span.parent_key.map(to_db);
+            let follows = Json(span.follows);
+            let level = span.level as i32;
+            let file_line = span.file_line.map(i64::from);
+            let fields = Json(span.fields);
+            let busy = span.busy as i64;
+            let entered_at = span.entered_at.map(to_db);
+            let client_created_at = span.client_created_at.map(to_db);
+
+            self.execute(
+                "INSERT INTO spans VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+                &[
+                    &key,
+                    &connection_key,
+                    &id,
+                    &closed_at,
+                    &parent_id,
+                    &follows,
+                    &span.target,
+                    &span.name,
+                    &level,
+                    &span.file_name,
+                    &file_line,
+                    &fields,
+                    &busy,
+                    &entered_at,
+                    &client_created_at,
+                ],
+            )
+            .await;
+        })
+    }
+
+    fn insert_span_event(&mut self, span_event: SpanEvent) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            let key = to_db(span_event.timestamp);
+            let connection_key = to_db(span_event.connection_key);
+            let span_key = to_db(span_event.span_key);
+            let (kind, data) = match span_event.kind {
+                SpanEventKind::Create(create) => ("create", Some(serde_json::to_value(create))),
+                SpanEventKind::Update(update) => ("update", Some(serde_json::to_value(update))),
+                SpanEventKind::Follows(follows) => ("follows", Some(serde_json::to_value(follows))),
+                SpanEventKind::Enter => ("enter", None),
+                SpanEventKind::Exit => ("exit", None),
+                SpanEventKind::Close => ("close", None),
+            };
+            let data = data.map(|data| Json(data.unwrap()));
+
+            self.execute(
+                "INSERT INTO span_events VALUES ($1, $2, $3, $4, $5)",
+                &[&key, &connection_key, &span_key, &kind, &data],
+            )
+            .await;
+        })
+    }
+
+    fn insert_events(&mut self, events: Vec<Event>) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            let client = self.writer().await;
+
+            // a single statement is used so the batch is one round trip
+            let mut keys = Vec::with_capacity(events.len());
+            let mut connection_keys = Vec::with_capacity(events.len());
+            let mut span_keys = Vec::with_capacity(events.len());
+            let mut targets = Vec::with_capacity(events.len());
+            let mut names = Vec::with_capacity(events.len());
+            let mut levels = Vec::with_capacity(events.len());
+            let mut file_names = Vec::with_capacity(events.len());
+            let mut file_lines = Vec::with_capacity(events.len());
+            let mut fields = Vec::with_capacity(events.len());
+            let mut client_timestamps = Vec::with_capacity(events.len());
+
+            for event in events {
+                keys.push(to_db(event.timestamp));
+                connection_keys.push(to_db(event.connection_key));
+                span_keys.push(event.span_key.map(to_db));
+                targets.push(event.target);
+                names.push(event.name);
+                levels.push(event.level as i32);
+                file_names.push(event.file_name);
+                file_lines.push(event.file_line.map(i64::from));
+                fields.push(Json(event.fields));
+                client_timestamps.push(event.client_timestamp.map(to_db));
+            }
+
+            client
+                .execute(
+                    "INSERT INTO events SELECT * FROM UNNEST(
+                        $1::INT8[], $2::INT8[], $3::INT8[], $4::TEXT[], $5::TEXT[],
+                        $6::INT4[], $7::TEXT[], $8::INT8[], $9::JSONB[], $10::INT8[]
+                    )",
+                    &[
+                        &keys,
+                        &connection_keys,
+                        &span_keys,
+                        &targets,
+                        &names,
+                        &levels,
+                        &file_names,
+                        &file_lines,
+                        &fields,
+                        &client_timestamps,
+                    ],
+                )
+                .await
+                .unwrap();
+        })
+    }
+
+    fn update_connection_disconnected(
+        &mut self,
+        at: Timestamp,
+        disconnected: Timestamp,
+    ) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            self.execute(
+                "UPDATE connections SET disconnected_at = $2 WHERE key = $1",
+                &[&to_db(at), &to_db(disconnected)],
+            )
+            .await;
+        })
+    }
+
+    fn update_span_closed(&mut self, at: Timestamp, closed: Timestamp) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            self.execute(
+                "UPDATE spans SET closed_at = $2 WHERE key = $1",
+                &[&to_db(at), &to_db(closed)],
+            )
+            .await;
+        })
+    }
+
+    fn update_span_fields(
+        &mut self,
+        at: Timestamp,
+        fields: BTreeMap<String, Value>,
+    ) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            // jsonb concatenation replaces the values of existing keys
+            self.execute(
+                "UPDATE spans SET fields = fields || $2 WHERE key = $1",
+                &[&to_db(at), &Json(fields)],
+            )
+            .await;
+        })
+    }
+
+    fn update_span_follows(&mut self, at: Timestamp, follows: SpanKey) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            self.execute(
+                "UPDATE spans SET follows = follows || $2 WHERE key = $1",
+                &[&to_db(at), &Json([follows])],
+            )
+            .await;
+        })
+    }
+
+    fn update_span_busy(
+        &mut self,
+        at: Timestamp,
+        busy: u64,
+        entered_at: Option<Timestamp>,
+    ) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            self.execute(
+                "UPDATE spans SET busy = $2, entered_at = $3 WHERE key = $1",
+                &[&to_db(at), &(busy as i64), &entered_at.map(to_db)],
+            )
+            .await;
+        })
+    }
+
+    fn drop_connections(&mut self, connections: Vec<Timestamp>) -> BoxFuture<'_, ()> {
+        Box::pin(self.drop_keys("DELETE FROM connections WHERE key = ANY($1)", connections))
+    }
+
+    fn drop_spans(&mut self, spans: Vec<Timestamp>) -> BoxFuture<'_, ()> {
+        Box::pin(self.drop_keys("DELETE FROM spans WHERE key = ANY($1)", spans))
+    }
+
+    fn drop_span_events(&mut self, span_events: Vec<Timestamp>) -> BoxFuture<'_, ()> {
+        Box::pin(self.drop_keys("DELETE FROM span_events WHERE key = ANY($1)", span_events))
+    }
+
+    fn drop_events(&mut self, events: Vec<Timestamp>) -> BoxFuture<'_, ()> {
+        Box::pin(self.drop_keys("DELETE FROM events WHERE key = ANY($1)", events))
+    }
+
+    fn clear(&mut self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            self.writer()
+                .await
+                .batch_execute("TRUNCATE connections, spans, span_events, events")
+                .await
+                .unwrap();
+        })
+    }
+
+    fn load_indexes(&self) -> BoxFuture<'_, Option<Vec<u8>>> {
+        Box::pin(async move {
+            if !self.has_saved_indexes {
+                return None;
+            }
+
+            let saved = self
+                .client
+                .query_opt(
+                    "SELECT connections, spans, span_events, events, data FROM indexes",
+                    &[],
+                )
+                .await
+                .unwrap()?;
+            let counts = self.client.query_one(TABLE_COUNTS, &[]).await.unwrap();
+
+            // this shouldn't happen, but the indexes can't be trusted if the
+            // tables were changed by something else
+            if (0..4).any(|i| saved.get::<_, i64>(i) != counts.get::<_, i64>(i)) {
+                return None;
+            }
+
+            Some(saved.get(4))
+        })
+    }
+
+    fn save_indexes(&mut self, indexes: Vec<u8>) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            let tx = self.client.transaction().await.unwrap();
+            let counts = tx.query_one(TABLE_COUNTS, &[]).await.unwrap();
+            let counts: [i64; 4] = [counts.get(0), counts.get(1), counts.get(2), counts.get(3)];
+
+            tx.execute("DELETE FROM indexes", &[]).await.unwrap();
+            tx.execute(
+                "INSERT INTO indexes VALUES ($1, $2, $3, $4, $5)",
+                &[&counts[0], &counts[1], &counts[2], &counts[3], &indexes],
+            )
+            .await
+            .unwrap();
+
+            tx.commit().await.unwrap();
+            self.has_saved_indexes = true;
+        })
+    }
+
+    fn load_saved_filters(&self) -> BoxFuture<'_, Vec<(String, String)>> {
+        Box::pin(async move {
+            let rows = self
+                .client
+                .query("SELECT name, filter FROM saved_filters", &[])
+                .await
+                .unwrap();
+
+            rows.iter().map(|row| (row.get(0), row.get(1))).collect()
+        })
+    }
+
+    fn save_filter(&mut self, name: String, filter: String) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            self.client
+                .execute(
+                    "INSERT INTO saved_filters VALUES ($1, $2) ON CONFLICT (name) DO UPDATE SET filter = EXCLUDED.filter",
+                    &[&name, &filter],
+                )
+                .await
+                .unwrap();
+        })
+    }
+
+    fn delete_saved_filter(&mut self, name: String) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            self.client
+                .execute("DELETE FROM saved_filters WHERE name = $1", &[&name])
+                .await
+                .unwrap();
+        })
+    }
+}
+
+// keys are microsecond timestamps so they fit in a signed 64-bit integer
+fn to_db(at: Timestamp) -> i64 {
+    at.get() as i64
+}
+
+fn from_db(value: i64) -> Timestamp {
+    Timestamp::new(value as u64).unwrap()
+}
+
+fn connection_from_row(row: &Row) -> Connection {
+    let key: i64 = row.get(0);
+    let id: i64 = row.get(1);
+    let disconnected_at: Option<i64> = row.get(2);
+    let Json(fields) = row.get(3);
+    let schema: i64 = row.get(4);
+
+    Connection {
+        id: id as u64,
+        connected_at: from_db(key),
+        disconnected_at: disconnected_at.map(from_db),
+        schema: schema as u32,
+        fields,
+    }
+}
+
+fn span_from_row(row: &Row) -> Span {
+    let key: i64 = row.get(0);
+    let connection_key: i64 = row.get(1);
+    let id: i64 = row.get(2);
+    let closed_at: Option<i64> = row.get(3);
+    let parent_key: Option<i64> = row.get(4);
+    let Json(follows) = row.get(5);
+    let level: i32 = row.get(8);
+    let file_line: Option<i64> = row.get(10);
+    let Json(fields) = row.get(11);
+    let busy: i64 = row.get(12);
+    let entered_at: Option<i64> = row.get(13);
+    let client_created_at: Option<i64> = row.get(14);
+
+    Span {
+        created_at: from_db(key),
+        client_created_at: client_created_at.map(from_db),
+        connection_key: from_db(connection_key),
+        id: SpanId::new(id as u64).unwrap(),
+        closed_at: closed_at.map(from_db),
+        parent_key: parent_key.map(from_db),
+        follows,
+        target: row.get(6),
+        name: row.get(7),
+        level: Level::clamped(level),
+        file_name: row.get(9),
+        file_line: file_line.map(|line| line as u32),
+        fields,
+        busy: busy as u64,
+        entered_at: entered_at.map(from_db),
+    }
+}
+
+fn span_event_from_row(row: &Row) -> SpanEvent {
+    let key: i64 = row.get(0);
+    let connection_key: i64 = row.get(1);
+    let span_key: i64 = row.get(2);
+    let kind: &str = row.get(3);
+    let data: Option<Json<serde_json::Value>> = row.get(4);
+    let data = data.map(|Json(data)| data);
+
+    let kind = match kind {
+        "create" => SpanEventKind::Create(serde_json::from_value(data.unwrap()).unwrap()),
+        "update" => SpanEventKind::Update(serde_json::from_value(data.unwrap()).unwrap()),
+        "follows" => SpanEventKind::Follows(serde_json::from_value(data.unwrap()).unwrap()),
+        "enter" => SpanEventKind::Enter,
+        "exit" => SpanEventKind::Exit,
+        "close" => SpanEventKind::Close,
+        _ => panic!("unknown span event kind"),
+    };
+
+    SpanEvent {
+        connection_key: from_db(connection_key),
+        timestamp: from_db(key),
+        span_key: from_db(span_key),
+        kind,
+    }
+}
+
+fn event_from_row(row: &Row) -> Event {
+    let key: i64 = row.get(0);
+    let connection_key: i64 = row.get(1);
+    let span_key: Option<i64> = row.get(2);
+    let level: i32 = row.get(5);
+    let file_line: Option<i64> = row.get(7);
+    let Json(fields) = row.get(8);
+    let client_timestamp: Option<i64> = row.get(9);
+
+    Event {
+        timestamp: from_db(key),
+        client_timestamp: client_timestamp.map(from_db),
+        connection_key: from_db(connection_key),
+        span_key: span_key.map(from_db),
+        target: row.get(3),
+        name: row.get(4),
+        level: Level::clamped(level),
+        file_name: row.get(6),
+        file_line: file_line.map(|line| line as u32),
+        fields,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Builder;
+
+    use super::*;
+    use crate::models::{NewCreateSpanEvent, NewUpdateSpanEvent};
+    use crate::storage::BlockOnStorage;
+    use crate::{
+        ConnectionKey, FilterPredicate, NewConnection, NewEvent, NewSpanEvent, NewSpanEventKind,
+        Order, Query, RawEngine, Storage,
+    };
+
+    fn make_event(key: u64) -> Event {
+        Event {
+            connection_key: ConnectionKey::new(1).unwrap(),
+            timestamp: Timestamp::new(key).unwrap(),
+            client_timestamp: None,
+            span_key: None,
+            target: "crate::storage::postgres::tests".to_owned(),
+            name: "event".to_owned(),
+            level: Level::Info,
+            file_name: None,
+            file_line: Some(7),
+            fields: BTreeMap::from([("n".to_owned(), Value::U64(key))]),
+        }
+    }
+
+    #[test]
+    #[ignore = "requires a Postgres server given by VENATOR_TEST_POSTGRES"]
+    fn events_round_trip() {
+        let config = std::env::var("VENATOR_TEST_POSTGRES").unwrap();
+        let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+        let storage = runtime.block_on(PostgresStorage::connect(&config)).unwrap();
+
+        let mut storage = BlockOnStorage::new(storage, runtime.handle().clone());
+        storage.clear();
+
+        storage.insert_events(vec![make_event(1), make_event(2), make_event(3)]);
+        storage.drop_events(&[Timestamp::new(2).unwrap()]);
+
+        let event = storage.get_event(Timestamp::new(3).unwrap()).unwrap();
+        assert_eq!(event.file_line, Some(7));
+        assert_eq!(event.fields["n"], Value::U64(3));
+        assert!(storage.get_event(Timestamp::new(2).unwrap()).is_none());
+        assert_eq!(storage.get_all_events().count(), 2);
+
+        storage.clear();
+    }
+
+    #[test]
+    #[ignore = "requires a Postgres server given by VENATOR_TEST_POSTGRES"]
+    fn engine_reloads_from_postgres() {
+        let config = std::env::var("VENATOR_TEST_POSTGRES").unwrap();
+        let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+        let connect = || {
+            let storage = runtime.block_on(PostgresStorage::connect(&config)).unwrap();
+            BlockOnStorage::new(storage, runtime.handle().clone())
+        };
+
+        let mut storage = connect();
+        storage.clear();
+
+        let mut engine = RawEngine::new(storage);
+        let connection_key = engine
+            .insert_connection(NewConnection::new(1, BTreeMap::new()))
+            .unwrap();
+        engine
+            .insert_span_event(NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(1).unwrap(),
+                span_id: 1.try_into().unwrap(),
+                kind: NewSpanEventKind::Create(NewCreateSpanEvent {
+                    parent_id: None,
+                    target: "crate::storage::postgres::tests".to_owned(),
+                    name: "span".to_owned(),
+                    level: 2,
+                    file_name: None,
+                    file_line: None,
+                    fields: BTreeMap::from([("user".to_owned(), Value::Str("bob".to_owned()))]),
+                }),
+            })
+            .unwrap();
+        engine
+            .insert_span_event(NewSpanEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(2).unwrap(),
+                span_id: 1.try_into().unwrap(),
+                kind: NewSpanEventKind::Update(NewUpdateSpanEvent {
+                    fields: BTreeMap::from([("user".to_owned(), Value::Str("alice".to_owned()))]),
+                }),
+            })
+            .unwrap();
+        engine
+            .insert_event(NewEvent {
+                connection_key,
+                seq: None,
+                timestamp: Timestamp::new(3).unwrap(),
+                span_id: Some(1.try_into().unwrap()),
+                name: "event".to_owned(),
+                target: "crate::storage::postgres::tests".to_owned(),
+                level: 2,
+                file_name: None,
+                file_line: None,
+                fields: BTreeMap::new(),
+            })
+            .unwrap();
+        engine.flush();
+        drop(engine);
+
+        assert!(connect().load_indexes().is_some());
+
+        let engine = RawEngine::new(connect());
+        let query = |filter: &str| Query {
+            filter: FilterPredicate::parse(filter).unwrap(),
+            order: Order::Asc,
+            limit: 5,
+            start: Timestamp::MIN,
+            end: Timestamp::MAX,
+            previous: None,
+        };
+
+        assert_eq!(engine.query_event(query("#stack: 1-1")).len(), 1);
+        assert_eq!(engine.query_span(query("@user: alice")).len(), 1);
+        assert_eq!(engine.storage.get_all_span_events().count(), 2);
+        drop(engine);
+
+        connect().clear();
+    }
+}
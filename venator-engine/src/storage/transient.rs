@@ -5,7 +5,9 @@ use super::Storage;
 use crate::models::Value;
 use crate::{Connection, Event, Span, SpanEvent, SpanKey, Timestamp};
 
-/// This storage implementation just holds elements in memory.
+/// This storage implementation just holds elements in memory. Elements are
+/// shared via `Arc` so getters don't clone them, and updates only clone an
+/// element if it is still referenced elsewhere.
 pub struct TransientStorage {
     connections: BTreeMap<Timestamp, Arc<Connection>>,
     spans: BTreeMap<Timestamp, Arc<Span>>,
@@ -79,34 +81,34 @@ impl Storage for TransientStorage {
     }
 
     fn update_connection_disconnected(&mut self, at: Timestamp, disconnected_at: Timestamp) {
-        if let Some(connection) = self.connections.get(&at) {
-            let mut connection = (**connection).clone();
-            connection.disconnected_at = Some(disconnected_at);
-            self.connections.insert(at, Arc::new(connection));
+        if let Some(connection) = self.connections.get_mut(&at) {
+            Arc::make_mut(connection).disconnected_at = Some(disconnected_at);
         }
     }
 
     fn update_span_closed(&mut self, at: Timestamp, closed_at: Timestamp) {
-        if let Some(span) = self.spans.get(&at) {
-            let mut span = (**span).clone();
-            span.closed_at = Some(closed_at);
-            self.spans.insert(at, Arc::new(span));
+        if let Some(span) = self.spans.get_mut(&at) {
+            Arc::make_mut(span).closed_at = Some(closed_at);
         }
     }
 
     fn update_span_fields(&mut self, at: Timestamp, fields: BTreeMap<String, Value>) {
-        if let Some(span) = self.spans.get(&at) {
-            let mut span = (**span).clone();
-            span.fields.extend(fields);
-            self.spans.insert(at, Arc::new(span));
+        if let Some(span) = self.spans.get_mut(&at) {
+            Arc::make_mut(span).fields.extend(fields);
         }
     }
 
     fn update_span_follows(&mut self, at: Timestamp, follows: SpanKey) {
-        if let Some(span) = self.spans.get(&at) {
-            let mut span = (**span).clone();
-            span.follows.push(follows);
-            self.spans.insert(at, Arc::new(span));
+        if let Some(span) = self.spans.get_mut(&at) {
+            Arc::make_mut(span).follows.push(follows);
+        }
+    }
+
+    fn update_span_busy(&mut self, at: Timestamp, busy: u64, entered_at: Option<Timestamp>) {
+        if let Some(span) = self.spans.get_mut(&at) {
+            let span = Arc::make_mut(span);
+            span.busy = busy;
+            span.entered_at = entered_at;
         }
     }
 
@@ -133,4 +135,11 @@ impl Storage for TransientStorage {
             self.events.remove(at);
         }
     }
+
+    fn clear(&mut self) {
+        self.connections.clear();
+        self.spans.clear();
+        self.span_events.clear();
+        self.events.clear();
+    }
 }
@@ -24,6 +24,10 @@ use messaging::{Handshake, Message};
 /// to finalize.
 pub struct VenatorBuilder {
     host: Option<String>,
+    schema: u32,
+    connection_id: Option<u64>,
+    max_message_size: u32,
+    token: Option<String>,
     fields: BTreeMap<String, OwnedValue>,
 }
 
@@ -79,6 +83,93 @@ impl VenatorBuilder {
         self
     }
 
+    /// This will set the schema version of the `Venator` layer. The Venator app
+    /// records this with the connection so that it can be filtered by via
+    /// `#schema` when the shape of your telemetry changes over time.
+    ///
+    /// Setting the schema version again will overwrite the previous value. The
+    /// default is `1`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use venator::Venator;
+    /// let venator_layer = Venator::builder()
+    ///     .with_schema_version(2)
+    ///     .build()
+    ///     .install();
+    /// ```
+    pub fn with_schema_version(mut self, schema: u32) -> VenatorBuilder {
+        self.schema = schema;
+        self
+    }
+
+    /// This will set the id that the Venator app uses for the connection, so
+    /// that it can be correlated with your own logs or filtered via
+    /// `#connection`. If not set, the app will generate a random one.
+    ///
+    /// The app rejects a connection if another connection with the same id is
+    /// still connected. Once that connection has disconnected the id can be
+    /// reused (like when reconnecting), which creates a new connection and
+    /// `#connection` filters will then refer to the newest one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use venator::Venator;
+    /// let venator_layer = Venator::builder()
+    ///     .with_connection_id(42)
+    ///     .build()
+    ///     .install();
+    /// ```
+    pub fn with_connection_id(mut self, connection_id: u64) -> VenatorBuilder {
+        self.connection_id = Some(connection_id);
+        self
+    }
+
+    /// This will set the token sent to the Venator app when connecting. This
+    /// is required if the app was started with `--token`, otherwise the
+    /// connection is rejected.
+    ///
+    /// Setting the token again will overwrite the previous value. There is no
+    /// token by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use venator::Venator;
+    /// let venator_layer = Venator::builder()
+    ///     .with_token("my-secret")
+    ///     .build()
+    ///     .install();
+    /// ```
+    pub fn with_token<T: Into<String>>(mut self, token: T) -> VenatorBuilder {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// This will set the largest message in bytes that the `Venator` layer
+    /// will send. Events and spans that encode to more than this (for example
+    /// with large backtraces or JSON blobs as attributes) are dropped.
+    ///
+    /// Setting this above 65535 requires a version of the Venator app that
+    /// supports large messages; older versions will reject the connection.
+    /// The app may also impose its own limit. The default is `65535`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use venator::Venator;
+    /// let venator_layer = Venator::builder()
+    ///     .with_max_message_size(1024 * 1024)
+    ///     .build()
+    ///     .install();
+    /// ```
+    pub fn with_max_message_size(mut self, max_message_size: u32) -> VenatorBuilder {
+        self.max_message_size = max_message_size;
+        self
+    }
+
     /// This will build the `Venator` layer. It will need to be added to another
     /// subscriber via `.with()` or installed globally with [`.install()`](Venator::install)
     /// to be useful.
@@ -102,10 +193,18 @@ impl VenatorBuilder {
     ///     .init();
     /// ```
     pub fn build(self) -> Venator {
-        let connection = Connection::new(self.host, self.fields);
+        let connection = Connection::new(
+            self.host,
+            self.schema,
+            self.connection_id,
+            self.max_message_size,
+            self.token,
+            self.fields,
+        );
 
         Venator {
             connection: Mutex::new(connection),
+            max_message_size: self.max_message_size,
         }
     }
 }
@@ -117,6 +216,7 @@ impl VenatorBuilder {
 /// `default()`.
 pub struct Venator {
     connection: Mutex<Connection>,
+    max_message_size: u32,
 }
 
 impl Venator {
@@ -125,6 +225,10 @@ impl Venator {
     pub fn builder() -> VenatorBuilder {
         VenatorBuilder {
             host: None,
+            schema: 1,
+            connection_id: None,
+            max_message_size: u16::MAX as u32,
+            token: None,
             fields: BTreeMap::new(),
         }
     }
@@ -153,7 +257,7 @@ impl Venator {
 
         let mut buffer = SCRATCH.with(|b| b.take());
 
-        if let Err(err) = messaging::encode(&mut buffer, &message) {
+        if let Err(err) = messaging::encode(&mut buffer, &message, self.max_message_size) {
             error!(parent: None, "failed to encode message: {err:?}");
             return;
         };
@@ -264,15 +368,30 @@ where
 
 struct Connection {
     host: Option<String>,
+    schema: u32,
+    connection_id: Option<u64>,
+    max_message_size: u32,
+    token: Option<String>,
     fields: BTreeMap<String, OwnedValue>,
     stream: Option<TcpStream>,
     last_connect_attempt: Instant,
 }
 
 impl Connection {
-    fn new(host: Option<String>, fields: BTreeMap<String, OwnedValue>) -> Connection {
+    fn new(
+        host: Option<String>,
+        schema: u32,
+        connection_id: Option<u64>,
+        max_message_size: u32,
+        token: Option<String>,
+        fields: BTreeMap<String, OwnedValue>,
+    ) -> Connection {
         Connection {
             host,
+            schema,
+            connection_id,
+            max_message_size,
+            token,
             fields,
             stream: None,
             last_connect_attempt: Instant::now() - Duration::from_secs(10),
@@ -313,11 +432,17 @@ impl Connection {
 
         let handshake = Handshake {
             fields: self.fields.clone(),
+            schema: self.schema,
+            connection_id: self.connection_id,
+            max_message_size: self.max_message_size,
+            token: self.token.clone(),
         };
 
         let mut buffer = vec![];
 
-        if let Err(err) = messaging::encode(&mut buffer, &handshake) {
+        // the handshake always uses a two-byte length prefix since that is all
+        // older versions of the app understand
+        if let Err(err) = messaging::encode(&mut buffer, &handshake, u16::MAX as u32) {
             error!(parent: None, "failed to encode handshake: {err:?}");
             return;
         };
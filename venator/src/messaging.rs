@@ -25,21 +25,30 @@ fn now() -> NonZeroU64 {
     NonZeroU64::new(microseconds as u64).unwrap()
 }
 
-pub(crate) fn encode<T: Serialize>(buffer: &mut Vec<u8>, payload: &T) -> Result<(), BincodeError> {
-    // this uses a two-byte length prefix followed by the bincode-ed payload
+pub(crate) fn encode<T: Serialize>(
+    buffer: &mut Vec<u8>,
+    payload: &T,
+    max_size: u32,
+) -> Result<(), BincodeError> {
+    // this uses a length prefix followed by the bincode-ed payload, the prefix
+    // is two bytes unless the max size doesn't fit in that, then it is four
 
-    buffer.resize(2, 0);
+    let prefix_size = if max_size > u16::MAX as u32 { 4 } else { 2 };
+
+    buffer.resize(prefix_size, 0);
 
     DefaultOptions::new()
         .with_varint_encoding()
         .with_big_endian()
-        .with_limit(u16::MAX as u64)
+        .with_limit(max_size as u64)
         .serialize_into(&mut *buffer, payload)?;
 
-    let payload_size = buffer.len() - 2;
-    let payload_size_bytes = (payload_size as u16).to_be_bytes();
-
-    buffer[0..2].copy_from_slice(&payload_size_bytes);
+    let payload_size = buffer.len() - prefix_size;
+    if prefix_size == 4 {
+        buffer[0..4].copy_from_slice(&(payload_size as u32).to_be_bytes());
+    } else {
+        buffer[0..2].copy_from_slice(&(payload_size as u16).to_be_bytes());
+    }
 
     Ok(())
 }
@@ -47,6 +56,12 @@ pub(crate) fn encode<T: Serialize>(buffer: &mut Vec<u8>, payload: &T) -> Result<
 #[derive(Serialize)]
 pub struct Handshake {
     pub fields: BTreeMap<String, OwnedValue>,
+    // these must stay in order and at the end since older versions of the app
+    // don't expect them
+    pub schema: u32,
+    pub connection_id: Option<u64>,
+    pub max_message_size: u32,
+    pub token: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -229,3 +244,31 @@ fn level_to_number(level: Level) -> i32 {
         Level::ERROR => 4,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_uses_wide_prefix_for_large_max_size() {
+        let payload = "x".repeat(200 * 1024);
+        let mut buffer = vec![];
+
+        assert!(encode(&mut buffer, &payload, u16::MAX as u32).is_err());
+
+        encode(&mut buffer, &payload, 1024 * 1024).unwrap();
+        let length = u32::from_be_bytes(buffer[0..4].try_into().unwrap());
+        assert_eq!(length as usize, buffer.len() - 4);
+
+        let decoded: String = DefaultOptions::new()
+            .with_varint_encoding()
+            .with_big_endian()
+            .deserialize(&buffer[4..])
+            .unwrap();
+        assert_eq!(decoded, payload);
+
+        encode(&mut buffer, &"small", u16::MAX as u32).unwrap();
+        let length = u16::from_be_bytes(buffer[0..2].try_into().unwrap());
+        assert_eq!(length as usize, buffer.len() - 2);
+    }
+}